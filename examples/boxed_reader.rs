@@ -0,0 +1,41 @@
+// Count the elements of a PBF file from a source that is chosen at runtime. The first command
+// line argument is either "-" to read from the standard input or a "file://" URL.
+
+extern crate osmpbf;
+
+use osmpbf::{Element, ElementReader};
+use std::error::Error;
+use std::io::{BufReader, Read};
+
+fn open(url: &str) -> Result<Box<dyn Read>, Box<dyn Error>> {
+    if url == "-" {
+        return Ok(Box::new(BufReader::new(std::io::stdin())));
+    }
+    match url.strip_prefix("file://") {
+        Some(path) => Ok(Box::new(BufReader::new(std::fs::File::open(path)?))),
+        None => Err(format!("unsupported URL: {}", url).into()),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let arg = std::env::args()
+        .nth(1)
+        .ok_or("need \"-\" or a file:// URL as argument")?;
+    let reader = ElementReader::new(open(&arg)?);
+
+    println!("Counting...");
+    let mut nodes = 0_u64;
+    let mut ways = 0_u64;
+    let mut relations = 0_u64;
+
+    reader.for_each(|element| match element {
+        Element::Node(_) | Element::DenseNode(_) => nodes += 1,
+        Element::Way(_) => ways += 1,
+        Element::Relation(_) => relations += 1,
+    })?;
+
+    println!("Nodes: {}", nodes);
+    println!("Ways: {}", ways);
+    println!("Relations: {}", relations);
+    Ok(())
+}
@@ -0,0 +1,47 @@
+// Measure how long it takes to decompress all blobs of a PBF file given as the first command line
+// argument. The zlib backend is chosen at compile time, so compare the backends by running the
+// example once per feature set:
+//
+//     cargo run --release --example decompress -- planet.osm.pbf
+//     cargo run --release --example decompress --features zlib-ng -- planet.osm.pbf
+//     cargo run --release --example decompress --features libdeflate -- planet.osm.pbf
+
+extern crate osmpbf;
+
+use osmpbf::{BlobReader, Result};
+use std::time::Instant;
+
+fn backend() -> &'static str {
+    if cfg!(feature = "libdeflate") {
+        "libdeflate"
+    } else if cfg!(feature = "zlib-ng") {
+        "zlib-ng"
+    } else if cfg!(feature = "system-libz") {
+        "system libz"
+    } else {
+        "inflate"
+    }
+}
+
+fn main() -> Result<()> {
+    let arg = std::env::args_os()
+        .nth(1)
+        .expect("need a *.osm.pbf file as argument");
+
+    // Read all blobs up front, so only the decompression is measured.
+    let blobs = BlobReader::from_path(&arg)?.collect::<Result<Vec<_>>>()?;
+
+    let start = Instant::now();
+    let mut bytes = 0_u64;
+    for blob in &blobs {
+        bytes += blob.decompress()?.len() as u64;
+    }
+    let elapsed = start.elapsed();
+
+    println!("Backend: {}", backend());
+    println!("Blobs: {}", blobs.len());
+    println!("Decompressed bytes: {}", bytes);
+    println!("Time: {:?}", elapsed);
+
+    Ok(())
+}
@@ -0,0 +1,50 @@
+// Compare the time it takes to sum up the coordinates of all dense nodes in a PBF file
+// given as the first command line argument, once with the per-node iterator and once with
+// the bulk arrays of `PrimitiveBlock::dense_arrays`.
+
+extern crate osmpbf;
+
+use osmpbf::{BlobDecode, BlobReader};
+use std::error::Error;
+use std::time::Instant;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let arg = std::env::args_os()
+        .nth(1)
+        .ok_or("need a *.osm.pbf file as argument")?;
+
+    // Decode all blocks up front, so that only the access to the nodes is measured.
+    let mut blocks = vec![];
+    for blob in BlobReader::from_path(&arg)? {
+        if let BlobDecode::OsmData(block) = blob?.decode()? {
+            blocks.push(block);
+        }
+    }
+
+    let start = Instant::now();
+    let mut sum = (0_i64, 0_i64);
+    for block in &blocks {
+        for group in block.groups() {
+            for node in group.dense_nodes() {
+                sum.0 = sum.0.wrapping_add(node.nano_lat());
+                sum.1 = sum.1.wrapping_add(node.nano_lon());
+            }
+        }
+    }
+    println!("per-node iterator: {:?} (checksum {:?})", start.elapsed(), sum);
+
+    let start = Instant::now();
+    let mut sum = (0_i64, 0_i64);
+    for block in &blocks {
+        let arrays = block.dense_arrays();
+        for lat in arrays.nano_lats() {
+            sum.0 = sum.0.wrapping_add(*lat);
+        }
+        for lon in arrays.nano_lons() {
+            sum.1 = sum.1.wrapping_add(*lon);
+        }
+    }
+    println!("dense arrays:      {:?} (checksum {:?})", start.elapsed(), sum);
+
+    Ok(())
+}
@@ -0,0 +1,17 @@
+// Count the elements of a small PBF file that is embedded into the executable.
+
+extern crate osmpbf;
+
+use osmpbf::*;
+
+static DATA: &[u8] = include_bytes!("../tests/test.osm.pbf");
+
+fn main() {
+    let reader = ElementReader::from_bytes(DATA);
+    let mut elements = 0_u64;
+
+    match reader.for_each(|_element| elements += 1) {
+        Ok(()) => println!("Elements: {}", elements),
+        Err(e) => println!("{}", e),
+    }
+}
@@ -0,0 +1,52 @@
+// Count the occurrences of all tag keys in a PBF file given as the first command line argument.
+// Compares the time of thread-local accumulators (`par_for_each_init`) with a single accumulator
+// that is shared between threads behind a mutex.
+
+extern crate osmpbf;
+
+use osmpbf::{Element, ElementReader};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::Instant;
+
+fn count_keys(counts: &mut HashMap<String, u64>, element: Element) {
+    let mut count = |key: &str| *counts.entry(key.to_string()).or_insert(0) += 1;
+    match element {
+        Element::Node(node) => node.tags().for_each(|(key, _)| count(key)),
+        Element::DenseNode(node) => node.tags().for_each(|(key, _)| count(key)),
+        Element::Way(way) => way.tags().for_each(|(key, _)| count(key)),
+        Element::Relation(rel) => rel.tags().for_each(|(key, _)| count(key)),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let arg = std::env::args_os()
+        .nth(1)
+        .ok_or("need a *.osm.pbf file as argument")?;
+
+    let start = Instant::now();
+    let counts = ElementReader::from_path(&arg)?.par_for_each_init(
+        HashMap::new,
+        count_keys,
+        |mut a, b| {
+            for (key, count) in b {
+                *a.entry(key).or_insert(0) += count;
+            }
+            a
+        },
+    )?;
+    println!("thread-local: {:?} ({} keys)", start.elapsed(), counts.len());
+
+    let start = Instant::now();
+    let shared = Mutex::new(HashMap::new());
+    ElementReader::from_path(&arg)?.par_map_reduce(
+        |element| count_keys(&mut shared.lock().unwrap(), element),
+        || (),
+        |_, _| (),
+    )?;
+    let counts = shared.into_inner().unwrap();
+    println!("mutex:        {:?} ({} keys)", start.elapsed(), counts.len());
+
+    Ok(())
+}
@@ -0,0 +1,172 @@
+//! Read blobs asynchronously with tokio
+//!
+//! This module is only available with the `tokio` feature.
+
+use blob::{Blob, ByteOffset};
+use block::PrimitiveBlock;
+use error::{Error, Result};
+use framing::BlobFramer;
+use futures_core::Stream;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+use tokio::task::{spawn_blocking, JoinHandle};
+
+/// The maximum number of bytes that are read from the underlying reader at once.
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug)]
+enum State {
+    /// The position of the underlying reader is not known yet.
+    Start,
+    /// Waiting for the position of the underlying reader.
+    Position,
+    /// Reading blobs.
+    Read,
+    /// The end of the stream was reached or an error occurred.
+    Done,
+}
+
+/// An asynchronous reader for PBF files that allows iterating over `Blob`s as a `Stream`.
+///
+/// This is the counterpart of `BlobReader` for tokio's `AsyncRead`. Like `BlobReader`, it never
+/// decompresses the content of a blob. Decoding is CPU-bound, so it should be moved off the
+/// async runtime, e.g. with `spawn_to_primitiveblock`.
+///
+/// The bytes are split into blobs by a `BlobFramer`, so the same limits as for `BlobReader` apply
+/// and the buffer of a blob only grows as its bytes arrive. The stream stops after the first
+/// error.
+///
+/// # Example
+/// ```
+/// extern crate futures_core;
+/// extern crate osmpbf;
+/// extern crate tokio_runtime as tokio;
+///
+/// use futures_core::Stream;
+/// use osmpbf::*;
+/// use std::future::Future;
+/// use std::pin::Pin;
+/// use std::task::{Context, Poll};
+///
+/// // A future that counts the blobs of a stream
+/// struct CountBlobs<S>(S, u64);
+///
+/// impl<S: Stream<Item = Result<Blob>> + Unpin> Future for CountBlobs<S> {
+///     type Output = Result<u64>;
+///
+///     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<u64>> {
+///         loop {
+///             match Pin::new(&mut self.0).poll_next(cx) {
+///                 Poll::Ready(Some(Ok(_))) => self.1 += 1,
+///                 Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(err)),
+///                 Poll::Ready(None) => return Poll::Ready(Ok(self.1)),
+///                 Poll::Pending => return Poll::Pending,
+///             }
+///         }
+///     }
+/// }
+///
+/// # fn foo() -> Result<()> {
+/// let rt = tokio::runtime::Builder::new_current_thread().build()?;
+/// let file = rt.block_on(tokio::fs::File::open("tests/test.osm.pbf"))?;
+/// let reader = AsyncBlobReader::new(file);
+///
+/// let blob_count = rt.block_on(CountBlobs(reader, 0))?;
+///
+/// assert_eq!(blob_count, 2);
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct AsyncBlobReader<R> {
+    reader: R,
+    state: State,
+    framer: BlobFramer,
+    buf: Vec<u8>,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncBlobReader<R> {
+    /// Creates a new `AsyncBlobReader` that starts reading at the current position of `reader`.
+    /// The position is used to determine the offsets of the blobs (see `Blob::offset`).
+    pub fn new(reader: R) -> AsyncBlobReader<R> {
+        AsyncBlobReader {
+            reader,
+            state: State::Start,
+            framer: BlobFramer::new(),
+            buf: vec![],
+        }
+    }
+
+    fn fail(&mut self, err: Error) -> Poll<Option<Result<Blob>>> {
+        self.state = State::Done;
+        self.framer.fail();
+        self.buf = vec![];
+        Poll::Ready(Some(Err(err)))
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> Stream for AsyncBlobReader<R> {
+    type Item = Result<Blob>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match this.state {
+                State::Start => {
+                    if let Err(e) = Pin::new(&mut this.reader).start_seek(SeekFrom::Current(0)) {
+                        return this.fail(e.into());
+                    }
+                    this.state = State::Position;
+                }
+                State::Position => match Pin::new(&mut this.reader).poll_complete(cx) {
+                    Poll::Ready(Ok(pos)) => {
+                        this.framer.reset(ByteOffset(pos));
+                        this.buf = vec![0; READ_BUFFER_SIZE];
+                        this.state = State::Read;
+                    }
+                    Poll::Ready(Err(e)) => return this.fail(e.into()),
+                    Poll::Pending => return Poll::Pending,
+                },
+                State::Read => {
+                    // Never read more than the framer consumes, so no bytes are left over.
+                    let len = this.framer.bytes_needed().min(this.buf.len());
+                    let mut read_buf = ReadBuf::new(&mut this.buf[..len]);
+                    match Pin::new(&mut this.reader).poll_read(cx, &mut read_buf) {
+                        Poll::Ready(Ok(())) => {}
+                        Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::Interrupted => {
+                            continue
+                        }
+                        Poll::Ready(Err(e)) => return this.fail(e.into()),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                    let filled = read_buf.filled().len();
+
+                    if filled == 0 {
+                        this.state = State::Done;
+                        this.buf = vec![];
+                        return match this.framer.finish() {
+                            Ok(()) => Poll::Ready(None),
+                            Err(err) => Poll::Ready(Some(Err(err))),
+                        };
+                    }
+                    match this.framer.feed(&this.buf[..filled]) {
+                        Ok((_, Some(blob))) => return Poll::Ready(Some(Ok(blob))),
+                        Ok((_, None)) => {}
+                        Err(err) => return this.fail(err),
+                    }
+                }
+                State::Done => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// Decodes the given blob to a `PrimitiveBlock` on tokio's blocking thread pool, so the
+/// potentially expensive decompression does not block the async runtime. This has to be called
+/// from within a tokio runtime.
+pub fn spawn_to_primitiveblock(blob: Blob) -> JoinHandle<Result<PrimitiveBlock>> {
+    spawn_blocking(move || blob.to_primitiveblock())
+}
@@ -4,12 +4,18 @@ extern crate byteorder;
 extern crate protobuf;
 
 use block::{HeaderBlock, PrimitiveBlock};
-use byteorder::ReadBytesExt;
-use error::{new_blob_error, new_protobuf_error, BlobError, Result};
+use byteorder::ByteOrder;
+use error::{
+    new_blob_error, new_protobuf_error, new_unexpected_eof_error, BlobError, Error, Result,
+};
+use framing::BlobFramer;
 use proto::fileformat;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::num::ParseIntError;
 use std::path::Path;
+use std::str::FromStr;
 use util::{parse_message_from_bytes, parse_message_from_reader};
 
 #[cfg(feature = "system-libz")]
@@ -18,6 +24,11 @@ use flate2::read::ZlibDecoder;
 #[cfg(not(feature = "system-libz"))]
 use inflate::DeflateDecoder;
 
+#[cfg(feature = "gzip")]
+use flate2::bufread::MultiGzDecoder;
+#[cfg(feature = "gzip")]
+use std::io::{self, Chain, Cursor};
+
 /// Maximum allowed `BlobHeader` size in bytes.
 pub static MAX_BLOB_HEADER_SIZE: u64 = 64 * 1024;
 
@@ -49,14 +60,93 @@ pub enum BlobDecode<'a> {
     Unknown(&'a str),
 }
 
+/// The compression method of a blob.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Compression {
+    /// Blob content is stored uncompressed.
+    None,
+    /// Blob content is compressed with zlib.
+    Zlib,
+    /// Blob content is compressed with LZMA. Decoding requires the `lzma` feature.
+    Lzma,
+    /// Blob content is compressed with Zstandard. Decoding requires the `zstd` feature.
+    Zstd,
+    /// The blob does not contain data in a known format.
+    Unknown,
+}
+
 /// The offset of a blob in bytes from stream start.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+///
+/// It can be stored as a checkpoint to resume reading a file later on (see
+/// `BlobReader::next_offset`). For a textual representation, it implements `Display` and
+/// `FromStr`, which format and parse the plain number of bytes. The raw number is available as
+/// the public field or with `u64::from`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct ByteOffset(pub u64);
 
+impl ByteOffset {
+    /// Returns the offset that is `n` bytes further, or `None` if it overflows.
+    pub fn advance(self, n: u64) -> Option<ByteOffset> {
+        self.0.checked_add(n).map(ByteOffset)
+    }
+}
+
+impl From<u64> for ByteOffset {
+    fn from(offset: u64) -> ByteOffset {
+        ByteOffset(offset)
+    }
+}
+
+impl From<ByteOffset> for u64 {
+    fn from(offset: ByteOffset) -> u64 {
+        offset.0
+    }
+}
+
+impl fmt::Display for ByteOffset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for ByteOffset {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> ::std::result::Result<ByteOffset, ParseIntError> {
+        s.parse().map(ByteOffset)
+    }
+}
+
+/// A reader that is also seekable.
+///
+/// `Box<dyn Read>` can already be used with all readers of this crate that only require `Read`,
+/// but Rust does not allow a trait object of the form `Box<dyn Read + Seek>`. Use
+/// `Box<dyn ReadSeek>` instead to choose a seekable source at runtime, e.g. for an
+/// `IndexedReader`. It is implemented for all types that implement both `Read` and `Seek`.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let source: Box<dyn ReadSeek> = Box::new(std::fs::File::open("tests/test.osm.pbf")?);
+/// let mut reader = IndexedReader::new(source)?;
+///
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
 /// A blob.
 ///
 /// A PBF file consists of a sequence of blobs. This type supports decoding the content of a blob
 /// to different types of blocks that are usually more interesting to the user.
+///
+/// The content of a blob is kept in its compressed form and is only decompressed when one of the
+/// decoding methods is called.
 #[derive(Clone, Debug)]
 pub struct Blob {
     header: fileformat::BlobHeader,
@@ -65,7 +155,7 @@ pub struct Blob {
 }
 
 impl Blob {
-    fn new(
+    pub(crate) fn new(
         header: fileformat::BlobHeader,
         blob: fileformat::Blob,
         offset: Option<ByteOffset>,
@@ -108,6 +198,76 @@ impl Blob {
         self.offset
     }
 
+    /// Returns the compression method of the blob content without decompressing it.
+    pub fn compression(&self) -> Compression {
+        if self.blob.has_raw() {
+            Compression::None
+        } else if self.blob.has_zlib_data() {
+            Compression::Zlib
+        } else if self.blob.has_lzma_data() {
+            Compression::Lzma
+        } else if self.blob.has_zstd_data() {
+            Compression::Zstd
+        } else {
+            Compression::Unknown
+        }
+    }
+
+    /// Returns the uncompressed size of the blob content in bytes as declared by the `raw_size`
+    /// field, or `None` if the field is absent. This is only a hint from the writer that is read
+    /// without decompressing, e.g. to bound the memory of blobs that are decoded in parallel.
+    pub fn raw_size(&self) -> Option<i32> {
+        if self.blob.has_raw_size() {
+            Some(self.blob.get_raw_size())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the size of the blob content in bytes as stored in the file.
+    pub(crate) fn data_size(&self) -> u64 {
+        u64::from(self.header.get_datasize() as u32)
+    }
+
+    /// Returns the uncompressed size of the blob content if it is known without decompressing.
+    pub(crate) fn uncompressed_size_hint(&self) -> Option<u64> {
+        if self.blob.has_raw() {
+            Some(self.blob.get_raw().len() as u64)
+        } else if self.blob.has_raw_size() {
+            Some(u64::from(self.blob.get_raw_size() as u32))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the decompressed content of the blob without parsing it. This works for blobs of
+    /// any type, so it can be used to handle custom blob types (see `BlobType::Unknown`). Use
+    /// `to_headerblock` and `to_primitiveblock` for the known types instead, as they avoid
+    /// copying the content.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     let blob = blob?;
+    ///     if let BlobType::Unknown(name) = blob.get_type() {
+    ///         let payload = blob.decompress()?;
+    ///         println!("custom blob {} with {} bytes", name, payload.len());
+    ///     }
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn decompress(&self) -> Result<Vec<u8>> {
+        decompress_blob(&self.blob)
+    }
+
     /// Tries to decode the blob to a `HeaderBlock`. This operation might involve an expensive
     /// decompression step.
     pub fn to_headerblock(&self) -> Result<HeaderBlock> {
@@ -115,9 +275,11 @@ impl Blob {
     }
 
     /// Tries to decode the blob to a `PrimitiveBlock`. This operation might involve an expensive
-    /// decompression step.
+    /// decompression step. The returned block owns its data and its groups can be iterated any
+    /// number of times, so it can be kept around (e.g. keyed by `offset`) to avoid decoding the
+    /// same blob again.
     pub fn to_primitiveblock(&self) -> Result<PrimitiveBlock> {
-        decode_blob(&self.blob).map(PrimitiveBlock::new)
+        decode_blob(&self.blob).and_then(PrimitiveBlock::new)
     }
 }
 
@@ -130,7 +292,7 @@ pub struct BlobHeader {
 }
 
 impl BlobHeader {
-    fn new(header: fileformat::BlobHeader) -> Self {
+    pub(crate) fn new(header: fileformat::BlobHeader) -> Self {
         BlobHeader { header }
     }
 
@@ -151,12 +313,18 @@ impl BlobHeader {
 
 
 /// A reader for PBF files that allows iterating over `Blob`s.
+///
+/// Iterating never decompresses the content of a blob. This is deferred until the blob is decoded
+/// (see `Blob::decode`), so scans that only look at blob types and offsets stay cheap.
+///
+/// The blobs are split off the stream by a `BlobFramer`, which can also be used on its own to
+/// read from transports that do not implement `Read`.
 #[derive(Clone, Debug)]
 pub struct BlobReader<R: Read> {
     reader: R,
-    /// Current reader offset in bytes from the start of the stream.
-    offset: Option<ByteOffset>,
-    last_blob_ok: bool,
+    /// Splits the bytes of `reader` into blobs and tracks the current offset. A header that was
+    /// read ahead by `skip_to_data` is kept in it.
+    framer: BlobFramer,
 }
 
 impl<R: Read> BlobReader<R> {
@@ -177,56 +345,235 @@ impl<R: Read> BlobReader<R> {
     /// # foo().unwrap();
     /// ```
     pub fn new(reader: R) -> BlobReader<R> {
-        BlobReader {
-            reader,
-            offset: None,
-            last_blob_ok: true,
-        }
+        let mut framer = BlobFramer::new();
+        framer.set_offset(None);
+        BlobReader { reader, framer }
     }
 
-    fn read_blob_header(&mut self) -> Option<Result<fileformat::BlobHeader>> {
-        let header_size: u64 = match self.reader.read_u32::<byteorder::BigEndian>() {
-            Ok(n) => {
-                self.offset = self.offset.map(|x| ByteOffset(x.0 + 4));
-                u64::from(n)
+    /// Returns the byte offset of the blob that the next call to `next` returns, without reading
+    /// it. Store it as a checkpoint to resume reading later with `seek`.
+    ///
+    /// Returns `None` if the offset is unknown, because the reader was created with `new` from a
+    /// stream whose position is unknown, the end of the stream was reached or an error
+    /// occurred.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    /// let _header = reader.next().unwrap()?;
+    /// let checkpoint = reader.next_offset().unwrap();
+    ///
+    /// // ... later, e.g. in another process
+    /// let mut reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    /// reader.seek(checkpoint)?;
+    /// let blob = reader.next().unwrap()?;
+    ///
+    /// assert_eq!(blob.offset(), Some(checkpoint));
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn next_offset(&self) -> Option<ByteOffset> {
+        self.framer.next_offset()
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Returns the underlying reader. Reading from it invalidates the offset of this reader, so
+    /// it has to be restored with a seek afterwards.
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Consumes the `BlobReader` and returns the underlying reader.
+    ///
+    /// `BlobReader` does not buffer any data itself: after a blob was read successfully, the
+    /// reader is positioned at the start of the next blob (see `next_offset`). After
+    /// `skip_to_data`, the header of that blob has already been read. After an error, the
+    /// position is unspecified. Bytes that a buffering reader like `BufReader` has read
+    /// ahead stay in its buffer and are returned by the next read.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    /// use std::io::{Seek, SeekFrom};
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let f = std::fs::File::open("tests/test.osm.pbf")?;
+    /// let mut reader = BlobReader::new_seekable(f)?;
+    /// let _header = reader.next().unwrap()?;
+    /// let next = reader.next_offset().unwrap();
+    ///
+    /// let mut f = reader.into_inner();
+    /// assert_eq!(f.seek(SeekFrom::Current(0))?, next.0);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Skips all blobs before the first `OSMData` blob, usually the `OSMHeader` blob, so that
+    /// the next call to `next` returns the first blob with elements. This is useful for tools
+    /// that read the `HeaderBlock` separately (see `HeaderBlock::read_from_path`).
+    ///
+    /// Only the headers of the blobs are parsed, their content is read and discarded. The
+    /// header of the first `OSMData` blob is kept in the `BlobReader`, so this also works for
+    /// streams that cannot seek. Calling it again before `next` has no effect.
+    ///
+    /// Returns true if an `OSMData` blob follows and false if the stream ends before one. The
+    /// offset of that blob is returned by `next_offset`, which is `None` if offsets are unknown.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    /// assert!(reader.skip_to_data()?);
+    /// let offset = reader.next_offset();
+    ///
+    /// let blob = reader.next().unwrap()?;
+    /// assert_eq!(blob.get_type(), BlobType::OsmData);
+    /// assert_eq!(blob.offset(), offset);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn skip_to_data(&mut self) -> Result<bool> {
+        loop {
+            let is_data = self
+                .framer
+                .header()
+                .map(|header| header.blob_type() == BlobType::OsmData);
+            match is_data {
+                Some(true) => return Ok(true),
+                Some(false) => self.framer.skip_blob(),
+                None => {}
             }
-            Err(e) => {
-                self.offset = None;
-                match e.kind() {
-                    ::std::io::ErrorKind::UnexpectedEof => {
-                        //TODO This also accepts corrupted files in the case of 1-3 available bytes
-                        return None;
-                    }
-                    _ => {
-                        self.last_blob_ok = false;
-                        return Some(Err(new_blob_error(BlobError::InvalidHeaderSize)));
-                    }
-                }
+            match self.framer.read_header(&mut self.reader) {
+                Some(Ok(())) => {}
+                Some(Err(err)) => return Err(err),
+                None => return Ok(false),
             }
-        };
+        }
+    }
+}
+
+/// The magic bytes at the start of every gzip stream.
+#[cfg(feature = "gzip")]
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
-        if header_size >= MAX_BLOB_HEADER_SIZE {
-            self.last_blob_ok = false;
-            return Some(Err(new_blob_error(BlobError::HeaderTooBig {
-                size: header_size,
-            })));
+/// A reader that decompresses gzip-wrapped PBF files (`*.osm.pbf.gz`) on the fly and passes
+/// through all other input unchanged.
+///
+/// Whole-file gzip is unrelated to the compression of individual blobs (see
+/// `Blob::compression`), it is just another layer on top of the file. Use it with
+/// `BlobReader::new_autodetect` or any other reader constructor, e.g. `ElementReader::new`.
+///
+/// This type is only available with the `gzip` feature.
+#[cfg(feature = "gzip")]
+#[derive(Debug)]
+pub enum AutodetectReader<R: Read> {
+    /// The input is not gzip-compressed and is read as is. The bytes that were read to detect
+    /// the format are put back in front of the remaining input.
+    Plain(BufReader<Chain<Cursor<Vec<u8>>, R>>),
+    /// The input starts with the gzip magic bytes and is decompressed.
+    Gzip(MultiGzDecoder<BufReader<Chain<Cursor<Vec<u8>>, R>>>),
+}
+
+#[cfg(feature = "gzip")]
+impl<R: Read> AutodetectReader<R> {
+    /// Reads the first two bytes of `reader` and wraps it in a gzip decoder if they are the gzip
+    /// magic bytes. The bytes are put back in front of the stream, so plain PBF files are read
+    /// from the start as usual.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let f = std::fs::File::open("tests/test.osm.pbf.gz")?;
+    /// let reader = ElementReader::new(AutodetectReader::new(f)?);
+    ///
+    /// let mut elements = 0;
+    /// reader.for_each(|_| elements += 1)?;
+    ///
+    /// # assert_eq!(elements, 5);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn new(mut reader: R) -> Result<AutodetectReader<R>> {
+        // A single read may return fewer bytes, so read until both are there or the input ends.
+        let mut prefix = Vec::with_capacity(GZIP_MAGIC.len());
+        reader
+            .by_ref()
+            .take(GZIP_MAGIC.len() as u64)
+            .read_to_end(&mut prefix)?;
+        let is_gzip = prefix[..] == GZIP_MAGIC[..];
+        let reader = BufReader::new(Cursor::new(prefix).chain(reader));
+        if is_gzip {
+            Ok(AutodetectReader::Gzip(MultiGzDecoder::new(reader)))
+        } else {
+            Ok(AutodetectReader::Plain(reader))
         }
+    }
 
-        let header: fileformat::BlobHeader =
-            match parse_message_from_reader(&mut self.reader.by_ref().take(header_size)) {
-                Ok(header) => header,
-                Err(e) => {
-                    self.offset = None;
-                    self.last_blob_ok = false;
-                    return Some(Err(new_protobuf_error(e, "blob header")));
-                }
-            };
+    /// Returns true if the input is gzip-compressed.
+    pub fn is_gzip(&self) -> bool {
+        match *self {
+            AutodetectReader::Plain(_) => false,
+            AutodetectReader::Gzip(_) => true,
+        }
+    }
+}
 
-        self.offset = self
-            .offset
-            .map(|x| ByteOffset(x.0 + header_size));
+#[cfg(feature = "gzip")]
+impl<R: Read> Read for AutodetectReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            AutodetectReader::Plain(ref mut reader) => reader.read(buf),
+            AutodetectReader::Gzip(ref mut reader) => reader.read(buf),
+        }
+    }
+}
 
-        Some(Ok(header))
+#[cfg(feature = "gzip")]
+impl<R: Read> BlobReader<AutodetectReader<R>> {
+    /// Creates a new `BlobReader` that transparently decompresses gzip-wrapped PBF files (see
+    /// `AutodetectReader`). Other input is read just like with `BlobReader::new`.
+    ///
+    /// Offsets are unknown (see `BlobReader::offset`), because positions in the decompressed
+    /// stream do not correspond to positions in the file.
+    ///
+    /// This function is only available with the `gzip` feature.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let f = std::fs::File::open("tests/test.osm.pbf.gz")?;
+    /// let reader = BlobReader::new_autodetect(f)?;
+    ///
+    /// # assert_eq!(reader.count(), 2);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn new_autodetect(reader: R) -> Result<BlobReader<AutodetectReader<R>>> {
+        Ok(BlobReader::new(AutodetectReader::new(reader)?))
     }
 }
 
@@ -252,8 +599,7 @@ impl BlobReader<BufReader<File>> {
 
         Ok(BlobReader {
             reader,
-            offset: Some(ByteOffset(0)),
-            last_blob_ok: true,
+            framer: BlobFramer::new(),
         })
     }
 }
@@ -262,35 +608,7 @@ impl<R: Read> Iterator for BlobReader<R> {
     type Item = Result<Blob>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // Stop iteration if there was an error.
-        if !self.last_blob_ok {
-            return None;
-        }
-
-        let prev_offset = self.offset;
-
-        let header = match self.read_blob_header() {
-            Some(Ok(header)) => header,
-            Some(Err(err)) => return Some(Err(err)),
-            None => return None,
-        };
-
-        let blob: fileformat::Blob = match parse_message_from_reader(
-            &mut self.reader.by_ref().take(header.get_datasize() as u64),
-        ) {
-            Ok(blob) => blob,
-            Err(e) => {
-                self.offset = None;
-                self.last_blob_ok = false;
-                return Some(Err(new_protobuf_error(e, "blob content")));
-            }
-        };
-
-        self.offset = self
-            .offset
-            .map(|x| ByteOffset(x.0 + header.get_datasize() as u64));
-
-        Some(Ok(Blob::new(header, blob, prev_offset)))
+        self.framer.read_blob(&mut self.reader)
     }
 }
 
@@ -319,8 +637,7 @@ impl<R: Read + Seek> BlobReader<R> {
 
         Ok(BlobReader {
             reader,
-            offset: Some(ByteOffset(pos)),
-            last_blob_ok: true,
+            framer: BlobFramer::starting_at(ByteOffset(pos)),
         })
     }
 
@@ -346,25 +663,51 @@ impl<R: Read + Seek> BlobReader<R> {
     pub fn seek(&mut self, pos: ByteOffset) -> Result<()> {
         match self.reader.seek(SeekFrom::Start(pos.0)) {
             Ok(offset) => {
-                self.offset = Some(ByteOffset(offset));
+                self.framer.set_offset(Some(ByteOffset(offset)));
                 Ok(())
             }
             Err(e) => {
-                self.offset = None;
+                self.framer.set_offset(None);
                 Err(e.into())
             }
         }
     }
 
+    /// Seeks to `start` and returns an iterator over the blobs that start before `end`. `start`
+    /// is inclusive and has to be the offset of a blob, `end` is exclusive and can be any
+    /// offset. This allows splitting a file into contiguous shards, e.g. at the offsets of
+    /// `next_header_skip_blob`, so that every blob belongs to exactly one shard.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = BlobReader::seekable_from_path("tests/test.osm.pbf")?;
+    /// let len = std::fs::metadata("tests/test.osm.pbf")?.len();
+    ///
+    /// // Process the blobs that start in the first half of the file
+    /// for blob in reader.seek_range(ByteOffset(0), ByteOffset(len / 2))? {
+    ///     println!("blob at {}", blob?.offset().unwrap());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn seek_range(&mut self, start: ByteOffset, end: ByteOffset) -> Result<BlobRangeIter<R>> {
+        self.seek(start)?;
+        Ok(BlobRangeIter { reader: self, end })
+    }
+
     /// Seek to an offset in bytes. (See `std::io::Seek`)
     pub fn seek_raw(&mut self, pos: SeekFrom) -> Result<u64> {
         match self.reader.seek(pos) {
             Ok(offset) => {
-                self.offset = Some(ByteOffset(offset));
+                self.framer.set_offset(Some(ByteOffset(offset)));
                 Ok(offset)
             }
             Err(e) => {
-                self.offset = None;
+                self.framer.set_offset(None);
                 Err(e.into())
             }
         }
@@ -375,27 +718,169 @@ impl<R: Read + Seek> BlobReader<R> {
     /// On success, returns the `BlobHeader` and the byte offset of the header which can also be
     /// used as an offset for reading the entire `Blob` (including header).
     pub fn next_header_skip_blob(&mut self) -> Option<Result<(BlobHeader, Option<ByteOffset>)>> {
-        // Stop iteration if there was an error.
-        if !self.last_blob_ok {
-            return None;
+        // The header might have been read ahead by `skip_to_data`.
+        if self.framer.header().is_none() {
+            if let Err(err) = self.framer.read_header(&mut self.reader)? {
+                return Some(Err(err));
+            }
         }
+        let header = self.framer.header()?;
+        let prev_offset = self.framer.next_offset();
 
-        let prev_offset = self.offset;
+        // skip blob (which also adjusts the offset)
+        if let Err(err) = self.seek_raw(SeekFrom::Current(i64::from(header.get_blob_size()))) {
+            self.framer.fail();
+            return Some(Err(err));
+        }
 
-        // read header
-        let header = match self.read_blob_header() {
-            Some(Ok(header)) => header,
-            Some(Err(err)) => return Some(Err(err)),
-            None => return None,
-        };
+        Some(Ok((header, prev_offset)))
+    }
 
-        // skip blob (which also adjusts self.offset)
-        if let Err(err) = self.seek_raw(SeekFrom::Current(header.get_datasize() as i64)) {
-            self.last_blob_ok = false;
-            return Some(Err(err));
+    /// Turns this reader into an iterator that skips corrupt blobs instead of stopping at the
+    /// first error. Each error is passed to the closure `on_error`, then the reader searches for
+    /// the start of the next valid blob and continues from there.
+    ///
+    /// A position is accepted as the start of a blob if it holds a size prefix below
+    /// `MAX_BLOB_HEADER_SIZE`, followed by a `BlobHeader` that can be parsed, that has an ASCII
+    /// type string and a valid data size. Errors in the compressed content of a blob only show up
+    /// when it is decoded (see `Blob::decode`) and do not affect the iteration.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut errors = vec![];
+    /// let reader = BlobReader::seekable_from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader.lenient(|err| errors.push(err)) {
+    ///     if let BlobDecode::OsmData(block) = blob.decode()? {
+    ///         println!("{} elements", block.elements().count());
+    ///     }
+    /// }
+    ///
+    /// println!("skipped {} corrupt blobs", errors.len());
+    /// # assert!(errors.is_empty());
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn lenient<E>(self, on_error: E) -> LenientBlobReader<R, E>
+    where
+        E: FnMut(Error),
+    {
+        LenientBlobReader {
+            reader: self,
+            on_error,
         }
+    }
+
+    /// Searches for the start of the next valid blob at or after `from` and moves the reader
+    /// there. Returns false if the end of the stream is reached without finding one.
+    fn resync(&mut self, from: ByteOffset) -> Result<bool> {
+        // The header of every candidate position in the first half of the window is completely
+        // contained in the window.
+        let step = MAX_BLOB_HEADER_SIZE as usize;
+        let window_len = 2 * step + 4;
+        let mut window = Vec::with_capacity(window_len);
+        let mut pos = from.0;
+
+        loop {
+            self.seek(ByteOffset(pos))?;
+            window.clear();
+            self.reader
+                .by_ref()
+                .take(window_len as u64)
+                .read_to_end(&mut window)?;
+
+            let at_end = window.len() < window_len;
+            let candidates = if at_end { window.len() } else { step };
+            if let Some(i) = (0..candidates).find(|&i| is_blob_start(&window[i..])) {
+                let start = ByteOffset(pos + i as u64);
+                self.seek(start)?;
+                self.framer.reset(start);
+                return Ok(true);
+            }
+            if at_end {
+                self.framer.set_offset(None);
+                return Ok(false);
+            }
+            pos += step as u64;
+        }
+    }
+}
+
+/// An iterator over the blobs of a `BlobReader` that start before a given offset (see
+/// `BlobReader::seek_range`).
+#[derive(Debug)]
+pub struct BlobRangeIter<'a, R: Read + 'a> {
+    reader: &'a mut BlobReader<R>,
+    end: ByteOffset,
+}
+
+impl<'a, R: Read + 'a> Iterator for BlobRangeIter<'a, R> {
+    type Item = Result<Blob>;
 
-        Some(Ok((BlobHeader::new(header), prev_offset)))
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.reader.next_offset() {
+            Some(offset) if offset < self.end => self.reader.next(),
+            _ => None,
+        }
+    }
+}
+
+/// Returns true if the given bytes start with a size prefix and a plausible `BlobHeader`.
+fn is_blob_start(bytes: &[u8]) -> bool {
+    if bytes.len() < 4 {
+        return false;
+    }
+    let size = match decode_blob_header_size(&bytes[..4], None) {
+        Ok(size) => size as usize,
+        Err(_) => return false,
+    };
+    if size == 0 || bytes.len() < 4 + size {
+        return false;
+    }
+    match parse_message_from_bytes::<fileformat::BlobHeader>(&bytes[4..4 + size]) {
+        Ok(header) => {
+            let blob_type = header.get_field_type();
+            !blob_type.is_empty() && blob_type.is_ascii() && blob_data_size(&header).is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
+/// An iterator over the blobs of a `BlobReader` that skips corrupt blobs (see
+/// `BlobReader::lenient`).
+#[derive(Debug)]
+pub struct LenientBlobReader<R: Read + Seek, E> {
+    reader: BlobReader<R>,
+    on_error: E,
+}
+
+impl<R: Read + Seek, E: FnMut(Error)> Iterator for LenientBlobReader<R, E> {
+    type Item = Blob;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let start = self.reader.next_offset();
+            let err = match self.reader.next()? {
+                Ok(blob) => return Some(blob),
+                Err(err) => err,
+            };
+            (self.on_error)(err);
+
+            // The offset is always known for seekable readers, unless seeking failed before.
+            let start = start?;
+            match self.reader.resync(ByteOffset(start.0 + 1)) {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(err) => {
+                    (self.on_error)(err);
+                    return None;
+                }
+            }
+        }
     }
 }
 
@@ -423,7 +908,176 @@ impl BlobReader<BufReader<File>> {
     }
 }
 
-#[cfg(feature = "system-libz")]
+/// Builds a `BlobReader` with custom options. `BlobReader::new` and friends use the defaults.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let f = std::fs::File::open("tests/test.osm.pbf")?;
+/// let reader = BlobReaderBuilder::new(f)
+///     .buffer_size(1 << 20)
+///     .check_blob_types(true)
+///     .build();
+///
+/// for blob in reader {
+///     println!("blob of type {:?}", blob?.get_type());
+/// }
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct BlobReaderBuilder<R> {
+    reader: R,
+    buffer_size: Option<usize>,
+    strict_header_size: bool,
+    check_blob_types: bool,
+    max_blob_size: u64,
+}
+
+impl<R: Read> BlobReaderBuilder<R> {
+    /// Creates a new builder that wraps the given reader.
+    pub fn new(reader: R) -> BlobReaderBuilder<R> {
+        BlobReaderBuilder {
+            reader,
+            buffer_size: None,
+            strict_header_size: true,
+            check_blob_types: false,
+            max_blob_size: MAX_BLOB_MESSAGE_SIZE,
+        }
+    }
+
+    /// Sets the capacity of the `BufReader` that wraps the reader in bytes. Defaults to the
+    /// capacity of `BufReader::new`. Larger buffers reduce the number of reads on slow storage.
+    pub fn buffer_size(mut self, size: usize) -> BlobReaderBuilder<R> {
+        self.buffer_size = Some(size);
+        self
+    }
+
+    /// Sets whether blob headers that are bigger than `MAX_BLOB_HEADER_SIZE` are rejected with
+    /// `BlobError::HeaderTooBig`. Defaults to true. Disabling this accepts any header size that
+    /// fits the size prefix, which is useful for non-conforming writers.
+    pub fn strict_header_size(mut self, strict: bool) -> BlobReaderBuilder<R> {
+        self.strict_header_size = strict;
+        self
+    }
+
+    /// Sets whether blobs whose type is neither "OSMHeader" nor "OSMData" are rejected with
+    /// `BlobError::UnknownBlobType` while iterating. Defaults to false, so unknown blobs are
+    /// returned and can be ignored (see `BlobType::Unknown`).
+    pub fn check_blob_types(mut self, check: bool) -> BlobReaderBuilder<R> {
+        self.check_blob_types = check;
+        self
+    }
+
+    /// Sets the maximum size of a blob in bytes, as declared by its `BlobHeader`. Bigger blobs
+    /// are rejected with `BlobError::MessageTooBig` before any of their content is read or
+    /// allocated, which protects services that read untrusted files. Defaults to
+    /// `MAX_BLOB_MESSAGE_SIZE` (32 MiB), the limit of the PBF specification. Bigger values are
+    /// reduced to it, as bigger blobs could not be decoded anyway.
+    pub fn max_blob_size(mut self, size: u64) -> BlobReaderBuilder<R> {
+        self.max_blob_size = size.min(MAX_BLOB_MESSAGE_SIZE);
+        self
+    }
+
+    /// Creates the `BlobReader`. Like `BlobReader::new`, the offsets of the blobs are unknown.
+    pub fn build(self) -> BlobReader<BufReader<R>> {
+        let strict_header_size = self.strict_header_size;
+        let check_blob_types = self.check_blob_types;
+        let max_blob_size = self.max_blob_size;
+        let mut reader = BlobReader::new(self.buf_reader());
+        reader.framer.set_strict_header_size(strict_header_size);
+        reader.framer.set_check_blob_types(check_blob_types);
+        reader.framer.set_max_blob_size(max_blob_size);
+        reader
+    }
+
+    fn buf_reader(self) -> BufReader<R> {
+        match self.buffer_size {
+            Some(size) => BufReader::with_capacity(size, self.reader),
+            None => BufReader::new(self.reader),
+        }
+    }
+}
+
+impl<R: Read + Seek> BlobReaderBuilder<R> {
+    /// Creates a seekable `BlobReader` that is initialized with a valid offset (see
+    /// `BlobReader::new_seekable`).
+    pub fn build_seekable(self) -> Result<BlobReader<BufReader<R>>> {
+        let strict_header_size = self.strict_header_size;
+        let check_blob_types = self.check_blob_types;
+        let max_blob_size = self.max_blob_size;
+        let mut reader = BlobReader::new_seekable(self.buf_reader())?;
+        reader.framer.set_strict_header_size(strict_header_size);
+        reader.framer.set_check_blob_types(check_blob_types);
+        reader.framer.set_max_blob_size(max_blob_size);
+        Ok(reader)
+    }
+}
+
+/// Decodes the size prefix of a `BlobHeader` from the bytes that could be read before the end of
+/// the stream (at most four). `offset` is the offset of the prefix, if known. This is the framing
+/// logic that is shared by `BlobReader` and the asynchronous reader.
+pub(crate) fn decode_blob_header_size(prefix: &[u8], offset: Option<ByteOffset>) -> Result<u64> {
+    decode_blob_header_size_with_limit(prefix, offset, MAX_BLOB_HEADER_SIZE)
+}
+
+/// Like `decode_blob_header_size`, but fails if the size is at least `max_size` instead of
+/// `MAX_BLOB_HEADER_SIZE`.
+pub(crate) fn decode_blob_header_size_with_limit(
+    prefix: &[u8],
+    offset: Option<ByteOffset>,
+    max_size: u64,
+) -> Result<u64> {
+    if prefix.len() < 4 {
+        let eof_offset = offset.map(|x| ByteOffset(x.0 + prefix.len() as u64));
+        return Err(new_unexpected_eof_error(eof_offset));
+    }
+
+    let size = u64::from(byteorder::BigEndian::read_u32(prefix));
+    if size >= max_size {
+        Err(new_blob_error(BlobError::HeaderTooBig { size }))
+    } else {
+        Ok(size)
+    }
+}
+
+/// Fails if the type of the given header is neither "OSMHeader" nor "OSMData".
+pub(crate) fn check_blob_type(header: &fileformat::BlobHeader) -> Result<()> {
+    match header.get_field_type() {
+        "OSMHeader" | "OSMData" => Ok(()),
+        blob_type => Err(new_blob_error(BlobError::UnknownBlobType {
+            blob_type: blob_type.to_string(),
+        })),
+    }
+}
+
+/// Returns the size of the blob that follows the given header in bytes. Fails if the size is
+/// negative or bigger than `MAX_BLOB_MESSAGE_SIZE`.
+pub(crate) fn blob_data_size(header: &fileformat::BlobHeader) -> Result<u64> {
+    blob_data_size_with_limit(header, MAX_BLOB_MESSAGE_SIZE)
+}
+
+/// Like `blob_data_size`, but fails if the size is bigger than `max_size` instead of
+/// `MAX_BLOB_MESSAGE_SIZE`.
+pub(crate) fn blob_data_size_with_limit(
+    header: &fileformat::BlobHeader,
+    max_size: u64,
+) -> Result<u64> {
+    let size = header.get_datasize();
+    if size < 0 {
+        Err(new_blob_error(BlobError::InvalidBlobSize { size }))
+    } else if size as u64 > max_size {
+        Err(new_blob_error(BlobError::MessageTooBig {
+            size: size as u64,
+        }))
+    } else {
+        Ok(size as u64)
+    }
+}
+
 pub(crate) fn decode_blob<T>(blob: &fileformat::Blob) -> Result<T>
 where
     T: protobuf::Message,
@@ -437,31 +1091,488 @@ where
             Err(new_blob_error(BlobError::MessageTooBig { size }))
         }
     } else if blob.has_zlib_data() {
-        let mut decoder = ZlibDecoder::new(blob.get_zlib_data()).take(MAX_BLOB_MESSAGE_SIZE);
-        parse_message_from_reader(&mut decoder).map_err(|e| new_protobuf_error(e, "blob zlib data"))
+        decode_zlib_blob(blob)
+    } else if blob.has_lzma_data() {
+        let mut decoder = lzma_decoder(blob.get_lzma_data())?.take(MAX_BLOB_MESSAGE_SIZE);
+        parse_message_from_reader(&mut decoder).map_err(|e| new_protobuf_error(e, "blob lzma data"))
+    } else if blob.has_zstd_data() {
+        let mut decoder = zstd_decoder(blob.get_zstd_data())?.take(MAX_BLOB_MESSAGE_SIZE);
+        parse_message_from_reader(&mut decoder).map_err(|e| new_protobuf_error(e, "blob zstd data"))
     } else {
         Err(new_blob_error(BlobError::Empty))
     }
 }
 
-#[cfg(not(feature = "system-libz"))]
-pub(crate) fn decode_blob<T>(blob: &fileformat::Blob) -> Result<T>
-where
-    T: protobuf::Message,
-{
+/// Returns the decompressed content of the given blob without parsing it.
+pub(crate) fn decompress_blob(blob: &fileformat::Blob) -> Result<Vec<u8>> {
     if blob.has_raw() {
         let size = blob.get_raw().len() as u64;
         if size < MAX_BLOB_MESSAGE_SIZE {
-            parse_message_from_bytes(blob.get_raw())
-                .map_err(|e| new_protobuf_error(e, "raw blob data"))
+            Ok(blob.get_raw().to_vec())
         } else {
             Err(new_blob_error(BlobError::MessageTooBig { size }))
         }
     } else if blob.has_zlib_data() {
-        let mut decoder =
-            DeflateDecoder::from_zlib(blob.get_zlib_data()).take(MAX_BLOB_MESSAGE_SIZE);
-        parse_message_from_reader(&mut decoder).map_err(|e| new_protobuf_error(e, "blob zlib data"))
+        zlib_decompress(blob)
+    } else if blob.has_lzma_data() {
+        read_decompressed(lzma_decoder(blob.get_lzma_data())?)
+    } else if blob.has_zstd_data() {
+        read_decompressed(zstd_decoder(blob.get_zstd_data())?)
     } else {
         Err(new_blob_error(BlobError::Empty))
     }
 }
+
+/// Reads all data from the given decoder. Fails if it exceeds `MAX_BLOB_MESSAGE_SIZE`.
+fn read_decompressed<D: Read>(decoder: D) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    decoder
+        .take(MAX_BLOB_MESSAGE_SIZE)
+        .read_to_end(&mut bytes)?;
+    let size = bytes.len() as u64;
+    if size < MAX_BLOB_MESSAGE_SIZE {
+        Ok(bytes)
+    } else {
+        Err(new_blob_error(BlobError::MessageTooBig { size }))
+    }
+}
+
+/// Decompresses and parses the zlib data of the given blob. Without the `libdeflate` feature, the
+/// data is decompressed while it is parsed.
+#[cfg(not(feature = "libdeflate"))]
+fn decode_zlib_blob<T>(blob: &fileformat::Blob) -> Result<T>
+where
+    T: protobuf::Message,
+{
+    let mut decoder = zlib_decoder(blob.get_zlib_data()).take(MAX_BLOB_MESSAGE_SIZE);
+    parse_message_from_reader(&mut decoder).map_err(|e| new_protobuf_error(e, "blob zlib data"))
+}
+
+#[cfg(feature = "libdeflate")]
+fn decode_zlib_blob<T>(blob: &fileformat::Blob) -> Result<T>
+where
+    T: protobuf::Message,
+{
+    let bytes = zlib_decompress(blob)?;
+    parse_message_from_bytes(&bytes).map_err(|e| new_protobuf_error(e, "blob zlib data"))
+}
+
+/// Returns the decompressed zlib data of the given blob.
+#[cfg(not(feature = "libdeflate"))]
+fn zlib_decompress(blob: &fileformat::Blob) -> Result<Vec<u8>> {
+    read_decompressed(zlib_decoder(blob.get_zlib_data()))
+}
+
+/// The maximum compression ratio of deflate, used to limit the output buffer for libdeflate.
+#[cfg(feature = "libdeflate")]
+const MAX_DEFLATE_RATIO: u64 = 1032;
+
+/// Returns the decompressed zlib data of the given blob. libdeflate decompresses the whole
+/// buffer at once and needs to know the size of the output in advance, so blobs without a
+/// `raw_size` fall back to the streaming decoder. So do blobs whose `raw_size` is too small for
+/// the data.
+#[cfg(feature = "libdeflate")]
+fn zlib_decompress(blob: &fileformat::Blob) -> Result<Vec<u8>> {
+    let data = blob.get_zlib_data();
+    if !blob.has_raw_size() || blob.get_raw_size() < 0 {
+        return read_decompressed(zlib_decoder(data));
+    }
+    let size = blob.get_raw_size() as u64;
+    if size >= MAX_BLOB_MESSAGE_SIZE {
+        return Err(new_blob_error(BlobError::MessageTooBig { size }));
+    }
+    // Do not trust the declared size further than the data can expand
+    let capacity = size.min(data.len() as u64 * MAX_DEFLATE_RATIO);
+    let mut bytes = vec![0; capacity as usize];
+    match ::libdeflater::Decompressor::new().zlib_decompress(data, &mut bytes) {
+        Ok(len) => {
+            bytes.truncate(len);
+            Ok(bytes)
+        }
+        Err(::libdeflater::DecompressionError::InsufficientSpace) => {
+            read_decompressed(zlib_decoder(data))
+        }
+        Err(e) => Err(::std::io::Error::new(::std::io::ErrorKind::InvalidData, e).into()),
+    }
+}
+
+#[cfg(feature = "system-libz")]
+fn zlib_decoder<'a>(data: &'a [u8]) -> impl Read + 'a {
+    ZlibDecoder::new(data)
+}
+
+#[cfg(not(feature = "system-libz"))]
+fn zlib_decoder<'a>(data: &'a [u8]) -> impl Read + 'a {
+    DeflateDecoder::from_zlib(data)
+}
+
+#[cfg(feature = "lzma")]
+fn lzma_decoder<'a>(data: &'a [u8]) -> Result<impl Read + 'a> {
+    // The format of the data is not specified, so accept both .xz and legacy .lzma streams.
+    let stream =
+        ::xz2::stream::Stream::new_auto_decoder(u64::MAX, 0).map_err(::std::io::Error::from)?;
+    Ok(::xz2::read::XzDecoder::new_stream(data, stream))
+}
+
+#[cfg(not(feature = "lzma"))]
+fn lzma_decoder(_data: &[u8]) -> Result<::std::io::Empty> {
+    Err(new_blob_error(BlobError::UnsupportedCompression {
+        compression: Compression::Lzma,
+    }))
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_decoder<'a>(data: &'a [u8]) -> Result<impl Read + 'a> {
+    Ok(::zstd::stream::read::Decoder::new(data)?)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_decoder(_data: &[u8]) -> Result<::std::io::Empty> {
+    Err(new_blob_error(BlobError::UnsupportedCompression {
+        compression: Compression::Zstd,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::osmformat;
+    use protobuf::Message;
+
+    fn zstd_blob(data: Vec<u8>, raw_size: usize) -> Blob {
+        let mut header = fileformat::BlobHeader::new();
+        header.set_field_type("OSMData".to_string());
+        let mut blob = fileformat::Blob::new();
+        blob.set_raw_size(raw_size as i32);
+        blob.set_zstd_data(data);
+        Blob::new(header, blob, None)
+    }
+
+    fn lzma_blob(data: Vec<u8>, raw_size: usize) -> Blob {
+        let mut header = fileformat::BlobHeader::new();
+        header.set_field_type("OSMData".to_string());
+        let mut blob = fileformat::Blob::new();
+        blob.set_raw_size(raw_size as i32);
+        blob.set_lzma_data(data);
+        Blob::new(header, blob, None)
+    }
+
+    fn encoded_block() -> Vec<u8> {
+        let mut block = osmformat::PrimitiveBlock::new();
+        block.mut_stringtable().mut_s().push(vec![]);
+        let mut group = osmformat::PrimitiveGroup::new();
+        let mut node = osmformat::Node::new();
+        node.set_id(42);
+        node.set_lat(0);
+        node.set_lon(0);
+        group.mut_nodes().push(node);
+        block.mut_primitivegroup().push(group);
+        block.write_to_bytes().unwrap()
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn decode_zstd_blob() {
+        let raw = encoded_block();
+        let blob = zstd_blob(::zstd::encode_all(&raw[..], 0).unwrap(), raw.len());
+        assert_eq!(blob.compression(), Compression::Zstd);
+
+        let block = blob.to_primitiveblock().unwrap();
+        let ids: Vec<i64> = block
+            .groups()
+            .flat_map(|g| g.nodes())
+            .map(|n| n.id())
+            .collect();
+        assert_eq!(ids, vec![42]);
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn decode_zstd_blob_without_feature() {
+        use error::ErrorKind;
+
+        let raw = encoded_block();
+        let blob = zstd_blob(raw.clone(), raw.len());
+        assert_eq!(blob.compression(), Compression::Zstd);
+
+        match blob.to_primitiveblock().unwrap_err().kind() {
+            ErrorKind::Blob(BlobError::UnsupportedCompression { compression }) => {
+                assert_eq!(*compression, Compression::Zstd);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn raw_size() {
+        let raw = encoded_block();
+        assert_eq!(
+            zstd_blob(raw.clone(), raw.len()).raw_size(),
+            Some(raw.len() as i32)
+        );
+
+        let mut blob = fileformat::Blob::new();
+        blob.set_raw(raw);
+        let blob = Blob::new(fileformat::BlobHeader::new(), blob, None);
+        assert_eq!(blob.raw_size(), None);
+        assert!(blob.to_primitiveblock().is_ok());
+    }
+
+    #[test]
+    fn decompress_zlib_blob() {
+        // Exercises the zlib backend that is selected by the enabled features.
+        let mut blobs = BlobReader::from_path("tests/test.osm.pbf").unwrap();
+        let mut blob = blobs.nth(1).unwrap().unwrap();
+        assert_eq!(blob.compression(), Compression::Zlib);
+        let bytes = blob.decompress().unwrap();
+        assert_eq!(Some(bytes.len() as i32), blob.raw_size());
+
+        // The size is optional and some backends need it in advance.
+        blob.blob.clear_raw_size();
+        assert_eq!(blob.decompress().unwrap(), bytes);
+
+        // A wrong size must not break the decompression.
+        blob.blob.set_raw_size(10);
+        assert_eq!(blob.decompress().unwrap(), bytes);
+        blob.blob.set_raw_size(MAX_BLOB_MESSAGE_SIZE as i32 - 1);
+        assert_eq!(blob.decompress().unwrap(), bytes);
+        let block = blob.to_primitiveblock().unwrap();
+        assert_eq!(block.groups().flat_map(|g| g.ways()).count(), 1);
+    }
+
+    #[test]
+    fn decompress_unknown_blob() {
+        let mut header = fileformat::BlobHeader::new();
+        header.set_field_type("CustomGraph".to_string());
+        let mut blob = fileformat::Blob::new();
+        blob.set_raw(b"payload".to_vec());
+        let blob = Blob::new(header, blob, None);
+
+        assert_eq!(blob.get_type(), BlobType::Unknown("CustomGraph"));
+        assert_eq!(blob.decompress().unwrap(), b"payload".to_vec());
+    }
+
+    #[cfg(feature = "lzma")]
+    #[test]
+    fn decode_lzma_blob() {
+        use std::io::Read;
+
+        let raw = encoded_block();
+        let mut compressed = vec![];
+        ::xz2::read::XzEncoder::new(&raw[..], 6)
+            .read_to_end(&mut compressed)
+            .unwrap();
+        let blob = lzma_blob(compressed, raw.len());
+        assert_eq!(blob.compression(), Compression::Lzma);
+
+        let block = blob.to_primitiveblock().unwrap();
+        let ids: Vec<i64> = block
+            .groups()
+            .flat_map(|g| g.nodes())
+            .map(|n| n.id())
+            .collect();
+        assert_eq!(ids, vec![42]);
+    }
+
+    #[cfg(not(feature = "lzma"))]
+    #[test]
+    fn decode_lzma_blob_without_feature() {
+        use error::ErrorKind;
+
+        let raw = encoded_block();
+        let blob = lzma_blob(raw.clone(), raw.len());
+        assert_eq!(blob.compression(), Compression::Lzma);
+
+        match blob.to_primitiveblock().unwrap_err().kind() {
+            ErrorKind::Blob(BlobError::UnsupportedCompression { compression }) => {
+                assert_eq!(*compression, Compression::Lzma);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn byte_offset() {
+        let offset = ByteOffset::from(1200);
+        assert_eq!(offset.advance(34), Some(ByteOffset(1234)));
+        assert_eq!(ByteOffset(u64::MAX).advance(1), None);
+        assert_eq!(u64::from(offset), 1200);
+        assert_eq!(offset.to_string(), "1200");
+        assert_eq!("1200".parse::<ByteOffset>(), Ok(offset));
+        assert!(ByteOffset(5) < offset);
+    }
+
+    /// Writes a file with three blocks of one node each and returns it together with the
+    /// offset of the middle blob.
+    fn three_block_pbf() -> (Vec<u8>, usize) {
+        use std::io::Cursor;
+        use writer::{BlobWriter, PrimitiveBlockBuilder};
+
+        let mut writer = BlobWriter::new(Vec::new());
+        for id in 1..4 {
+            let mut block = PrimitiveBlockBuilder::new();
+            block.add_node(id, 0.0, 0.0, Vec::<(&str, &str)>::new());
+            writer.write_block(block).unwrap();
+        }
+        let pbf = writer.into_inner().unwrap();
+
+        let offsets: Vec<_> = BlobReader::new_seekable(Cursor::new(&pbf[..]))
+            .unwrap()
+            .map(|blob| blob.unwrap().offset().unwrap().0 as usize)
+            .collect();
+        assert_eq!(offsets.len(), 4);
+        (pbf, offsets[2])
+    }
+
+    fn lenient_node_ids(pbf: &[u8]) -> (Vec<i64>, usize) {
+        use std::io::Cursor;
+
+        let mut errors = 0;
+        let ids = BlobReader::new_seekable(Cursor::new(pbf))
+            .unwrap()
+            .lenient(|_| errors += 1)
+            .filter(|blob| blob.get_type() == BlobType::OsmData)
+            .map(|blob| blob.to_primitiveblock().unwrap())
+            .flat_map(|block| {
+                block
+                    .groups()
+                    .flat_map(|g| g.dense_nodes())
+                    .map(|n| n.id)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        (ids, errors)
+    }
+
+    #[test]
+    fn lenient_skips_corrupt_blobs() {
+        let (pbf, middle) = three_block_pbf();
+        assert_eq!(lenient_node_ids(&pbf), (vec![1, 2, 3], 0));
+
+        // Header size prefix is too big
+        let mut corrupt = pbf.clone();
+        for byte in &mut corrupt[middle..middle + 4] {
+            *byte = 0xff;
+        }
+        assert_eq!(lenient_node_ids(&corrupt), (vec![1, 3], 1));
+
+        // Header cannot be parsed
+        let mut corrupt = pbf.clone();
+        for byte in &mut corrupt[middle + 4..middle + 12] {
+            *byte = 0xff;
+        }
+        assert_eq!(lenient_node_ids(&corrupt), (vec![1, 3], 1));
+
+        // Truncated last blob
+        let (ids, errors) = lenient_node_ids(&pbf[..pbf.len() - 3]);
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(errors, 1);
+    }
+
+    /// Encodes a single blob with the given type and index data in the header.
+    fn framed_blob(blob_type: &str, indexdata: Vec<u8>) -> Vec<u8> {
+        let mut blob = fileformat::Blob::new();
+        blob.set_raw(vec![1, 2, 3]);
+        let blob = blob.write_to_bytes().unwrap();
+
+        let mut header = fileformat::BlobHeader::new();
+        header.set_field_type(blob_type.to_string());
+        header.set_indexdata(indexdata);
+        header.set_datasize(blob.len() as i32);
+        let header = header.write_to_bytes().unwrap();
+
+        let mut pbf = vec![0; 4];
+        byteorder::BigEndian::write_u32(&mut pbf, header.len() as u32);
+        pbf.extend(header);
+        pbf.extend(blob);
+        pbf
+    }
+
+    fn builder_result(pbf: &[u8], strict: bool, check_types: bool) -> Result<Blob> {
+        BlobReaderBuilder::new(pbf)
+            .buffer_size(16)
+            .strict_header_size(strict)
+            .check_blob_types(check_types)
+            .build()
+            .next()
+            .unwrap()
+    }
+
+    #[test]
+    fn builder_options() {
+        use error::ErrorKind;
+
+        let unknown = framed_blob("Custom", vec![]);
+        let blob = builder_result(&unknown, true, false).unwrap();
+        assert_eq!(blob.get_type(), BlobType::Unknown("Custom"));
+        match builder_result(&unknown, true, true).unwrap_err().kind() {
+            ErrorKind::Blob(BlobError::UnknownBlobType { blob_type }) => {
+                assert_eq!(blob_type, "Custom")
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        let big = framed_blob("OSMData", vec![0; MAX_BLOB_HEADER_SIZE as usize]);
+        match builder_result(&big, true, true).unwrap_err().kind() {
+            ErrorKind::Blob(BlobError::HeaderTooBig { .. }) => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+        let blob = builder_result(&big, false, true).unwrap();
+        assert_eq!(blob.get_type(), BlobType::OsmData);
+        assert_eq!(blob.offset(), None);
+    }
+
+    #[test]
+    fn hostile_sizes() {
+        use error::ErrorKind;
+
+        // A header size prefix of 2 GiB, but no header follows.
+        let pbf = [0x7f, 0xff, 0xff, 0xff, 0, 0];
+        let mut reader = BlobReader::new(&pbf[..]);
+        match reader.next().unwrap().unwrap_err().kind() {
+            ErrorKind::Blob(BlobError::HeaderTooBig { size }) => assert_eq!(*size, 0x7fff_ffff),
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(reader.next().is_none());
+        // Without the limit, the stream just ends too early.
+        match builder_result(&pbf, false, false).unwrap_err().kind() {
+            ErrorKind::UnexpectedEof { .. } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // A valid header that announces a blob of 2 GiB.
+        let mut header = fileformat::BlobHeader::new();
+        header.set_field_type("OSMData".to_string());
+        header.set_datasize(i32::max_value());
+        let header = header.write_to_bytes().unwrap();
+        let mut pbf = vec![0; 4];
+        byteorder::BigEndian::write_u32(&mut pbf, header.len() as u32);
+        pbf.extend(header);
+        match BlobReader::new(&pbf[..])
+            .next()
+            .unwrap()
+            .unwrap_err()
+            .kind()
+        {
+            ErrorKind::Blob(BlobError::MessageTooBig { size }) => {
+                assert_eq!(*size, i32::max_value() as u64)
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // A lower, configured limit
+        let pbf = framed_blob("OSMData", vec![]);
+        let reader = |max| {
+            BlobReaderBuilder::new(&pbf[..])
+                .max_blob_size(max)
+                .build()
+                .next()
+                .unwrap()
+        };
+        assert!(reader(1024).is_ok());
+        match reader(4).unwrap_err().kind() {
+            ErrorKind::Blob(BlobError::MessageTooBig { size }) => assert!(*size > 4),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
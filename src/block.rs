@@ -1,10 +1,24 @@
 //! `HeaderBlock`, `PrimitiveBlock` and `PrimitiveGroup`s
 
-use dense::DenseNodeIter;
-use elements::{Element, Node, Relation, Way};
+use blob::{BlobReader, BlobType};
+use dense::{validate_dense_keys_vals, DenseArrays, DenseInfoIter, DenseNodeIter};
+use elements::{BoundingBox, Changeset, Coordinate, Element, ElementTypeSet, Node, Relation, Way};
 use error::{new_error, ErrorKind, Result};
 use proto::osmformat;
 use std;
+use std::borrow::Cow;
+use std::io::Read;
+use std::path::Path;
+
+/// The required features that this crate is able to parse (see
+/// `HeaderBlock::unsupported_feature`). The node locations of `LocationsOnWays` files are
+/// skipped, their ways are read like any other way.
+pub static SUPPORTED_FEATURES: &[&str] = &[
+    "OsmSchema-V0.6",
+    "DenseNodes",
+    "HistoricalInformation",
+    "LocationsOnWays",
+];
 
 /// A `HeaderBlock`. It contains metadata about following `PrimitiveBlock`s.
 #[derive(Clone, Debug)]
@@ -17,6 +31,49 @@ impl HeaderBlock {
         HeaderBlock { header }
     }
 
+    /// Reads only the `HeaderBlock` of the PBF file at the given path. The file has to start with
+    /// a header blob and no other blobs are read, so this is cheap even for huge files.
+    ///
+    /// # Errors
+    /// Returns `ErrorKind::MissingHeaderBlock` if the file is empty or starts with another type
+    /// of blob and the errors of reading and decoding the header.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let header = HeaderBlock::read_from_path("tests/test.osm.pbf")?;
+    ///
+    /// println!("required features: {:?}", header.required_features());
+    /// # assert!(header.required_features().iter().any(|f| f == "OsmSchema-V0.6"));
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn read_from_path<P: AsRef<Path>>(path: P) -> Result<HeaderBlock> {
+        Self::read_from_blobs(BlobReader::from_path(path)?)
+    }
+
+    /// Reads only the `HeaderBlock` from the start of the given reader (see `read_from_path`).
+    pub fn read_from<R: Read>(reader: R) -> Result<HeaderBlock> {
+        Self::read_from_blobs(BlobReader::new(reader))
+    }
+
+    fn read_from_blobs<R: Read>(mut blobs: BlobReader<R>) -> Result<HeaderBlock> {
+        match blobs.next() {
+            Some(blob) => {
+                let blob = blob?;
+                if blob.get_type() == BlobType::OsmHeader {
+                    blob.to_headerblock()
+                } else {
+                    Err(new_error(ErrorKind::MissingHeaderBlock))
+                }
+            }
+            None => Err(new_error(ErrorKind::MissingHeaderBlock)),
+        }
+    }
+
     /// Returns a list of required features that a parser needs to implement to parse the following
     /// `PrimitiveBlock`s.
     pub fn required_features(&self) -> &[String] {
@@ -27,6 +84,89 @@ impl HeaderBlock {
     pub fn optional_features(&self) -> &[String] {
         self.header.get_optional_features()
     }
+
+    /// Returns true if the file contains historical information ("HistoricalInformation"
+    /// feature). Such full history files contain all versions of each element, so ids are not
+    /// unique, and deleted versions are marked as not visible (see `Info::visible`).
+    pub fn has_historical_information(&self) -> bool {
+        self.required_features()
+            .iter()
+            .chain(self.optional_features())
+            .any(|feature| feature == "HistoricalInformation")
+    }
+
+    /// Returns the first required feature that is not contained in `SUPPORTED_FEATURES`, or
+    /// `None` if this crate can parse all of them.
+    pub fn unsupported_feature(&self) -> Option<&str> {
+        self.required_features()
+            .iter()
+            .map(|feature| feature.as_str())
+            .find(|feature| !SUPPORTED_FEATURES.contains(feature))
+    }
+
+    /// Returns the bounding box of all nodes in the file, if the file declares one.
+    pub fn bbox(&self) -> Option<BoundingBox> {
+        if self.header.has_bbox() {
+            let bbox = self.header.get_bbox();
+            Some(BoundingBox::new(
+                Coordinate::new(bbox.get_bottom(), bbox.get_left()),
+                Coordinate::new(bbox.get_top(), bbox.get_right()),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the replication timestamp in seconds since epoch
+    /// ("osmosis_replication_timestamp"), if the file declares one. Together with
+    /// `replication_sequence` it tells which replication diff has to be applied next.
+    pub fn replication_timestamp(&self) -> Option<i64> {
+        if self.header.has_osmosis_replication_timestamp() {
+            Some(self.header.get_osmosis_replication_timestamp())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the replication sequence number ("osmosis_replication_sequence_number"), if the
+    /// file declares one.
+    pub fn replication_sequence(&self) -> Option<i64> {
+        if self.header.has_osmosis_replication_sequence_number() {
+            Some(self.header.get_osmosis_replication_sequence_number())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the base URL of the replication server ("osmosis_replication_base_url"), if the
+    /// file declares one.
+    pub fn replication_base_url(&self) -> Option<&str> {
+        if self.header.has_osmosis_replication_base_url() {
+            Some(self.header.get_osmosis_replication_base_url())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the name of the program that wrote the file ("writingprogram"), e.g. an osmium
+    /// or osmosis version string, if the file declares one.
+    pub fn writing_program(&self) -> Option<&str> {
+        if self.header.has_writingprogram() {
+            Some(self.header.get_writingprogram())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the source of the data ("source"), e.g. a URL of the bounding box that was
+    /// exported, if the file declares one.
+    pub fn source(&self) -> Option<&str> {
+        if self.header.has_source() {
+            Some(self.header.get_source())
+        } else {
+            None
+        }
+    }
 }
 
 /// A `PrimitiveBlock`. It contains a sequence of groups.
@@ -36,8 +176,12 @@ pub struct PrimitiveBlock {
 }
 
 impl PrimitiveBlock {
-    pub(crate) fn new(block: osmformat::PrimitiveBlock) -> PrimitiveBlock {
-        PrimitiveBlock { block }
+    /// Wraps a decoded protobuf message. Fails if the dense nodes of the block are malformed.
+    pub(crate) fn new(block: osmformat::PrimitiveBlock) -> Result<PrimitiveBlock> {
+        for group in block.get_primitivegroup() {
+            validate_dense_keys_vals(group.get_dense())?;
+        }
+        Ok(PrimitiveBlock { block })
     }
 
     /// Returns an iterator over the elements in this `PrimitiveBlock`.
@@ -71,6 +215,172 @@ impl PrimitiveBlock {
         }
     }
 
+    /// Calls the given closure on each element whose type is contained in `types`. Groups that
+    /// cannot contain any of the selected types are skipped without decoding their elements.
+    pub fn for_each_element_of_types<F>(&self, types: ElementTypeSet, mut f: F)
+    where
+        F: for<'a> FnMut(Element<'a>),
+    {
+        for group in self.groups() {
+            if types.contains(ElementTypeSet::NODE) {
+                for node in group.nodes() {
+                    f(Element::Node(node))
+                }
+            }
+            if types.contains(ElementTypeSet::DENSE_NODE) {
+                for dnode in group.dense_nodes() {
+                    f(Element::DenseNode(dnode))
+                }
+            }
+            if types.contains(ElementTypeSet::WAY) {
+                for way in group.ways() {
+                    f(Element::Way(way));
+                }
+            }
+            if types.contains(ElementTypeSet::RELATION) {
+                for relation in group.relations() {
+                    f(Element::Relation(relation));
+                }
+            }
+        }
+    }
+
+    /// Decodes the ids and coordinates of all dense nodes in this block into parallel arrays.
+    /// Nodes that are not stored as dense nodes are not included.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         let arrays = block.dense_arrays();
+    ///         let max_lat = arrays.nano_lats().iter().max();
+    ///         println!("{} dense nodes, northernmost at {:?}", arrays.len(), max_lat);
+    ///     }
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn dense_arrays(&self) -> DenseArrays {
+        DenseArrays::from_block(&self.block)
+    }
+
+    /// Looks up the given node ids among the nodes and dense nodes of this block and returns
+    /// each id together with its coordinates, or `None` if the block does not contain a node with
+    /// this id. The result has the same order as `ids`.
+    ///
+    /// `ids` has to be sorted in ascending order. The nodes of the block are visited only once,
+    /// so resolving many ids is not much more expensive than resolving a single one.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         for (id, coord) in block.resolve_nodes(&[105, 107]) {
+    ///             match coord {
+    ///                 Some(coord) => println!("node {}: {}, {}", id, coord.lat(), coord.lon()),
+    ///                 None => println!("node {} is not in this block", id),
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn resolve_nodes(&self, ids: &[i64]) -> Vec<(i64, Option<Coordinate>)> {
+        let mut resolved: Vec<(i64, Option<Coordinate>)> =
+            ids.iter().map(|&id| (id, None)).collect();
+        self.for_each_node_with_ids(ids, |index, element| {
+            let coord = match element {
+                Element::Node(node) => node.coordinate(),
+                Element::DenseNode(node) => node.coordinate(),
+                Element::Way(_) | Element::Relation(_) => return,
+            };
+            for entry in resolved[index..]
+                .iter_mut()
+                .take_while(|entry| entry.0 == ids[index])
+            {
+                entry.1 = Some(coord);
+            }
+        });
+        resolved
+    }
+
+    /// Calls the given closure on each node and dense node of this block whose id is contained in
+    /// the sorted slice `ids`. The closure also receives the index of the first occurrence of the
+    /// id in `ids`.
+    pub(crate) fn for_each_node_with_ids<'a, F>(&'a self, ids: &[i64], mut f: F)
+    where
+        F: FnMut(usize, Element<'a>),
+    {
+        if ids.is_empty() {
+            return;
+        }
+
+        let find = |id: i64| {
+            // Index of the first id that is not smaller than `id`
+            let index = ids.partition_point(|&probe| probe < id);
+            if index < ids.len() && ids[index] == id {
+                Some(index)
+            } else {
+                None
+            }
+        };
+
+        for group in self.groups() {
+            for node in group.nodes() {
+                if let Some(index) = find(node.id()) {
+                    f(index, Element::Node(node));
+                }
+            }
+            for node in group.dense_nodes() {
+                if let Some(index) = find(node.id) {
+                    f(index, Element::DenseNode(node));
+                }
+            }
+        }
+    }
+
+    /// Returns the granularity of coordinates in units of nanodegrees. Raw latitudes and
+    /// longitudes of the nodes in this block have to be multiplied by it and added to
+    /// `lat_offset` or `lon_offset` to get nanodegrees. The default is 100.
+    ///
+    /// The coordinate accessors of the elements already do this conversion, so this is only
+    /// needed for code that decodes raw values itself.
+    pub fn granularity(&self) -> i32 {
+        self.block.get_granularity()
+    }
+
+    /// Returns the latitude offset in nanodegrees (see `granularity`). The default is 0.
+    pub fn lat_offset(&self) -> i64 {
+        self.block.get_lat_offset()
+    }
+
+    /// Returns the longitude offset in nanodegrees (see `granularity`). The default is 0.
+    pub fn lon_offset(&self) -> i64 {
+        self.block.get_lon_offset()
+    }
+
+    /// Returns the granularity of timestamps in units of milliseconds. Raw timestamps of the
+    /// elements in this block have to be multiplied by it to get milliseconds since the epoch.
+    /// The default is 1000.
+    pub fn date_granularity(&self) -> i32 {
+        self.block.get_date_granularity()
+    }
+
     /// Returns the raw stringtable. Elements in a `PrimitiveBlock` do not store strings
     /// themselves; instead, they just store indices to the stringtable. By convention, the
     /// contained strings are UTF-8 encoded but it is not safe to assume that (use
@@ -78,6 +388,143 @@ impl PrimitiveBlock {
     pub fn raw_stringtable(&self) -> &[Vec<u8>] {
         self.block.get_stringtable().get_s()
     }
+
+    /// Returns an iterator over the strings of the stringtable, in the order of their indices.
+    /// Entries that are not valid UTF-8 are returned as errors.
+    ///
+    /// Together with the `raw_tags` methods of the elements, this allows to process tags by
+    /// their stringtable indices and to resolve each distinct string only once per block.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         // Count the tag keys of all ways by their index
+    ///         let mut counts = vec![0_u64; block.stringtable_len()];
+    ///         for way in block.groups().flat_map(|g| g.ways()) {
+    ///             for (key, _) in way.raw_tags() {
+    ///                 counts[key as usize] += 1;
+    ///             }
+    ///         }
+    ///
+    ///         for (string, count) in block.strings().zip(counts) {
+    ///             if count > 0 {
+    ///                 println!("{}: {}", string?, count);
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn strings(&self) -> StringTableIter {
+        StringTableIter {
+            strings: self.block.get_stringtable().get_s().iter().enumerate(),
+        }
+    }
+
+    /// Returns the string at the given index of the stringtable.
+    pub fn string(&self, index: usize) -> Result<&str> {
+        str_from_stringtable(&self.block, index)
+    }
+
+    /// Returns the string at the given index of the stringtable like `string`, but a string
+    /// that is not valid UTF-8 is decoded with `String::from_utf8_lossy` instead of returning
+    /// an error.
+    pub fn string_lossy(&self, index: usize) -> Result<Cow<str>> {
+        str_lossy_from_stringtable(&self.block, index)
+    }
+
+    /// Returns the index of the given string in the stringtable, or `None` if the block does not
+    /// contain it. Index 0 is reserved as a delimiter and is never returned.
+    ///
+    /// Looking up a string once per block allows comparing tags by their indices, e.g. with
+    /// `DenseNode::has_tag_key_index`, instead of resolving the tags of every element.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    /// let mut named_nodes = 0;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         if let Some(index) = block.string_index("name") {
+    ///             named_nodes += block
+    ///                 .groups()
+    ///                 .flat_map(|g| g.dense_nodes())
+    ///                 .filter(|node| node.has_tag_key_index(index))
+    ///                 .count();
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// println!("Number of named dense nodes: {}", named_nodes);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn string_index(&self, string: &str) -> Option<u32> {
+        self.raw_stringtable()
+            .iter()
+            .skip(1)
+            .position(|s| s.as_slice() == string.as_bytes())
+            .map(|i| i as u32 + 1)
+    }
+
+    /// Returns the number of entries in the stringtable.
+    pub fn stringtable_len(&self) -> usize {
+        self.block.get_stringtable().get_s().len()
+    }
+}
+
+/// An iterator over the strings in the stringtable of a `PrimitiveBlock`.
+#[derive(Clone, Debug)]
+pub struct StringTableIter<'a> {
+    strings: std::iter::Enumerate<std::slice::Iter<'a, Vec<u8>>>,
+}
+
+impl<'a> Iterator for StringTableIter<'a> {
+    type Item = Result<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.strings.next().map(|(index, bytes)| {
+            std::str::from_utf8(bytes)
+                .map_err(|e| new_error(ErrorKind::StringtableUtf8 { err: e, index }))
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.strings.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for StringTableIter<'a> {}
+
+/// The type of the elements that a `PrimitiveGroup` contains (see `PrimitiveGroup::kind`).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum GroupKind {
+    /// The group contains `Node`s.
+    Nodes,
+    /// The group contains `DenseNode`s.
+    DenseNodes,
+    /// The group contains `Way`s.
+    Ways,
+    /// The group contains `Relation`s.
+    Relations,
+    /// The group contains changesets, which this crate does not decode.
+    Changesets,
+    /// The group contains no elements.
+    Empty,
 }
 
 /// A `PrimitiveGroup` contains a sequence of elements of one type.
@@ -95,25 +542,134 @@ impl<'a> PrimitiveGroup<'a> {
         PrimitiveGroup { block, group }
     }
 
+    /// Returns the type of the elements in this group without decoding them. This allows
+    /// dispatching once per group instead of calling all element iterators.
+    ///
+    /// A group should only contain elements of one type. If a malformed group contains several
+    /// types, the first one in the order of the `GroupKind` variants is returned.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         for group in block.groups() {
+    ///             match group.kind() {
+    ///                 GroupKind::Ways => println!("{} ways", group.ways().count()),
+    ///                 kind => println!("group of {:?}", kind),
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn kind(&self) -> GroupKind {
+        if !self.group.get_nodes().is_empty() {
+            GroupKind::Nodes
+        } else if !self.group.get_dense().get_id().is_empty() {
+            GroupKind::DenseNodes
+        } else if !self.group.get_ways().is_empty() {
+            GroupKind::Ways
+        } else if !self.group.get_relations().is_empty() {
+            GroupKind::Relations
+        } else if !self.group.get_changesets().is_empty() {
+            GroupKind::Changesets
+        } else {
+            GroupKind::Empty
+        }
+    }
+
     /// Returns an iterator over the nodes in this group.
     pub fn nodes(&self) -> GroupNodeIter<'a> {
         GroupNodeIter::new(self.block, self.group)
     }
 
+    /// Returns the number of (non-dense) nodes in this group without decoding them.
+    pub fn nodes_len(&self) -> usize {
+        self.group.get_nodes().len()
+    }
+
     /// Returns an iterator over the dense nodes in this group.
     pub fn dense_nodes(&self) -> DenseNodeIter<'a> {
         DenseNodeIter::new(self.block, self.group.get_dense())
     }
 
+    /// Returns an iterator over the ids and metadata of the dense nodes in this group. This only
+    /// decodes the delta encoded metadata arrays, which is faster than iterating over
+    /// `dense_nodes` if the coordinates and tags are not needed, e.g. to analyze the edit history
+    /// of a history file. The iterator is empty if the dense nodes have no metadata.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         for group in block.groups() {
+    ///             for (id, info) in group.dense_infos() {
+    ///                 println!("node {} v{} at {}", id, info.version(), info.timestamp_secs());
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn dense_infos(&self) -> DenseInfoIter<'a> {
+        DenseInfoIter::new(self.block, self.group.get_dense())
+    }
+
+    /// Returns the number of dense nodes in this group without decoding them. This is useful to
+    /// allocate storage for the nodes before iterating over them with `dense_nodes`.
+    pub fn dense_nodes_len(&self) -> usize {
+        self.group.get_dense().get_id().len()
+    }
+
     /// Returns an iterator over the ways in this group.
     pub fn ways(&self) -> GroupWayIter<'a> {
         GroupWayIter::new(self.block, self.group)
     }
 
+    /// Returns the number of ways in this group without decoding them.
+    pub fn ways_len(&self) -> usize {
+        self.group.get_ways().len()
+    }
+
     /// Returns an iterator over the relations in this group.
     pub fn relations(&self) -> GroupRelationIter<'a> {
         GroupRelationIter::new(self.block, self.group)
     }
+
+    /// Returns the number of relations in this group without decoding them.
+    pub fn relations_len(&self) -> usize {
+        self.group.get_relations().len()
+    }
+
+    /// Returns the number of elements in this group without decoding them, no matter which type
+    /// they have. Together with `PrimitiveBlock::stringtable_len`, this allows reserving buffers
+    /// before iterating over the elements or their tags.
+    pub fn elements_len(&self) -> usize {
+        self.nodes_len() + self.dense_nodes_len() + self.ways_len() + self.relations_len()
+    }
+
+    /// Returns an iterator over the changesets in this group. Changesets are not returned by the
+    /// element iterators of `PrimitiveBlock` and `ElementReader`.
+    pub fn changesets(&self) -> GroupChangesetIter<'a> {
+        GroupChangesetIter {
+            changesets: self.group.get_changesets().iter(),
+        }
+    }
 }
 
 /// An iterator over the elements in a `PrimitiveGroup`.
@@ -317,6 +873,26 @@ impl<'a> Iterator for GroupWayIter<'a> {
 
 impl<'a> ExactSizeIterator for GroupWayIter<'a> {}
 
+/// An iterator over the changesets in a `PrimitiveGroup`.
+#[derive(Clone, Debug)]
+pub struct GroupChangesetIter<'a> {
+    changesets: std::slice::Iter<'a, osmformat::ChangeSet>,
+}
+
+impl<'a> Iterator for GroupChangesetIter<'a> {
+    type Item = Changeset<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.changesets.next().map(Changeset::new)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.changesets.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for GroupChangesetIter<'a> {}
+
 /// An iterator over the relations in a `PrimitiveGroup`.
 #[derive(Clone, Debug)]
 pub struct GroupRelationIter<'a> {
@@ -353,6 +929,19 @@ impl<'a> Iterator for GroupRelationIter<'a> {
 
 impl<'a> ExactSizeIterator for GroupRelationIter<'a> {}
 
+/// Like `str_from_stringtable`, but strings that are not valid UTF-8 are decoded with
+/// `String::from_utf8_lossy`.
+pub(crate) fn str_lossy_from_stringtable(
+    block: &osmformat::PrimitiveBlock,
+    index: usize,
+) -> Result<Cow<str>> {
+    if let Some(vec) = block.get_stringtable().get_s().get(index) {
+        Ok(String::from_utf8_lossy(vec))
+    } else {
+        Err(new_error(ErrorKind::StringtableIndexOutOfBounds { index }))
+    }
+}
+
 pub(crate) fn str_from_stringtable(
     block: &osmformat::PrimitiveBlock,
     index: usize,
@@ -364,3 +953,97 @@ pub(crate) fn str_from_stringtable(
         Err(new_error(ErrorKind::StringtableIndexOutOfBounds { index }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changesets() {
+        let mut block = osmformat::PrimitiveBlock::new();
+        block.mut_stringtable().mut_s().push(vec![]);
+        let mut group = osmformat::PrimitiveGroup::new();
+        for id in &[42, 43] {
+            let mut changeset = osmformat::ChangeSet::new();
+            changeset.set_id(*id);
+            group.mut_changesets().push(changeset);
+        }
+        block.mut_primitivegroup().push(group);
+        let block = PrimitiveBlock::new(block).unwrap();
+
+        let group = block.groups().next().unwrap();
+        assert_eq!(group.kind(), GroupKind::Changesets);
+        assert_eq!(group.changesets().len(), 2);
+        let ids: Vec<_> = group.changesets().map(|c| c.id()).collect();
+        assert_eq!(ids, vec![42, 43]);
+        assert_eq!(block.elements().count(), 0);
+    }
+
+    #[test]
+    fn invalid_utf8_strings() {
+        use blob::BlobDecode;
+        use std::io::Cursor;
+        use util::encode_test_pbf;
+
+        let mut block = osmformat::PrimitiveBlock::new();
+        for s in &[
+            &b""[..],
+            b"name",
+            b"caf\xe9",
+            b"amenity",
+            b"cafe",
+            b"outer\xff",
+        ] {
+            block.mut_stringtable().mut_s().push(s.to_vec());
+        }
+        let mut group = osmformat::PrimitiveGroup::new();
+        let mut way = osmformat::Way::new();
+        way.set_id(1);
+        way.set_keys(vec![1, 3]);
+        way.set_vals(vec![2, 4]);
+        group.mut_ways().push(way);
+        let mut relation = osmformat::Relation::new();
+        relation.set_id(2);
+        relation.set_keys(vec![3]);
+        relation.set_vals(vec![4]);
+        relation.set_roles_sid(vec![5]);
+        relation.set_memids(vec![1]);
+        relation.set_types(vec![osmformat::Relation_MemberType::WAY]);
+        group.mut_relations().push(relation);
+        block.mut_primitivegroup().push(group);
+
+        let pbf = encode_test_pbf(&[block]);
+        let blob = BlobReader::new(Cursor::new(pbf)).nth(1).unwrap().unwrap();
+        let block = match blob.decode().unwrap() {
+            BlobDecode::OsmData(block) => block,
+            _ => panic!("expected a primitive block"),
+        };
+
+        assert!(block.string(2).is_err());
+        assert_eq!(block.string_lossy(2).unwrap(), "caf\u{fffd}");
+        assert_eq!(block.string_lossy(1).unwrap(), "name");
+        assert!(block.string_lossy(6).is_err());
+
+        let way = block.groups().flat_map(|g| g.ways()).next().unwrap();
+        // The strict iterator stops at the invalid value.
+        assert_eq!(way.tags().count(), 0);
+        let tags: Vec<_> = way
+            .tags_lossy()
+            .map(|(k, v)| (k.into_owned(), v.into_owned()))
+            .collect();
+        assert_eq!(
+            tags,
+            vec![
+                ("name".to_string(), "caf\u{fffd}".to_string()),
+                ("amenity".to_string(), "cafe".to_string()),
+            ]
+        );
+        assert_eq!(Element::Way(way).tags_lossy().len(), 2);
+
+        let relation = block.groups().flat_map(|g| g.relations()).next().unwrap();
+        let member = relation.members().next().unwrap();
+        assert!(member.role().is_err());
+        assert_eq!(member.role_lossy().unwrap(), "outer\u{fffd}");
+        assert_eq!(relation.tags_lossy().count(), 1);
+    }
+}
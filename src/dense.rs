@@ -1,7 +1,10 @@
 //! Iterate over the dense nodes in a `PrimitiveGroup`
 
 use block::str_from_stringtable;
-use error::Result;
+use elements::{Coordinate, FilteredTagIter, LossyTagIter};
+use error::{new_error, ErrorKind, Result};
+use filter::CompiledKeyAllowlist;
+use owned::{owned_tags, OwnedNode};
 use proto::osmformat;
 use std;
 
@@ -14,7 +17,7 @@ pub struct DenseNode<'a> {
     /// The node id. It should be unique between nodes and might be negative to indicate
     /// that the element has not yet been uploaded to a server.
     pub id: i64,
-    /// The version of this element.
+    /// The version of this element or -1 if the group of dense nodes has no metadata.
     pub version: i32,
     timestamp: i64,
     /// The changeset id.
@@ -25,14 +28,41 @@ pub struct DenseNode<'a> {
     lat: i64,
     lon: i64,
     keys_vals_indices: &'a [i32],
+    has_info: bool,
+    visible: bool,
 }
 
 impl<'a> DenseNode<'a> {
+    /// Returns the metadata of this node or `None` if the group of dense nodes has no metadata.
+    /// The values are the same as in the public fields and accessors of `DenseNode`, but with
+    /// the visibility flag of history files in addition. Use `PrimitiveGroup::dense_infos` to
+    /// decode only the metadata of all dense nodes in a group.
+    pub fn info(&self) -> Option<DenseNodeInfo<'a>> {
+        if self.has_info {
+            Some(DenseNodeInfo {
+                block: self.block,
+                version: self.version,
+                timestamp: self.timestamp,
+                changeset: self.changeset,
+                uid: self.uid,
+                user_sid: self.user_sid,
+                visible: self.visible,
+            })
+        } else {
+            None
+        }
+    }
+
     /// Returns the user name.
     pub fn user(&self) -> Result<&'a str> {
         str_from_stringtable(self.block, self.user_sid as usize)
     }
 
+    /// Returns the coordinates of this node.
+    pub fn coordinate(&self) -> Coordinate {
+        Coordinate::new(self.nano_lat(), self.nano_lon())
+    }
+
     /// Returns the latitude coordinate in degrees.
     pub fn lat(&self) -> f64 {
         1e-9 * self.nano_lat() as f64
@@ -68,6 +98,12 @@ impl<'a> DenseNode<'a> {
         self.timestamp * i64::from(self.block.get_date_granularity())
     }
 
+    /// Returns the time stamp in seconds since the epoch. This is derived from
+    /// `milli_timestamp` by rounding down to full seconds.
+    pub fn timestamp_secs(&self) -> i64 {
+        self.milli_timestamp().div_euclid(1000)
+    }
+
     /// Returns an iterator over the tags of this node (See [OSM wiki](http://wiki.openstreetmap.org/wiki/Tags)).
     pub fn tags(&self) -> DenseTagIter<'a> {
         DenseTagIter {
@@ -76,6 +112,42 @@ impl<'a> DenseNode<'a> {
         }
     }
 
+    /// Clears `buf` and fills it with the tags of this node. Same as `Way::collect_tags_into`.
+    pub fn collect_tags_into(&self, buf: &mut Vec<(&'a str, &'a str)>) {
+        buf.clear();
+        buf.extend(self.tags());
+    }
+
+    /// Returns an iterator over the tags of this node that tolerates strings which are not
+    /// valid UTF-8. Same as `Way::tags_lossy`.
+    pub fn tags_lossy(&self) -> LossyTagIter<'a> {
+        LossyTagIter::new_dense(self.block, self.raw_tags())
+    }
+
+    /// Returns an iterator over the tags of this node whose keys are allowed by `keys`. Same as
+    /// `Way::tags_filtered`.
+    pub fn tags_filtered<'k>(&self, keys: &'k CompiledKeyAllowlist) -> FilteredTagIter<'a, 'k> {
+        FilteredTagIter::new_dense(self.block, self.raw_tags(), keys)
+    }
+
+    /// Returns an owned copy of this node that does not borrow from the block.
+    pub fn to_owned(&self) -> OwnedNode {
+        OwnedNode {
+            id: self.id,
+            coordinate: self.coordinate(),
+            tags: owned_tags(self.tags()),
+        }
+    }
+
+    /// Returns true if this node has a tag whose key is the string at the given index of the
+    /// stringtable (see `PrimitiveBlock::string_index`). Only integers are compared, no strings
+    /// are resolved. Index 0 is the delimiter of the dense `keys_vals` array and never matches.
+    pub fn has_tag_key_index(&self, index: u32) -> bool {
+        self.keys_vals_indices
+            .chunks(2)
+            .any(|key_val| key_val[0] as u32 == index && index != 0)
+    }
+
     /// Returns an iterator over the tags of this node
     /// (See [OSM wiki](http://wiki.openstreetmap.org/wiki/Tags)).
     /// A tag is represented as a pair of indices (key and value) to the stringtable of the current
@@ -87,6 +159,151 @@ impl<'a> DenseNode<'a> {
     }
 }
 
+/// The metadata of a dense node (See `DenseNode::info`).
+#[derive(Clone, Debug)]
+pub struct DenseNodeInfo<'a> {
+    block: &'a osmformat::PrimitiveBlock,
+    version: i32,
+    timestamp: i64,
+    changeset: i64,
+    uid: i32,
+    user_sid: i32,
+    visible: bool,
+}
+
+impl<'a> DenseNodeInfo<'a> {
+    /// Returns the version of this node.
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// Returns the time stamp in milliseconds since the epoch.
+    pub fn milli_timestamp(&self) -> i64 {
+        self.timestamp * i64::from(self.block.get_date_granularity())
+    }
+
+    /// Returns the time stamp in seconds since the epoch. This is derived from
+    /// `milli_timestamp` by rounding down to full seconds.
+    pub fn timestamp_secs(&self) -> i64 {
+        self.milli_timestamp().div_euclid(1000)
+    }
+
+    /// Returns the changeset id.
+    pub fn changeset(&self) -> i64 {
+        self.changeset
+    }
+
+    /// Returns the user id.
+    pub fn uid(&self) -> i32 {
+        self.uid
+    }
+
+    /// Returns the user name.
+    pub fn user(&self) -> Result<&'a str> {
+        str_from_stringtable(self.block, self.user_sid as usize)
+    }
+
+    /// Returns the visibility status of this node. This is only relevant if the PBF file contains
+    /// historical information.
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+}
+
+/// The ids and coordinates of all dense nodes in a `PrimitiveBlock`, decoded into parallel
+/// arrays. The values at the same index belong to the same node.
+///
+/// This is meant for bulk processing of many nodes, e.g. to hand over coordinates to columnar or
+/// vectorized code. Decoding the arrays is considerably faster than iterating over `DenseNode`s,
+/// because only the delta encoded ids and coordinates are decoded and no metadata or tags.
+#[derive(Clone, Debug, Default)]
+pub struct DenseArrays {
+    ids: Vec<i64>,
+    nano_lats: Vec<i64>,
+    nano_lons: Vec<i64>,
+}
+
+impl DenseArrays {
+    pub(crate) fn from_block(block: &osmformat::PrimitiveBlock) -> DenseArrays {
+        // Allocate the arrays for all groups at once instead of growing them group by group.
+        let capacity = block
+            .get_primitivegroup()
+            .iter()
+            .map(|group| group.get_dense().get_id().len())
+            .sum();
+        let mut arrays = DenseArrays {
+            ids: Vec::with_capacity(capacity),
+            nano_lats: Vec::with_capacity(capacity),
+            nano_lons: Vec::with_capacity(capacity),
+        };
+        for group in block.get_primitivegroup() {
+            arrays.extend(block, group.get_dense());
+        }
+        arrays
+    }
+
+    fn extend(&mut self, block: &osmformat::PrimitiveBlock, osmdense: &osmformat::DenseNodes) {
+        let granularity = i64::from(block.get_granularity());
+        let lat_offset = block.get_lat_offset();
+        let lon_offset = block.get_lon_offset();
+
+        // Only decode as many nodes as all three arrays provide, just like `DenseNodeIter`.
+        let dids = osmdense.get_id();
+        let dlats = osmdense.get_lat();
+        let dlons = osmdense.get_lon();
+        let len = dids.len().min(dlats.len()).min(dlons.len());
+
+        let mut cid = 0;
+        for did in &dids[..len] {
+            cid += did;
+            self.ids.push(cid);
+        }
+
+        let mut clat = 0;
+        for dlat in &dlats[..len] {
+            clat += dlat;
+            self.nano_lats.push(lat_offset + granularity * clat);
+        }
+
+        let mut clon = 0;
+        for dlon in &dlons[..len] {
+            clon += dlon;
+            self.nano_lons.push(lon_offset + granularity * clon);
+        }
+    }
+
+    /// Returns the number of nodes.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Returns true if there are no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Returns the node ids.
+    pub fn ids(&self) -> &[i64] {
+        &self.ids
+    }
+
+    /// Returns the latitude coordinates in nanodegrees (10⁻⁹).
+    pub fn nano_lats(&self) -> &[i64] {
+        &self.nano_lats
+    }
+
+    /// Returns the longitude coordinates in nanodegrees (10⁻⁹).
+    pub fn nano_lons(&self) -> &[i64] {
+        &self.nano_lons
+    }
+
+    /// Consumes the arrays and returns the node ids, latitudes and longitudes (both in
+    /// nanodegrees) as separate vectors.
+    pub fn into_vecs(self) -> (Vec<i64>, Vec<i64>, Vec<i64>) {
+        (self.ids, self.nano_lats, self.nano_lons)
+    }
+}
+
 /// An iterator over dense nodes. It decodes the delta encoded values.
 #[derive(Clone, Debug)]
 pub struct DenseNodeIter<'a> {
@@ -102,12 +319,14 @@ pub struct DenseNodeIter<'a> {
     cuid: i32,
     duser_sids: std::slice::Iter<'a, i32>, // deltas
     cuser_sid: i32,
+    visibles: std::slice::Iter<'a, bool>,
     dlats: std::slice::Iter<'a, i64>, // deltas
     clat: i64,
     dlons: std::slice::Iter<'a, i64>, // deltas
     clon: i64,
     keys_vals_slice: &'a [i32],
     keys_vals_index: usize,
+    has_info: bool,
 }
 
 impl<'a> DenseNodeIter<'a> {
@@ -129,12 +348,14 @@ impl<'a> DenseNodeIter<'a> {
             cuid: 0,
             duser_sids: info.get_user_sid().iter(),
             cuser_sid: 0,
+            visibles: info.get_visible().iter(),
             dlats: osmdense.get_lat().iter(),
             clat: 0,
             dlons: osmdense.get_lon().iter(),
             clon: 0,
             keys_vals_slice: osmdense.get_keys_vals(),
             keys_vals_index: 0,
+            has_info: osmdense.has_denseinfo(),
         }
     }
 
@@ -152,12 +373,14 @@ impl<'a> DenseNodeIter<'a> {
             cuid: 0,
             duser_sids: [].iter(),
             cuser_sid: 0,
+            visibles: [].iter(),
             dlats: [].iter(),
             clat: 0,
             dlons: [].iter(),
             clon: 0,
             keys_vals_slice: &[],
             keys_vals_index: 0,
+            has_info: false,
         }
     }
 }
@@ -165,6 +388,133 @@ impl<'a> DenseNodeIter<'a> {
 impl<'a> Iterator for DenseNodeIter<'a> {
     type Item = DenseNode<'a>;
 
+    fn next(&mut self) -> Option<Self::Item> {
+        let (did, dlat, dlon) = match (self.dids.next(), self.dlats.next(), self.dlons.next()) {
+            (Some(did), Some(dlat), Some(dlon)) => (did, dlat, dlon),
+            _ => return None,
+        };
+
+        // The metadata is optional, nodes without it get a version of -1.
+        let version = if self.has_info {
+            match (
+                self.versions.next(),
+                self.dtimestamps.next(),
+                self.dchangesets.next(),
+                self.duids.next(),
+                self.duser_sids.next(),
+            ) {
+                (
+                    Some(version),
+                    Some(dtimestamp),
+                    Some(dchangeset),
+                    Some(duid),
+                    Some(duser_sid),
+                ) => {
+                    self.ctimestamp += *dtimestamp;
+                    self.cchangeset += *dchangeset;
+                    self.cuid += *duid;
+                    self.cuser_sid += *duser_sid;
+                    *version
+                }
+                _ => return None,
+            }
+        } else {
+            -1
+        };
+        // If the visible flags are not present they must be assumed to be true.
+        let visible = self.visibles.next().cloned().unwrap_or(true);
+
+        self.cid += *did;
+        self.clat += *dlat;
+        self.clon += *dlon;
+
+        let start_index = self.keys_vals_index;
+        let mut end_index = start_index;
+        for chunk in self.keys_vals_slice[self.keys_vals_index..].chunks(2) {
+            if chunk[0] != 0 && chunk.len() == 2 {
+                end_index += 2;
+                self.keys_vals_index += 2;
+            } else {
+                self.keys_vals_index += 1;
+                break;
+            }
+        }
+
+        Some(DenseNode {
+            block: self.block,
+            id: self.cid,
+            version,
+            timestamp: self.ctimestamp,
+            changeset: self.cchangeset,
+            uid: self.cuid,
+            user_sid: self.cuser_sid,
+            lat: self.clat,
+            lon: self.clon,
+            keys_vals_indices: &self.keys_vals_slice[start_index..end_index],
+            has_info: self.has_info,
+            visible,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.dids.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for DenseNodeIter<'a> {}
+
+/// An iterator over the ids and metadata of dense nodes (See `PrimitiveGroup::dense_infos`). It
+/// decodes the delta encoded ids, time stamps, changesets, user ids and user names, but skips
+/// the coordinates and tags.
+#[derive(Clone, Debug)]
+pub struct DenseInfoIter<'a> {
+    block: &'a osmformat::PrimitiveBlock,
+    dids: std::slice::Iter<'a, i64>, // deltas
+    cid: i64,                        // current id
+    versions: std::slice::Iter<'a, i32>,
+    dtimestamps: std::slice::Iter<'a, i64>, // deltas
+    ctimestamp: i64,
+    dchangesets: std::slice::Iter<'a, i64>, // deltas
+    cchangeset: i64,
+    duids: std::slice::Iter<'a, i32>, // deltas
+    cuid: i32,
+    duser_sids: std::slice::Iter<'a, i32>, // deltas
+    cuser_sid: i32,
+    visibles: std::slice::Iter<'a, bool>,
+}
+
+impl<'a> DenseInfoIter<'a> {
+    pub(crate) fn new(
+        block: &'a osmformat::PrimitiveBlock,
+        osmdense: &'a osmformat::DenseNodes,
+    ) -> DenseInfoIter<'a> {
+        let info = osmdense.get_denseinfo();
+        DenseInfoIter {
+            block,
+            // Without metadata there is nothing to iterate over.
+            dids: if osmdense.has_denseinfo() {
+                osmdense.get_id().iter()
+            } else {
+                [].iter()
+            },
+            cid: 0,
+            versions: info.get_version().iter(),
+            dtimestamps: info.get_timestamp().iter(),
+            ctimestamp: 0,
+            dchangesets: info.get_changeset().iter(),
+            cchangeset: 0,
+            duids: info.get_uid().iter(),
+            cuid: 0,
+            duser_sids: info.get_user_sid().iter(),
+            cuser_sid: 0,
+            visibles: info.get_visible().iter(),
+        }
+    }
+}
+
+impl<'a> Iterator for DenseInfoIter<'a> {
+    type Item = (i64, DenseNodeInfo<'a>);
+
     fn next(&mut self) -> Option<Self::Item> {
         match (
             self.dids.next(),
@@ -173,8 +523,6 @@ impl<'a> Iterator for DenseNodeIter<'a> {
             self.dchangesets.next(),
             self.duids.next(),
             self.duser_sids.next(),
-            self.dlats.next(),
-            self.dlons.next(),
         ) {
             (
                 Some(did),
@@ -183,52 +531,75 @@ impl<'a> Iterator for DenseNodeIter<'a> {
                 Some(dchangeset),
                 Some(duid),
                 Some(duser_sid),
-                Some(dlat),
-                Some(dlon),
             ) => {
                 self.cid += *did;
                 self.ctimestamp += *dtimestamp;
                 self.cchangeset += *dchangeset;
                 self.cuid += *duid;
                 self.cuser_sid += *duser_sid;
-                self.clat += *dlat;
-                self.clon += *dlon;
-
-                let start_index = self.keys_vals_index;
-                let mut end_index = start_index;
-                for chunk in self.keys_vals_slice[self.keys_vals_index..].chunks(2) {
-                    if chunk[0] != 0 && chunk.len() == 2 {
-                        end_index += 2;
-                        self.keys_vals_index += 2;
-                    } else {
-                        self.keys_vals_index += 1;
-                        break;
-                    }
-                }
-
-                Some(DenseNode {
-                    block: self.block,
-                    id: self.cid,
-                    version: *version,
-                    timestamp: self.ctimestamp,
-                    changeset: self.cchangeset,
-                    uid: self.cuid,
-                    user_sid: self.cuser_sid,
-                    lat: self.clat,
-                    lon: self.clon,
-                    keys_vals_indices: &self.keys_vals_slice[start_index..end_index],
-                })
+                // If the visible flags are not present they must be assumed to be true.
+                let visible = self.visibles.next().cloned().unwrap_or(true);
+
+                Some((
+                    self.cid,
+                    DenseNodeInfo {
+                        block: self.block,
+                        version: *version,
+                        timestamp: self.ctimestamp,
+                        changeset: self.cchangeset,
+                        uid: self.cuid,
+                        user_sid: self.cuser_sid,
+                        visible,
+                    },
+                ))
             }
             _ => None,
         }
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.dids.size_hint()
+        // The metadata arrays might be shorter than the ids of a malformed block.
+        (0, self.dids.size_hint().1)
     }
 }
 
-impl<'a> ExactSizeIterator for DenseNodeIter<'a> {}
+/// Checks that the `keys_vals` array of the given dense nodes contains a list of key/value pairs
+/// that is terminated by 0 for each node. An empty array is valid and means that no node has tags.
+pub(crate) fn validate_dense_keys_vals(osmdense: &osmformat::DenseNodes) -> Result<()> {
+    let keys_vals = osmdense.get_keys_vals();
+    if keys_vals.is_empty() {
+        return Ok(());
+    }
+
+    let node_count = osmdense.get_id().len();
+    let mut index = 0;
+    for node_index in 0..node_count {
+        loop {
+            match keys_vals.get(index) {
+                // The node is terminated.
+                Some(&0) => {
+                    index += 1;
+                    break;
+                }
+                // A key is followed by its value.
+                Some(_) if index + 1 < keys_vals.len() => index += 2,
+                // A key without a value or a missing terminator.
+                _ => {
+                    return Err(new_error(ErrorKind::InvalidDenseKeysVals { node_index }));
+                }
+            }
+        }
+    }
+
+    if index == keys_vals.len() {
+        Ok(())
+    } else {
+        // There are more entries than nodes.
+        Err(new_error(ErrorKind::InvalidDenseKeysVals {
+            node_index: node_count,
+        }))
+    }
+}
 
 /// An iterator over the tags in a dense node.
 #[derive(Clone, Debug)]
@@ -289,3 +660,198 @@ impl<'a> Iterator for DenseRawTagIter<'a> {
 }
 
 impl<'a> ExactSizeIterator for DenseRawTagIter<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blob::BlobReader;
+    use block::PrimitiveBlock;
+    use std::io::Cursor;
+    use util::encode_test_pbf;
+
+    fn dense(ids: &[i64], keys_vals: &[i32]) -> osmformat::DenseNodes {
+        let mut dense = osmformat::DenseNodes::new();
+        dense.set_id(ids.to_vec());
+        dense.set_lat(vec![0; ids.len()]);
+        dense.set_lon(vec![0; ids.len()]);
+        dense.set_keys_vals(keys_vals.to_vec());
+        dense
+    }
+
+    fn invalid_node_index(dense: &osmformat::DenseNodes) -> Option<usize> {
+        match validate_dense_keys_vals(dense) {
+            Ok(()) => None,
+            Err(err) => match *err.kind() {
+                ErrorKind::InvalidDenseKeysVals { node_index } => Some(node_index),
+                ref kind => panic!("unexpected error kind: {:?}", kind),
+            },
+        }
+    }
+
+    #[test]
+    fn test_validate_dense_keys_vals() {
+        // valid
+        assert_eq!(invalid_node_index(&dense(&[1, 1, 1], &[])), None);
+        assert_eq!(invalid_node_index(&dense(&[1, 1, 1], &[0, 0, 0])), None);
+        assert_eq!(
+            invalid_node_index(&dense(&[1, 1], &[1, 2, 3, 4, 0, 5, 6, 0])),
+            None
+        );
+
+        // key without value
+        assert_eq!(invalid_node_index(&dense(&[1, 1], &[0, 1])), Some(1));
+
+        // missing terminator
+        assert_eq!(invalid_node_index(&dense(&[1, 1], &[1, 2, 3, 0])), Some(0));
+        assert_eq!(
+            invalid_node_index(&dense(&[1, 1], &[1, 2, 0, 3, 4])),
+            Some(1)
+        );
+        assert_eq!(invalid_node_index(&dense(&[1, 1, 1], &[0, 0])), Some(2));
+
+        // more entries than nodes
+        assert_eq!(invalid_node_index(&dense(&[1], &[0, 1, 2, 0])), Some(1));
+    }
+
+    #[test]
+    fn test_decode_malformed_dense_nodes() {
+        let mut group = osmformat::PrimitiveGroup::new();
+        // The second node has a key without a value.
+        group.set_dense(dense(&[1, 1, 1], &[1, 1, 0, 1]));
+        let mut block = osmformat::PrimitiveBlock::new();
+        block.mut_stringtable().mut_s().push(vec![]);
+        block.mut_stringtable().mut_s().push(b"key".to_vec());
+        block.mut_primitivegroup().push(group);
+
+        let pbf = encode_test_pbf(&[block]);
+        let blobs: Vec<_> = BlobReader::new(Cursor::new(pbf))
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(blobs.len(), 2);
+
+        let err = blobs[1].to_primitiveblock().unwrap_err();
+        match *err.kind() {
+            ErrorKind::InvalidDenseKeysVals { node_index } => assert_eq!(node_index, 1),
+            ref kind => panic!("unexpected error kind: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_has_tag_key_index() {
+        let mut group = osmformat::PrimitiveGroup::new();
+        group.set_dense(dense(&[1, 1, 1], &[1, 2, 3, 4, 0, 0, 4, 1, 0]));
+        let mut block = osmformat::PrimitiveBlock::new();
+        for s in &["", "amenity", "cafe", "name", "x"] {
+            block.mut_stringtable().mut_s().push(s.as_bytes().to_vec());
+        }
+        block.mut_primitivegroup().push(group);
+        let block = PrimitiveBlock::new(block).unwrap();
+
+        assert_eq!(block.string_index("amenity"), Some(1));
+        assert_eq!(block.string_index("x"), Some(4));
+        assert_eq!(block.string_index(""), None);
+        assert_eq!(block.string_index("shop"), None);
+
+        let nodes: Vec<_> = block.groups().flat_map(|g| g.dense_nodes()).collect();
+        let with_key = |index| {
+            nodes
+                .iter()
+                .map(|n| n.has_tag_key_index(index))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(with_key(1), vec![true, false, false]);
+        assert_eq!(with_key(2), vec![false, false, false]);
+        assert_eq!(with_key(4), vec![false, false, true]);
+        assert_eq!(with_key(0), vec![false, false, false]);
+    }
+
+    #[test]
+    fn test_dense_node_info() {
+        let mut info = osmformat::DenseInfo::new();
+        info.set_version(vec![3, 1]);
+        info.set_timestamp(vec![1000, -10]);
+        info.set_changeset(vec![50, 1]);
+        info.set_uid(vec![7, -2]);
+        info.set_user_sid(vec![1, 0]);
+        info.set_visible(vec![true, false]);
+
+        let mut with_info = dense(&[1, 1], &[]);
+        with_info.set_denseinfo(info);
+        let mut block = osmformat::PrimitiveBlock::new();
+        block.mut_stringtable().mut_s().push(vec![]);
+        block.mut_stringtable().mut_s().push(b"user".to_vec());
+
+        let nodes: Vec<_> = DenseNodeIter::new(&block, &with_info).collect();
+        let infos: Vec<_> = nodes.iter().map(|n| n.info().unwrap()).collect();
+        assert_eq!(infos[0].version(), 3);
+        assert_eq!(infos[1].version(), 1);
+        assert_eq!(infos[0].timestamp_secs(), 1000);
+        assert_eq!(infos[1].milli_timestamp(), 990_000);
+        assert_eq!(infos[1].changeset(), 51);
+        assert_eq!(infos[1].uid(), 5);
+        assert_eq!(infos[1].user().unwrap(), "user");
+        assert!(infos[0].visible());
+        assert!(!infos[1].visible());
+
+        let without_info = dense(&[1, 1], &[]);
+        let nodes: Vec<_> = DenseNodeIter::new(&block, &without_info).collect();
+        assert_eq!(nodes.len(), 2);
+        assert!(nodes.iter().all(|n| n.info().is_none() && n.version == -1));
+    }
+
+    #[test]
+    fn test_dense_info_iter() {
+        // Three versions of node 10 and two of node 11, like in a history file. All arrays are
+        // delta encoded.
+        let mut info = osmformat::DenseInfo::new();
+        info.set_version(vec![1, 2, 3, 1, 2]);
+        info.set_timestamp(vec![1000, 50, 200, -1100, 30]);
+        info.set_changeset(vec![7, 1, 3, -10, 2]);
+        info.set_uid(vec![4, 0, 1, -5, 0]);
+        info.set_user_sid(vec![1, 0, 1, -1, 0]);
+        info.set_visible(vec![true, true, false, true, true]);
+
+        let mut with_info = dense(&[10, 0, 0, 1, 0], &[]);
+        with_info.set_denseinfo(info);
+        let mut block = osmformat::PrimitiveBlock::new();
+        for s in &["", "alice", "bob"] {
+            block.mut_stringtable().mut_s().push(s.as_bytes().to_vec());
+        }
+
+        let infos: Vec<_> = DenseInfoIter::new(&block, &with_info).collect();
+        let ids: Vec<_> = infos.iter().map(|&(id, _)| id).collect();
+        assert_eq!(ids, vec![10, 10, 10, 11, 11]);
+
+        let timestamps: Vec<_> = infos.iter().map(|&(_, ref i)| i.timestamp_secs()).collect();
+        assert_eq!(timestamps, vec![1000, 1050, 1250, 150, 180]);
+        // The versions of each node are in chronological order.
+        for window in infos.windows(2) {
+            if window[0].0 == window[1].0 {
+                assert!(window[0].1.milli_timestamp() <= window[1].1.milli_timestamp());
+                assert!(window[0].1.version() < window[1].1.version());
+            }
+        }
+
+        let changesets: Vec<_> = infos.iter().map(|&(_, ref i)| i.changeset()).collect();
+        assert_eq!(changesets, vec![7, 8, 11, 1, 3]);
+        let uids: Vec<_> = infos.iter().map(|&(_, ref i)| i.uid()).collect();
+        assert_eq!(uids, vec![4, 4, 5, 0, 0]);
+        let users: Vec<_> = infos.iter().map(|&(_, ref i)| i.user().unwrap()).collect();
+        assert_eq!(users, vec!["alice", "alice", "bob", "alice", "alice"]);
+        assert!(!infos[2].1.visible());
+
+        // Same values as the metadata of the fully decoded nodes.
+        let nodes: Vec<_> = DenseNodeIter::new(&block, &with_info).collect();
+        assert_eq!(nodes.len(), infos.len());
+        for (node, &(id, ref info)) in nodes.iter().zip(&infos) {
+            let node_info = node.info().unwrap();
+            assert_eq!(node.id, id);
+            assert_eq!(node_info.milli_timestamp(), info.milli_timestamp());
+            assert_eq!(node_info.changeset(), info.changeset());
+            assert_eq!(node_info.visible(), info.visible());
+        }
+
+        let without_info = dense(&[1, 1], &[]);
+        assert_eq!(DenseInfoIter::new(&block, &without_info).count(), 0);
+    }
+}
@@ -0,0 +1,414 @@
+//! Compare the elements of two sorted PBF files
+
+use blob::{BlobDecode, BlobReader};
+use block::PrimitiveBlock;
+use elements::Element;
+use error::{new_error, ErrorKind, Result};
+use reader::ElementReader;
+use std::cmp::Ordering;
+use std::io::Read;
+
+/// A difference between an old and a new version of a PBF file, as reported by `diff_elements`.
+#[derive(Clone, Debug)]
+pub enum DiffEvent<'a> {
+    /// The element only exists in the new file.
+    Added(Element<'a>),
+    /// The element only exists in the old file.
+    Removed(Element<'a>),
+    /// The element exists in both files, but its version, tags or geometry differ. The first
+    /// element is taken from the old file and the second one from the new file.
+    Modified(Element<'a>, Element<'a>),
+}
+
+/// Compares the elements of two PBF files and calls the given closure for each element that was
+/// added, removed or modified in `new` relative to `old`. Elements are identified by their type
+/// and id, so a node and a dense node with the same id are considered to be the same element.
+/// An element counts as modified if its version, its tags (ignoring their order), its
+/// coordinates (for nodes), its node references (for ways) or its members (for relations) differ.
+///
+/// Both files have to be sorted: nodes first, then ways, then relations, and each type in
+/// ascending order of ids. This is the usual order of planet dumps and extracts, and is announced
+/// with the optional feature "Sort.Type_then_ID" in the header block. The files are compared with
+/// a single merge-join pass, so at most one decoded `PrimitiveBlock` per file is kept in memory
+/// and memory usage does not depend on the size of the files.
+///
+/// # Errors
+/// Returns the first Error encountered while parsing the PBF structure of either file.
+/// Returns an error of kind `ErrorKind::UnsortedElements` if the elements of one of the files
+/// are not sorted.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let old = ElementReader::from_path("tests/test.osm.pbf")?;
+/// let new = ElementReader::from_path("tests/test_nozlib.osm.pbf")?;
+/// let mut changes = 0_u64;
+///
+/// diff_elements(old, new, |event| {
+///     match event {
+///         DiffEvent::Added(_) | DiffEvent::Removed(_) | DiffEvent::Modified(_, _) => changes += 1,
+///     }
+/// })?;
+///
+/// println!("Number of changed elements: {}", changes);
+///
+/// # assert_eq!(changes, 0);
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+pub fn diff_elements<R1, R2, F>(
+    old: ElementReader<R1>,
+    new: ElementReader<R2>,
+    mut f: F,
+) -> Result<()>
+where
+    R1: Read,
+    R2: Read,
+    F: for<'a> FnMut(DiffEvent<'a>),
+{
+    let mut old = SortedBlocks::new(old.into_blob_reader());
+    let mut new = SortedBlocks::new(new.into_blob_reader());
+
+    // A partly compared block that is merged with the following blocks of the other file: true if
+    // it belongs to the old file, the block and the number of its elements that were already
+    // compared. A block is held at most once, so its compared elements are skipped only once.
+    let mut held: Option<(bool, PrimitiveBlock, usize)> = None;
+
+    loop {
+        let (held_is_old, block, pos) = match held.take() {
+            Some(held) => held,
+            None => match old.next_block()? {
+                Some(block) => (true, block, 0),
+                None => {
+                    while let Some(block) = new.next_block()? {
+                        block.elements().for_each(|e| f(DiffEvent::Added(e)));
+                    }
+                    return Ok(());
+                }
+            },
+        };
+        let mut held_elements = block.elements().skip(pos).peekable();
+
+        // Merge the held block with the blocks of the other file until it is exhausted.
+        loop {
+            let other_block = if held_is_old {
+                new.next_block()?
+            } else {
+                old.next_block()?
+            };
+            let other_block = match other_block {
+                Some(other_block) => other_block,
+                None => {
+                    // The other file is at its end, so the rest of the held file can be reported
+                    // directly.
+                    held_elements.for_each(|e| f(only_in(held_is_old, e)));
+                    loop {
+                        let block = if held_is_old {
+                            old.next_block()?
+                        } else {
+                            new.next_block()?
+                        };
+                        match block {
+                            Some(block) => {
+                                block.elements().for_each(|e| f(only_in(held_is_old, e)))
+                            }
+                            None => return Ok(()),
+                        }
+                    }
+                }
+            };
+            let mut other_elements = other_block.elements().peekable();
+            let mut other_pos = 0;
+
+            loop {
+                let ordering = match (held_elements.peek(), other_elements.peek()) {
+                    (Some(h), Some(o)) => element_key(h).cmp(&element_key(o)),
+                    _ => break,
+                };
+                let (old_element, new_element) = match ordering {
+                    Ordering::Less => (held_elements.next(), None),
+                    Ordering::Greater => {
+                        other_pos += 1;
+                        (None, other_elements.next())
+                    }
+                    Ordering::Equal => {
+                        other_pos += 1;
+                        (held_elements.next(), other_elements.next())
+                    }
+                };
+                let (old_element, new_element) = if held_is_old {
+                    (old_element, new_element)
+                } else {
+                    (new_element, old_element)
+                };
+                match (old_element, new_element) {
+                    (Some(old_element), None) => f(DiffEvent::Removed(old_element)),
+                    (None, Some(new_element)) => f(DiffEvent::Added(new_element)),
+                    (Some(old_element), Some(new_element)) => {
+                        if is_modified(&old_element, &new_element) {
+                            f(DiffEvent::Modified(old_element, new_element));
+                        }
+                    }
+                    (None, None) => unreachable!(),
+                }
+            }
+
+            if held_elements.peek().is_none() {
+                // Hold on to the rest of the other block instead.
+                let other_done = other_elements.peek().is_none();
+                drop(other_elements);
+                if !other_done {
+                    held = Some((!held_is_old, other_block, other_pos));
+                }
+                break;
+            }
+        }
+    }
+}
+
+/// Returns the event for an element that only exists in the old file if `is_old` is true, or
+/// only in the new file otherwise.
+fn only_in(is_old: bool, element: Element) -> DiffEvent {
+    if is_old {
+        DiffEvent::Removed(element)
+    } else {
+        DiffEvent::Added(element)
+    }
+}
+
+/// The sort key of an element: type (nodes, ways, relations) and id.
+fn element_key(element: &Element) -> (u8, i64) {
+    match *element {
+        Element::Node(ref node) => (0, node.id()),
+        Element::DenseNode(ref node) => (0, node.id),
+        Element::Way(ref way) => (1, way.id()),
+        Element::Relation(ref rel) => (2, rel.id()),
+    }
+}
+
+/// The version of an element or `None` if it has no metadata, so that nodes and dense nodes
+/// without metadata have the same version.
+fn element_version(element: &Element) -> Option<i32> {
+    match *element {
+        Element::Node(ref node) => node.info().version(),
+        Element::DenseNode(ref node) => node
+            .info()
+            .map(|info| info.version())
+            .filter(|&version| version != -1),
+        Element::Way(ref way) => way.info().version(),
+        Element::Relation(ref rel) => rel.info().version(),
+    }
+}
+
+/// Returns the tags of an element, sorted so that they can be compared regardless of their order.
+fn sorted_tags<'a>(element: &Element<'a>) -> Vec<(&'a str, &'a str)> {
+    let mut tags: Vec<_> = match *element {
+        Element::Node(ref node) => node.tags().collect(),
+        Element::DenseNode(ref node) => node.tags().collect(),
+        Element::Way(ref way) => way.tags().collect(),
+        Element::Relation(ref rel) => rel.tags().collect(),
+    };
+    tags.sort();
+    tags
+}
+
+fn is_modified(old: &Element, new: &Element) -> bool {
+    if element_version(old) != element_version(new) {
+        return true;
+    }
+
+    let geometry_modified = match (old, new) {
+        (Element::Way(old), Element::Way(new)) => !old.refs().eq(new.refs()),
+        (Element::Relation(old), Element::Relation(new)) => {
+            old.members().len() != new.members().len()
+                || old.members().zip(new.members()).any(|(a, b)| {
+                    a.member_id != b.member_id
+                        || a.member_type != b.member_type
+                        || a.role().ok() != b.role().ok()
+                })
+        }
+        _ => node_coordinates(old) != node_coordinates(new),
+    };
+
+    geometry_modified || sorted_tags(old) != sorted_tags(new)
+}
+
+fn node_coordinates(element: &Element) -> Option<(i64, i64)> {
+    match *element {
+        Element::Node(ref node) => Some((node.nano_lat(), node.nano_lon())),
+        Element::DenseNode(ref node) => Some((node.nano_lat(), node.nano_lon())),
+        Element::Way(_) | Element::Relation(_) => None,
+    }
+}
+
+/// The `PrimitiveBlock`s of a file, checked to be sorted by their sort key.
+struct SortedBlocks<R: Read> {
+    blobs: BlobReader<R>,
+    last_key: Option<(u8, i64)>,
+}
+
+impl<R: Read> SortedBlocks<R> {
+    fn new(blobs: BlobReader<R>) -> SortedBlocks<R> {
+        SortedBlocks {
+            blobs,
+            last_key: None,
+        }
+    }
+
+    /// Returns the next block or `None` at the end of the file. Each block is checked to continue
+    /// the sort order of the previous blocks before any of its elements are compared.
+    fn next_block(&mut self) -> Result<Option<PrimitiveBlock>> {
+        while let Some(blob) = self.blobs.next() {
+            if let BlobDecode::OsmData(block) = blob?.decode()? {
+                for element in block.elements() {
+                    let key = element_key(&element);
+                    if let Some(last_key) = self.last_key {
+                        if key <= last_key {
+                            return Err(new_error(ErrorKind::UnsortedElements { id: key.1 }));
+                        }
+                    }
+                    self.last_key = Some(key);
+                }
+                return Ok(Some(block));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::osmformat;
+    use std::io::Cursor;
+    use util::{block, encode_test_pbf, node_at, way};
+
+    fn node(id: i64, lat: i64, version: i32) -> osmformat::Node {
+        let mut node = node_at(id, lat, 0);
+        node.mut_info().set_version(version);
+        node
+    }
+
+    /// Creates a block with dense nodes without metadata from `(id, lat)` pairs.
+    fn dense_block(nodes: &[(i64, i64)]) -> osmformat::PrimitiveBlock {
+        let mut dense = osmformat::DenseNodes::new();
+        let (mut last_id, mut last_lat) = (0, 0);
+        for &(id, lat) in nodes {
+            dense.mut_id().push(id - last_id);
+            dense.mut_lat().push(lat - last_lat);
+            dense.mut_lon().push(0);
+            last_id = id;
+            last_lat = lat;
+        }
+        let mut group = osmformat::PrimitiveGroup::new();
+        group.set_dense(dense);
+        let mut block = block(vec![], vec![]);
+        block.mut_primitivegroup().push(group);
+        block
+    }
+
+    fn diff(
+        old: &[osmformat::PrimitiveBlock],
+        new: &[osmformat::PrimitiveBlock],
+    ) -> Result<Vec<String>> {
+        let old = encode_test_pbf(old);
+        let new = encode_test_pbf(new);
+        let mut events = vec![];
+        diff_elements(
+            ElementReader::new(Cursor::new(old)),
+            ElementReader::new(Cursor::new(new)),
+            |event| {
+                let event = match event {
+                    DiffEvent::Added(e) => format!("+{:?}", element_key(&e)),
+                    DiffEvent::Removed(e) => format!("-{:?}", element_key(&e)),
+                    DiffEvent::Modified(a, b) => {
+                        assert_eq!(element_key(&a), element_key(&b));
+                        format!("~{:?}", element_key(&a))
+                    }
+                };
+                events.push(event);
+            },
+        )?;
+        Ok(events)
+    }
+
+    #[test]
+    fn test_diff_across_block_boundaries() {
+        let old = [
+            block(vec![node(1, 0, 1), node(2, 0, 1), node(3, 0, 1)], vec![]),
+            block(
+                vec![node(5, 0, 1)],
+                vec![way(10, &[1, 2]), way(11, &[2, 3])],
+            ),
+        ];
+        let new = [
+            block(vec![node(1, 0, 1)], vec![]),
+            block(vec![node(3, 7, 1), node(4, 0, 1), node(5, 0, 2)], vec![]),
+            block(
+                vec![],
+                vec![way(10, &[1, 2]), way(11, &[3, 4]), way(12, &[1])],
+            ),
+        ];
+
+        let events = diff(&old, &new).unwrap();
+        assert_eq!(
+            events,
+            vec!["-(0, 2)", "~(0, 3)", "+(0, 4)", "~(0, 5)", "~(1, 11)", "+(1, 12)",]
+        );
+
+        let events = diff(&new, &old).unwrap();
+        assert_eq!(
+            events,
+            vec!["+(0, 2)", "~(0, 3)", "-(0, 4)", "~(0, 5)", "~(1, 11)", "-(1, 12)",]
+        );
+    }
+
+    #[test]
+    fn test_diff_one_block_against_many() {
+        let big = [block((1..=6).map(|id| node(id, 0, 1)).collect(), vec![])];
+        let small: Vec<_> = (1..=6)
+            .filter(|&id| id != 4)
+            .map(|id| block(vec![node(id, 0, 1)], vec![]))
+            .collect();
+
+        assert_eq!(diff(&big, &small).unwrap(), vec!["-(0, 4)"]);
+        assert_eq!(diff(&small, &big).unwrap(), vec!["+(0, 4)"]);
+    }
+
+    #[test]
+    fn test_diff_mixed_node_encodings() {
+        // Neither the plain nor the dense nodes have metadata, so only the coordinates differ.
+        let plain = [block(vec![node_at(1, 0, 0), node_at(2, 5, 0)], vec![])];
+        let dense = [dense_block(&[(1, 0), (2, 7)])];
+
+        assert_eq!(diff(&plain, &dense).unwrap(), vec!["~(0, 2)"]);
+        assert_eq!(diff(&dense, &plain).unwrap(), vec!["~(0, 2)"]);
+    }
+
+    #[test]
+    fn test_diff_empty_file() {
+        let blocks = [block(vec![node(1, 0, 1)], vec![way(10, &[1])])];
+
+        assert_eq!(diff(&blocks, &[]).unwrap(), vec!["-(0, 1)", "-(1, 10)"]);
+        assert_eq!(diff(&[], &blocks).unwrap(), vec!["+(0, 1)", "+(1, 10)"]);
+        assert!(diff(&blocks, &blocks).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_diff_unsorted() {
+        let sorted = [block(vec![node(1, 0, 1), node(2, 0, 1)], vec![])];
+        let unsorted = [
+            block(vec![node(1, 0, 1), node(3, 0, 1)], vec![]),
+            block(vec![node(2, 0, 1)], vec![]),
+        ];
+
+        let err = diff(&sorted, &unsorted).unwrap_err();
+        match *err.kind() {
+            ErrorKind::UnsortedElements { id } => assert_eq!(id, 2),
+            ref kind => panic!("unexpected error kind: {:?}", kind),
+        }
+    }
+}
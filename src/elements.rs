@@ -1,11 +1,15 @@
 //! Nodes, ways and relations
 
-use block::str_from_stringtable;
-use dense::DenseNode;
+use block::{str_from_stringtable, str_lossy_from_stringtable};
+use dense::{DenseNode, DenseRawTagIter, DenseTagIter};
 use error::Result;
+use filter::CompiledKeyAllowlist;
+use owned::{owned_tags, OwnedElement, OwnedNode, OwnedRelMember, OwnedRelation, OwnedWay};
 use proto::osmformat;
 use proto::osmformat::PrimitiveBlock;
 use std;
+use std::borrow::Cow;
+use std::ops::BitOr;
 
 /// An enum with the OSM core elements: nodes, ways and relations.
 #[derive(Clone, Debug)]
@@ -25,6 +29,255 @@ pub enum Element<'a> {
     Relation(Relation<'a>),
 }
 
+impl<'a> Element<'a> {
+    /// Returns the id of the element. Ids are only unique among elements of the same type, so
+    /// e.g. a node and a way can have the same id.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// reader.for_each(|element| {
+    ///     println!("id {} with {} tags", element.id(), element.tags().len());
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn id(&self) -> i64 {
+        match *self {
+            Element::Node(ref node) => node.id(),
+            Element::DenseNode(ref node) => node.id,
+            Element::Way(ref way) => way.id(),
+            Element::Relation(ref relation) => relation.id(),
+        }
+    }
+
+    /// Returns an iterator over the tags of the element, regardless of its type.
+    pub fn tags(&self) -> ElementTagIter<'a> {
+        let inner = match *self {
+            Element::Node(ref node) => ElementTagIterInner::Tags(node.tags()),
+            Element::DenseNode(ref node) => ElementTagIterInner::Dense(node.tags()),
+            Element::Way(ref way) => ElementTagIterInner::Tags(way.tags()),
+            Element::Relation(ref relation) => ElementTagIterInner::Tags(relation.tags()),
+        };
+        ElementTagIter { inner }
+    }
+
+    /// Clears `buf` and fills it with the tags of the element, regardless of its type (see
+    /// `Way::collect_tags_into`).
+    pub fn collect_tags_into(&self, buf: &mut Vec<(&'a str, &'a str)>) {
+        buf.clear();
+        buf.extend(self.tags());
+    }
+
+    /// Returns an iterator over the tags of the element that tolerates strings which are not
+    /// valid UTF-8, regardless of its type (see `Way::tags_lossy`).
+    pub fn tags_lossy(&self) -> LossyTagIter<'a> {
+        match *self {
+            Element::Node(ref node) => node.tags_lossy(),
+            Element::DenseNode(ref node) => node.tags_lossy(),
+            Element::Way(ref way) => way.tags_lossy(),
+            Element::Relation(ref relation) => relation.tags_lossy(),
+        }
+    }
+
+    /// Returns an iterator over the tags of the element whose keys are allowed by `keys`,
+    /// regardless of its type (see `Way::tags_filtered`).
+    pub fn tags_filtered<'k>(&self, keys: &'k CompiledKeyAllowlist) -> FilteredTagIter<'a, 'k> {
+        match *self {
+            Element::Node(ref node) => node.tags_filtered(keys),
+            Element::DenseNode(ref node) => node.tags_filtered(keys),
+            Element::Way(ref way) => way.tags_filtered(keys),
+            Element::Relation(ref relation) => relation.tags_filtered(keys),
+        }
+    }
+
+    /// Returns an owned copy of the element that does not borrow from its block (see
+    /// `Way::to_owned`).
+    ///
+    /// # Errors
+    /// Returns an error if the role of a relation member cannot be resolved.
+    pub fn to_owned(&self) -> Result<OwnedElement> {
+        Ok(match *self {
+            Element::Node(ref node) => OwnedElement::Node(node.to_owned()),
+            Element::DenseNode(ref node) => OwnedElement::Node(node.to_owned()),
+            Element::Way(ref way) => OwnedElement::Way(way.to_owned()),
+            Element::Relation(ref relation) => OwnedElement::Relation(relation.to_owned()?),
+        })
+    }
+}
+
+/// A set of element types that is used to select elements by their type.
+///
+/// Sets can be combined with the `|` operator.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// let types = ElementTypeSet::WAY | ElementTypeSet::RELATION;
+///
+/// assert!(types.contains(ElementTypeSet::WAY));
+/// assert!(!types.contains(ElementTypeSet::NODE));
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ElementTypeSet(u8);
+
+impl ElementTypeSet {
+    /// The empty set.
+    pub const NONE: ElementTypeSet = ElementTypeSet(0);
+    /// Selects `Element::Node`.
+    pub const NODE: ElementTypeSet = ElementTypeSet(1);
+    /// Selects `Element::DenseNode`.
+    pub const DENSE_NODE: ElementTypeSet = ElementTypeSet(1 << 1);
+    /// Selects `Element::Node` and `Element::DenseNode`.
+    pub const NODES: ElementTypeSet = ElementTypeSet(1 | 1 << 1);
+    /// Selects `Element::Way`.
+    pub const WAY: ElementTypeSet = ElementTypeSet(1 << 2);
+    /// Selects `Element::Relation`.
+    pub const RELATION: ElementTypeSet = ElementTypeSet(1 << 3);
+    /// Selects all element types.
+    pub const ALL: ElementTypeSet = ElementTypeSet(0b1111);
+
+    /// Returns true if all types of `other` are contained in this set.
+    pub fn contains(self, other: ElementTypeSet) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if this set contains at least one of the types of `other`.
+    pub fn intersects(self, other: ElementTypeSet) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Returns true if the type of the given element is contained in this set.
+    pub fn contains_element(self, element: &Element) -> bool {
+        let t = match *element {
+            Element::Node(_) => ElementTypeSet::NODE,
+            Element::DenseNode(_) => ElementTypeSet::DENSE_NODE,
+            Element::Way(_) => ElementTypeSet::WAY,
+            Element::Relation(_) => ElementTypeSet::RELATION,
+        };
+        self.contains(t)
+    }
+}
+
+impl BitOr for ElementTypeSet {
+    type Output = ElementTypeSet;
+
+    fn bitor(self, rhs: ElementTypeSet) -> ElementTypeSet {
+        ElementTypeSet(self.0 | rhs.0)
+    }
+}
+
+/// The coordinates of a node.
+///
+/// The coordinates are stored as integers in nanodegrees, so no precision is lost when converting
+/// from the representation in a `PrimitiveBlock`. `Node::coordinate` and `DenseNode::coordinate`
+/// both apply the granularity and offsets of their block.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Coordinate {
+    nano_lat: i64,
+    nano_lon: i64,
+}
+
+impl Coordinate {
+    /// Creates a new `Coordinate` from a latitude and a longitude in nanodegrees (10⁻⁹).
+    pub fn new(nano_lat: i64, nano_lon: i64) -> Coordinate {
+        Coordinate { nano_lat, nano_lon }
+    }
+
+    /// Creates a new `Coordinate` from a latitude and a longitude in degrees.
+    pub fn from_degrees(lat: f64, lon: f64) -> Coordinate {
+        Coordinate {
+            nano_lat: (lat * 1e9).round() as i64,
+            nano_lon: (lon * 1e9).round() as i64,
+        }
+    }
+
+    /// Returns the latitude coordinate in degrees.
+    pub fn lat(&self) -> f64 {
+        1e-9 * self.nano_lat as f64
+    }
+
+    /// Returns the latitude coordinate in nanodegrees (10⁻⁹).
+    pub fn nano_lat(&self) -> i64 {
+        self.nano_lat
+    }
+
+    /// Returns the latitude coordinate in decimicrodegrees (10⁻⁷).
+    pub fn decimicro_lat(&self) -> i32 {
+        (self.nano_lat / 100) as i32
+    }
+
+    /// Returns the longitude coordinate in degrees.
+    pub fn lon(&self) -> f64 {
+        1e-9 * self.nano_lon as f64
+    }
+
+    /// Returns the longitude coordinate in nanodegrees (10⁻⁹).
+    pub fn nano_lon(&self) -> i64 {
+        self.nano_lon
+    }
+
+    /// Returns the longitude coordinate in decimicrodegrees (10⁻⁷).
+    pub fn decimicro_lon(&self) -> i32 {
+        (self.nano_lon / 100) as i32
+    }
+}
+
+/// A rectangular area between a minimum and a maximum coordinate (both inclusive).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct BoundingBox {
+    min: Coordinate,
+    max: Coordinate,
+}
+
+impl BoundingBox {
+    /// Creates a new `BoundingBox` from the minimum latitude/longitude and the maximum
+    /// latitude/longitude. Boxes that cross the antimeridian are not supported.
+    pub fn new(min: Coordinate, max: Coordinate) -> BoundingBox {
+        BoundingBox { min, max }
+    }
+
+    /// Returns the coordinate with the minimum latitude and longitude.
+    pub fn min(&self) -> Coordinate {
+        self.min
+    }
+
+    /// Returns the coordinate with the maximum latitude and longitude.
+    pub fn max(&self) -> Coordinate {
+        self.max
+    }
+
+    /// Returns true if the given coordinate is inside of this bounding box or on its border.
+    pub fn contains(&self, coordinate: Coordinate) -> bool {
+        coordinate.nano_lat >= self.min.nano_lat
+            && coordinate.nano_lat <= self.max.nano_lat
+            && coordinate.nano_lon >= self.min.nano_lon
+            && coordinate.nano_lon <= self.max.nano_lon
+    }
+
+    /// Returns true if this bounding box and the other one have at least one coordinate in
+    /// common.
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.min.nano_lat <= other.max.nano_lat
+            && other.min.nano_lat <= self.max.nano_lat
+            && self.min.nano_lon <= other.max.nano_lon
+            && other.min.nano_lon <= self.max.nano_lon
+    }
+
+    /// Grows the bounding box to contain the given coordinate.
+    pub(crate) fn extend(&mut self, coordinate: Coordinate) {
+        self.min.nano_lat = self.min.nano_lat.min(coordinate.nano_lat);
+        self.min.nano_lon = self.min.nano_lon.min(coordinate.nano_lon);
+        self.max.nano_lat = self.max.nano_lat.max(coordinate.nano_lat);
+        self.max.nano_lon = self.max.nano_lon.max(coordinate.nano_lon);
+    }
+}
+
 /// An OpenStreetMap node element (See [OSM wiki](http://wiki.openstreetmap.org/wiki/Node)).
 #[derive(Clone, Debug)]
 pub struct Node<'a> {
@@ -74,11 +327,43 @@ impl<'a> Node<'a> {
         }
     }
 
+    /// Clears `buf` and fills it with the tags of this node. Same as `Way::collect_tags_into`.
+    pub fn collect_tags_into(&self, buf: &mut Vec<(&'a str, &'a str)>) {
+        buf.clear();
+        buf.extend(self.tags());
+    }
+
+    /// Returns an iterator over the tags of this node that tolerates strings which are not
+    /// valid UTF-8. Same as `Way::tags_lossy`.
+    pub fn tags_lossy(&self) -> LossyTagIter<'a> {
+        LossyTagIter::new(self.block, self.raw_tags())
+    }
+
+    /// Returns an iterator over the tags of this node whose keys are allowed by `keys`. Same as
+    /// `Way::tags_filtered`.
+    pub fn tags_filtered<'k>(&self, keys: &'k CompiledKeyAllowlist) -> FilteredTagIter<'a, 'k> {
+        FilteredTagIter::new(self.block, self.raw_tags(), keys)
+    }
+
     /// Returns additional metadata for this element.
     pub fn info(&self) -> Info<'a> {
         Info::new(self.block, self.osmnode.get_info())
     }
 
+    /// Returns an owned copy of this node that does not borrow from the block.
+    pub fn to_owned(&self) -> OwnedNode {
+        OwnedNode {
+            id: self.id(),
+            coordinate: self.coordinate(),
+            tags: owned_tags(self.tags()),
+        }
+    }
+
+    /// Returns the coordinates of this node.
+    pub fn coordinate(&self) -> Coordinate {
+        Coordinate::new(self.nano_lat(), self.nano_lon())
+    }
+
     /// Returns the latitude coordinate in degrees.
     pub fn lat(&self) -> f64 {
         1e-9 * self.nano_lat() as f64
@@ -154,7 +439,9 @@ impl<'a> Way<'a> {
 
     /// Returns an iterator over the tags of this way
     /// (See [OSM wiki](http://wiki.openstreetmap.org/wiki/Tags)).
-    /// A tag is represented as a pair of strings (key and value).
+    /// A tag is represented as a pair of strings (key and value). The iteration stops at the
+    /// first tag with a string that is not valid UTF-8, use `tags_lossy` to read all tags of
+    /// malformed files.
     ///
     /// # Example
     /// ```
@@ -183,11 +470,116 @@ impl<'a> Way<'a> {
         }
     }
 
+    /// Clears `buf` and fills it with the tags of this way. Unlike collecting `tags` into a new
+    /// `Vec`, this allows reusing the same buffer for many elements without allocating each time.
+    /// The strings borrow from the `PrimitiveBlock`, so the buffer can only be reused within the
+    /// same block, e.g. for all ways of a `PrimitiveGroup`.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         let mut tags = vec![];
+    ///         for way in block.groups().flat_map(|group| group.ways()) {
+    ///             way.collect_tags_into(&mut tags);
+    ///             println!("way {}: {:?}", way.id(), tags);
+    /// #           assert!(tags.contains(&("building", "yes")));
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn collect_tags_into(&self, buf: &mut Vec<(&'a str, &'a str)>) {
+        buf.clear();
+        buf.extend(self.tags());
+    }
+
+    /// Returns an iterator over the tags of this way like `tags`, but strings that are not valid
+    /// UTF-8 are decoded with `String::from_utf8_lossy`, which replaces invalid sequences with
+    /// `U+FFFD REPLACEMENT CHARACTER`. Valid strings are borrowed from the block, so this only
+    /// allocates for invalid ones.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// reader.for_each(|element| {
+    ///     if let Element::Way(way) = element {
+    ///         for (key, value) in way.tags_lossy() {
+    ///             println!("key: {}, value: {}", key, value);
+    ///         }
+    ///     }
+    /// })?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn tags_lossy(&self) -> LossyTagIter<'a> {
+        LossyTagIter::new(self.block, self.raw_tags())
+    }
+
+    /// Returns an iterator over the tags of this way whose keys are allowed by `keys`, a
+    /// `KeyAllowlist` that is compiled for the block of this way. The other tags are skipped
+    /// by comparing stringtable indices, so their strings are never resolved.
+    pub fn tags_filtered<'k>(&self, keys: &'k CompiledKeyAllowlist) -> FilteredTagIter<'a, 'k> {
+        FilteredTagIter::new(self.block, self.raw_tags(), keys)
+    }
+
     /// Returns additional metadata for this element.
     pub fn info(&self) -> Info<'a> {
         Info::new(self.block, self.osmway.get_info())
     }
 
+    /// Returns an owned copy of this way that does not borrow from the block. All tags are
+    /// resolved to owned strings and all references are decoded, so the copy can be kept after
+    /// the block is dropped or sent to another thread.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut buildings = vec![];
+    ///
+    /// reader.read_ways_and_deps(
+    ///     |way| way.tags().any(|key_value| key_value == ("building", "yes")),
+    ///     |element| {
+    ///         if let Element::Way(way) = element {
+    ///             buildings.push(way.to_owned());
+    ///         }
+    ///     },
+    /// )?;
+    /// drop(reader);
+    ///
+    /// for way in &buildings {
+    ///     println!("way {} with {} nodes", way.id, way.refs.len());
+    /// }
+    /// # assert_eq!(buildings.len(), 1);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn to_owned(&self) -> OwnedWay {
+        OwnedWay {
+            id: self.id(),
+            refs: self.refs().collect(),
+            tags: owned_tags(self.tags()),
+        }
+    }
+
     /// Returns an iterator over the references of this way. Each reference should correspond to a
     /// node id.
     pub fn refs(&self) -> WayRefIter<'a> {
@@ -197,8 +589,46 @@ impl<'a> Way<'a> {
         }
     }
 
-    /// Returns a slice of delta coded node ids.
-    pub fn raw_refs(&self) -> &[i64] {
+    /// Returns the number of node references of this way without decoding them.
+    pub fn node_count(&self) -> usize {
+        self.osmway.get_refs().len()
+    }
+
+    /// Returns true if the first and the last node reference of this way are equal, e.g. for
+    /// areas. Ways with less than two references are not closed. The last reference is the
+    /// first one plus the sum of the remaining deltas, so this does not allocate.
+    pub fn is_closed(&self) -> bool {
+        let deltas = self.osmway.get_refs();
+        deltas.len() >= 2 && deltas[1..].iter().sum::<i64>() == 0
+    }
+
+    /// Returns a slice of delta coded node ids. The slice is not decoded: the first entry is a
+    /// node id and every following entry is the difference to the previous node id, so consumers
+    /// are responsible for computing the running sum themselves (`refs` does this). This allows
+    /// decoding the references with custom, e.g. vectorized, code.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// reader.for_each(|element| {
+    ///     if let Element::Way(way) = element {
+    ///         let mut node_ids = way.raw_refs().to_vec();
+    ///         for i in 1..node_ids.len() {
+    ///             node_ids[i] += node_ids[i - 1];
+    ///         }
+    ///         assert!(node_ids.into_iter().eq(way.refs()));
+    ///     }
+    /// })?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn raw_refs(&self) -> &'a [i64] {
         self.osmway.get_refs()
     }
 
@@ -273,6 +703,24 @@ impl<'a> Relation<'a> {
         }
     }
 
+    /// Clears `buf` and fills it with the tags of this relation. Same as `Way::collect_tags_into`.
+    pub fn collect_tags_into(&self, buf: &mut Vec<(&'a str, &'a str)>) {
+        buf.clear();
+        buf.extend(self.tags());
+    }
+
+    /// Returns an iterator over the tags of this relation that tolerates strings which are not
+    /// valid UTF-8. Same as `Way::tags_lossy`.
+    pub fn tags_lossy(&self) -> LossyTagIter<'a> {
+        LossyTagIter::new(self.block, self.raw_tags())
+    }
+
+    /// Returns an iterator over the tags of this relation whose keys are allowed by `keys`. Same
+    /// as `Way::tags_filtered`.
+    pub fn tags_filtered<'k>(&self, keys: &'k CompiledKeyAllowlist) -> FilteredTagIter<'a, 'k> {
+        FilteredTagIter::new(self.block, self.raw_tags(), keys)
+    }
+
     /// Returns additional metadata for this element.
     pub fn info(&self) -> Info<'a> {
         Info::new(self.block, self.osmrel.get_info())
@@ -283,6 +731,29 @@ impl<'a> Relation<'a> {
         RelMemberIter::new(self.block, self.osmrel)
     }
 
+    /// Returns an owned copy of this relation that does not borrow from the block (see
+    /// `Way::to_owned`).
+    ///
+    /// # Errors
+    /// Returns an error if the role of a member cannot be resolved.
+    pub fn to_owned(&self) -> Result<OwnedRelation> {
+        let members = self
+            .members()
+            .map(|member| {
+                Ok(OwnedRelMember {
+                    member_type: member.member_type(),
+                    member_id: member.member_id(),
+                    role: member.role()?.to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(OwnedRelation {
+            id: self.id(),
+            members,
+            tags: owned_tags(self.tags()),
+        })
+    }
+
     /// Returns an iterator over the tags of this relation
     /// (See [OSM wiki](http://wiki.openstreetmap.org/wiki/Tags)).
     /// A tag is represented as a pair of indices (key and value) to the stringtable of the current
@@ -304,6 +775,28 @@ impl<'a> Relation<'a> {
     }
 }
 
+/// An OpenStreetMap changeset (See
+/// [OSM wiki](http://wiki.openstreetmap.org/wiki/Changeset)).
+///
+/// Changesets can be stored in groups of their own (see `PrimitiveGroup::changesets`). The PBF
+/// format only defines the id of a changeset so far: the fields for tags, metadata and the
+/// bounding box are reserved in the format specification, but not used by any writer.
+#[derive(Clone, Debug)]
+pub struct Changeset<'a> {
+    changeset: &'a osmformat::ChangeSet,
+}
+
+impl<'a> Changeset<'a> {
+    pub(crate) fn new(changeset: &'a osmformat::ChangeSet) -> Changeset<'a> {
+        Changeset { changeset }
+    }
+
+    /// Returns the changeset id.
+    pub fn id(&self) -> i64 {
+        self.changeset.get_id()
+    }
+}
+
 /// An iterator over the references of a way.
 ///
 /// Each reference corresponds to a node id.
@@ -334,7 +827,7 @@ impl<'a> Iterator for WayRefIter<'a> {
 impl<'a> ExactSizeIterator for WayRefIter<'a> {}
 
 /// The element type of a relation member.
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum RelMemberType {
     Node,
     Way,
@@ -368,6 +861,23 @@ impl<'a> RelMember<'a> {
     pub fn role(&self) -> Result<&'a str> {
         str_from_stringtable(self.block, self.role_sid as usize)
     }
+
+    /// Returns the role of a relation member like `role`, but a role that is not valid UTF-8 is
+    /// decoded with `String::from_utf8_lossy` instead of returning an error.
+    pub fn role_lossy(&self) -> Result<Cow<'a, str>> {
+        str_lossy_from_stringtable(self.block, self.role_sid as usize)
+    }
+
+    /// Returns the element type of the referenced member.
+    pub fn member_type(&self) -> RelMemberType {
+        self.member_type
+    }
+
+    /// Returns the id of the referenced member. The ids are delta encoded in the PBF file and
+    /// already decoded by `RelMemberIter`.
+    pub fn member_id(&self) -> i64 {
+        self.member_id
+    }
 }
 
 /// An iterator over the members of a relation.
@@ -455,6 +965,171 @@ impl<'a> Iterator for TagIter<'a> {
 
 impl<'a> ExactSizeIterator for TagIter<'a> {}
 
+#[derive(Clone, Debug)]
+enum ElementTagIterInner<'a> {
+    Tags(TagIter<'a>),
+    Dense(DenseTagIter<'a>),
+}
+
+/// An iterator over the tags of an `Element` of any type (see `Element::tags`). It returns a pair
+/// of strings (key and value).
+#[derive(Clone, Debug)]
+pub struct ElementTagIter<'a> {
+    inner: ElementTagIterInner<'a>,
+}
+
+impl<'a> Iterator for ElementTagIter<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner {
+            ElementTagIterInner::Tags(ref mut iter) => iter.next(),
+            ElementTagIterInner::Dense(ref mut iter) => iter.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.inner {
+            ElementTagIterInner::Tags(ref iter) => iter.size_hint(),
+            ElementTagIterInner::Dense(ref iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl<'a> ExactSizeIterator for ElementTagIter<'a> {}
+
+/// The raw tags of a node, way or relation, or of a dense node.
+#[derive(Clone, Debug)]
+enum AnyRawTagIter<'a> {
+    Tags(RawTagIter<'a>),
+    Dense(DenseRawTagIter<'a>),
+}
+
+impl<'a> Iterator for AnyRawTagIter<'a> {
+    type Item = (u32, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match *self {
+            AnyRawTagIter::Tags(ref mut iter) => iter.next(),
+            AnyRawTagIter::Dense(ref mut iter) => {
+                let (key_index, val_index) = iter.next()?;
+                Some((key_index as u32, val_index as u32))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match *self {
+            AnyRawTagIter::Tags(ref iter) => iter.size_hint(),
+            AnyRawTagIter::Dense(ref iter) => iter.size_hint(),
+        }
+    }
+}
+
+/// An iterator over the tags of an element whose keys are contained in a `KeyAllowlist` (see
+/// `Way::tags_filtered`). It returns a pair of strings (key and value).
+#[derive(Clone, Debug)]
+pub struct FilteredTagIter<'a, 'k> {
+    block: &'a PrimitiveBlock,
+    inner: AnyRawTagIter<'a>,
+    keys: &'k CompiledKeyAllowlist,
+}
+
+impl<'a, 'k> FilteredTagIter<'a, 'k> {
+    pub(crate) fn new(
+        block: &'a PrimitiveBlock,
+        raw_tags: RawTagIter<'a>,
+        keys: &'k CompiledKeyAllowlist,
+    ) -> FilteredTagIter<'a, 'k> {
+        FilteredTagIter {
+            block,
+            inner: AnyRawTagIter::Tags(raw_tags),
+            keys,
+        }
+    }
+
+    pub(crate) fn new_dense(
+        block: &'a PrimitiveBlock,
+        raw_tags: DenseRawTagIter<'a>,
+        keys: &'k CompiledKeyAllowlist,
+    ) -> FilteredTagIter<'a, 'k> {
+        FilteredTagIter {
+            block,
+            inner: AnyRawTagIter::Dense(raw_tags),
+            keys,
+        }
+    }
+}
+
+impl<'a, 'k> Iterator for FilteredTagIter<'a, 'k> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (key_index, val_index) = self.inner.next()?;
+            if self.keys.contains_index(key_index) {
+                let k_res = str_from_stringtable(self.block, key_index as usize);
+                let v_res = str_from_stringtable(self.block, val_index as usize);
+                return match (k_res, v_res) {
+                    (Ok(k), Ok(v)) => Some((k, v)),
+                    _ => None,
+                };
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
+
+/// An iterator over the tags of an element that tolerates strings which are not valid UTF-8
+/// (see `Way::tags_lossy`). It returns a pair of strings (key and value).
+#[derive(Clone, Debug)]
+pub struct LossyTagIter<'a> {
+    block: &'a PrimitiveBlock,
+    inner: AnyRawTagIter<'a>,
+}
+
+impl<'a> LossyTagIter<'a> {
+    pub(crate) fn new(block: &'a PrimitiveBlock, raw_tags: RawTagIter<'a>) -> LossyTagIter<'a> {
+        LossyTagIter {
+            block,
+            inner: AnyRawTagIter::Tags(raw_tags),
+        }
+    }
+
+    pub(crate) fn new_dense(
+        block: &'a PrimitiveBlock,
+        raw_tags: DenseRawTagIter<'a>,
+    ) -> LossyTagIter<'a> {
+        LossyTagIter {
+            block,
+            inner: AnyRawTagIter::Dense(raw_tags),
+        }
+    }
+}
+
+impl<'a> Iterator for LossyTagIter<'a> {
+    type Item = (Cow<'a, str>, Cow<'a, str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key_index, val_index) = self.inner.next()?;
+        let k_res = str_lossy_from_stringtable(self.block, key_index as usize);
+        let v_res = str_lossy_from_stringtable(self.block, val_index as usize);
+        match (k_res, v_res) {
+            (Ok(k), Ok(v)) => Some((k, v)),
+            _ => None,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for LossyTagIter<'a> {}
+
 /// An iterator over the tags of an element. It returns a pair of indices (key and value) to the
 /// stringtable of the current `PrimitiveBlock`.
 #[derive(Clone, Debug)]
@@ -512,6 +1187,12 @@ impl<'a> Info<'a> {
         }
     }
 
+    /// Returns the time stamp in seconds since the epoch. This is derived from
+    /// `milli_timestamp` by rounding down to full seconds.
+    pub fn timestamp_secs(&self) -> Option<i64> {
+        self.milli_timestamp().map(|t| t.div_euclid(1000))
+    }
+
     /// Returns the changeset id.
     pub fn changeset(&self) -> Option<i64> {
         if self.info.has_changeset() {
@@ -5,6 +5,7 @@ use std::result;
 use std::str;
 use std::str::Utf8Error;
 
+use blob::{ByteOffset, Compression};
 use protobuf::ProtobufError;
 
 // Error data structures are modeled just like in the `csv` crate by BurntSushi.
@@ -17,6 +18,10 @@ pub(crate) fn new_blob_error(kind: BlobError) -> Error {
     Error(Box::new(ErrorKind::Blob(kind)))
 }
 
+pub(crate) fn new_unexpected_eof_error(offset: Option<ByteOffset>) -> Error {
+    Error(Box::new(ErrorKind::UnexpectedEof { offset }))
+}
+
 pub(crate) fn new_protobuf_error(err: ProtobufError, location: &'static str) -> Error {
     Error(Box::new(ErrorKind::Protobuf { err, location }))
 }
@@ -45,7 +50,11 @@ impl Error {
 pub enum ErrorKind {
     /// An error for I/O operations.
     Io(io::Error),
-    /// An error that occurs when decoding a protobuf message.
+    /// The stream ends in the middle of a blob, e.g. because the file is truncated. `offset` is
+    /// the byte offset from the stream start where the data ended, if the reader knows its
+    /// position.
+    UnexpectedEof { offset: Option<ByteOffset> },
+    /// An error that occurs when decoding a protobuf message, e.g. because the data is corrupt.
     Protobuf {
         err: ProtobufError,
         location: &'static str,
@@ -57,6 +66,21 @@ pub enum ErrorKind {
     StringtableIndexOutOfBounds { index: usize },
     /// An error that occurs when decoding `Blob`s.
     Blob(BlobError),
+    /// The `keys_vals` array of a group of dense nodes is malformed: a key is missing its value,
+    /// a node is missing its terminating 0 or the array has more entries than nodes. `node_index`
+    /// is the index of the first affected node in its group.
+    InvalidDenseKeysVals { node_index: usize },
+    /// The elements of a file are not sorted by type and id, although the operation requires
+    /// it. `id` is the id of the first element that is out of order.
+    UnsortedElements { id: i64 },
+    /// The `HeaderBlock` of a file declares a required feature that this crate does not support
+    /// (see `SUPPORTED_FEATURES`).
+    UnsupportedFeature { feature: String },
+    /// A saved index could not be loaded, because it is malformed or was created for a
+    /// different file or a different version of the same file. `reason` describes the problem.
+    InvalidIndex { reason: &'static str },
+    /// The file does not start with a `HeaderBlock`, e.g. because it is empty.
+    MissingHeaderBlock,
 
     //TODO add UnexpectedPrimitiveBlock
     /// Hints that destructuring should not be exhaustive.
@@ -83,8 +107,25 @@ pub enum BlobError {
         /// Blob content size in bytes.
         size: u64,
     },
+    /// The size of a blob as declared in its header is negative.
+    InvalidBlobSize {
+        /// Declared blob size in bytes.
+        size: i32,
+    },
     /// The blob is empty because the `raw` and `zlib-data` fields are missing.
     Empty,
+    /// The blob content is compressed with a method that is not supported, either at all or
+    /// because the corresponding crate feature (e.g. `zstd`) is disabled.
+    UnsupportedCompression {
+        /// The compression method of the blob.
+        compression: Compression,
+    },
+    /// The type of a blob is neither "OSMHeader" nor "OSMData". This is only reported by readers
+    /// that check blob types (see `BlobReaderBuilder::check_blob_types`).
+    UnknownBlobType {
+        /// The type string of the blob header.
+        blob_type: String,
+    },
     /// Hints that destructuring should not be exhaustive.
     #[doc(hidden)]
     __Nonexhaustive,
@@ -106,6 +147,7 @@ impl StdError for Error {
     fn description(&self) -> &str {
         match *self.0 {
             ErrorKind::Io(ref err) => err.description(),
+            ErrorKind::UnexpectedEof { .. } => "unexpected end of file",
             ErrorKind::Protobuf { ref err, .. } => err.description(),
             ErrorKind::StringtableUtf8 { ref err, .. } => err.description(),
             ErrorKind::StringtableIndexOutOfBounds { .. } => "stringtable index out of bounds",
@@ -114,7 +156,17 @@ impl StdError for Error {
             }
             ErrorKind::Blob(BlobError::HeaderTooBig { .. }) => "blob header is too big",
             ErrorKind::Blob(BlobError::MessageTooBig { .. }) => "blob message is too big",
+            ErrorKind::Blob(BlobError::InvalidBlobSize { .. }) => "blob size is invalid",
             ErrorKind::Blob(BlobError::Empty) => "blob is missing fields 'raw' and 'zlib_data",
+            ErrorKind::Blob(BlobError::UnsupportedCompression { .. }) => {
+                "blob compression method is not supported"
+            }
+            ErrorKind::Blob(BlobError::UnknownBlobType { .. }) => "blob type is unknown",
+            ErrorKind::InvalidDenseKeysVals { .. } => "malformed keys_vals array of dense nodes",
+            ErrorKind::UnsortedElements { .. } => "elements are not sorted by type and id",
+            ErrorKind::UnsupportedFeature { .. } => "unsupported required feature",
+            ErrorKind::InvalidIndex { .. } => "invalid index",
+            ErrorKind::MissingHeaderBlock => "file does not start with a header block",
             _ => unreachable!(),
         }
     }
@@ -122,13 +174,22 @@ impl StdError for Error {
     fn cause(&self) -> Option<&dyn StdError> {
         match *self.0 {
             ErrorKind::Io(ref err) => Some(err),
+            ErrorKind::UnexpectedEof { .. } => None,
             ErrorKind::Protobuf { ref err, .. } => Some(err),
             ErrorKind::StringtableUtf8 { ref err, .. } => Some(err),
             ErrorKind::StringtableIndexOutOfBounds { .. } => None,
             ErrorKind::Blob(BlobError::InvalidHeaderSize) => None,
             ErrorKind::Blob(BlobError::HeaderTooBig { .. }) => None,
             ErrorKind::Blob(BlobError::MessageTooBig { .. }) => None,
+            ErrorKind::Blob(BlobError::InvalidBlobSize { .. }) => None,
             ErrorKind::Blob(BlobError::Empty) => None,
+            ErrorKind::Blob(BlobError::UnsupportedCompression { .. }) => None,
+            ErrorKind::Blob(BlobError::UnknownBlobType { .. }) => None,
+            ErrorKind::InvalidDenseKeysVals { .. } => None,
+            ErrorKind::UnsortedElements { .. } => None,
+            ErrorKind::UnsupportedFeature { .. } => None,
+            ErrorKind::InvalidIndex { .. } => None,
+            ErrorKind::MissingHeaderBlock => None,
             _ => unreachable!(),
         }
     }
@@ -138,6 +199,10 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self.0 {
             ErrorKind::Io(ref err) => err.fmt(f),
+            ErrorKind::UnexpectedEof {
+                offset: Some(offset),
+            } => write!(f, "unexpected end of file at byte offset {}", offset),
+            ErrorKind::UnexpectedEof { offset: None } => write!(f, "unexpected end of file"),
             ErrorKind::Protobuf { ref err, location } => {
                 write!(f, "protobuf error at '{}': {}", location, err)
             }
@@ -156,9 +221,35 @@ impl fmt::Display for Error {
             ErrorKind::Blob(BlobError::MessageTooBig { size }) => {
                 write!(f, "blob message is too big: {} bytes", size)
             }
+            ErrorKind::Blob(BlobError::InvalidBlobSize { size }) => {
+                write!(f, "blob size is invalid: {} bytes", size)
+            }
             ErrorKind::Blob(BlobError::Empty) => {
                 write!(f, "blob is missing fields 'raw' and 'zlib_data'")
             }
+            ErrorKind::Blob(BlobError::UnsupportedCompression { compression }) => {
+                write!(
+                    f,
+                    "blob compression method is not supported: {:?}",
+                    compression
+                )
+            }
+            ErrorKind::Blob(BlobError::UnknownBlobType { ref blob_type }) => {
+                write!(f, "blob type is unknown: {}", blob_type)
+            }
+            ErrorKind::InvalidDenseKeysVals { node_index } => write!(
+                f,
+                "malformed keys_vals array of dense nodes at node index {}",
+                node_index
+            ),
+            ErrorKind::UnsortedElements { id } => {
+                write!(f, "elements are not sorted by type and id at id {}", id)
+            }
+            ErrorKind::UnsupportedFeature { ref feature } => {
+                write!(f, "unsupported required feature: {}", feature)
+            }
+            ErrorKind::InvalidIndex { reason } => write!(f, "invalid index: {}", reason),
+            ErrorKind::MissingHeaderBlock => write!(f, "file does not start with a header block"),
             _ => unreachable!(),
         }
     }
@@ -0,0 +1,339 @@
+//! Filter elements by their tags
+
+use block::PrimitiveBlock;
+use dense::DenseNode;
+use elements::{Element, Node, Relation, Way};
+use std::collections::HashMap;
+
+/// A filter that matches elements with at least one of the given tags.
+///
+/// Elements in a `PrimitiveBlock` only store indices to the stringtable of the block. Instead of
+/// resolving and comparing strings for every element, a `TagFilter` is compiled once per block
+/// into stringtable indices (see `compile`), so that matching an element only compares integers.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// // Match buildings and all elements with an "amenity" tag.
+/// let filter = TagFilter::new()
+///     .key_value("building", "yes")
+///     .key("amenity");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TagFilter {
+    conditions: Vec<(String, Option<String>)>,
+}
+
+impl TagFilter {
+    /// Creates a new `TagFilter` that does not match any element.
+    pub fn new() -> TagFilter {
+        TagFilter { conditions: vec![] }
+    }
+
+    /// Also match elements that have a tag with the given key and any value.
+    pub fn key<K: Into<String>>(mut self, key: K) -> TagFilter {
+        self.conditions.push((key.into(), None));
+        self
+    }
+
+    /// Also match elements that have a tag with the given key and value.
+    pub fn key_value<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> TagFilter {
+        self.conditions.push((key.into(), Some(value.into())));
+        self
+    }
+
+    /// Translates the filter to the stringtable indices of the given block. The result can only be
+    /// used for elements of this block.
+    pub fn compile(&self, block: &PrimitiveBlock) -> CompiledTagFilter {
+        let mut indices: HashMap<&[u8], Vec<u32>> = HashMap::new();
+        for (key, value) in &self.conditions {
+            indices.insert(key.as_bytes(), vec![]);
+            if let Some(value) = value {
+                indices.insert(value.as_bytes(), vec![]);
+            }
+        }
+
+        // Index 0 is reserved as a delimiter. A string may occur more than once in the
+        // stringtable, so all of its indices are kept.
+        for (i, s) in block.raw_stringtable().iter().enumerate().skip(1) {
+            if let Some(index) = indices.get_mut(s.as_slice()) {
+                index.push(i as u32);
+            }
+        }
+
+        let mut conditions = vec![];
+        for (key, value) in &self.conditions {
+            for &key_index in &indices[key.as_bytes()] {
+                match value {
+                    None => conditions.push((key_index, None)),
+                    Some(value) => {
+                        for &value_index in &indices[value.as_bytes()] {
+                            conditions.push((key_index, Some(value_index)));
+                        }
+                    }
+                }
+            }
+        }
+
+        CompiledTagFilter { conditions }
+    }
+}
+
+/// A `TagFilter` that is translated to the stringtable indices of a specific `PrimitiveBlock`.
+#[derive(Clone, Debug)]
+pub struct CompiledTagFilter {
+    conditions: Vec<(u32, Option<u32>)>,
+}
+
+impl CompiledTagFilter {
+    /// Returns true if no element of the block can match, because none of the searched tags occur
+    /// in the stringtable of the block.
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    /// Returns true if one of the given tags matches. The tags are pairs of stringtable indices
+    /// (key and value) as returned by the `raw_tags` methods of the elements.
+    pub fn matches_raw_tags<I: IntoIterator<Item = (u32, u32)>>(&self, raw_tags: I) -> bool {
+        if self.conditions.is_empty() {
+            return false;
+        }
+        raw_tags.into_iter().any(|(key, value)| {
+            self.conditions.iter().any(|&(k, v)| match v {
+                Some(v) => k == key && v == value,
+                None => k == key,
+            })
+        })
+    }
+}
+
+/// A list of tag keys that selects which tags the `tags_filtered` methods of the elements
+/// return, e.g. to keep only the tags that are needed downstream.
+///
+/// Like a `TagFilter`, it is compiled once per block into stringtable indices (see `compile`),
+/// so the tags of each element are selected by comparing integers and the strings of all other
+/// tags are never resolved.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let allowlist = KeyAllowlist::new(&["building", "name"]);
+/// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+///
+/// for blob in reader {
+///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+///         let keys = allowlist.compile(&block);
+///         for way in block.groups().flat_map(|group| group.ways()) {
+///             for (key, value) in way.tags_filtered(&keys) {
+///                 println!("way {}: {}={}", way.id(), key, value);
+///             }
+/// #           assert!(way.tags_filtered(&keys).any(|tag| tag == ("building", "yes")));
+///         }
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct KeyAllowlist {
+    keys: Vec<String>,
+}
+
+impl KeyAllowlist {
+    /// Creates a new `KeyAllowlist` that keeps the tags with the given keys.
+    pub fn new<K: AsRef<str>>(keys: &[K]) -> KeyAllowlist {
+        KeyAllowlist {
+            keys: keys.iter().map(|key| key.as_ref().to_string()).collect(),
+        }
+    }
+
+    /// Also keep tags with the given key.
+    pub fn key<K: Into<String>>(mut self, key: K) -> KeyAllowlist {
+        self.keys.push(key.into());
+        self
+    }
+
+    /// Translates the allowlist to the stringtable indices of the given block. The result can
+    /// only be used for elements of this block.
+    pub fn compile(&self, block: &PrimitiveBlock) -> CompiledKeyAllowlist {
+        // Index 0 is reserved as a delimiter and never a key.
+        let mut indices: Vec<u32> = block
+            .raw_stringtable()
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter(|&(_, s)| self.keys.iter().any(|key| key.as_bytes() == s.as_slice()))
+            .map(|(i, _)| i as u32)
+            .collect();
+        indices.sort_unstable();
+        CompiledKeyAllowlist { indices }
+    }
+}
+
+/// A `KeyAllowlist` that is translated to the stringtable indices of a specific `PrimitiveBlock`.
+#[derive(Clone, Debug)]
+pub struct CompiledKeyAllowlist {
+    /// Sorted stringtable indices of the allowed keys.
+    indices: Vec<u32>,
+}
+
+impl CompiledKeyAllowlist {
+    /// Returns true if no tag of the block is allowed, because none of the keys occur in the
+    /// stringtable of the block.
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Returns true if the key with the given stringtable index is allowed.
+    pub fn contains_index(&self, key_index: u32) -> bool {
+        self.indices.binary_search(&key_index).is_ok()
+    }
+}
+
+/// Elements that have tags. This allows evaluating a `TagPredicate` on nodes, dense nodes, ways,
+/// relations and `Element`s alike.
+pub trait Tagged {
+    /// Returns true if the closure returns true for at least one tag (key and value).
+    fn any_tag<F: FnMut(&str, &str) -> bool>(&self, f: F) -> bool;
+}
+
+impl<'a> Tagged for Node<'a> {
+    fn any_tag<F: FnMut(&str, &str) -> bool>(&self, mut f: F) -> bool {
+        self.tags().any(|(key, value)| f(key, value))
+    }
+}
+
+impl<'a> Tagged for DenseNode<'a> {
+    fn any_tag<F: FnMut(&str, &str) -> bool>(&self, mut f: F) -> bool {
+        self.tags().any(|(key, value)| f(key, value))
+    }
+}
+
+impl<'a> Tagged for Way<'a> {
+    fn any_tag<F: FnMut(&str, &str) -> bool>(&self, mut f: F) -> bool {
+        self.tags().any(|(key, value)| f(key, value))
+    }
+}
+
+impl<'a> Tagged for Relation<'a> {
+    fn any_tag<F: FnMut(&str, &str) -> bool>(&self, mut f: F) -> bool {
+        self.tags().any(|(key, value)| f(key, value))
+    }
+}
+
+impl<'a> Tagged for Element<'a> {
+    fn any_tag<F: FnMut(&str, &str) -> bool>(&self, f: F) -> bool {
+        match *self {
+            Element::Node(ref node) => node.any_tag(f),
+            Element::DenseNode(ref node) => node.any_tag(f),
+            Element::Way(ref way) => way.any_tag(f),
+            Element::Relation(ref relation) => relation.any_tag(f),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Predicate {
+    HasKey(String),
+    KeyValue(String, String),
+    AnyOf(Vec<TagPredicate>),
+    AllOf(Vec<TagPredicate>),
+}
+
+/// A composable condition on the tags of an element. It is created with the functions `has_key`,
+/// `key_value`, `any_of` and `all_of`.
+///
+/// In contrast to `TagFilter`, a `TagPredicate` compares strings and can express conjunctions.
+/// The methods `ways`, `relations` and `elements` turn it into closures that can be passed as
+/// filters to the methods of `IndexedReader`.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+///
+/// // Buildings that are also shops, and all amenities
+/// let predicate = any_of(vec![
+///     all_of(vec![key_value("building", "yes"), has_key("shop")]),
+///     has_key("amenity"),
+/// ]);
+///
+/// reader.read_ways_and_deps(predicate.ways(), |element| {
+///     println!("{:?}", element);
+/// })?;
+///
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct TagPredicate {
+    predicate: Predicate,
+}
+
+/// Matches elements that have a tag with the given key and any value.
+pub fn has_key<K: Into<String>>(key: K) -> TagPredicate {
+    TagPredicate {
+        predicate: Predicate::HasKey(key.into()),
+    }
+}
+
+/// Matches elements that have a tag with the given key and value.
+pub fn key_value<K: Into<String>, V: Into<String>>(key: K, value: V) -> TagPredicate {
+    TagPredicate {
+        predicate: Predicate::KeyValue(key.into(), value.into()),
+    }
+}
+
+/// Matches elements that match at least one of the given predicates. Matches nothing if
+/// `predicates` is empty.
+pub fn any_of<I: IntoIterator<Item = TagPredicate>>(predicates: I) -> TagPredicate {
+    TagPredicate {
+        predicate: Predicate::AnyOf(predicates.into_iter().collect()),
+    }
+}
+
+/// Matches elements that match all of the given predicates. Matches everything if `predicates`
+/// is empty.
+pub fn all_of<I: IntoIterator<Item = TagPredicate>>(predicates: I) -> TagPredicate {
+    TagPredicate {
+        predicate: Predicate::AllOf(predicates.into_iter().collect()),
+    }
+}
+
+impl TagPredicate {
+    /// Returns true if the tags of the given element satisfy this predicate.
+    pub fn matches<T: Tagged>(&self, element: &T) -> bool {
+        match self.predicate {
+            Predicate::HasKey(ref key) => element.any_tag(|k, _| k == key),
+            Predicate::KeyValue(ref key, ref value) => {
+                element.any_tag(|k, v| k == key && v == value)
+            }
+            Predicate::AnyOf(ref predicates) => predicates.iter().any(|p| p.matches(element)),
+            Predicate::AllOf(ref predicates) => predicates.iter().all(|p| p.matches(element)),
+        }
+    }
+
+    /// Returns a closure that matches ways, e.g. for `IndexedReader::read_ways_and_deps`.
+    pub fn ways(self) -> impl for<'a> Fn(&Way<'a>) -> bool {
+        move |way: &Way| self.matches(way)
+    }
+
+    /// Returns a closure that matches relations, e.g. for
+    /// `IndexedReader::read_relations_and_deps`.
+    pub fn relations(self) -> impl for<'a> Fn(&Relation<'a>) -> bool {
+        move |relation: &Relation| self.matches(relation)
+    }
+
+    /// Returns a closure that matches elements of any type, e.g. for
+    /// `IndexedReader::read_nodes_and_deps`.
+    pub fn elements(self) -> impl for<'a> Fn(&Element<'a>) -> bool {
+        move |element: &Element| self.matches(element)
+    }
+}
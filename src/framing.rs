@@ -0,0 +1,474 @@
+//! Split a PBF stream into blobs without doing any I/O
+
+use blob::{
+    blob_data_size_with_limit, check_blob_type, decode_blob_header_size_with_limit, Blob,
+    BlobHeader, ByteOffset, MAX_BLOB_HEADER_SIZE, MAX_BLOB_MESSAGE_SIZE,
+};
+use error::{new_blob_error, new_protobuf_error, new_unexpected_eof_error, BlobError, Result};
+use proto::fileformat;
+use std::io::{self, Read};
+use std::mem;
+use util::parse_message_from_bytes;
+
+/// The part of a blob that a `BlobFramer` is currently reading.
+#[derive(Clone, Debug)]
+enum FrameState {
+    /// The four byte size prefix of the next `BlobHeader`.
+    HeaderSize,
+    /// A `BlobHeader` of the given size in bytes.
+    Header(usize),
+    /// The content of the blob with the given header.
+    Content(fileformat::BlobHeader),
+    /// The content of a skipped blob, with the number of bytes that are left.
+    Skip(usize),
+    /// An error occurred, so the remaining bytes cannot be framed.
+    Failed,
+}
+
+/// Splits a PBF stream into `Blob`s without reading from it. This is the framing logic of
+/// `BlobReader`, decoupled from `std::io::Read`, so it can be driven by any transport, e.g. an
+/// object store client or an event loop.
+///
+/// The caller feeds the bytes of the stream in order with `feed`. `bytes_needed` tells how many
+/// bytes are missing to complete the current part of a blob (its size prefix, its header or its
+/// content), so transports can request exactly that many bytes. When a blob is complete, it is
+/// returned by `feed`. At the end of the stream, `finish` checks that the stream did not end in
+/// the middle of a blob.
+///
+/// The offsets of the blobs are counted from the offset that was passed to `starting_at`, or from
+/// zero for `new`.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let bytes = std::fs::read("tests/test.osm.pbf")?;
+/// let mut framer = BlobFramer::new();
+/// let mut blobs = vec![];
+///
+/// // Feed the stream in arbitrary chunks, as a network transport would deliver it
+/// for chunk in bytes.chunks(1000) {
+///     let mut chunk = chunk;
+///     while !chunk.is_empty() {
+///         let (consumed, blob) = framer.feed(chunk)?;
+///         blobs.extend(blob);
+///         chunk = &chunk[consumed..];
+///     }
+/// }
+/// framer.finish()?;
+///
+/// assert_eq!(blobs.len(), 2);
+/// assert_eq!(blobs[0].get_type(), BlobType::OsmHeader);
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct BlobFramer {
+    state: FrameState,
+    /// The bytes of the current part that have been fed so far.
+    buf: Vec<u8>,
+    /// Offset of the current part from the start of the stream.
+    offset: Option<ByteOffset>,
+    /// Offset of the blob that is currently framed.
+    blob_offset: Option<ByteOffset>,
+    /// Reject blob headers that are bigger than `MAX_BLOB_HEADER_SIZE`.
+    strict_header_size: bool,
+    /// Reject blobs whose type is neither "OSMHeader" nor "OSMData".
+    check_blob_types: bool,
+    /// Reject blobs that are bigger than this size in bytes.
+    max_blob_size: u64,
+}
+
+impl BlobFramer {
+    /// Creates a new `BlobFramer` for a stream that starts at offset zero.
+    pub fn new() -> BlobFramer {
+        BlobFramer::starting_at(ByteOffset(0))
+    }
+
+    /// Creates a new `BlobFramer` for the part of a stream that starts at the given offset, e.g.
+    /// after seeking to a blob that was found with `IndexedReader::locate`. The offset has to be
+    /// the start of a blob.
+    pub fn starting_at(offset: ByteOffset) -> BlobFramer {
+        BlobFramer {
+            state: FrameState::HeaderSize,
+            buf: vec![],
+            offset: Some(offset),
+            blob_offset: Some(offset),
+            strict_header_size: true,
+            check_blob_types: false,
+            max_blob_size: MAX_BLOB_MESSAGE_SIZE,
+        }
+    }
+
+    /// Sets whether blob headers that are bigger than `MAX_BLOB_HEADER_SIZE` are rejected (see
+    /// `BlobReaderBuilder::strict_header_size`). Defaults to true.
+    pub fn set_strict_header_size(&mut self, strict: bool) {
+        self.strict_header_size = strict;
+    }
+
+    /// Sets whether blobs whose type is neither "OSMHeader" nor "OSMData" are rejected (see
+    /// `BlobReaderBuilder::check_blob_types`). Defaults to false.
+    pub fn set_check_blob_types(&mut self, check: bool) {
+        self.check_blob_types = check;
+    }
+
+    /// Sets the maximum size of a blob in bytes (see `BlobReaderBuilder::max_blob_size`).
+    /// Defaults to `MAX_BLOB_MESSAGE_SIZE`, bigger values are reduced to it.
+    pub fn set_max_blob_size(&mut self, size: u64) {
+        self.max_blob_size = size.min(MAX_BLOB_MESSAGE_SIZE);
+    }
+
+    /// Returns the number of bytes that are missing to complete the current part of the blob.
+    /// `feed` never consumes more bytes than this. Returns zero after an error, as no more bytes
+    /// can be framed.
+    pub fn bytes_needed(&self) -> usize {
+        match self.state {
+            FrameState::HeaderSize => 4 - self.buf.len(),
+            FrameState::Header(size) => size - self.buf.len(),
+            FrameState::Content(ref header) => header.get_datasize() as usize - self.buf.len(),
+            FrameState::Skip(left) => left,
+            FrameState::Failed => 0,
+        }
+    }
+
+    /// Feeds the next bytes of the stream. Consumes at most `bytes_needed` of them and returns
+    /// how many were consumed, together with the blob that they completed, if any. The bytes
+    /// that were not consumed have to be fed again.
+    ///
+    /// # Errors
+    /// Returns an error if the bytes do not form a valid blob. All further bytes are ignored
+    /// then.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<(usize, Option<Blob>)> {
+        if let FrameState::Failed = self.state {
+            return Ok((0, None));
+        }
+        let len = bytes.len().min(self.bytes_needed());
+        if let FrameState::Skip(ref mut left) = self.state {
+            *left -= len;
+            self.offset = self.offset.map(|x| ByteOffset(x.0 + len as u64));
+        } else {
+            self.buf.extend_from_slice(&bytes[..len]);
+        }
+        self.advance(false).map(|blob| (len, blob))
+    }
+
+    /// Signals the end of the stream.
+    ///
+    /// # Errors
+    /// Returns an `UnexpectedEof` error if the stream ended in the middle of a blob.
+    pub fn finish(&mut self) -> Result<()> {
+        match self.state {
+            FrameState::HeaderSize if self.buf.is_empty() => Ok(()),
+            FrameState::Failed => Ok(()),
+            _ => {
+                let offset = self.offset.map(|x| ByteOffset(x.0 + self.buf.len() as u64));
+                self.fail();
+                Err(new_unexpected_eof_error(offset))
+            }
+        }
+    }
+
+    /// Returns the offset of the blob that is currently framed, i.e. the one that `feed` returns
+    /// next. Returns `None` after an error.
+    pub fn next_offset(&self) -> Option<ByteOffset> {
+        match self.state {
+            FrameState::Failed => None,
+            _ => self.blob_offset,
+        }
+    }
+
+    /// Returns the header of the blob whose content is currently framed. This allows deciding
+    /// whether the content is needed at all before it is fed (see `skip_blob`).
+    pub fn header(&self) -> Option<BlobHeader> {
+        match self.state {
+            FrameState::Content(ref header) => Some(BlobHeader::new(header.clone())),
+            _ => None,
+        }
+    }
+
+    /// Skips the content of the blob whose header was returned by `header`. Its remaining
+    /// bytes still have to be fed, but they are discarded instead of buffered and no blob is
+    /// returned for them. Transports that can seek might rather skip the bytes themselves and
+    /// continue with a new `BlobFramer` (see `starting_at`). Does nothing if no header has been
+    /// read.
+    pub fn skip_blob(&mut self) {
+        if let FrameState::Content(_) = self.state {
+            let left = self.bytes_needed();
+            self.offset = self.offset.map(|x| ByteOffset(x.0 + self.buf.len() as u64));
+            self.buf = vec![];
+            self.state = FrameState::Skip(left);
+            if left == 0 {
+                self.state = FrameState::HeaderSize;
+                self.blob_offset = self.offset;
+            }
+        }
+    }
+
+    /// Discards a partially framed blob and any error, and continues with a blob that starts at
+    /// the given offset, e.g. after the transport seeked.
+    pub fn reset(&mut self, offset: ByteOffset) {
+        self.state = FrameState::HeaderSize;
+        self.buf = vec![];
+        self.offset = Some(offset);
+        self.blob_offset = Some(offset);
+    }
+
+    /// Like `reset`, but keeps an error and allows unknown offsets.
+    pub(crate) fn set_offset(&mut self, offset: Option<ByteOffset>) {
+        if let FrameState::Failed = self.state {
+            return;
+        }
+        self.state = FrameState::HeaderSize;
+        self.buf = vec![];
+        self.offset = offset;
+        self.blob_offset = offset;
+    }
+
+    /// Stops framing, e.g. because the transport failed.
+    pub(crate) fn fail(&mut self) {
+        self.state = FrameState::Failed;
+        self.buf = vec![];
+        self.offset = None;
+        self.blob_offset = None;
+    }
+
+    /// Reads from `reader` until the next blob is complete. Returns `None` at the end of the
+    /// stream or after an error.
+    pub(crate) fn read_blob<R: Read>(&mut self, reader: &mut R) -> Option<Result<Blob>> {
+        match self.read_from(reader, false)? {
+            Ok(Some(blob)) => Some(Ok(blob)),
+            Ok(None) => unreachable!("only headers stop early"),
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    /// Reads from `reader` until the header of the next blob is complete, so it is returned by
+    /// `header`. Returns `None` at the end of the stream or after an error.
+    pub(crate) fn read_header<R: Read>(&mut self, reader: &mut R) -> Option<Result<()>> {
+        match self.read_from(reader, true)? {
+            Ok(Some(_)) => unreachable!("headers are complete before their blob"),
+            Ok(None) => Some(Ok(())),
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    fn read_from<R: Read>(
+        &mut self,
+        reader: &mut R,
+        stop_at_header: bool,
+    ) -> Option<Result<Option<Blob>>> {
+        loop {
+            match self.state {
+                FrameState::Failed => return None,
+                FrameState::Content(_) if stop_at_header => return Some(Ok(None)),
+                _ => {}
+            }
+
+            let needed = self.bytes_needed();
+            let skip = match self.state {
+                FrameState::Skip(_) => true,
+                _ => false,
+            };
+            let read = if skip {
+                let read = io::copy(&mut reader.by_ref().take(needed as u64), &mut io::sink());
+                if let Ok(len) = read {
+                    self.state = FrameState::Skip(needed - len as usize);
+                    self.offset = self.offset.map(|x| ByteOffset(x.0 + len));
+                }
+                read
+            } else {
+                // Do not allocate more than a valid blob up front. Headers are only bigger if
+                // `strict_header_size` is disabled and the stream might end before.
+                self.buf.reserve(needed.min(MAX_BLOB_MESSAGE_SIZE as usize));
+                reader
+                    .by_ref()
+                    .take(needed as u64)
+                    .read_to_end(&mut self.buf)
+                    .map(|len| len as u64)
+            };
+
+            match read {
+                Ok(len) if (len as usize) < needed => {
+                    return match self.finish() {
+                        Ok(()) => {
+                            self.set_offset(None);
+                            None
+                        }
+                        Err(err) => Some(Err(err)),
+                    };
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    let err = match self.state {
+                        FrameState::HeaderSize => new_blob_error(BlobError::InvalidHeaderSize),
+                        _ => err.into(),
+                    };
+                    self.fail();
+                    return Some(Err(err));
+                }
+            }
+
+            match self.advance(stop_at_header) {
+                Ok(Some(blob)) => return Some(Ok(Some(blob))),
+                Ok(None) => {}
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+
+    /// Completes the current part and the following ones as long as no more bytes are needed.
+    /// Stops before the content of a blob if `stop_at_header` is set.
+    fn advance(&mut self, stop_at_header: bool) -> Result<Option<Blob>> {
+        while self.bytes_needed() == 0 {
+            match self.state {
+                FrameState::Failed => break,
+                FrameState::Content(_) if stop_at_header => break,
+                _ => {}
+            }
+            match self.complete_part() {
+                Ok(Some(blob)) => return Ok(Some(blob)),
+                Ok(None) => {}
+                Err(err) => {
+                    self.fail();
+                    return Err(err);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Decodes the completely buffered current part and continues with the next one.
+    fn complete_part(&mut self) -> Result<Option<Blob>> {
+        let bytes = mem::replace(&mut self.buf, vec![]);
+        let state = mem::replace(&mut self.state, FrameState::HeaderSize);
+        let offset = self.offset;
+        self.offset = offset.map(|x| ByteOffset(x.0 + bytes.len() as u64));
+
+        match state {
+            FrameState::HeaderSize => {
+                let max_size = if self.strict_header_size {
+                    MAX_BLOB_HEADER_SIZE
+                } else {
+                    u64::max_value()
+                };
+                let size = decode_blob_header_size_with_limit(&bytes, offset, max_size)?;
+                self.state = FrameState::Header(size as usize);
+                Ok(None)
+            }
+            FrameState::Header(_) => {
+                let header: fileformat::BlobHeader = parse_message_from_bytes(&bytes)
+                    .map_err(|e| new_protobuf_error(e, "blob header"))?;
+                blob_data_size_with_limit(&header, self.max_blob_size)?;
+                if self.check_blob_types {
+                    check_blob_type(&header)?;
+                }
+                self.state = FrameState::Content(header);
+                Ok(None)
+            }
+            FrameState::Content(header) => {
+                let blob: fileformat::Blob = parse_message_from_bytes(&bytes)
+                    .map_err(|e| new_protobuf_error(e, "blob content"))?;
+                let blob = Blob::new(header, blob, self.blob_offset);
+                self.blob_offset = self.offset;
+                Ok(Some(blob))
+            }
+            FrameState::Skip(_) => {
+                self.blob_offset = self.offset;
+                Ok(None)
+            }
+            FrameState::Failed => {
+                self.state = FrameState::Failed;
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Default for BlobFramer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blob::{BlobReader, BlobType};
+    use error::ErrorKind;
+    use std::io::Cursor;
+
+    fn feed_all(framer: &mut BlobFramer, mut bytes: &[u8], chunk: usize) -> Result<Vec<Blob>> {
+        let mut blobs = vec![];
+        while !bytes.is_empty() {
+            let len = chunk.min(bytes.len());
+            let (consumed, blob) = framer.feed(&bytes[..len])?;
+            assert!(consumed <= len);
+            blobs.extend(blob);
+            bytes = &bytes[consumed..];
+        }
+        Ok(blobs)
+    }
+
+    #[test]
+    fn feed_in_chunks() {
+        let bytes = ::std::fs::read("tests/test.osm.pbf").unwrap();
+        let expected: Vec<_> = BlobReader::new_seekable(Cursor::new(&bytes))
+            .unwrap()
+            .map(|blob| blob.unwrap().offset())
+            .collect();
+
+        for &chunk in &[1, 3, 4, 100, bytes.len()] {
+            let mut framer = BlobFramer::new();
+            let blobs = feed_all(&mut framer, &bytes, chunk).unwrap();
+            framer.finish().unwrap();
+
+            let offsets: Vec<_> = blobs.iter().map(|blob| blob.offset()).collect();
+            assert_eq!(offsets, expected);
+            assert_eq!(blobs[0].get_type(), BlobType::OsmHeader);
+            assert_eq!(blobs[1].get_type(), BlobType::OsmData);
+            assert_eq!(framer.next_offset(), Some(ByteOffset(bytes.len() as u64)));
+        }
+    }
+
+    #[test]
+    fn skip_and_truncate() {
+        let bytes = ::std::fs::read("tests/test.osm.pbf").unwrap();
+        let second = BlobReader::new_seekable(Cursor::new(&bytes))
+            .unwrap()
+            .nth(1)
+            .unwrap()
+            .unwrap()
+            .offset()
+            .unwrap();
+
+        // Skip the header blob
+        let mut framer = BlobFramer::new();
+        let mut rest = &bytes[..];
+        while framer.header().is_none() {
+            let (consumed, blob) = framer.feed(rest).unwrap();
+            assert!(blob.is_none());
+            rest = &rest[consumed..];
+        }
+        assert_eq!(framer.header().unwrap().blob_type(), BlobType::OsmHeader);
+        framer.skip_blob();
+        let blobs = feed_all(&mut framer, rest, 7).unwrap();
+        assert_eq!(blobs.len(), 1);
+        assert_eq!(blobs[0].offset(), Some(second));
+
+        // Truncate the second blob
+        let mut framer = BlobFramer::starting_at(second);
+        let truncated = &bytes[second.0 as usize..bytes.len() - 1];
+        assert!(feed_all(&mut framer, truncated, 5).unwrap().is_empty());
+        assert_eq!(framer.bytes_needed(), 1);
+        match framer.finish().unwrap_err().kind() {
+            ErrorKind::UnexpectedEof { offset } => {
+                assert_eq!(*offset, Some(ByteOffset(bytes.len() as u64 - 1)))
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert_eq!(framer.next_offset(), None);
+        assert_eq!(framer.bytes_needed(), 0);
+    }
+}
@@ -1,46 +1,300 @@
 //! Speed up searches by using an index
 
-use error::Result;
-use std::collections::BTreeSet;
+use block::PrimitiveBlock;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use error::{new_error, ErrorKind, Result};
+use filter::{CompiledTagFilter, TagFilter};
+use mmap_blob::Mmap;
+use owned::{OwnedNode, OwnedRelation, OwnedWay};
+use rayon::prelude::*;
+use stats::{BlobStats, ElementCounts, FileStats};
+use std;
+use std::collections::{BTreeSet, HashMap, VecDeque};
 use std::fs::File;
-use std::io::{Read, Seek};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use std::ops::RangeInclusive;
 use std::path::Path;
-use {BlobReader, BlobType, ByteOffset, Element, Way};
+use std::sync::Arc;
+use writer::BlobWriter;
+use {
+    Blob, BlobReader, BlobType, BoundingBox, ByteOffset, Coordinate, Element, RelMemberType,
+    Relation, Way,
+};
 
 /// Stores the minimum and maximum id of every element type.
-#[derive(Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct IdRanges {
     node_ids: Option<RangeInclusive<i64>>,
     way_ids: Option<RangeInclusive<i64>>,
     relation_ids: Option<RangeInclusive<i64>>,
 }
 
+impl IdRanges {
+    /// Returns the range of node ids or `None` if it is unknown or there are no nodes.
+    pub fn node_ids(&self) -> Option<RangeInclusive<i64>> {
+        self.node_ids.clone()
+    }
+
+    /// Returns the range of way ids or `None` if it is unknown or there are no ways.
+    pub fn way_ids(&self) -> Option<RangeInclusive<i64>> {
+        self.way_ids.clone()
+    }
+
+    /// Returns the range of relation ids or `None` if it is unknown or there are no relations.
+    pub fn relation_ids(&self) -> Option<RangeInclusive<i64>> {
+        self.relation_ids.clone()
+    }
+
+    /// Extends the ranges so that they also include the ranges of `other`.
+    fn extend(&mut self, other: &IdRanges) {
+        fn union(a: &mut Option<RangeInclusive<i64>>, b: &Option<RangeInclusive<i64>>) {
+            if let Some(ref b) = *b {
+                *a = Some(match a.take() {
+                    Some(a) => {
+                        RangeInclusive::new(*a.start().min(b.start()), *a.end().max(b.end()))
+                    }
+                    None => b.clone(),
+                });
+            }
+        }
+
+        union(&mut self.node_ids, &other.node_ids);
+        union(&mut self.way_ids, &other.way_ids);
+        union(&mut self.relation_ids, &other.relation_ids);
+    }
+}
+
 /// Returns true if the given set contains at least one value that is inside the given range.
 fn range_included(range: RangeInclusive<i64>, node_ids: &BTreeSet<i64>) -> bool {
     node_ids.range(range).next().is_some()
 }
 
+/// The content type of a blob without the string identifier of unknown types.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum SimpleBlobType {
+pub enum SimpleBlobType {
+    /// Blob contains a `HeaderBlock`.
     Header,
+    /// Blob contains a `PrimitiveBlock`.
     Primitive,
+    /// An unknown blob type.
     Unknown,
 }
 
+impl SimpleBlobType {
+    fn from_blob_type(blob_type: &BlobType) -> SimpleBlobType {
+        match *blob_type {
+            BlobType::OsmHeader => SimpleBlobType::Header,
+            BlobType::OsmData => SimpleBlobType::Primitive,
+            BlobType::Unknown(_) => SimpleBlobType::Unknown,
+        }
+    }
+}
+
+/// The order in which `IndexedReader::read_ways_and_deps_ordered` returns ways and their
+/// dependent nodes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DepsOrder {
+    /// Return all matching ways first and then their nodes. This is the order of
+    /// `read_ways_and_deps`.
+    WaysFirst,
+    /// Return all dependent nodes first and then the matching ways, so that all available nodes
+    /// of a way are known before the way itself is returned.
+    NodesFirst,
+}
+
+/// Which ways and nodes `IndexedReader::read_polygon` returns for ways that are only partly
+/// inside of the polygon.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WayCompleteness {
+    /// Return all ways with at least one node inside of the polygon, but only the nodes inside.
+    /// Ways that cross the border reference nodes that are not returned.
+    InsideNodes,
+    /// Return all ways with at least one node inside of the polygon together with all of their
+    /// nodes, including those outside, so that the geometry of each way is complete.
+    CompleteWays,
+}
+
+/// The progress of a pass over the blobs of a file (see
+/// `IndexedReader::read_ways_and_deps_with_progress`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Progress {
+    /// The number of the current pass, starting at 1.
+    pub pass: u32,
+    /// The number of primitive blobs that have been processed in the current pass.
+    pub blobs_processed: usize,
+    /// The number of primitive blobs in the file.
+    pub total_blobs: usize,
+    /// The offset of the blob that has just been processed.
+    pub offset: ByteOffset,
+}
+
+/// The maximum number of offending ids that a `ValidationReport` stores per kind of problem.
+pub const VALIDATION_SAMPLE_SIZE: usize = 100;
+
+/// The result of `IndexedReader::validate_references`. It counts references to elements that do
+/// not exist in the file. Every missing element is counted once, no matter how often it is
+/// referenced.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ValidationReport {
+    /// The number of distinct node ids that are referenced by ways, but missing in the file.
+    pub missing_way_nodes: u64,
+    /// Up to `VALIDATION_SAMPLE_SIZE` of the missing node ids of `missing_way_nodes`, sorted by
+    /// id.
+    pub missing_way_node_samples: Vec<i64>,
+    /// The number of distinct relation members (type and id) that are missing in the file.
+    pub missing_relation_members: u64,
+    /// Up to `VALIDATION_SAMPLE_SIZE` of the missing members of `missing_relation_members`:
+    /// nodes first, then ways, then relations, each sorted by id.
+    pub missing_relation_member_samples: Vec<(RelMemberType, i64)>,
+}
+
+impl ValidationReport {
+    /// Returns true if all references could be resolved.
+    pub fn is_valid(&self) -> bool {
+        self.missing_way_nodes == 0 && self.missing_relation_members == 0
+    }
+}
+
+/// Counts the items of the iterator and returns the count together with the first
+/// `VALIDATION_SAMPLE_SIZE` items.
+fn count_with_samples<T, I: Iterator<Item = T>>(iter: I) -> (u64, Vec<T>) {
+    let mut count = 0;
+    let mut samples = vec![];
+    for item in iter {
+        if samples.len() < VALIDATION_SAMPLE_SIZE {
+            samples.push(item);
+        }
+        count += 1;
+    }
+    (count, samples)
+}
+
 #[derive(Debug)]
 struct BlobInfo {
     offset: ByteOffset,
     blob_type: SimpleBlobType,
     id_ranges: Option<IdRanges>,
+    node_bbox: Option<BoundingBox>,
+}
+
+impl BlobInfo {
+    /// Stores the id ranges and the bounding box of the nodes of the given block, which is the
+    /// decoded content of this blob.
+    fn index_block(&mut self, block: &PrimitiveBlock) {
+        fn include(range: &mut Option<RangeInclusive<i64>>, id: i64) {
+            *range = Some(match range.take() {
+                Some(r) => RangeInclusive::new(id.min(*r.start()), id.max(*r.end())),
+                None => RangeInclusive::new(id, id),
+            });
+        }
+
+        fn include_coordinate(bbox: &mut Option<BoundingBox>, coordinate: Coordinate) {
+            match *bbox {
+                Some(ref mut bbox) => bbox.extend(coordinate),
+                None => *bbox = Some(BoundingBox::new(coordinate, coordinate)),
+            }
+        }
+
+        let mut ranges = IdRanges {
+            node_ids: None,
+            way_ids: None,
+            relation_ids: None,
+        };
+        let mut node_bbox = None;
+        for group in block.groups() {
+            for node in group.nodes() {
+                include(&mut ranges.node_ids, node.id());
+                include_coordinate(&mut node_bbox, node.coordinate());
+            }
+            for node in group.dense_nodes() {
+                include(&mut ranges.node_ids, node.id);
+                include_coordinate(&mut node_bbox, node.coordinate());
+            }
+            for way in group.ways() {
+                include(&mut ranges.way_ids, way.id());
+            }
+            for relation in group.relations() {
+                include(&mut ranges.relation_ids, relation.id());
+            }
+        }
+        self.id_ranges = Some(ranges);
+        self.node_bbox = node_bbox;
+    }
+}
+
+/// A summary of an indexed blob. It can be used by external tools to consume the index of an
+/// `IndexedReader` without scanning the file again.
+#[derive(Clone, Debug)]
+pub struct BlobSummary {
+    offset: ByteOffset,
+    blob_type: SimpleBlobType,
+    id_ranges: Option<IdRanges>,
+    node_bbox: Option<BoundingBox>,
+}
+
+impl BlobSummary {
+    fn new(info: &BlobInfo) -> BlobSummary {
+        BlobSummary {
+            offset: info.offset,
+            blob_type: info.blob_type,
+            id_ranges: info.id_ranges.clone(),
+            node_bbox: info.node_bbox,
+        }
+    }
+
+    /// Returns the byte offset of the blob from the start of the file.
+    pub fn offset(&self) -> ByteOffset {
+        self.offset
+    }
+
+    /// Returns the type of the blob.
+    pub fn blob_type(&self) -> SimpleBlobType {
+        self.blob_type
+    }
+
+    /// Returns the id ranges of the elements in this blob. This is `None` for non-primitive blobs
+    /// and for blobs that have not been decoded yet by one of the `read_*` methods or by
+    /// `IndexedReader::create_full_index`.
+    pub fn id_ranges(&self) -> Option<&IdRanges> {
+        self.id_ranges.as_ref()
+    }
+
+    /// Returns the bounding box of the nodes in this blob. Like the id ranges, it is only known
+    /// after the blob has been decoded by one of the `read_*` methods. It is also `None` if the
+    /// blob contains no nodes.
+    pub fn node_bbox(&self) -> Option<BoundingBox> {
+        self.node_bbox
+    }
+}
+
+/// An iterator over the summaries of all indexed blobs.
+#[derive(Clone, Debug)]
+pub struct BlobSummaryIter<'a> {
+    infos: std::slice::Iter<'a, BlobInfo>,
+}
+
+impl<'a> Iterator for BlobSummaryIter<'a> {
+    type Item = BlobSummary;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.infos.next().map(BlobSummary::new)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.infos.size_hint()
+    }
 }
 
+impl<'a> ExactSizeIterator for BlobSummaryIter<'a> {}
+
 /// Allows filtering elements and iterating over their dependencies.
 /// It chooses an efficient method for navigating the PBF structure to achieve this in reasonable
 /// time and with reasonable memory.
 pub struct IndexedReader<R: Read + Seek> {
     reader: BlobReader<R>,
     index: Vec<BlobInfo>,
+    block_cache: BlockCache,
+    full_index: bool,
 }
 
 impl<R: Read + Seek> IndexedReader<R> {
@@ -65,9 +319,118 @@ impl<R: Read + Seek> IndexedReader<R> {
         Ok(Self {
             reader,
             index: vec![],
+            block_cache: BlockCache::new(0),
+            full_index: false,
         })
     }
 
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.reader.get_ref()
+    }
+
+    /// Consumes the `IndexedReader` and returns the underlying reader, e.g. to reuse an open file
+    /// after all searches are done. The index is dropped. The position of the reader is
+    /// unspecified, as the searches seek around in the file, so seek to a known position
+    /// before reading from it again.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// reader.read_ways_and_deps(|_| true, |_| {})?;
+    ///
+    /// let mut f = reader.into_inner();
+    /// let size = std::io::Seek::seek(&mut f, std::io::SeekFrom::End(0))?;
+    /// # assert!(size > 0);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+
+    /// Keeps up to `blocks` decoded `PrimitiveBlock`s in memory, so the searches that read the
+    /// same blob in several passes (e.g. `read_ways_and_deps` for files that store ways and
+    /// their nodes in the same blocks) do not decompress and decode it again. The cache is
+    /// disabled by default, because a decoded block can take up several megabytes. When it is
+    /// full, the least recently used block is dropped.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// reader.set_block_cache_size(16);
+    ///
+    /// let mut elements = 0;
+    /// reader.read_ways_and_deps(|_| true, |_| elements += 1)?;
+    /// # assert_eq!(elements, 4);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn set_block_cache_size(&mut self, blocks: usize) {
+        self.block_cache.set_capacity(blocks);
+    }
+
+    /// Sets whether the index that the `read_*` and `get_*` methods create on first use is a full
+    /// index (see `create_full_index`) with the id ranges of nodes, ways and relations of every
+    /// blob. Defaults to false, which only scans the headers of the blobs and fills in the id
+    /// ranges lazily as blobs are decoded.
+    ///
+    /// A full index has to decode every primitive blob up front, which is expensive on large
+    /// files. It pays off when many lookups follow, e.g. `get_way` or `get_relation` calls or
+    /// relation dependencies, because blobs that cannot contain the requested ids are skipped
+    /// from the start.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// reader.set_full_index(true);
+    ///
+    /// let way = reader.get_way(107)?;
+    /// # assert!(way.is_some());
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn set_full_index(&mut self, full: bool) {
+        self.full_index = full;
+    }
+
+    /// Creates a full index unless the index already contains the id ranges of all primitive
+    /// blobs.
+    fn ensure_full_index(&mut self) -> Result<()> {
+        let indexed = !self.index.is_empty()
+            && self.index.iter().all(|info| {
+                info.blob_type != SimpleBlobType::Primitive || info.id_ranges.is_some()
+            });
+        if indexed {
+            Ok(())
+        } else {
+            self.create_full_index()
+        }
+    }
+
+    /// Creates the index if it does not exist yet, a full index if `set_full_index` is enabled.
+    fn ensure_index(&mut self) -> Result<()> {
+        if !self.index.is_empty() {
+            Ok(())
+        } else if self.full_index {
+            self.create_full_index()
+        } else {
+            self.create_index()
+        }
+    }
+
     pub fn create_index(&mut self) -> Result<()> {
         // remove old items
         self.index.clear();
@@ -76,24 +439,25 @@ impl<R: Read + Seek> IndexedReader<R> {
             let (header, offset) = result?;
             // Reader is seekable, so offset should be Some(ByteOffset)
             let offset = offset.unwrap();
-            let blob_type = match header.blob_type() {
-                BlobType::OsmHeader => SimpleBlobType::Header,
-                BlobType::OsmData => SimpleBlobType::Primitive,
-                BlobType::Unknown(_) => SimpleBlobType::Unknown,
-            };
 
             self.index.push(BlobInfo {
                 offset,
-                blob_type,
+                blob_type: SimpleBlobType::from_blob_type(&header.blob_type()),
                 id_ranges: None,
+                node_bbox: None,
             });
         }
 
         Ok(())
     }
 
-    /// Filter ways using a closure and return matching ways and their dependent nodes (`Node`s and
-    /// `DenseNode`s) in another closure.
+    /// Creates the index like `create_index` and also decodes all primitive blobs to store their
+    /// id ranges and node bounding boxes. Otherwise, these are only filled in lazily by the
+    /// `read_*` methods.
+    ///
+    /// The blobs are read sequentially in batches, but decoded and indexed in parallel. This
+    /// makes it considerably faster than a sequential scan on large files, especially on fast
+    /// storage where decompression is the bottleneck.
     ///
     /// # Example
     /// ```
@@ -101,186 +465,3865 @@ impl<R: Read + Seek> IndexedReader<R> {
     ///
     /// # fn foo() -> Result<()> {
     /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
-    /// let mut ways = 0;
-    /// let mut nodes = 0;
-    ///
-    /// // Filter all ways that are buildings and count their nodes.
-    /// reader.read_ways_and_deps(
-    ///     |way| {
-    ///         // Filter ways. Return true if tags contain "building": "yes".
-    ///         way.tags().any(|key_value| key_value == ("building", "yes"))
-    ///     },
-    ///     |element| {
-    ///         // Increment counter
-    ///         match element {
-    ///             Element::Way(way) => ways += 1,
-    ///             Element::Node(node) => nodes += 1,
-    ///             Element::DenseNode(dense_node) => nodes += 1,
-    ///             Element::Relation(_) => (), // should not occur
-    ///         }
-    ///     },
-    /// )?;
-    ///
-    /// println!("ways:  {}\nnodes: {}", ways, nodes);
+    /// reader.create_full_index()?;
     ///
-    /// # assert_eq!(ways, 1);
-    /// # assert_eq!(nodes, 3);
+    /// for summary in reader.iter_blobs() {
+    ///     if summary.blob_type() == SimpleBlobType::Primitive {
+    ///         assert!(summary.id_ranges().is_some());
+    ///     }
+    /// }
     /// # Ok(())
     /// # }
     /// # foo().unwrap();
     /// ```
-    pub fn read_ways_and_deps<F, E>(
-        &mut self,
-        mut filter: F,
-        mut element_callback: E,
-    ) -> Result<()>
-    where
-        F: for<'a> FnMut(&Way<'a>) -> bool,
-        E: for<'a> FnMut(&Element<'a>),
-    {
-        // Create index
-        if self.index.is_empty() {
-            self.create_index()?;
-        }
-
-        let mut node_ids: BTreeSet<i64> = BTreeSet::new();
-
-        // First pass:
-        //   * Filter ways and store their dependencies as node IDs
-        //   * Store range of node IDs (min and max value) of each block
-        for info in &mut self.index {
-            //TODO do something useful with header blocks
-            if info.blob_type == SimpleBlobType::Primitive {
-                self.reader.seek(info.offset)?;
-                let blob = self.reader.next().ok_or_else(|| {
-                    ::std::io::Error::new(
-                        ::std::io::ErrorKind::UnexpectedEof,
-                        "could not read next blob",
-                    )
-                })??;
-                let block = blob.to_primitiveblock()?;
-                let mut min_node_id: Option<i64> = None;
-                let mut max_node_id: Option<i64> = None;
-                for group in block.groups() {
-                    // filter ways and record node IDs
-                    for way in group.ways() {
-                        if filter(&way) {
-                            let refs = way.refs();
-
-                            node_ids.extend(refs);
+    pub fn create_full_index(&mut self) -> Result<()> {
+        self.create_index()?;
 
-                            // Return way
-                            element_callback(&Element::Way(way));
-                        }
-                    }
+        let start = match self.index.first() {
+            Some(info) => info.offset,
+            None => return Ok(()),
+        };
+        self.reader.seek(start)?;
 
-                    // Check node IDs of this block, record min and max
+        let batch_size = ::rayon::current_num_threads() * 4;
+        loop {
+            let blobs = self
+                .reader
+                .by_ref()
+                .take(batch_size)
+                .collect::<Result<Vec<_>>>()?;
+            if blobs.is_empty() {
+                return Ok(());
+            }
 
-                    let mut check_min_max = |id| {
-                        min_node_id = Some(min_node_id.map_or(id, |x| x.min(id)));
-                        max_node_id = Some(max_node_id.map_or(id, |x| x.max(id)));
+            let infos = blobs
+                .into_par_iter()
+                .filter(|blob| blob.get_type() == BlobType::OsmData)
+                .map(|blob| {
+                    let mut info = BlobInfo {
+                        offset: blob.offset().unwrap(),
+                        blob_type: SimpleBlobType::Primitive,
+                        id_ranges: None,
+                        node_bbox: None,
                     };
+                    info.index_block(&blob.to_primitiveblock()?);
+                    Ok(info)
+                })
+                .collect::<Result<Vec<_>>>()?;
 
-                    for node in group.nodes() {
-                        check_min_max(node.id())
-                    }
-                    for node in group.dense_nodes() {
-                        check_min_max(node.id)
-                    }
-                }
-                if let (Some(min), Some(max)) = (min_node_id, max_node_id) {
-                    info.id_ranges = Some(IdRanges {
-                        node_ids: Some(RangeInclusive::new(min, max)),
-                        way_ids: None,
-                        relation_ids: None,
-                    });
+            for info in infos {
+                if let Ok(i) = self.index.binary_search_by_key(&info.offset, |i| i.offset) {
+                    self.index[i] = info;
                 }
             }
         }
+    }
 
-        // Second pass:
-        //   * Iterate only over blobs that may include the node IDs we're searching for
-        for info in &mut self.index {
-            if info.blob_type == SimpleBlobType::Primitive {
-                if let Some(node_id_range) = info.id_ranges.as_ref().and_then(|r| r.node_ids.as_ref()) {
-                    if range_included(node_id_range.clone(), &node_ids) {
-                        //TODO Only collect into Vec if range has a reasonable size
-                        let node_ids: Vec<i64> = node_ids.range(node_id_range.clone()).map(|x| *x).collect();
-                        self.reader.seek(info.offset)?;
-                        let blob = self.reader.next().ok_or_else(|| {
-                            ::std::io::Error::new(
-                                ::std::io::ErrorKind::UnexpectedEof,
-                                "could not read next blob",
-                            )
-                        })??;
-                        let block = blob.to_primitiveblock()?;
-                        for group in block.groups() {
-                            for node in group.nodes() {
-                                if node_ids.binary_search(&node.id()).is_ok() {
-                                    // ID found, return node
-                                    element_callback(&Element::Node(node));
-                                }
-                            }
-                            for node in group.dense_nodes() {
-                                if node_ids.binary_search(&node.id).is_ok() {
-                                    // ID found, return dense node
-                                    element_callback(&Element::DenseNode(node));
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+    /// Returns an iterator over summaries of all indexed blobs in file order. The index is empty
+    /// until `create_index` or one of the `read_*` methods has been called.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// reader.create_index()?;
+    ///
+    /// for summary in reader.iter_blobs() {
+    ///     println!("{:?} blob at offset {}", summary.blob_type(), summary.offset().0);
+    /// }
+    ///
+    /// # assert_eq!(reader.iter_blobs().len(), 2);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn iter_blobs(&self) -> BlobSummaryIter {
+        BlobSummaryIter {
+            infos: self.index.iter(),
         }
-
-        Ok(())
     }
-}
 
-impl IndexedReader<File> {
-    /// Creates a new `IndexedReader` from a given path.
+    /// Returns the number of indexed blobs of all types. Like `iter_blobs`, this does not create
+    /// the index, so it returns 0 until `create_index` or one of the `read_*` methods has been
+    /// called.
     ///
     /// # Example
     /// ```
     /// use osmpbf::*;
     ///
     /// # fn foo() -> Result<()> {
-    /// let reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// reader.create_index()?;
     ///
+    /// println!(
+    ///     "{} blobs ({} header, {} primitive) in {} bytes",
+    ///     reader.blob_count(),
+    ///     reader.header_blob_count(),
+    ///     reader.primitive_blob_count(),
+    ///     reader.file_len()?
+    /// );
+    /// # assert_eq!(reader.blob_count(), 2);
+    /// # assert_eq!(reader.header_blob_count(), 1);
+    /// # assert_eq!(reader.primitive_blob_count(), 1);
     /// # Ok(())
     /// # }
     /// # foo().unwrap();
     /// ```
-    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
-        //TODO take some more measurements to determine if `BufReader` should be used here
-        let f = File::open(path)?;
-        Self::new(f)
+    pub fn blob_count(&self) -> usize {
+        self.index.len()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Returns the number of indexed `OSMHeader` blobs (see `blob_count`).
+    pub fn header_blob_count(&self) -> usize {
+        self.count_blobs(SimpleBlobType::Header)
+    }
 
-    #[test]
-    fn test_range_included_set() {
-        let mut set = BTreeSet::<i64>::new();
-        set.extend(&[1,2,6]);
+    /// Returns the number of indexed `OSMData` blobs (see `blob_count`).
+    pub fn primitive_blob_count(&self) -> usize {
+        self.count_blobs(SimpleBlobType::Primitive)
+    }
 
-        assert_eq!(range_included(RangeInclusive::new(0, 0), &set), false);
-        assert_eq!(range_included(RangeInclusive::new(1, 1), &set), true);
-        assert_eq!(range_included(RangeInclusive::new(2, 2), &set), true);
-        assert_eq!(range_included(RangeInclusive::new(3, 3), &set), false);
-        assert_eq!(range_included(RangeInclusive::new(3, 5), &set), false);
-        assert_eq!(range_included(RangeInclusive::new(3, 6), &set), true);
-        assert_eq!(range_included(RangeInclusive::new(6, 6), &set), true);
-        assert_eq!(range_included(RangeInclusive::new(7, 7), &set), false);
-        assert_eq!(range_included(RangeInclusive::new(0, 1), &set), true);
-        assert_eq!(range_included(RangeInclusive::new(6, 7), &set), true);
-        assert_eq!(range_included(RangeInclusive::new(2, 3), &set), true);
-        assert_eq!(range_included(RangeInclusive::new(5, 6), &set), true);
-        assert_eq!(range_included(RangeInclusive::new(5, 8), &set), true);
-        assert_eq!(range_included(RangeInclusive::new(0, 8), &set), true);
-        assert_eq!(range_included(RangeInclusive::new(0, 4), &set), true);
+    fn count_blobs(&self, blob_type: SimpleBlobType) -> usize {
+        self.index
+            .iter()
+            .filter(|info| info.blob_type == blob_type)
+            .count()
+    }
+
+    /// Returns the length of the underlying file or stream in bytes. This seeks to the end of
+    /// the stream and back, but does not need the index.
+    pub fn file_len(&mut self) -> Result<u64> {
+        let pos = self.reader.seek_raw(SeekFrom::Current(0))?;
+        let len = self.reader.seek_raw(SeekFrom::End(0))?;
+        self.reader.seek_raw(SeekFrom::Start(pos))?;
+        Ok(len)
+    }
+
+    /// Writes the index to the given writer in a compact binary format, so that it can be
+    /// restored with `load_index` instead of scanning the file again. This includes the id
+    /// ranges and bounding boxes of all blobs that have been decoded so far. The index is
+    /// created first if it is empty.
+    ///
+    /// The saved index records the length of the source file and a checksum of its first and
+    /// last bytes (up to 64 KiB each), which is used to reject the index for another file.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut saved = vec![];
+    /// reader.save_index(&mut saved)?;
+    ///
+    /// // ... later, e.g. in another process
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// reader.load_index(&saved[..])?;
+    ///
+    /// # assert_eq!(reader.iter_blobs().len(), 2);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn save_index<W: Write>(&mut self, mut writer: W) -> Result<()> {
+        self.ensure_index()?;
+
+        let (file_len, checksum) = source_fingerprint(&mut self.reader)?;
+        writer.write_all(INDEX_MAGIC)?;
+        writer.write_u32::<LittleEndian>(INDEX_VERSION)?;
+        writer.write_u64::<LittleEndian>(file_len)?;
+        writer.write_u64::<LittleEndian>(checksum)?;
+        writer.write_u64::<LittleEndian>(self.index.len() as u64)?;
+        for info in &self.index {
+            info.write_to(&mut writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Replaces the index with one that was written by `save_index`, so that the file does not
+    /// need to be scanned again. Fails with an error of kind `ErrorKind::InvalidIndex` if the
+    /// saved index is malformed or does not match the source file of this reader, e.g. because
+    /// the file has changed since the index was saved. The current index is kept in this case.
+    pub fn load_index<I: Read>(&mut self, mut reader: I) -> Result<()> {
+        fn invalid(reason: &'static str) -> ::error::Error {
+            new_error(ErrorKind::InvalidIndex { reason })
+        }
+
+        let mut magic = [0_u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != INDEX_MAGIC {
+            return Err(invalid("unknown format"));
+        }
+        if reader.read_u32::<LittleEndian>()? != INDEX_VERSION {
+            return Err(invalid("unsupported format version"));
+        }
+
+        let (file_len, checksum) = source_fingerprint(&mut self.reader)?;
+        if reader.read_u64::<LittleEndian>()? != file_len {
+            return Err(invalid("file length differs"));
+        }
+        if reader.read_u64::<LittleEndian>()? != checksum {
+            return Err(invalid("file content differs"));
+        }
+
+        let len = reader.read_u64::<LittleEndian>()?;
+        let mut index = vec![];
+        for _ in 0..len {
+            index.push(
+                BlobInfo::read_from(&mut reader).map_err(|e| match *e.kind() {
+                    ErrorKind::Io(_) => invalid("truncated data"),
+                    _ => e,
+                })?,
+            );
+        }
+        if index.iter().any(|info| info.offset.0 >= file_len) {
+            return Err(invalid("blob offset out of bounds"));
+        }
+
+        self.index = index;
+        Ok(())
+    }
+
+    /// Scans all blobs and returns statistics about the file. The sizes and compression methods
+    /// are read from the blob messages without decompressing them. If `count_elements` is true,
+    /// all `PrimitiveBlock`s are also decompressed and decoded to count their elements, which is
+    /// much slower (see `FileStats`). Their id ranges and node bounding boxes are stored in the
+    /// index then.
+    ///
+    /// Unlike the headers that `create_index` reads, the statistics need the whole blob messages.
+    /// If there is no index yet, it is created in the same pass, so the file is read only once.
+    /// Otherwise, the blobs are read again at the offsets of the index.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let stats = reader.file_stats(true)?;
+    ///
+    /// println!("blobs: {}", stats.blobs());
+    /// println!("compressed size: {} bytes", stats.compressed_size);
+    /// println!("elements: {:?}", stats.element_counts);
+    ///
+    /// # assert_eq!(stats.blobs(), 2);
+    /// # assert_eq!(stats.element_counts.map(|c| c.total()), Some(5));
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn file_stats(&mut self, count_elements: bool) -> Result<FileStats> {
+        let mut stats = FileStats::default();
+        let mut counts = ElementCounts::default();
+        let mut add_blob = |info: &mut BlobInfo, blob: &Blob| -> Result<()> {
+            stats.add_blob(blob);
+            if count_elements && info.blob_type == SimpleBlobType::Primitive {
+                let block = blob.to_primitiveblock()?;
+                counts += ElementCounts::from_block(&block);
+                info.index_block(&block);
+            }
+            Ok(())
+        };
+
+        if self.index.is_empty() && !self.full_index {
+            // Create the index while reading the blobs, like `create_index` does with the headers
+            let mut index = vec![];
+            while let Some(blob) = self.reader.next() {
+                let blob = blob?;
+                let mut info = BlobInfo {
+                    // Reader is seekable, so offset should be Some(ByteOffset)
+                    offset: blob.offset().unwrap(),
+                    blob_type: SimpleBlobType::from_blob_type(&blob.get_type()),
+                    id_ranges: None,
+                    node_bbox: None,
+                };
+                add_blob(&mut info, &blob)?;
+                index.push(info);
+            }
+            self.index = index;
+        } else {
+            // Create index
+            self.ensure_index()?;
+
+            for info in &mut self.index {
+                let blob = read_blob_at(&mut self.reader, info.offset)?;
+                add_blob(info, &blob)?;
+            }
+        }
+
+        if count_elements {
+            stats.element_counts = Some(counts);
+        }
+
+        Ok(stats)
+    }
+
+    /// Scans all blobs once and returns detailed statistics: the totals of `file_stats` with
+    /// element counts, the id ranges of the whole file and a `BlobStats` entry for every blob in
+    /// file order. All `PrimitiveBlock`s are decoded for this, so it is as slow as
+    /// `file_stats(true)`. The id ranges are also stored in the index, so later searches do not
+    /// need to decode blobs just to find them.
+    ///
+    /// The result implements `Display` to print a short summary.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let stats = reader.collect_stats()?;
+    ///
+    /// println!("{}", stats);
+    /// for blob in &stats.blob_stats {
+    ///     println!("{}: {:?}", blob.offset, blob.compression_ratio());
+    /// }
+    ///
+    /// # assert_eq!(stats.blob_stats.len(), 2);
+    /// # assert_eq!(stats.id_ranges.and_then(|r| r.way_ids()), Some(107..=107));
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn collect_stats(&mut self) -> Result<FileStats> {
+        // Create index
+        self.ensure_index()?;
+
+        let mut stats = FileStats::default();
+        let mut counts = ElementCounts::default();
+        let mut id_ranges: Option<IdRanges> = None;
+
+        for info in &mut self.index {
+            let blob = read_blob_at(&mut self.reader, info.offset)?;
+            stats.add_blob(&blob);
+
+            let mut blob_stats = BlobStats {
+                offset: info.offset,
+                blob_type: info.blob_type,
+                compression: blob.compression(),
+                compressed_size: blob.data_size(),
+                uncompressed_size: blob.uncompressed_size_hint(),
+                element_counts: None,
+                id_ranges: None,
+            };
+            if info.blob_type == SimpleBlobType::Primitive {
+                let block = blob.to_primitiveblock()?;
+                let block_counts = ElementCounts::from_block(&block);
+                counts += block_counts;
+                info.index_block(&block);
+                if let Some(ref ranges) = info.id_ranges {
+                    match id_ranges {
+                        Some(ref mut id_ranges) => id_ranges.extend(ranges),
+                        None => id_ranges = Some(ranges.clone()),
+                    }
+                }
+                blob_stats.element_counts = Some(block_counts);
+                blob_stats.id_ranges = info.id_ranges.clone();
+            }
+            stats.blob_stats.push(blob_stats);
+        }
+
+        stats.element_counts = Some(counts);
+        stats.id_ranges = id_ranges;
+        Ok(stats)
+    }
+
+    /// Filter ways using a closure and return matching ways and their dependent nodes (`Node`s and
+    /// `DenseNode`s) in another closure.
+    ///
+    /// On success, returns the sorted ids of all referenced nodes that could not be found in the
+    /// file. This is common for extracts that are cut along a bounding box.
+    ///
+    /// Nodes are looked up by id only. For files with historical information (see
+    /// `HeaderBlock::has_historical_information`), all versions of the matching ways and all
+    /// versions of their nodes are returned, including deleted ones.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut ways = 0;
+    /// let mut nodes = 0;
+    ///
+    /// // Filter all ways that are buildings and count their nodes.
+    /// let missing_nodes = reader.read_ways_and_deps(
+    ///     |way| {
+    ///         // Filter ways. Return true if tags contain "building": "yes".
+    ///         way.tags().any(|key_value| key_value == ("building", "yes"))
+    ///     },
+    ///     |element| {
+    ///         // Increment counter
+    ///         match element {
+    ///             Element::Way(way) => ways += 1,
+    ///             Element::Node(node) => nodes += 1,
+    ///             Element::DenseNode(dense_node) => nodes += 1,
+    ///             Element::Relation(_) => (), // should not occur
+    ///         }
+    ///     },
+    /// )?;
+    ///
+    /// println!("ways:  {}\nnodes: {}", ways, nodes);
+    /// println!("missing nodes: {:?}", missing_nodes);
+    ///
+    /// # assert_eq!(ways, 1);
+    /// # assert_eq!(nodes, 3);
+    /// # assert!(missing_nodes.is_empty());
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn read_ways_and_deps<F, E>(&mut self, filter: F, element_callback: E) -> Result<Vec<i64>>
+    where
+        F: for<'a> FnMut(&Way<'a>) -> bool,
+        E: for<'a> FnMut(&Element<'a>),
+    {
+        self.read_ways_and_deps_with(
+            &mut ClosureWayFilter(filter, element_callback),
+            DepsOrder::WaysFirst,
+            false,
+            &mut |_| {},
+        )
+    }
+
+    /// Same as `read_ways_and_deps`, but calls the closure `progress` after each primitive blob
+    /// of each pass over the file. This allows showing the progress of long-running searches,
+    /// e.g. in command line tools.
+    ///
+    /// The first pass visits all primitive blobs, the second pass visits the same blobs again,
+    /// but only decodes those that may contain the searched nodes.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// reader.read_ways_and_deps_with_progress(
+    ///     |way| way.tags().any(|key_value| key_value == ("building", "yes")),
+    ///     |element| println!("{:?}", element),
+    ///     |progress| {
+    ///         println!(
+    ///             "pass {}: {}/{} blobs (at byte {})",
+    ///             progress.pass, progress.blobs_processed, progress.total_blobs, progress.offset
+    ///         );
+    ///     },
+    /// )?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn read_ways_and_deps_with_progress<F, E, P>(
+        &mut self,
+        filter: F,
+        element_callback: E,
+        mut progress: P,
+    ) -> Result<Vec<i64>>
+    where
+        F: for<'a> FnMut(&Way<'a>) -> bool,
+        E: for<'a> FnMut(&Element<'a>),
+        P: FnMut(Progress),
+    {
+        self.read_ways_and_deps_with(
+            &mut ClosureWayFilter(filter, element_callback),
+            DepsOrder::WaysFirst,
+            false,
+            &mut progress,
+        )
+    }
+
+    /// Same as `read_ways_and_deps`, but the blobs of the second pass, which searches the nodes
+    /// of the matching ways, are decoded in parallel. They are still read sequentially from the
+    /// underlying reader, so this is most useful if decoding is the bottleneck, e.g. if many ways
+    /// match and their nodes are spread over many blobs.
+    ///
+    /// Both closures are called on the current thread and the elements are returned in the same
+    /// order as with `read_ways_and_deps`, so the closures do not need to be `Send` or `Sync`.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut nodes = 0;
+    ///
+    /// let missing_nodes = reader.par_read_ways_and_deps(
+    ///     |way| way.tags().any(|key_value| key_value == ("building", "yes")),
+    ///     |element| {
+    ///         if let Element::DenseNode(_) = element {
+    ///             nodes += 1;
+    ///         }
+    ///     },
+    /// )?;
+    ///
+    /// # assert_eq!(nodes, 3);
+    /// # assert!(missing_nodes.is_empty());
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn par_read_ways_and_deps<F, E>(
+        &mut self,
+        filter: F,
+        element_callback: E,
+    ) -> Result<Vec<i64>>
+    where
+        F: for<'a> FnMut(&Way<'a>) -> bool,
+        E: for<'a> FnMut(&Element<'a>),
+    {
+        self.read_ways_and_deps_with(
+            &mut ClosureWayFilter(filter, element_callback),
+            DepsOrder::WaysFirst,
+            true,
+            &mut |_| {},
+        )
+    }
+
+    /// Filter ways using a closure and return matching ways and their dependent nodes (`Node`s and
+    /// `DenseNode`s) in another closure, in the given order. With `DepsOrder::WaysFirst` this is
+    /// the same as `read_ways_and_deps`.
+    ///
+    /// With `DepsOrder::NodesFirst`, the matching ways are only returned after all of their
+    /// nodes. For this, the position of each matching way is kept in memory (one entry per way)
+    /// and all blocks that contain matching ways are read and decoded a second time at the end.
+    /// The filter closure is only called once for each way.
+    ///
+    /// On success, returns the sorted ids of all referenced nodes that could not be found in the
+    /// file.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    /// use std::collections::HashMap;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut coords = HashMap::new();
+    ///
+    /// // Compute the number of known coordinates of each building.
+    /// reader.read_ways_and_deps_ordered(
+    ///     |way| way.tags().any(|key_value| key_value == ("building", "yes")),
+    ///     DepsOrder::NodesFirst,
+    ///     |element| {
+    ///         match element {
+    ///             Element::Node(node) => {
+    ///                 coords.insert(node.id(), (node.lat(), node.lon()));
+    ///             }
+    ///             Element::DenseNode(node) => {
+    ///                 coords.insert(node.id, (node.lat(), node.lon()));
+    ///             }
+    ///             Element::Way(way) => {
+    ///                 // All nodes of this way have already been returned.
+    ///                 let known = way.refs().filter(|id| coords.contains_key(id)).count();
+    ///                 println!("way {}: {} coordinates", way.id(), known);
+    /// #               assert_eq!(known, 4);
+    ///             }
+    ///             Element::Relation(_) => (), // should not occur
+    ///         }
+    ///     },
+    /// )?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn read_ways_and_deps_ordered<F, E>(
+        &mut self,
+        filter: F,
+        order: DepsOrder,
+        element_callback: E,
+    ) -> Result<Vec<i64>>
+    where
+        F: for<'a> FnMut(&Way<'a>) -> bool,
+        E: for<'a> FnMut(&Element<'a>),
+    {
+        self.read_ways_and_deps_with(
+            &mut ClosureWayFilter(filter, element_callback),
+            order,
+            false,
+            &mut |_| {},
+        )
+    }
+
+    /// Same as `read_ways_and_deps`, but returns the elements in batches: `batch_callback` is
+    /// called once per block with all matching ways of the block and once per block with all
+    /// of its dependent nodes. This avoids the overhead of calling a closure for every element
+    /// and fits consumers that process elements in bulk, e.g. multi-row database inserts.
+    ///
+    /// The elements borrow from their block, so a batch is only valid during the call of the
+    /// closure. Batches are never empty. The filter closure is called for all ways of a block
+    /// before the ways of the block are returned.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut rows = vec![];
+    ///
+    /// reader.read_ways_and_deps_batched(
+    ///     |way| way.tags().any(|key_value| key_value == ("building", "yes")),
+    ///     |elements| {
+    ///         // Insert all elements of the batch at once
+    ///         let ids: Vec<i64> = elements.iter().map(|e| e.id()).collect();
+    ///         rows.push(ids);
+    ///     },
+    /// )?;
+    ///
+    /// # assert_eq!(rows, vec![vec![107], vec![105, 106, 108]]);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn read_ways_and_deps_batched<F, B>(
+        &mut self,
+        filter: F,
+        batch_callback: B,
+    ) -> Result<Vec<i64>>
+    where
+        F: for<'a> FnMut(&Way<'a>) -> bool,
+        B: for<'a> FnMut(&[Element<'a>]),
+    {
+        self.read_ways_and_deps_with(
+            &mut BatchWayFilter(filter, batch_callback),
+            DepsOrder::WaysFirst,
+            false,
+            &mut |_| {},
+        )
+    }
+
+    /// Filter ways using a closure and return the geometry of each matching way in another
+    /// closure: the way itself and the coordinates of its nodes in the order of its refs.
+    ///
+    /// This builds on `read_ways_and_deps_ordered` with `DepsOrder::NodesFirst`, so the
+    /// coordinates of all referenced nodes are buffered in memory until the ways are returned.
+    /// Ways that reference nodes which are missing from the file (which is common in extracts)
+    /// are not passed to the closure. On success, returns the number of these incomplete ways.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// let incomplete = reader.read_ways_geometry(
+    ///     |way| way.tags().any(|key_value| key_value == ("building", "yes")),
+    ///     |way, coords| {
+    ///         println!("way {} has {} points", way.id(), coords.len());
+    /// #       assert_eq!(coords.len(), 4);
+    ///     },
+    /// )?;
+    ///
+    /// println!("incomplete ways: {}", incomplete);
+    /// # assert_eq!(incomplete, 0);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn read_ways_geometry<F, G>(&mut self, filter: F, mut geometry_callback: G) -> Result<u64>
+    where
+        F: for<'a> FnMut(&Way<'a>) -> bool,
+        G: for<'a> FnMut(&Way<'a>, &[Coordinate]),
+    {
+        let mut coordinates: HashMap<i64, Coordinate> = HashMap::new();
+        let mut points: Vec<Coordinate> = vec![];
+        let mut incomplete = 0;
+
+        self.read_ways_and_deps_ordered(filter, DepsOrder::NodesFirst, |element| match *element {
+            Element::Node(ref node) => {
+                coordinates.insert(node.id(), node.coordinate());
+            }
+            Element::DenseNode(ref node) => {
+                coordinates.insert(node.id, node.coordinate());
+            }
+            Element::Way(ref way) => {
+                points.clear();
+                for id in way.refs() {
+                    match coordinates.get(&id) {
+                        Some(&coord) => points.push(coord),
+                        None => {
+                            incomplete += 1;
+                            return;
+                        }
+                    }
+                }
+                geometry_callback(way, &points);
+            }
+            Element::Relation(_) => {}
+        })?;
+
+        Ok(incomplete)
+    }
+
+    /// Return ways that match the given `TagFilter` and their dependent nodes (`Node`s and
+    /// `DenseNode`s) in a closure. This behaves just like `read_ways_and_deps`, but the filter is
+    /// compiled once per block to stringtable indices, so no strings are compared for each way.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut ways = 0;
+    /// let mut nodes = 0;
+    ///
+    /// let filter = TagFilter::new().key_value("building", "yes");
+    ///
+    /// reader.read_ways_matching(&filter, |element| {
+    ///     match element {
+    ///         Element::Way(_) => ways += 1,
+    ///         _ => nodes += 1,
+    ///     }
+    /// })?;
+    ///
+    /// # assert_eq!(ways, 1);
+    /// # assert_eq!(nodes, 3);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn read_ways_matching<E>(
+        &mut self,
+        filter: &TagFilter,
+        element_callback: E,
+    ) -> Result<Vec<i64>>
+    where
+        E: for<'a> FnMut(&Element<'a>),
+    {
+        let mut filter = TagWayFilter {
+            filter,
+            compiled: None,
+            element_callback,
+        };
+        self.read_ways_and_deps_with(&mut filter, DepsOrder::WaysFirst, false, &mut |_| {})
+    }
+
+    /// Filter ways and return matching ways and their dependent nodes (`Node`s and `DenseNode`s)
+    /// with a `WayDepsVisitor`. This behaves just like `read_ways_and_deps`, but a single value
+    /// implements both the filter and the callback, so both can share mutable state without
+    /// `RefCell`s or a second pass over the collected results.
+    ///
+    /// On success, returns the sorted ids of all referenced nodes that could not be found in the
+    /// file.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// struct Buildings {
+    ///     ways: u64,
+    ///     nodes: u64,
+    /// }
+    ///
+    /// impl WayDepsVisitor for Buildings {
+    ///     fn keep_way(&mut self, way: &Way) -> bool {
+    ///         way.tags().any(|key_value| key_value == ("building", "yes"))
+    ///     }
+    ///
+    ///     fn visit(&mut self, element: &Element) {
+    ///         match *element {
+    ///             Element::Way(_) => self.ways += 1,
+    ///             _ => self.nodes += 1,
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut buildings = Buildings { ways: 0, nodes: 0 };
+    ///
+    /// reader.read_ways_and_deps_visitor(&mut buildings)?;
+    ///
+    /// println!("ways:  {}\nnodes: {}", buildings.ways, buildings.nodes);
+    /// # assert_eq!(buildings.ways, 1);
+    /// # assert_eq!(buildings.nodes, 3);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn read_ways_and_deps_visitor<V>(&mut self, visitor: &mut V) -> Result<Vec<i64>>
+    where
+        V: WayDepsVisitor,
+    {
+        self.read_ways_and_deps_with(
+            &mut VisitorWayFilter(visitor),
+            DepsOrder::WaysFirst,
+            false,
+            &mut |_| {},
+        )
+    }
+
+    fn read_ways_and_deps_with<W>(
+        &mut self,
+        filter: &mut W,
+        order: DepsOrder,
+        parallel: bool,
+        progress: &mut dyn FnMut(Progress),
+    ) -> Result<Vec<i64>>
+    where
+        W: WayFilter,
+    {
+        // Create index
+        self.ensure_index()?;
+
+        let total_blobs = self
+            .index
+            .iter()
+            .filter(|info| info.blob_type == SimpleBlobType::Primitive)
+            .count();
+        let mut blobs_processed = 0;
+
+        let mut node_ids: BTreeSet<i64> = BTreeSet::new();
+        let batches = filter.batches();
+
+        // Positions of matching ways that are returned after their nodes: index of the blob and
+        // indices of the ways in its block.
+        let mut deferred_ways: Vec<(usize, Vec<usize>)> = vec![];
+
+        // First pass:
+        //   * Filter ways and store their dependencies as node IDs
+        //   * Store ranges of element IDs (min and max value) of each block
+        for (blob_index, info) in self.index.iter_mut().enumerate() {
+            //TODO do something useful with header blocks
+            if info.blob_type == SimpleBlobType::Primitive {
+                let block = read_block_at(&mut self.reader, &mut self.block_cache, info.offset)?;
+                filter.begin_block(&block);
+                let mut way_index = 0;
+                let mut ways = vec![];
+                let mut deferred_way_indices = vec![];
+                for group in block.groups() {
+                    // filter ways and record node IDs
+                    for way in group.ways() {
+                        if filter.keep(&way) {
+                            let refs = way.refs();
+
+                            node_ids.extend(refs);
+
+                            match order {
+                                // Return way
+                                DepsOrder::WaysFirst if batches => ways.push(Element::Way(way)),
+                                DepsOrder::WaysFirst => filter.visit(&Element::Way(way)),
+                                DepsOrder::NodesFirst => deferred_way_indices.push(way_index),
+                            }
+                        }
+                        way_index += 1;
+                    }
+                }
+                if !ways.is_empty() {
+                    filter.visit_batch(&ways);
+                }
+                if !deferred_way_indices.is_empty() {
+                    deferred_ways.push((blob_index, deferred_way_indices));
+                }
+                info.index_block(&block);
+
+                blobs_processed += 1;
+                progress(Progress {
+                    pass: 1,
+                    blobs_processed,
+                    total_blobs,
+                    offset: info.offset,
+                });
+            }
+        }
+
+        // Second pass:
+        //   * Iterate only over blobs that may include the node IDs we're searching for
+        let found_node_ids = self.read_nodes_with_ids(
+            &node_ids,
+            &mut |elements: &[Element]| filter.visit_batch(elements),
+            2,
+            parallel,
+            progress,
+        )?;
+
+        // Third pass (only for `DepsOrder::NodesFirst`):
+        //   * Return the matching ways that were found in the first pass
+        for (blob_index, way_indices) in deferred_ways {
+            let offset = self.index[blob_index].offset;
+            let block = read_block_at(&mut self.reader, &mut self.block_cache, offset)?;
+            let mut way_indices = way_indices.into_iter().peekable();
+            let mut ways = vec![];
+            for (way_index, way) in block.groups().flat_map(|group| group.ways()).enumerate() {
+                if way_indices.peek() == Some(&way_index) {
+                    way_indices.next();
+                    ways.push(Element::Way(way));
+                }
+            }
+            filter.visit_batch(&ways);
+        }
+
+        Ok(node_ids.difference(&found_node_ids).cloned().collect())
+    }
+
+    /// Returns all nodes (`Node`s and `DenseNode`s) with the given ids in a closure, one call
+    /// with all matching nodes per block. Only blobs whose id ranges are already known and may
+    /// include the ids are read. Returns the ids of the nodes that were found. `progress` is
+    /// called with the given pass number after each primitive blob.
+    ///
+    /// If `parallel` is true, the blobs are read in batches and each batch is decoded in
+    /// parallel. The closures are still called on the current thread in file order.
+    fn read_nodes_with_ids<E>(
+        &mut self,
+        node_ids: &BTreeSet<i64>,
+        element_callback: &mut E,
+        pass: u32,
+        parallel: bool,
+        progress: &mut dyn FnMut(Progress),
+    ) -> Result<BTreeSet<i64>>
+    where
+        E: for<'a> FnMut(&[Element<'a>]),
+    {
+        let mut found_node_ids: BTreeSet<i64> = BTreeSet::new();
+
+        let total_blobs = self
+            .index
+            .iter()
+            .filter(|info| info.blob_type == SimpleBlobType::Primitive)
+            .count();
+        let mut blobs_processed = 0;
+
+        let batch_size = if parallel {
+            ::rayon::current_num_threads() * 4
+        } else {
+            1
+        };
+        let mut infos = self
+            .index
+            .iter()
+            .filter(|info| info.blob_type == SimpleBlobType::Primitive)
+            .peekable();
+
+        while infos.peek().is_some() {
+            // Read the blobs of the batch that may include the node ids, together with the ids
+            // in their range.
+            let mut batch = Vec::with_capacity(batch_size);
+            for info in infos.by_ref().take(batch_size) {
+                let node_id_range = info
+                    .id_ranges
+                    .as_ref()
+                    .and_then(|r| r.node_ids.as_ref())
+                    .filter(|range| range_included((*range).clone(), node_ids));
+                let selected = match node_id_range {
+                    Some(range) => {
+                        //TODO Only collect into Vec if range has a reasonable size
+                        let ids: Vec<i64> = node_ids.range(range.clone()).cloned().collect();
+                        let block = match self.block_cache.get(info.offset) {
+                            Some(block) => Ok(block),
+                            None => Err(read_blob_at(&mut self.reader, info.offset)?),
+                        };
+                        Some((block, ids))
+                    }
+                    None => None,
+                };
+                batch.push((info.offset, selected));
+            }
+
+            // Cached blocks are `Ok`, blobs that still have to be decoded are `Err`.
+            type Selected = Option<(std::result::Result<Arc<PrimitiveBlock>, Blob>, Vec<i64>)>;
+            let decode = |(offset, selected): (ByteOffset, Selected)| -> Result<_> {
+                let selected = match selected {
+                    Some((Ok(block), ids)) => Some((block, ids, false)),
+                    Some((Err(blob), ids)) => {
+                        Some((Arc::new(blob.to_primitiveblock()?), ids, true))
+                    }
+                    None => None,
+                };
+                Ok((offset, selected))
+            };
+            let batch = if parallel {
+                batch
+                    .into_par_iter()
+                    .map(decode)
+                    .collect::<Result<Vec<_>>>()?
+            } else {
+                batch.into_iter().map(decode).collect::<Result<Vec<_>>>()?
+            };
+
+            for (offset, selected) in batch {
+                if let Some((block, ids, decoded)) = selected {
+                    {
+                        let mut nodes = vec![];
+                        block.for_each_node_with_ids(&ids, |index, element| {
+                            // ID found, return node
+                            found_node_ids.insert(ids[index]);
+                            nodes.push(element);
+                        });
+                        if !nodes.is_empty() {
+                            element_callback(&nodes);
+                        }
+                    }
+                    if decoded {
+                        self.block_cache.insert(offset, block);
+                    }
+                }
+
+                blobs_processed += 1;
+                progress(Progress {
+                    pass,
+                    blobs_processed,
+                    total_blobs,
+                    offset,
+                });
+            }
+        }
+
+        Ok(found_node_ids)
+    }
+
+    /// Filter relations using a closure and return matching relations and their members in
+    /// another closure. Member ways are returned together with their dependent nodes, so all
+    /// elements that are needed to build the geometry of a relation (e.g. a boundary) are
+    /// returned.
+    ///
+    /// Members that are relations themselves are resolved for one level: the member relations
+    /// and their node and way members (including the nodes of these ways) are returned as well.
+    /// Relations that are members of member relations are not returned.
+    ///
+    /// The elements are returned in this order: matching relations, member relations, ways, nodes.
+    /// Members that do not exist in the file are skipped.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut relations = 0;
+    /// let mut ways = 0;
+    /// let mut nodes = 0;
+    ///
+    /// reader.read_relations_and_deps(
+    ///     |relation| relation.tags().any(|key_value| key_value == ("rel_key", "rel_value")),
+    ///     |element| {
+    ///         match element {
+    ///             Element::Relation(_) => relations += 1,
+    ///             Element::Way(_) => ways += 1,
+    ///             Element::Node(_) | Element::DenseNode(_) => nodes += 1,
+    ///         }
+    ///     },
+    /// )?;
+    ///
+    /// println!("relations: {}\nways: {}\nnodes: {}", relations, ways, nodes);
+    ///
+    /// # assert_eq!((relations, ways, nodes), (1, 1, 3));
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn read_relations_and_deps<F, E>(&mut self, filter: F, element_callback: E) -> Result<()>
+    where
+        F: for<'a> FnMut(&Relation<'a>) -> bool,
+        E: for<'a> FnMut(&Element<'a>),
+    {
+        self.relations_and_deps(filter, element_callback, None)
+    }
+
+    /// Like `read_relations_and_deps`, but also computes the bounding box of each matching
+    /// relation. Relations have no coordinates of their own, so the box covers the resolved
+    /// nodes of their members: the member nodes, the nodes of the member ways and the same for
+    /// the member relations (one level, like the returned elements).
+    ///
+    /// After all elements have been returned, `bbox_callback` is called once for each matching
+    /// relation, in the order of the file, together with its bounding box. The bounding box is
+    /// `None` if none of the nodes could be resolved, e.g. because the members are missing from
+    /// the file. The members and the coordinates of all returned nodes are kept in memory until
+    /// then.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// reader.read_relations_and_deps_with_bbox(
+    ///     |relation| relation.tags().any(|key_value| key_value == ("rel_key", "rel_value")),
+    ///     |_element| {},
+    ///     |relation, bbox| match bbox {
+    ///         Some(bbox) => println!("relation {}: {:?}", relation.id(), bbox),
+    ///         None => println!("relation {} has no nodes", relation.id()),
+    ///     },
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn read_relations_and_deps_with_bbox<F, E, B>(
+        &mut self,
+        filter: F,
+        element_callback: E,
+        mut bbox_callback: B,
+    ) -> Result<()>
+    where
+        F: for<'a> FnMut(&Relation<'a>) -> bool,
+        E: for<'a> FnMut(&Element<'a>),
+        B: for<'a> FnMut(&Relation<'a>, Option<BoundingBox>),
+    {
+        self.relations_and_deps(filter, element_callback, Some(&mut bbox_callback))
+    }
+
+    fn relations_and_deps<F, E>(
+        &mut self,
+        mut filter: F,
+        mut element_callback: E,
+        bbox_callback: Option<&mut dyn for<'a> FnMut(&Relation<'a>, Option<BoundingBox>)>,
+    ) -> Result<()>
+    where
+        F: for<'a> FnMut(&Relation<'a>) -> bool,
+        E: for<'a> FnMut(&Element<'a>),
+    {
+        // Create index
+        self.ensure_index()?;
+
+        let mut relation_ids: BTreeSet<i64> = BTreeSet::new();
+        let mut member_relation_ids: BTreeSet<i64> = BTreeSet::new();
+        let mut way_ids: BTreeSet<i64> = BTreeSet::new();
+        let mut node_ids: BTreeSet<i64> = BTreeSet::new();
+
+        // Only needed for the bounding boxes
+        let with_bbox = bbox_callback.is_some();
+        let mut relation_members: HashMap<i64, RelationMembers> = HashMap::new();
+        let mut member_relation_members: HashMap<i64, RelationMembers> = HashMap::new();
+        let mut relation_offsets: Vec<ByteOffset> = vec![];
+        let mut way_refs: HashMap<i64, Vec<i64>> = HashMap::new();
+        let mut coordinates: HashMap<i64, Coordinate> = HashMap::new();
+
+        // First pass:
+        //   * Filter relations and store their members as IDs
+        //   * Store ranges of element IDs (min and max value) of each block
+        for info in &mut self.index {
+            if info.blob_type == SimpleBlobType::Primitive {
+                let block = read_block_at(&mut self.reader, &mut self.block_cache, info.offset)?;
+                for group in block.groups() {
+                    for relation in group.relations() {
+                        if filter(&relation) {
+                            relation_ids.insert(relation.id());
+                            collect_member_ids(
+                                &relation,
+                                &mut member_relation_ids,
+                                &mut way_ids,
+                                &mut node_ids,
+                            );
+                            if with_bbox {
+                                relation_members
+                                    .insert(relation.id(), RelationMembers::new(&relation));
+                                if relation_offsets.last() != Some(&info.offset) {
+                                    relation_offsets.push(info.offset);
+                                }
+                            }
+                            element_callback(&Element::Relation(relation));
+                        }
+                    }
+                }
+                info.index_block(&block);
+            }
+        }
+
+        // Second pass:
+        //   * Return member relations that have not been returned yet and store their members
+        //   * Members of member relations that are relations themselves are ignored
+        let member_relation_ids: BTreeSet<i64> = member_relation_ids
+            .difference(&relation_ids)
+            .cloned()
+            .collect();
+        let mut ignored_relation_ids: BTreeSet<i64> = BTreeSet::new();
+        for info in &self.index {
+            if let Some(range) = info.id_ranges.as_ref().and_then(|r| r.relation_ids()) {
+                if range_included(range, &member_relation_ids) {
+                    let block =
+                        read_block_at(&mut self.reader, &mut self.block_cache, info.offset)?;
+                    for group in block.groups() {
+                        for relation in group.relations() {
+                            if member_relation_ids.contains(&relation.id()) {
+                                collect_member_ids(
+                                    &relation,
+                                    &mut ignored_relation_ids,
+                                    &mut way_ids,
+                                    &mut node_ids,
+                                );
+                                if with_bbox {
+                                    member_relation_members
+                                        .insert(relation.id(), RelationMembers::new(&relation));
+                                }
+                                element_callback(&Element::Relation(relation));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Third pass:
+        //   * Return member ways and store their dependencies as node IDs
+        for info in &self.index {
+            if let Some(range) = info.id_ranges.as_ref().and_then(|r| r.way_ids()) {
+                if range_included(range, &way_ids) {
+                    let block =
+                        read_block_at(&mut self.reader, &mut self.block_cache, info.offset)?;
+                    for group in block.groups() {
+                        for way in group.ways() {
+                            if way_ids.contains(&way.id()) {
+                                node_ids.extend(way.refs());
+                                if with_bbox {
+                                    way_refs.insert(way.id(), way.refs().collect());
+                                }
+                                element_callback(&Element::Way(way));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Fourth pass:
+        //   * Return member nodes and nodes of member ways
+        self.read_nodes_with_ids(
+            &node_ids,
+            &mut |elements: &[Element]| {
+                for element in elements {
+                    if with_bbox {
+                        match *element {
+                            Element::Node(ref node) => {
+                                coordinates.insert(node.id(), node.coordinate());
+                            }
+                            Element::DenseNode(ref node) => {
+                                coordinates.insert(node.id, node.coordinate());
+                            }
+                            _ => {}
+                        }
+                    }
+                    element_callback(element);
+                }
+            },
+            4,
+            false,
+            &mut |_| {},
+        )?;
+
+        // Fifth pass:
+        //   * Return the matching relations again together with their bounding boxes
+        if let Some(bbox_callback) = bbox_callback {
+            for offset in relation_offsets {
+                let block = read_block_at(&mut self.reader, &mut self.block_cache, offset)?;
+                for group in block.groups() {
+                    for relation in group.relations() {
+                        if let Some(members) = relation_members.get(&relation.id()) {
+                            let mut bbox = None;
+                            members.extend_bbox(&mut bbox, &way_refs, &coordinates);
+                            for id in &members.relations {
+                                let member = member_relation_members
+                                    .get(id)
+                                    .or_else(|| relation_members.get(id));
+                                if let Some(member) = member {
+                                    member.extend_bbox(&mut bbox, &way_refs, &coordinates);
+                                }
+                            }
+                            bbox_callback(&relation, bbox);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Filter nodes (`Node`s and `DenseNode`s) using a closure and return matching nodes in
+    /// another closure. If `include_ways` is true, all ways that reference at least one of the
+    /// matching nodes are returned as well, after all nodes.
+    ///
+    /// Ways reference nodes but not the other way around, so finding the ways of a node requires
+    /// a second pass over all blocks with ways. No reverse index from nodes to ways is built for
+    /// this; only the ids of the matching nodes are kept in memory, so memory usage grows with
+    /// the number of matching nodes and not with the size of the file. To tell which of the
+    /// matching nodes a returned way references, check its `refs`.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut pois = 0;
+    /// let mut ways = 0;
+    ///
+    /// // Find all amenities and the ways they are part of.
+    /// reader.read_nodes_and_deps(
+    ///     |element| match element {
+    ///         Element::Node(node) => node.tags().any(|kv| kv.0 == "amenity"),
+    ///         Element::DenseNode(node) => node.tags().any(|kv| kv.0 == "amenity"),
+    ///         _ => false,
+    ///     },
+    ///     true,
+    ///     |element| {
+    ///         match element {
+    ///             Element::Way(_) => ways += 1,
+    ///             _ => pois += 1,
+    ///         }
+    ///     },
+    /// )?;
+    ///
+    /// println!("amenities: {}\nways: {}", pois, ways);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn read_nodes_and_deps<F, E>(
+        &mut self,
+        mut filter: F,
+        include_ways: bool,
+        mut element_callback: E,
+    ) -> Result<()>
+    where
+        F: for<'a> FnMut(&Element<'a>) -> bool,
+        E: for<'a> FnMut(&Element<'a>),
+    {
+        // Create index
+        self.ensure_index()?;
+
+        let mut node_ids: BTreeSet<i64> = BTreeSet::new();
+
+        // First pass:
+        //   * Filter nodes and store their IDs
+        //   * Store ranges of element IDs (min and max value) of each block
+        for info in &mut self.index {
+            if info.blob_type == SimpleBlobType::Primitive {
+                let block = read_block_at(&mut self.reader, &mut self.block_cache, info.offset)?;
+                for group in block.groups() {
+                    for node in group.nodes() {
+                        let id = node.id();
+                        let element = Element::Node(node);
+                        if filter(&element) {
+                            node_ids.insert(id);
+                            element_callback(&element);
+                        }
+                    }
+                    for node in group.dense_nodes() {
+                        let id = node.id;
+                        let element = Element::DenseNode(node);
+                        if filter(&element) {
+                            node_ids.insert(id);
+                            element_callback(&element);
+                        }
+                    }
+                }
+                info.index_block(&block);
+            }
+        }
+
+        if !include_ways || node_ids.is_empty() {
+            return Ok(());
+        }
+
+        // Second pass:
+        //   * Return ways that reference at least one of the matching nodes
+        for info in &self.index {
+            if info.id_ranges.as_ref().and_then(|r| r.way_ids()).is_some() {
+                let block = read_block_at(&mut self.reader, &mut self.block_cache, info.offset)?;
+                for group in block.groups() {
+                    for way in group.ways() {
+                        if way.refs().any(|id| node_ids.contains(&id)) {
+                            element_callback(&Element::Way(way));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the bounding box of all nodes in the file, or `None` if the file has no nodes.
+    /// Unlike the bounding box in the header of a file (see `HeaderBlock::bbox`), which might be
+    /// loose or missing, e.g. after cropping, it is computed from the actual coordinates. The
+    /// granularity and offsets of each block are applied (see `Coordinate`).
+    ///
+    /// The bounding boxes of the nodes of each blob are stored in the index (see
+    /// `BlobSummary::node_bbox`). If the index does not contain them for all blobs yet, a full
+    /// index is created first (see `create_full_index`), so only the first call decodes the
+    /// file.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// if let Some(bbox) = reader.compute_bounding_box()? {
+    ///     let (min, max) = (bbox.min(), bbox.max());
+    ///     println!("{} {} {} {}", min.lat(), min.lon(), max.lat(), max.lon());
+    /// #   assert!(bbox.min().lat() < bbox.max().lat());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn compute_bounding_box(&mut self) -> Result<Option<BoundingBox>> {
+        self.ensure_full_index()?;
+
+        let mut bbox: Option<BoundingBox> = None;
+        for node_bbox in self.index.iter().filter_map(|info| info.node_bbox) {
+            match bbox {
+                Some(ref mut bbox) => {
+                    bbox.extend(node_bbox.min());
+                    bbox.extend(node_bbox.max());
+                }
+                None => bbox = Some(node_bbox),
+            }
+        }
+        Ok(bbox)
+    }
+
+    /// Return all nodes (`Node`s and `DenseNode`s) inside of the given bounding box in a
+    /// closure. The bounding box is given in degrees as `(min_lat, min_lon, max_lat, max_lon)`,
+    /// its border is included.
+    ///
+    /// If the header of the file declares a bounding box that does not intersect the given one,
+    /// no blocks are decoded at all. Otherwise, all blocks are read once and the bounding box of
+    /// their nodes is stored in the index (see `BlobSummary::node_bbox`), so that later calls
+    /// skip blocks whose nodes are all outside of the queried area.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut nodes = 0;
+    ///
+    /// reader.read_bbox((52.1198, 11.62, 52.1200, 11.64), |element| {
+    ///     match element {
+    ///         Element::Node(_) | Element::DenseNode(_) => nodes += 1,
+    ///         _ => (), // should not occur
+    ///     }
+    /// })?;
+    ///
+    /// println!("nodes: {}", nodes);
+    ///
+    /// # assert_eq!(nodes, 2);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn read_bbox<E>(
+        &mut self,
+        bbox: (f64, f64, f64, f64),
+        mut element_callback: E,
+    ) -> Result<()>
+    where
+        E: for<'a> FnMut(&Element<'a>),
+    {
+        let (min_lat, min_lon, max_lat, max_lon) = bbox;
+        let bbox = BoundingBox::new(
+            Coordinate::from_degrees(min_lat, min_lon),
+            Coordinate::from_degrees(max_lat, max_lon),
+        );
+
+        // Create index
+        self.ensure_index()?;
+
+        for info in &mut self.index {
+            match info.blob_type {
+                SimpleBlobType::Header => {
+                    let header = read_blob_at(&mut self.reader, info.offset)?.to_headerblock()?;
+                    if let Some(file_bbox) = header.bbox() {
+                        if !file_bbox.intersects(&bbox) {
+                            return Ok(());
+                        }
+                    }
+                }
+                SimpleBlobType::Primitive => {
+                    // Skip blocks that are known to have no nodes inside of the bounding box
+                    let known_outside = match info.node_bbox {
+                        Some(node_bbox) => !node_bbox.intersects(&bbox),
+                        None => info.id_ranges.is_some(),
+                    };
+                    if known_outside {
+                        continue;
+                    }
+
+                    let block =
+                        read_block_at(&mut self.reader, &mut self.block_cache, info.offset)?;
+                    for group in block.groups() {
+                        for node in group.nodes() {
+                            if bbox.contains(node.coordinate()) {
+                                element_callback(&Element::Node(node));
+                            }
+                        }
+                        for node in group.dense_nodes() {
+                            if bbox.contains(node.coordinate()) {
+                                element_callback(&Element::DenseNode(node));
+                            }
+                        }
+                    }
+                    info.index_block(&block);
+                }
+                SimpleBlobType::Unknown => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return all nodes (`Node`s and `DenseNode`s) inside of the given polygon and the ways
+    /// that reference them in a closure. The polygon is a single ring of `(lat, lon)` points in
+    /// degrees. It may be given clockwise or counterclockwise and does not need to repeat the
+    /// first point at the end. Rings with fewer than three points contain nothing.
+    ///
+    /// Consecutive points are connected along the shorter way around the globe, so a ring may
+    /// cross the antimeridian, e.g. `(-1.0, 179.0)` followed by `(-1.0, -179.0)` is an edge of two
+    /// degrees. Rings that enclose a pole are not supported.
+    ///
+    /// All matching ways are returned first, then the nodes. `completeness` selects whether the
+    /// nodes of ways that cross the border of the polygon are returned as well. Ways are matched
+    /// by their nodes only: a way whose segments pass through the polygon without having a node
+    /// inside of it is not returned. Relations are never returned.
+    ///
+    /// Like `read_bbox`, the blocks are prefiltered with the bounding box of the polygon and the
+    /// bounding boxes of their nodes (see `BlobSummary::node_bbox`), so only blocks that may
+    /// contain matching nodes are tested point by point. All blocks with ways are decoded once.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let ring = [(52.119, 11.62), (52.121, 11.62), (52.121, 11.64), (52.119, 11.64)];
+    /// let mut ways = 0;
+    /// let mut nodes = 0;
+    ///
+    /// reader.read_polygon(&ring, WayCompleteness::CompleteWays, |element| {
+    ///     match element {
+    ///         Element::Way(_) => ways += 1,
+    ///         Element::Node(_) | Element::DenseNode(_) => nodes += 1,
+    ///         Element::Relation(_) => (), // should not occur
+    ///     }
+    /// })?;
+    ///
+    /// println!("ways: {}\nnodes: {}", ways, nodes);
+    ///
+    /// # assert_eq!((ways, nodes), (1, 3));
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn read_polygon<E>(
+        &mut self,
+        ring: &[(f64, f64)],
+        completeness: WayCompleteness,
+        mut element_callback: E,
+    ) -> Result<()>
+    where
+        E: for<'a> FnMut(&Element<'a>),
+    {
+        let polygon = Polygon::new(ring);
+        if polygon.bboxes.is_empty() {
+            return Ok(());
+        }
+
+        // Create index
+        self.ensure_index()?;
+
+        // First pass:
+        //   * Store the ids of all nodes inside of the polygon
+        //   * Store ranges of element IDs and node bounding boxes of each block
+        let mut inside_ids: BTreeSet<i64> = BTreeSet::new();
+        for info in &mut self.index {
+            match info.blob_type {
+                SimpleBlobType::Header => {
+                    let header = read_blob_at(&mut self.reader, info.offset)?.to_headerblock()?;
+                    if let Some(file_bbox) = header.bbox() {
+                        if !polygon.intersects(&file_bbox) {
+                            return Ok(());
+                        }
+                    }
+                }
+                SimpleBlobType::Primitive => {
+                    // Skip blocks that are known to have no nodes inside of the polygon
+                    let known_outside = match info.node_bbox {
+                        Some(node_bbox) => !polygon.intersects(&node_bbox),
+                        None => info.id_ranges.is_some(),
+                    };
+                    if known_outside {
+                        continue;
+                    }
+
+                    let block =
+                        read_block_at(&mut self.reader, &mut self.block_cache, info.offset)?;
+                    for group in block.groups() {
+                        for node in group.nodes() {
+                            if polygon.contains(node.coordinate()) {
+                                inside_ids.insert(node.id());
+                            }
+                        }
+                        for node in group.dense_nodes() {
+                            if polygon.contains(node.coordinate()) {
+                                inside_ids.insert(node.id);
+                            }
+                        }
+                    }
+                    info.index_block(&block);
+                }
+                SimpleBlobType::Unknown => {}
+            }
+        }
+
+        if inside_ids.is_empty() {
+            return Ok(());
+        }
+
+        // Second pass:
+        //   * Return ways with at least one node inside of the polygon
+        //   * Store the ids of their other nodes if complete ways are requested
+        let mut node_ids = inside_ids.clone();
+        for info in &self.index {
+            if info.id_ranges.as_ref().and_then(|r| r.way_ids()).is_some() {
+                let block = read_block_at(&mut self.reader, &mut self.block_cache, info.offset)?;
+                for group in block.groups() {
+                    for way in group.ways() {
+                        if way.refs().any(|id| inside_ids.contains(&id)) {
+                            if completeness == WayCompleteness::CompleteWays {
+                                node_ids.extend(way.refs());
+                            }
+                            element_callback(&Element::Way(way));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Third pass:
+        //   * Return the nodes
+        self.read_nodes_with_ids(
+            &node_ids,
+            &mut |elements: &[Element]| elements.iter().for_each(&mut element_callback),
+            3,
+            false,
+            &mut |_| {},
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns the node (`Node` or `DenseNode`) with the given id or `None` if the file does
+    /// not contain it.
+    ///
+    /// Blobs whose id ranges are already known (e.g. after `create_full_index` or a previous
+    /// search) are only decoded if their node id range includes the id. All other blobs are
+    /// decoded and indexed on the way, so repeated lookups get faster. The search stops at the
+    /// first match, so for files with historical information only one version is returned.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// if let Some(node) = reader.get_node(105)? {
+    ///     println!("node {} is at {:?}", node.id, node.coordinate);
+    /// }
+    /// # assert!(reader.get_node(105)?.is_some());
+    /// # assert!(reader.get_node(107)?.is_none());
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn get_node(&mut self, id: i64) -> Result<Option<OwnedNode>> {
+        self.find_element(id, IdRanges::node_ids, |block| {
+            for group in block.groups() {
+                if let Some(node) = group.nodes().find(|node| node.id() == id) {
+                    return Ok(Some(node.to_owned()));
+                }
+                if let Some(node) = group.dense_nodes().find(|node| node.id == id) {
+                    return Ok(Some(node.to_owned()));
+                }
+            }
+            Ok(None)
+        })
+    }
+
+    /// Returns the way with the given id or `None` if the file does not contain it (see
+    /// `get_node`).
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// if let Some(way) = reader.get_way(107)? {
+    ///     println!("way {} has the nodes {:?}", way.id, way.refs);
+    /// }
+    /// # assert_eq!(reader.get_way(107)?.map(|way| way.refs.len()), Some(4));
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn get_way(&mut self, id: i64) -> Result<Option<OwnedWay>> {
+        self.find_element(id, IdRanges::way_ids, |block| {
+            let mut ways = block.groups().flat_map(|group| group.ways());
+            Ok(ways.find(|way| way.id() == id).map(|way| way.to_owned()))
+        })
+    }
+
+    /// Returns the relation with the given id or `None` if the file does not contain it (see
+    /// `get_node`).
+    ///
+    /// # Errors
+    /// Returns an error if the role of a member cannot be resolved (see `Relation::to_owned`)
+    /// and the errors of reading and decoding the file.
+    pub fn get_relation(&mut self, id: i64) -> Result<Option<OwnedRelation>> {
+        self.find_element(id, IdRanges::relation_ids, |block| {
+            let mut relations = block.groups().flat_map(|group| group.relations());
+            match relations.find(|relation| relation.id() == id) {
+                Some(relation) => relation.to_owned().map(Some),
+                None => Ok(None),
+            }
+        })
+    }
+
+    /// Returns the offset of the first blob whose id range of the given element type contains
+    /// `id`, without decoding any blobs, or `None` if no blob may contain the element. This
+    /// allows collecting the blobs of many elements, e.g. the members of a relation, and
+    /// fetching them later on demand.
+    ///
+    /// The id ranges of all blobs are needed for this, so a full index is created first if the
+    /// index does not contain them yet (see `create_full_index`). An id range only stores the
+    /// minimum and maximum id of a blob, so the element is not guaranteed to exist in the
+    /// returned blob. In files that are sorted by type and id (the optional feature
+    /// "Sort.Type_then_ID", see `HeaderBlock::optional_features`), the ranges do not overlap and
+    /// the returned blob is the only one that may contain the element.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut fetch_queue = vec![];
+    ///
+    /// if let Some(relation) = reader.get_relation(120)? {
+    ///     for member in &relation.members {
+    ///         if let Some(offset) = reader.locate(member.member_type, member.member_id)? {
+    ///             fetch_queue.push((member.member_id, offset));
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// // ... later, fetch a member on demand
+    /// let mut blobs = BlobReader::seekable_from_path("tests/test.osm.pbf")?;
+    /// for (id, offset) in fetch_queue {
+    ///     blobs.seek(offset)?;
+    ///     let block = blobs.next().unwrap()?.to_primitiveblock()?;
+    ///     println!("member {} is in a block with {} elements", id, block.elements().count());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn locate(&mut self, member_type: RelMemberType, id: i64) -> Result<Option<ByteOffset>> {
+        self.ensure_full_index()?;
+
+        let range: fn(&IdRanges) -> Option<RangeInclusive<i64>> = match member_type {
+            RelMemberType::Node => IdRanges::node_ids,
+            RelMemberType::Way => IdRanges::way_ids,
+            RelMemberType::Relation => IdRanges::relation_ids,
+        };
+        Ok(self
+            .index
+            .iter()
+            .filter(|info| match info.id_ranges.as_ref().and_then(range) {
+                Some(ids) => ids.contains(&id),
+                None => false,
+            })
+            .map(|info| info.offset)
+            .next())
+    }
+
+    /// Reads the nodes (`Node` or `DenseNode`), ways and relations with the given ids in a single
+    /// pass over the file and calls `element_callback` on each of them in file order. This is a
+    /// batch version of `get_node`, `get_way` and `get_relation` for many ids, e.g. the result of
+    /// an external routing engine. Ids that the file does not contain are ignored, the lists do
+    /// not need to be sorted and may contain duplicates. If all lists are empty, nothing is read.
+    ///
+    /// Blobs whose id ranges are already known (e.g. after `create_full_index` or a previous
+    /// search) are only decoded if one of their id ranges includes a requested id of the same
+    /// element type. All other blobs are decoded and indexed on the way, so repeated queries get
+    /// faster. For files with historical information, all versions of the elements are returned.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut found = vec![];
+    ///
+    /// reader.read_ids(&[106, 105], &[107], &[], |element| found.push(element.id()))?;
+    ///
+    /// assert_eq!(found, vec![105, 106, 107]);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn read_ids<E>(
+        &mut self,
+        nodes: &[i64],
+        ways: &[i64],
+        relations: &[i64],
+        mut element_callback: E,
+    ) -> Result<()>
+    where
+        E: for<'a> FnMut(&Element<'a>),
+    {
+        let node_ids: BTreeSet<i64> = nodes.iter().cloned().collect();
+        let way_ids: BTreeSet<i64> = ways.iter().cloned().collect();
+        let relation_ids: BTreeSet<i64> = relations.iter().cloned().collect();
+        if node_ids.is_empty() && way_ids.is_empty() && relation_ids.is_empty() {
+            return Ok(());
+        }
+
+        // Create index
+        self.ensure_index()?;
+
+        for info in &mut self.index {
+            if info.blob_type != SimpleBlobType::Primitive {
+                continue;
+            }
+            if let Some(ref ranges) = info.id_ranges {
+                let included = |range: Option<RangeInclusive<i64>>, ids: &BTreeSet<i64>| match range
+                {
+                    Some(range) => range_included(range, ids),
+                    None => false,
+                };
+                if !included(ranges.node_ids(), &node_ids)
+                    && !included(ranges.way_ids(), &way_ids)
+                    && !included(ranges.relation_ids(), &relation_ids)
+                {
+                    continue;
+                }
+            }
+
+            let block = read_block_at(&mut self.reader, &mut self.block_cache, info.offset)?;
+            for group in block.groups() {
+                if !node_ids.is_empty() {
+                    for node in group.nodes() {
+                        if node_ids.contains(&node.id()) {
+                            element_callback(&Element::Node(node));
+                        }
+                    }
+                    for node in group.dense_nodes() {
+                        if node_ids.contains(&node.id) {
+                            element_callback(&Element::DenseNode(node));
+                        }
+                    }
+                }
+                if !way_ids.is_empty() {
+                    for way in group.ways() {
+                        if way_ids.contains(&way.id()) {
+                            element_callback(&Element::Way(way));
+                        }
+                    }
+                }
+                if !relation_ids.is_empty() {
+                    for relation in group.relations() {
+                        if relation_ids.contains(&relation.id()) {
+                            element_callback(&Element::Relation(relation));
+                        }
+                    }
+                }
+            }
+            if info.id_ranges.is_none() {
+                info.index_block(&block);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the blobs that may contain an element with the given id until `find` returns it.
+    /// `range` selects the id range of the element type.
+    fn find_element<T, F>(
+        &mut self,
+        id: i64,
+        range: fn(&IdRanges) -> Option<RangeInclusive<i64>>,
+        mut find: F,
+    ) -> Result<Option<T>>
+    where
+        F: FnMut(&PrimitiveBlock) -> Result<Option<T>>,
+    {
+        // Create index
+        self.ensure_index()?;
+
+        for info in &mut self.index {
+            if info.blob_type != SimpleBlobType::Primitive {
+                continue;
+            }
+            if let Some(ref ranges) = info.id_ranges {
+                match range(ranges) {
+                    Some(ref ids) if ids.contains(&id) => {}
+                    _ => continue,
+                }
+            }
+            let block = read_block_at(&mut self.reader, &mut self.block_cache, info.offset)?;
+            if info.id_ranges.is_none() {
+                info.index_block(&block);
+            }
+            if let Some(element) = find(&block)? {
+                return Ok(Some(element));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Checks that all references in the file can be resolved: every node of a way and every
+    /// member of a relation has to exist in the file. Missing elements are counted and sampled
+    /// in the returned report instead of causing an error, because extracts usually contain
+    /// references to elements outside of their area.
+    ///
+    /// The first pass reads all blocks to collect the references and to index their id ranges.
+    /// The following passes only decode the blocks whose id ranges may contain the referenced
+    /// elements. The referenced ids are kept in memory.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let report = reader.validate_references()?;
+    ///
+    /// if !report.is_valid() {
+    ///     println!(
+    ///         "{} missing nodes (e.g. {:?}), {} missing relation members",
+    ///         report.missing_way_nodes,
+    ///         report.missing_way_node_samples,
+    ///         report.missing_relation_members
+    ///     );
+    /// }
+    /// # assert!(report.is_valid());
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn validate_references(&mut self) -> Result<ValidationReport> {
+        // Create index
+        self.ensure_index()?;
+
+        let mut way_node_ids: BTreeSet<i64> = BTreeSet::new();
+        let mut member_relation_ids: BTreeSet<i64> = BTreeSet::new();
+        let mut member_way_ids: BTreeSet<i64> = BTreeSet::new();
+        let mut member_node_ids: BTreeSet<i64> = BTreeSet::new();
+
+        // First pass:
+        //   * Collect the node ids of all ways and the members of all relations
+        //   * Store ranges of element IDs (min and max value) of each block
+        for info in &mut self.index {
+            if info.blob_type == SimpleBlobType::Primitive {
+                let block = read_block_at(&mut self.reader, &mut self.block_cache, info.offset)?;
+                for group in block.groups() {
+                    for way in group.ways() {
+                        way_node_ids.extend(way.refs());
+                    }
+                    for relation in group.relations() {
+                        collect_member_ids(
+                            &relation,
+                            &mut member_relation_ids,
+                            &mut member_way_ids,
+                            &mut member_node_ids,
+                        );
+                    }
+                }
+                info.index_block(&block);
+            }
+        }
+
+        // Second pass:
+        //   * Find the referenced nodes
+        let node_ids: BTreeSet<i64> = way_node_ids.union(&member_node_ids).cloned().collect();
+        let found_node_ids =
+            self.read_nodes_with_ids(&node_ids, &mut |_: &[Element]| {}, 2, false, &mut |_| {})?;
+
+        // Third pass:
+        //   * Find the referenced ways and relations
+        let mut found_way_ids: BTreeSet<i64> = BTreeSet::new();
+        let mut found_relation_ids: BTreeSet<i64> = BTreeSet::new();
+        let may_include = |range: &Option<RangeInclusive<i64>>, ids: &BTreeSet<i64>| match *range {
+            Some(ref range) => range_included(range.clone(), ids),
+            None => false,
+        };
+        for info in &self.index {
+            let (has_ways, has_relations) = match info.id_ranges {
+                Some(ref ranges) => (
+                    may_include(&ranges.way_ids, &member_way_ids),
+                    may_include(&ranges.relation_ids, &member_relation_ids),
+                ),
+                None => (false, false),
+            };
+            if !has_ways && !has_relations {
+                continue;
+            }
+
+            let block = read_block_at(&mut self.reader, &mut self.block_cache, info.offset)?;
+            for group in block.groups() {
+                for way in group.ways() {
+                    if member_way_ids.contains(&way.id()) {
+                        found_way_ids.insert(way.id());
+                    }
+                }
+                for relation in group.relations() {
+                    if member_relation_ids.contains(&relation.id()) {
+                        found_relation_ids.insert(relation.id());
+                    }
+                }
+            }
+        }
+
+        let (missing_way_nodes, missing_way_node_samples) =
+            count_with_samples(way_node_ids.difference(&found_node_ids).cloned());
+        let missing_members = member_node_ids
+            .difference(&found_node_ids)
+            .map(|&id| (RelMemberType::Node, id))
+            .chain(
+                member_way_ids
+                    .difference(&found_way_ids)
+                    .map(|&id| (RelMemberType::Way, id)),
+            )
+            .chain(
+                member_relation_ids
+                    .difference(&found_relation_ids)
+                    .map(|&id| (RelMemberType::Relation, id)),
+            );
+        let (missing_relation_members, missing_relation_member_samples) =
+            count_with_samples(missing_members);
+
+        Ok(ValidationReport {
+            missing_way_nodes,
+            missing_way_node_samples,
+            missing_relation_members,
+            missing_relation_member_samples,
+        })
+    }
+
+    /// Writes an extract of the given bounding box as a new PBF file to `writer`. The bounding
+    /// box is given in degrees as `(min_lat, min_lon, max_lat, max_lon)`, like for `read_bbox`.
+    ///
+    /// The extract contains all nodes inside of the bounding box and all ways with at least one
+    /// node inside of it. Ways are not cut at the border, instead all of their nodes are written
+    /// as well, so every node that a written way references is also part of the extract (unless
+    /// it is missing from the source file). Relations are not written, the metadata of the
+    /// written elements is kept. The header of the extract declares the given bounding box.
+    ///
+    /// The nodes are written before the ways, both in the order of the source file.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut extract = vec![];
+    /// reader.extract_bbox_to((52.1198, 11.62, 52.1200, 11.64), &mut extract)?;
+    ///
+    /// let reader = ElementReader::new(&extract[..]);
+    /// let count = reader.par_map_reduce(|_| 1, || 0_u64, |a, b| a + b)?;
+    /// println!("{} elements in the extract", count);
+    /// # assert_eq!(count, 4);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn extract_bbox_to<W: Write>(
+        &mut self,
+        bbox: (f64, f64, f64, f64),
+        writer: W,
+    ) -> Result<()> {
+        let (min_lat, min_lon, max_lat, max_lon) = bbox;
+        let crop_box = BoundingBox::new(
+            Coordinate::from_degrees(min_lat, min_lon),
+            Coordinate::from_degrees(max_lat, max_lon),
+        );
+
+        // First pass:
+        //   * Find the nodes inside of the bounding box and index all blocks
+        let mut inside_node_ids: BTreeSet<i64> = BTreeSet::new();
+        self.read_bbox(bbox, |element| match *element {
+            Element::Node(ref node) => {
+                inside_node_ids.insert(node.id());
+            }
+            Element::DenseNode(ref node) => {
+                inside_node_ids.insert(node.id);
+            }
+            _ => {}
+        })?;
+
+        // Second pass:
+        //   * Select ways with at least one node inside of the bounding box and store their
+        //     positions and dependencies
+        let mut node_ids = inside_node_ids.clone();
+        let mut selected_ways: Vec<(usize, Vec<usize>)> = vec![];
+        if !inside_node_ids.is_empty() {
+            for (blob_index, info) in self.index.iter().enumerate() {
+                let may_have_ways = info
+                    .id_ranges
+                    .as_ref()
+                    .map_or(true, |r| r.way_ids.is_some());
+                if info.blob_type != SimpleBlobType::Primitive || !may_have_ways {
+                    continue;
+                }
+
+                let block = read_block_at(&mut self.reader, &mut self.block_cache, info.offset)?;
+                let mut way_indices = vec![];
+                let ways = block.groups().flat_map(|group| group.ways()).enumerate();
+                for (way_index, way) in ways {
+                    if way.refs().any(|id| inside_node_ids.contains(&id)) {
+                        node_ids.extend(way.refs());
+                        way_indices.push(way_index);
+                    }
+                }
+                if !way_indices.is_empty() {
+                    selected_ways.push((blob_index, way_indices));
+                }
+            }
+        }
+
+        let mut writer = BlobWriter::new(writer);
+        writer.set_bbox(crop_box);
+
+        // Third pass:
+        //   * Write the selected nodes
+        let mut result = Ok(());
+        self.read_nodes_with_ids(
+            &node_ids,
+            &mut |elements: &[Element]| {
+                for element in elements {
+                    if result.is_ok() {
+                        result = writer.add_element(element);
+                    }
+                }
+            },
+            3,
+            false,
+            &mut |_| {},
+        )?;
+        result?;
+
+        // Fourth pass:
+        //   * Write the selected ways
+        for (blob_index, way_indices) in selected_ways {
+            let offset = self.index[blob_index].offset;
+            let data = read_block_at(&mut self.reader, &mut self.block_cache, offset)?;
+            let mut way_indices = way_indices.into_iter().peekable();
+            let ways = data.groups().flat_map(|group| group.ways()).enumerate();
+            for (way_index, way) in ways {
+                if way_indices.peek() == Some(&way_index) {
+                    way_indices.next();
+                    writer.add_element(&Element::Way(way))?;
+                }
+            }
+        }
+
+        writer.into_inner()?;
+        Ok(())
+    }
+}
+
+/// Identifies a saved index.
+const INDEX_MAGIC: &[u8; 8] = b"OSMPBFIX";
+
+/// The version of the saved index format.
+const INDEX_VERSION: u32 = 1;
+
+/// The number of bytes at the start and at the end of a file that are included in the checksum
+/// of a saved index.
+const FINGERPRINT_SAMPLE_SIZE: u64 = 64 * 1024;
+
+/// Returns the length of the file and a checksum of its first and last bytes. The position of
+/// the reader is restored afterwards.
+fn source_fingerprint<R: Read + Seek>(reader: &mut BlobReader<R>) -> Result<(u64, u64)> {
+    let pos = reader.seek_raw(SeekFrom::Current(0))?;
+    let file_len = reader.seek_raw(SeekFrom::End(0))?;
+
+    // 64-bit FNV-1a, which is stable across platforms and Rust versions.
+    let mut checksum: u64 = 0xcbf2_9ce4_8422_2325;
+    let sample_size = FINGERPRINT_SAMPLE_SIZE.min(file_len);
+    let mut buf = vec![0_u8; sample_size as usize];
+    for &start in &[0, file_len - sample_size] {
+        reader.seek_raw(SeekFrom::Start(start))?;
+        reader.get_mut().read_exact(&mut buf)?;
+        for &byte in &buf {
+            checksum ^= u64::from(byte);
+            checksum = checksum.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    reader.seek_raw(SeekFrom::Start(pos))?;
+    Ok((file_len, checksum))
+}
+
+impl BlobInfo {
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        fn write_range<W: Write>(
+            writer: &mut W,
+            range: &Option<RangeInclusive<i64>>,
+        ) -> ::std::io::Result<()> {
+            match *range {
+                Some(ref range) => {
+                    writer.write_u8(1)?;
+                    writer.write_i64::<LittleEndian>(*range.start())?;
+                    writer.write_i64::<LittleEndian>(*range.end())
+                }
+                None => writer.write_u8(0),
+            }
+        }
+
+        writer.write_u64::<LittleEndian>(self.offset.0)?;
+        writer.write_u8(match self.blob_type {
+            SimpleBlobType::Header => 0,
+            SimpleBlobType::Primitive => 1,
+            SimpleBlobType::Unknown => 2,
+        })?;
+
+        match self.id_ranges {
+            Some(ref ranges) => {
+                writer.write_u8(1)?;
+                write_range(writer, &ranges.node_ids)?;
+                write_range(writer, &ranges.way_ids)?;
+                write_range(writer, &ranges.relation_ids)?;
+            }
+            None => writer.write_u8(0)?,
+        }
+
+        match self.node_bbox {
+            Some(bbox) => {
+                writer.write_u8(1)?;
+                writer.write_i64::<LittleEndian>(bbox.min().nano_lat())?;
+                writer.write_i64::<LittleEndian>(bbox.min().nano_lon())?;
+                writer.write_i64::<LittleEndian>(bbox.max().nano_lat())?;
+                writer.write_i64::<LittleEndian>(bbox.max().nano_lon())?;
+            }
+            None => writer.write_u8(0)?,
+        }
+        Ok(())
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> Result<BlobInfo> {
+        fn read_flag<R: Read>(reader: &mut R) -> Result<bool> {
+            match reader.read_u8()? {
+                0 => Ok(false),
+                1 => Ok(true),
+                _ => Err(new_error(ErrorKind::InvalidIndex {
+                    reason: "invalid flag",
+                })),
+            }
+        }
+
+        fn read_range<R: Read>(reader: &mut R) -> Result<Option<RangeInclusive<i64>>> {
+            if read_flag(reader)? {
+                let start = reader.read_i64::<LittleEndian>()?;
+                let end = reader.read_i64::<LittleEndian>()?;
+                Ok(Some(RangeInclusive::new(start, end)))
+            } else {
+                Ok(None)
+            }
+        }
+
+        let offset = ByteOffset(reader.read_u64::<LittleEndian>()?);
+        let blob_type = match reader.read_u8()? {
+            0 => SimpleBlobType::Header,
+            1 => SimpleBlobType::Primitive,
+            2 => SimpleBlobType::Unknown,
+            _ => {
+                return Err(new_error(ErrorKind::InvalidIndex {
+                    reason: "invalid blob type",
+                }))
+            }
+        };
+
+        let id_ranges = if read_flag(reader)? {
+            Some(IdRanges {
+                node_ids: read_range(reader)?,
+                way_ids: read_range(reader)?,
+                relation_ids: read_range(reader)?,
+            })
+        } else {
+            None
+        };
+
+        let node_bbox = if read_flag(reader)? {
+            let min_lat = reader.read_i64::<LittleEndian>()?;
+            let min_lon = reader.read_i64::<LittleEndian>()?;
+            let max_lat = reader.read_i64::<LittleEndian>()?;
+            let max_lon = reader.read_i64::<LittleEndian>()?;
+            Some(BoundingBox::new(
+                Coordinate::new(min_lat, min_lon),
+                Coordinate::new(max_lat, max_lon),
+            ))
+        } else {
+            None
+        };
+
+        Ok(BlobInfo {
+            offset,
+            blob_type,
+            id_ranges,
+            node_bbox,
+        })
+    }
+}
+
+/// Stores the IDs of the members of a relation by their type.
+fn collect_member_ids(
+    relation: &Relation,
+    relation_ids: &mut BTreeSet<i64>,
+    way_ids: &mut BTreeSet<i64>,
+    node_ids: &mut BTreeSet<i64>,
+) {
+    for member in relation.members() {
+        match member.member_type {
+            RelMemberType::Node => node_ids.insert(member.member_id),
+            RelMemberType::Way => way_ids.insert(member.member_id),
+            RelMemberType::Relation => relation_ids.insert(member.member_id),
+        };
+    }
+}
+
+/// A ring of `(lat, lon)` points in degrees for `IndexedReader::read_polygon`.
+#[derive(Debug)]
+struct Polygon {
+    /// The points of the ring. The longitudes are unwrapped, so that consecutive points are at
+    /// most 180 degrees apart. The first longitude is in `[-180, 180)`, the others may exceed
+    /// 180 degrees if the ring crosses the antimeridian.
+    points: Vec<(f64, f64)>,
+    /// The bounding box of the ring, split in two at the antimeridian. Empty if the ring has
+    /// fewer than three points.
+    bboxes: Vec<BoundingBox>,
+}
+
+impl Polygon {
+    fn new(ring: &[(f64, f64)]) -> Polygon {
+        let mut points: Vec<(f64, f64)> = Vec::with_capacity(ring.len());
+        for &(lat, lon) in ring {
+            let lon = match points.last() {
+                Some(&(_, prev_lon)) => lon + ((prev_lon - lon) / 360.0).round() * 360.0,
+                None => lon,
+            };
+            points.push((lat, lon));
+        }
+        if points.len() < 3 {
+            return Polygon {
+                points: vec![],
+                bboxes: vec![],
+            };
+        }
+
+        let mut min_lat = std::f64::INFINITY;
+        let mut max_lat = std::f64::NEG_INFINITY;
+        let mut min_lon = std::f64::INFINITY;
+        let mut max_lon = std::f64::NEG_INFINITY;
+        for &(lat, lon) in &points {
+            min_lat = min_lat.min(lat);
+            max_lat = max_lat.max(lat);
+            min_lon = min_lon.min(lon);
+            max_lon = max_lon.max(lon);
+        }
+
+        // Move the ring so that its western end is in [-180, 180)
+        let shift = ((min_lon + 180.0) / 360.0).floor() * 360.0;
+        for point in &mut points {
+            point.1 -= shift;
+        }
+        let min_lon = min_lon - shift;
+        let max_lon = max_lon - shift;
+
+        let bbox = |min_lon: f64, max_lon: f64| {
+            BoundingBox::new(
+                Coordinate::from_degrees(min_lat, min_lon),
+                Coordinate::from_degrees(max_lat, max_lon),
+            )
+        };
+        let bboxes = if max_lon > 180.0 {
+            vec![
+                bbox(min_lon, 180.0),
+                bbox(-180.0, (max_lon - 360.0).min(180.0)),
+            ]
+        } else {
+            vec![bbox(min_lon, max_lon)]
+        };
+
+        Polygon { points, bboxes }
+    }
+
+    /// Returns true if the bounding box of the polygon intersects the given one.
+    fn intersects(&self, bbox: &BoundingBox) -> bool {
+        self.bboxes.iter().any(|b| b.intersects(bbox))
+    }
+
+    /// Returns true if the coordinate is inside of the polygon (even-odd rule, so the winding
+    /// order does not matter).
+    fn contains(&self, coordinate: Coordinate) -> bool {
+        if !self.bboxes.iter().any(|b| b.contains(coordinate)) {
+            return false;
+        }
+        let (lat, lon) = (coordinate.lat(), coordinate.lon());
+        self.ring_contains(lat, lon) || self.ring_contains(lat, lon + 360.0)
+    }
+
+    fn ring_contains(&self, lat: f64, lon: f64) -> bool {
+        let mut inside = false;
+        let mut prev = self.points[self.points.len() - 1];
+        for &point in &self.points {
+            let ((lat_a, lon_a), (lat_b, lon_b)) = (prev, point);
+            if (lat_a > lat) != (lat_b > lat)
+                && lon < lon_a + (lon_b - lon_a) * (lat - lat_a) / (lat_b - lat_a)
+            {
+                inside = !inside;
+            }
+            prev = point;
+        }
+        inside
+    }
+}
+
+/// The member ids of a relation, to compute its bounding box (see
+/// `IndexedReader::read_relations_and_deps_with_bbox`).
+#[derive(Debug)]
+struct RelationMembers {
+    relations: Vec<i64>,
+    ways: Vec<i64>,
+    nodes: Vec<i64>,
+}
+
+impl RelationMembers {
+    fn new(relation: &Relation) -> RelationMembers {
+        let mut members = RelationMembers {
+            relations: vec![],
+            ways: vec![],
+            nodes: vec![],
+        };
+        for member in relation.members() {
+            match member.member_type {
+                RelMemberType::Node => members.nodes.push(member.member_id),
+                RelMemberType::Way => members.ways.push(member.member_id),
+                RelMemberType::Relation => members.relations.push(member.member_id),
+            }
+        }
+        members
+    }
+
+    /// Grows `bbox` to contain the member nodes and the nodes of the member ways. Nodes without
+    /// a known coordinate are skipped.
+    fn extend_bbox(
+        &self,
+        bbox: &mut Option<BoundingBox>,
+        way_refs: &HashMap<i64, Vec<i64>>,
+        coordinates: &HashMap<i64, Coordinate>,
+    ) {
+        let way_nodes = self
+            .ways
+            .iter()
+            .filter_map(|id| way_refs.get(id))
+            .flat_map(|refs| refs.iter());
+        for id in self.nodes.iter().chain(way_nodes) {
+            if let Some(&coordinate) = coordinates.get(id) {
+                match *bbox {
+                    Some(ref mut bbox) => bbox.extend(coordinate),
+                    None => *bbox = Some(BoundingBox::new(coordinate, coordinate)),
+                }
+            }
+        }
+    }
+}
+
+/// Decoded `PrimitiveBlock`s by the offset of their blob, in order of their last use.
+#[derive(Debug)]
+struct BlockCache {
+    capacity: usize,
+    blocks: VecDeque<(ByteOffset, Arc<PrimitiveBlock>)>,
+}
+
+impl BlockCache {
+    fn new(capacity: usize) -> BlockCache {
+        BlockCache {
+            capacity,
+            blocks: VecDeque::new(),
+        }
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.blocks.len() > capacity {
+            self.blocks.pop_front();
+        }
+    }
+
+    /// Returns the cached block of the blob at the given offset and marks it as recently used.
+    fn get(&mut self, offset: ByteOffset) -> Option<Arc<PrimitiveBlock>> {
+        let index = self.blocks.iter().position(|entry| entry.0 == offset)?;
+        let entry = self.blocks.remove(index)?;
+        let block = entry.1.clone();
+        self.blocks.push_back(entry);
+        Some(block)
+    }
+
+    fn insert(&mut self, offset: ByteOffset, block: Arc<PrimitiveBlock>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.blocks.len() >= self.capacity {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back((offset, block));
+    }
+}
+
+/// Returns the decoded block of the blob at the given offset, either from the cache or by
+/// reading and decoding it.
+fn read_block_at<R: Read + Seek>(
+    reader: &mut BlobReader<R>,
+    cache: &mut BlockCache,
+    offset: ByteOffset,
+) -> Result<Arc<PrimitiveBlock>> {
+    if let Some(block) = cache.get(offset) {
+        return Ok(block);
+    }
+    let block = Arc::new(read_blob_at(reader, offset)?.to_primitiveblock()?);
+    cache.insert(offset, block.clone());
+    Ok(block)
+}
+
+/// Seeks to the given offset and reads the blob at this position.
+fn read_blob_at<R: Read + Seek>(reader: &mut BlobReader<R>, offset: ByteOffset) -> Result<Blob> {
+    reader.seek(offset)?;
+    let blob = reader.next().ok_or_else(|| {
+        ::std::io::Error::new(
+            ::std::io::ErrorKind::UnexpectedEof,
+            "could not read next blob",
+        )
+    })??;
+    Ok(blob)
+}
+
+/// Filters ways and receives the results of `IndexedReader::read_ways_and_deps_visitor`.
+///
+/// This combines the two closures of `IndexedReader::read_ways_and_deps` in a single value, which
+/// is useful if filtering and collecting the results need the same mutable state.
+pub trait WayDepsVisitor {
+    /// Returns true if the given way and its dependent nodes should be passed to `visit`. This is
+    /// called exactly once for each way of the file.
+    fn keep_way(&mut self, way: &Way) -> bool;
+
+    /// Receives each matching way and each of its dependent nodes (`Node`s and `DenseNode`s).
+    fn visit(&mut self, element: &Element);
+}
+
+/// Decides which ways are returned by `read_ways_and_deps` and its variants.
+trait WayFilter {
+    /// Called once for each block before its ways are filtered.
+    fn begin_block(&mut self, _block: &PrimitiveBlock) {}
+
+    /// Returns true if the given way should be returned.
+    fn keep(&mut self, way: &Way) -> bool;
+
+    /// Receives a matching way or one of its dependent nodes.
+    fn visit(&mut self, element: &Element);
+
+    /// Returns true if the matching ways of a block should be collected and passed to
+    /// `visit_batch` after filtering the block, instead of visiting each way right after `keep`.
+    fn batches(&self) -> bool {
+        false
+    }
+
+    /// Receives the matching ways or dependent nodes of one block.
+    fn visit_batch(&mut self, elements: &[Element]) {
+        for element in elements {
+            self.visit(element);
+        }
+    }
+}
+
+struct ClosureWayFilter<F, E>(F, E);
+
+impl<F, E> WayFilter for ClosureWayFilter<F, E>
+where
+    F: for<'a> FnMut(&Way<'a>) -> bool,
+    E: for<'a> FnMut(&Element<'a>),
+{
+    fn keep(&mut self, way: &Way) -> bool {
+        (self.0)(way)
+    }
+
+    fn visit(&mut self, element: &Element) {
+        (self.1)(element)
+    }
+}
+
+struct BatchWayFilter<F, B>(F, B);
+
+impl<F, B> WayFilter for BatchWayFilter<F, B>
+where
+    F: for<'a> FnMut(&Way<'a>) -> bool,
+    B: for<'a> FnMut(&[Element<'a>]),
+{
+    fn keep(&mut self, way: &Way) -> bool {
+        (self.0)(way)
+    }
+
+    fn visit(&mut self, element: &Element) {
+        (self.1)(::std::slice::from_ref(element))
+    }
+
+    fn batches(&self) -> bool {
+        true
+    }
+
+    fn visit_batch(&mut self, elements: &[Element]) {
+        (self.1)(elements)
+    }
+}
+
+struct VisitorWayFilter<'v, V: 'v>(&'v mut V);
+
+impl<'v, V: WayDepsVisitor> WayFilter for VisitorWayFilter<'v, V> {
+    fn keep(&mut self, way: &Way) -> bool {
+        self.0.keep_way(way)
+    }
+
+    fn visit(&mut self, element: &Element) {
+        self.0.visit(element)
+    }
+}
+
+struct TagWayFilter<'f, E> {
+    filter: &'f TagFilter,
+    compiled: Option<CompiledTagFilter>,
+    element_callback: E,
+}
+
+impl<'f, E> WayFilter for TagWayFilter<'f, E>
+where
+    E: for<'a> FnMut(&Element<'a>),
+{
+    fn begin_block(&mut self, block: &PrimitiveBlock) {
+        self.compiled = Some(self.filter.compile(block));
+    }
+
+    fn keep(&mut self, way: &Way) -> bool {
+        match self.compiled {
+            Some(ref compiled) => compiled.matches_raw_tags(way.raw_tags()),
+            None => false,
+        }
+    }
+
+    fn visit(&mut self, element: &Element) {
+        (self.element_callback)(element)
+    }
+}
+
+impl IndexedReader<File> {
+    /// Creates a new `IndexedReader` from a given path.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        //TODO take some more measurements to determine if `BufReader` should be used here
+        let f = File::open(path)?;
+        Self::new(f)
+    }
+}
+
+impl<'a> IndexedReader<Cursor<&'a [u8]>> {
+    /// Creates a new `IndexedReader` from a PBF file that is already stored in memory.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let data = std::fs::read("tests/test.osm.pbf")?;
+    /// let reader = IndexedReader::from_bytes(&data)?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self> {
+        Self::new(Cursor::new(bytes))
+    }
+
+    /// Creates a new `IndexedReader` from a memory map. This is the same as `from_bytes` with
+    /// the mapped file: blobs are read with a `Cursor` over the mapping, so seeking is free and
+    /// no read calls are needed when blobs are read repeatedly, as in the later passes of
+    /// `read_ways_and_deps`. This is not zero-copy, the bytes of each blob are still copied out
+    /// of the mapping when it is read. `MmapBlobReader` iterates over the blobs of a mapping
+    /// without copying them before they are decoded.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mmap = unsafe { Mmap::from_path("tests/test.osm.pbf")? };
+    /// let reader = IndexedReader::from_mmap(&mmap)?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn from_mmap(mmap: &'a Mmap) -> Result<Self> {
+        Self::from_bytes(mmap.as_slice())
+    }
+}
+
+impl IndexedReader<Cursor<Vec<u8>>> {
+    /// Creates a new `IndexedReader` from a stream that does not implement `Seek`, e.g. the
+    /// standard input or a network connection. The whole stream is read into memory first, so
+    /// this needs as much memory as the size of the file and is only suitable for smaller
+    /// extracts. Use `from_path` or `from_mmap` for files on disk.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let stream = std::fs::File::open("tests/test.osm.pbf")?; // or `std::io::stdin()`
+    /// let mut reader = IndexedReader::from_reader_buffered(stream)?;
+    ///
+    /// # reader.create_index()?;
+    /// # assert_eq!(reader.iter_blobs().len(), 2);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn from_reader_buffered<R: Read>(mut reader: R) -> Result<Self> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        Self::new(Cursor::new(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::osmformat;
+    use util::{
+        block, encode_test_pbf, encode_test_pbf_with_header, node, node_at, relation,
+        relation_block, way,
+    };
+
+    #[test]
+    fn test_range_included_set() {
+        let mut set = BTreeSet::<i64>::new();
+        set.extend(&[1,2,6]);
+
+        assert_eq!(range_included(RangeInclusive::new(0, 0), &set), false);
+        assert_eq!(range_included(RangeInclusive::new(1, 1), &set), true);
+        assert_eq!(range_included(RangeInclusive::new(2, 2), &set), true);
+        assert_eq!(range_included(RangeInclusive::new(3, 3), &set), false);
+        assert_eq!(range_included(RangeInclusive::new(3, 5), &set), false);
+        assert_eq!(range_included(RangeInclusive::new(3, 6), &set), true);
+        assert_eq!(range_included(RangeInclusive::new(6, 6), &set), true);
+        assert_eq!(range_included(RangeInclusive::new(7, 7), &set), false);
+        assert_eq!(range_included(RangeInclusive::new(0, 1), &set), true);
+        assert_eq!(range_included(RangeInclusive::new(6, 7), &set), true);
+        assert_eq!(range_included(RangeInclusive::new(2, 3), &set), true);
+        assert_eq!(range_included(RangeInclusive::new(5, 6), &set), true);
+        assert_eq!(range_included(RangeInclusive::new(5, 8), &set), true);
+        assert_eq!(range_included(RangeInclusive::new(0, 8), &set), true);
+        assert_eq!(range_included(RangeInclusive::new(0, 4), &set), true);
+    }
+
+    #[test]
+    fn test_range_included_set_negative() {
+        let mut set = BTreeSet::<i64>::new();
+        set.extend(&[-6, -2, 0]);
+
+        assert!(!range_included(RangeInclusive::new(-8, -7), &set));
+        assert!(range_included(RangeInclusive::new(-7, -6), &set));
+        assert!(range_included(RangeInclusive::new(-6, -6), &set));
+        assert!(!range_included(RangeInclusive::new(-5, -3), &set));
+        assert!(range_included(RangeInclusive::new(-5, -2), &set));
+        assert!(!range_included(RangeInclusive::new(-1, -1), &set));
+        assert!(range_included(RangeInclusive::new(-1, 0), &set));
+        assert!(range_included(RangeInclusive::new(0, 0), &set));
+        assert!(!range_included(RangeInclusive::new(1, 5), &set));
+        assert!(range_included(RangeInclusive::new(i64::MIN, i64::MAX), &set));
+    }
+
+    #[test]
+    fn test_read_ways_and_deps_negative_ids() {
+        let pbf = encode_test_pbf(&[
+            block(vec![node(-3), node(-2), node(0)], vec![]),
+            block(vec![node(-1), node(1), node(2)], vec![]),
+            block(vec![], vec![way(-5, &[0, -3, -2, 0]), way(7, &[1])]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+
+        let mut ways = vec![];
+        let mut nodes = vec![];
+        reader
+            .read_ways_and_deps(
+                |way| way.id() < 0,
+                |element| match element {
+                    Element::Way(way) => ways.push(way.id()),
+                    Element::Node(node) => nodes.push(node.id()),
+                    Element::DenseNode(node) => nodes.push(node.id),
+                    Element::Relation(_) => panic!(),
+                },
+            )
+            .unwrap();
+
+        assert_eq!(ways, vec![-5]);
+        assert_eq!(nodes, vec![-3, -2, 0]);
+
+        let ranges: Vec<_> = reader
+            .iter_blobs()
+            .map(|s| s.id_ranges().and_then(|r| r.node_ids()))
+            .collect();
+        assert_eq!(ranges, vec![None, Some(-3..=0), Some(-1..=2), None]);
+    }
+
+    #[test]
+    fn test_get_element_by_id() {
+        use proto::osmformat::Relation_MemberType::WAY;
+
+        let pbf = encode_test_pbf(&[
+            block(vec![node(1), node(2)], vec![]),
+            block(vec![node(3)], vec![way(10, &[1, 3])]),
+            relation_block(vec![relation(20, &[(WAY, 10)])]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(&pbf[..])).unwrap();
+
+        // The first search indexes the blobs it decodes, later searches use the id ranges
+        for _ in 0..2 {
+            assert_eq!(reader.get_node(3).unwrap().map(|n| n.id), Some(3));
+            assert!(reader.get_node(10).unwrap().is_none());
+            assert_eq!(reader.get_way(10).unwrap().unwrap().refs, vec![1, 3]);
+            assert!(reader.get_way(1).unwrap().is_none());
+            let relation = reader.get_relation(20).unwrap().unwrap();
+            assert_eq!(relation.members[0].member_type, RelMemberType::Way);
+            assert_eq!(relation.members[0].member_id, 10);
+            assert!(reader.get_relation(10).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_block_cache() {
+        let decoded = |id| Arc::new(PrimitiveBlock::new(block(vec![node(id)], vec![])).unwrap());
+        let mut cache = BlockCache::new(2);
+        cache.insert(ByteOffset(1), decoded(1));
+        cache.insert(ByteOffset(2), decoded(2));
+        assert!(cache.get(ByteOffset(1)).is_some());
+
+        // The least recently used block is dropped
+        cache.insert(ByteOffset(3), decoded(3));
+        assert!(cache.get(ByteOffset(2)).is_none());
+        assert!(cache.get(ByteOffset(1)).is_some());
+
+        cache.set_capacity(0);
+        cache.insert(ByteOffset(4), decoded(4));
+        assert!(cache.blocks.is_empty());
+    }
+
+    #[test]
+    fn test_read_with_block_cache() {
+        // Ways and their nodes are stored in the same blocks, so they are read twice
+        let blocks: Vec<_> = (0..8)
+            .map(|b| {
+                block(
+                    vec![node(b * 2), node(b * 2 + 1)],
+                    vec![way(100 + b, &[b * 2])],
+                )
+            })
+            .collect();
+        let pbf = encode_test_pbf(&blocks);
+
+        let read = |cache_size: usize| {
+            let mut reader = IndexedReader::new(Cursor::new(&pbf[..])).unwrap();
+            reader.set_block_cache_size(cache_size);
+            let mut elements = vec![];
+            for &parallel in &[false, true] {
+                let filter = |way: &Way| way.id() % 3 != 0;
+                let callback = |element: &Element| match *element {
+                    Element::Node(ref node) => elements.push((0, node.id())),
+                    Element::Way(ref way) => elements.push((1, way.id())),
+                    _ => panic!("unexpected element"),
+                };
+                if parallel {
+                    reader.par_read_ways_and_deps(filter, callback).unwrap();
+                } else {
+                    reader.read_ways_and_deps(filter, callback).unwrap();
+                }
+            }
+            elements
+        };
+
+        assert_eq!(read(4), read(0));
+        assert_eq!(read(100), read(0));
+    }
+
+    #[test]
+    fn test_degenerate_ways_and_groups() {
+        // A block with only ways, one of them without refs, and an empty group
+        let mut ways_only = block(vec![], vec![way(10, &[]), way(11, &[1])]);
+        ways_only
+            .mut_primitivegroup()
+            .push(osmformat::PrimitiveGroup::new());
+        let pbf = encode_test_pbf(&[
+            ways_only,
+            block(vec![node(1)], vec![]),
+            block(vec![], vec![]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(&pbf[..])).unwrap();
+
+        let mut elements = vec![];
+        let missing = reader
+            .read_ways_and_deps(
+                |_| true,
+                |element| match *element {
+                    Element::Node(ref node) => elements.push((0, node.id(), 0)),
+                    Element::Way(ref way) => {
+                        assert_eq!(way.refs().len(), way.raw_refs().len());
+                        elements.push((1, way.id(), way.refs().count()))
+                    }
+                    _ => panic!("unexpected element"),
+                },
+            )
+            .unwrap();
+        assert!(missing.is_empty());
+        assert_eq!(elements, vec![(1, 10, 0), (1, 11, 1), (0, 1, 0)]);
+
+        // Blobs without nodes still get the ranges of their other elements
+        let ranges: Vec<_> = reader
+            .iter_blobs()
+            .map(|s| {
+                let ranges = s.id_ranges().cloned();
+                ranges.map(|r| (r.node_ids(), r.way_ids()))
+            })
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                None,
+                Some((None, Some(10..=11))),
+                Some((Some(1..=1), None)),
+                Some((None, None)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blob_counts() {
+        let pbf = encode_test_pbf(&[
+            block(vec![node(1)], vec![]),
+            block(vec![], vec![way(10, &[1])]),
+        ]);
+        let len = pbf.len() as u64;
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+        assert_eq!(reader.blob_count(), 0);
+        assert_eq!(reader.file_len().unwrap(), len);
+
+        reader.create_index().unwrap();
+        assert_eq!(reader.blob_count(), 3);
+        assert_eq!(reader.header_blob_count(), 1);
+        assert_eq!(reader.primitive_blob_count(), 2);
+        assert_eq!(reader.file_len().unwrap(), len);
+
+        // The position is restored after determining the length
+        let mut nodes = 0;
+        reader.read_ways_and_deps(|_| true, |_| nodes += 1).unwrap();
+        assert_eq!(nodes, 2);
+    }
+
+    #[test]
+    fn test_create_full_index() {
+        let pbf = encode_test_pbf(&[
+            block(vec![node(-3), node(-2), node(0)], vec![]),
+            block(vec![node(-1), node(1), node(2)], vec![]),
+            block(vec![], vec![way(-5, &[0, -3, -2, 0]), way(7, &[1])]),
+            relation_block(vec![relation(20, &[])]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+        reader.create_full_index().unwrap();
+
+        let summaries: Vec<_> = reader.iter_blobs().collect();
+        assert_eq!(summaries.len(), 5);
+        assert!(summaries[0].id_ranges().is_none());
+        assert!(summaries[1..].iter().all(|s| s.id_ranges().is_some()));
+
+        let ranges: Vec<_> = summaries[1..]
+            .iter()
+            .map(|s| {
+                let r = s.id_ranges().unwrap();
+                (r.node_ids(), r.way_ids(), r.relation_ids())
+            })
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (Some(-3..=0), None, None),
+                (Some(-1..=2), None, None),
+                (None, Some(-5..=7), None),
+                (None, None, Some(20..=20)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lazy_full_index() {
+        let pbf = encode_test_pbf(&[
+            block(vec![node(1), node(2)], vec![]),
+            block(vec![], vec![way(10, &[1, 2]), way(12, &[2])]),
+            block(vec![], vec![way(30, &[1])]),
+            relation_block(vec![relation(40, &[(Relation_MemberType::WAY, 30)])]),
+        ]);
+
+        // Without a full index, the id ranges are only known after the blobs were decoded.
+        let mut reader = IndexedReader::new(Cursor::new(pbf.clone())).unwrap();
+        assert_eq!(reader.get_node(1).unwrap().unwrap().id, 1);
+        let known: Vec<_> = reader
+            .iter_blobs()
+            .map(|s| s.id_ranges().is_some())
+            .collect();
+        assert_eq!(known, vec![false, true, false, false, false]);
+
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+        reader.set_full_index(true);
+        assert_eq!(reader.get_node(1).unwrap().unwrap().id, 1);
+        let way_ranges: Vec<_> = reader
+            .iter_blobs()
+            .skip(1)
+            .map(|s| s.id_ranges().unwrap().way_ids())
+            .collect();
+        assert_eq!(way_ranges, vec![None, Some(10..=12), Some(30..=30), None]);
+        let relation_ranges: Vec<_> = reader
+            .iter_blobs()
+            .skip(1)
+            .map(|s| s.id_ranges().unwrap().relation_ids())
+            .collect();
+        assert_eq!(relation_ranges, vec![None, None, None, Some(40..=40)]);
+
+        // Way id queries only match the blobs whose range contains one of the ids.
+        let way_ids: BTreeSet<i64> = [11, 30].iter().cloned().collect();
+        let matching: Vec<_> = way_ranges
+            .into_iter()
+            .map(|r| match r {
+                Some(r) => range_included(r, &way_ids),
+                None => false,
+            })
+            .collect();
+        assert_eq!(matching, vec![false, true, true, false]);
+        let way_ids: BTreeSet<i64> = [11].iter().cloned().collect();
+        assert!(range_included(10..=12, &way_ids));
+        assert!(!range_included(13..=29, &way_ids));
+
+        assert_eq!(reader.get_way(12).unwrap().unwrap().refs, vec![2]);
+        assert!(reader.get_way(11).unwrap().is_none());
+        assert_eq!(reader.get_relation(40).unwrap().unwrap().members.len(), 1);
+    }
+
+    #[test]
+    fn test_read_ids() {
+        fn read<R: Read + Seek>(
+            reader: &mut IndexedReader<R>,
+            nodes: &[i64],
+            ways: &[i64],
+            relations: &[i64],
+        ) -> Vec<(char, i64)> {
+            let mut found = vec![];
+            reader
+                .read_ids(nodes, ways, relations, |element| {
+                    let kind = match *element {
+                        Element::Node(_) | Element::DenseNode(_) => 'n',
+                        Element::Way(_) => 'w',
+                        Element::Relation(_) => 'r',
+                    };
+                    found.push((kind, element.id()));
+                })
+                .unwrap();
+            found
+        }
+
+        let pbf = encode_test_pbf(&[
+            block(vec![node(1), node(5)], vec![]),
+            block(vec![node(6), node(9)], vec![way(1, &[1])]),
+            block(vec![], vec![way(10, &[5]), way(20, &[9])]),
+            relation_block(vec![relation(1, &[(Relation_MemberType::WAY, 10)])]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+
+        // Indexes the blocks lazily on the first query, then uses the id ranges.
+        for _ in 0..2 {
+            assert_eq!(
+                read(&mut reader, &[9, 1, 9, 7], &[20, 1], &[1]),
+                vec![('n', 1), ('n', 9), ('w', 1), ('w', 20), ('r', 1)]
+            );
+            assert!(read(&mut reader, &[], &[5], &[]).is_empty());
+        }
+        assert!(reader
+            .iter_blobs()
+            .filter(|s| s.blob_type() == SimpleBlobType::Primitive)
+            .all(|s| s.id_ranges().is_some()));
+
+        // Nothing to find, so nothing is decoded or indexed
+        let pbf = reader.into_inner().into_inner();
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+        reader.create_index().unwrap();
+        assert!(read(&mut reader, &[], &[], &[]).is_empty());
+        assert!(reader.iter_blobs().all(|s| s.id_ranges().is_none()));
+    }
+
+    #[test]
+    fn test_locate() {
+        let pbf = encode_test_pbf(&[
+            block(vec![node(1), node(5)], vec![]),
+            block(vec![node(6), node(9)], vec![way(1, &[1])]),
+            block(vec![], vec![way(10, &[5]), way(20, &[9])]),
+            relation_block(vec![relation(1, &[(Relation_MemberType::WAY, 10)])]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+        reader.create_index().unwrap();
+        let offsets: Vec<_> = reader.iter_blobs().map(|s| s.offset()).collect();
+
+        // Creates the full index, as the index above has no id ranges.
+        assert_eq!(
+            reader.locate(RelMemberType::Node, 5).unwrap(),
+            Some(offsets[1])
+        );
+        assert_eq!(
+            reader.locate(RelMemberType::Node, 9).unwrap(),
+            Some(offsets[2])
+        );
+        assert_eq!(
+            reader.locate(RelMemberType::Way, 1).unwrap(),
+            Some(offsets[2])
+        );
+        // Inside of the range, but not necessarily in the blob
+        assert_eq!(
+            reader.locate(RelMemberType::Way, 15).unwrap(),
+            Some(offsets[3])
+        );
+        assert_eq!(
+            reader.locate(RelMemberType::Relation, 1).unwrap(),
+            Some(offsets[4])
+        );
+        assert_eq!(reader.locate(RelMemberType::Node, 10).unwrap(), None);
+        assert_eq!(reader.locate(RelMemberType::Way, 21).unwrap(), None);
+        assert_eq!(reader.locate(RelMemberType::Relation, 2).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_ways_and_deps_missing_nodes() {
+        let pbf = encode_test_pbf(&[
+            block(vec![node(1), node(2), node(4)], vec![]),
+            block(vec![node(8), node(9)], vec![]),
+            block(vec![], vec![way(10, &[0, 1, 3, 4, 8, 12]), way(11, &[1, 2])]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+
+        let mut nodes = vec![];
+        let missing = reader
+            .read_ways_and_deps(
+                |way| way.id() == 10,
+                |element| {
+                    if let Element::Node(node) = element {
+                        nodes.push(node.id());
+                    }
+                },
+            )
+            .unwrap();
+
+        assert_eq!(nodes, vec![1, 4, 8]);
+        assert_eq!(missing, vec![0, 3, 12]);
+    }
+
+    #[test]
+    fn test_par_read_ways_and_deps() {
+        // Many blocks with nodes and ways that reference nodes from all over the file
+        let mut blocks: Vec<_> = (0..64)
+            .map(|b| block((b * 10..b * 10 + 10).map(node).collect(), vec![]))
+            .collect();
+        blocks.push(block(
+            vec![],
+            (0..200)
+                .map(|w| way(1000 + w, &[w * 3, w * 3 + 7, 700 + w]))
+                .collect(),
+        ));
+        let pbf = encode_test_pbf(&blocks);
+
+        let read = |parallel: bool| {
+            let mut reader = IndexedReader::new(Cursor::new(&pbf[..])).unwrap();
+            let mut elements = vec![];
+            let filter = |way: &Way| way.id() % 2 == 0;
+            let callback = |element: &Element| match *element {
+                Element::Node(ref node) => elements.push((0, node.id())),
+                Element::Way(ref way) => elements.push((1, way.id())),
+                _ => panic!("unexpected element"),
+            };
+            let missing = if parallel {
+                reader.par_read_ways_and_deps(filter, callback)
+            } else {
+                reader.read_ways_and_deps(filter, callback)
+            };
+            (elements, missing.unwrap())
+        };
+
+        let (elements, missing) = read(true);
+        assert_eq!((elements.clone(), missing.clone()), read(false));
+        assert_eq!(elements.iter().filter(|e| e.0 == 1).count(), 100);
+        assert_eq!(missing, (700..900).step_by(2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_read_ways_and_deps_visitor() {
+        // Keeps the first `limit` ways, which needs state shared between filter and callback
+        struct FirstWays {
+            limit: usize,
+            elements: Vec<(u8, i64)>,
+        }
+
+        impl WayDepsVisitor for FirstWays {
+            fn keep_way(&mut self, _way: &Way) -> bool {
+                let kept = self.elements.iter().filter(|e| e.0 == 1).count();
+                kept < self.limit
+            }
+
+            fn visit(&mut self, element: &Element) {
+                match *element {
+                    Element::Node(ref node) => self.elements.push((0, node.id())),
+                    Element::Way(ref way) => self.elements.push((1, way.id())),
+                    _ => panic!("unexpected element"),
+                }
+            }
+        }
+
+        let pbf = encode_test_pbf(&[
+            block(
+                vec![],
+                vec![way(10, &[1, 2]), way(11, &[3]), way(12, &[4, 5])],
+            ),
+            block((1..6).map(node).collect(), vec![]),
+        ]);
+
+        let mut reader = IndexedReader::new(Cursor::new(&pbf[..])).unwrap();
+        let mut visitor = FirstWays {
+            limit: 2,
+            elements: vec![],
+        };
+        let missing = reader.read_ways_and_deps_visitor(&mut visitor).unwrap();
+
+        assert!(missing.is_empty());
+        assert_eq!(
+            visitor.elements,
+            vec![(1, 10), (1, 11), (0, 1), (0, 2), (0, 3)]
+        );
+    }
+
+    #[test]
+    fn test_read_ways_and_deps_batched() {
+        let pbf = encode_test_pbf(&[
+            block(
+                vec![node(1), node(2)],
+                vec![way(10, &[1, 3]), way(11, &[9]), way(12, &[2])],
+            ),
+            block(vec![node(3), node(4)], vec![way(13, &[4])]),
+        ]);
+
+        let mut reader = IndexedReader::new(Cursor::new(&pbf[..])).unwrap();
+        let mut batches = vec![];
+        let missing = reader
+            .read_ways_and_deps_batched(
+                |way| way.id() != 11,
+                |elements| batches.push(elements.iter().map(|e| e.id()).collect::<Vec<_>>()),
+            )
+            .unwrap();
+
+        assert!(missing.is_empty());
+        // The ways of each block, then the nodes of each block
+        assert_eq!(
+            batches,
+            vec![vec![10, 12], vec![13], vec![1, 2], vec![3, 4]]
+        );
+
+        // The elements are the same as with a callback per element
+        let mut elements = vec![];
+        reader
+            .read_ways_and_deps(|way| way.id() != 11, |e| elements.push(e.id()))
+            .unwrap();
+        assert_eq!(elements, batches.concat());
+    }
+
+    #[test]
+    fn test_read_ways_and_deps_ordered() {
+        let pbf = encode_test_pbf(&[
+            block(vec![], vec![way(10, &[1, 2]), way(11, &[3]), way(12, &[2, 4])]),
+            block(vec![node(1), node(2), node(3)], vec![]),
+            block(vec![node(4)], vec![way(13, &[4])]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+
+        let mut read = |order| {
+            let mut elements = vec![];
+            reader
+                .read_ways_and_deps_ordered(
+                    |way| way.id() != 11,
+                    order,
+                    |element| match element {
+                        Element::Node(node) => elements.push(format!("n{}", node.id())),
+                        Element::Way(way) => elements.push(format!("w{}", way.id())),
+                        _ => panic!("unexpected element"),
+                    },
+                )
+                .unwrap();
+            elements
+        };
+
+        assert_eq!(
+            read(DepsOrder::WaysFirst),
+            vec!["w10", "w12", "w13", "n1", "n2", "n4"]
+        );
+        assert_eq!(
+            read(DepsOrder::NodesFirst),
+            vec!["n1", "n2", "n4", "w10", "w12", "w13"]
+        );
+    }
+
+    #[test]
+    fn test_read_ways_geometry() {
+        let pbf = encode_test_pbf(&[
+            block(vec![node_at(1, 10, 20), node_at(2, 30, 40)], vec![]),
+            block(vec![node_at(3, 50, 60)], vec![]),
+            block(
+                vec![],
+                vec![way(10, &[3, 1, 2, 3]), way(11, &[1, 4]), way(12, &[2])],
+            ),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+
+        let mut geometries = vec![];
+        let incomplete = reader
+            .read_ways_geometry(
+                |way| way.id() < 12,
+                |way, coords| {
+                    let coords: Vec<_> = coords.iter().map(|c| c.nano_lat()).collect();
+                    geometries.push((way.id(), coords));
+                },
+            )
+            .unwrap();
+
+        // Way 11 references the missing node 4 and way 12 is filtered out.
+        assert_eq!(incomplete, 1);
+        assert_eq!(geometries, vec![(10, vec![5000, 1000, 3000, 5000])]);
+    }
+
+    #[test]
+    fn test_read_relations_and_deps() {
+        use proto::osmformat::Relation_MemberType::{NODE, RELATION, WAY};
+
+        let pbf = encode_test_pbf(&[
+            block(vec![node(1), node(2), node(3), node(4), node(5)], vec![]),
+            block(vec![], vec![way(10, &[1, 2]), way(11, &[3]), way(12, &[5])]),
+            relation_block(vec![
+                relation(20, &[(WAY, 10), (NODE, 4), (RELATION, 21)]),
+                relation(21, &[(WAY, 11), (RELATION, 22)]),
+                relation(22, &[(WAY, 12)]),
+            ]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+
+        let mut elements = vec![];
+        reader
+            .read_relations_and_deps(
+                |relation| relation.id() == 20,
+                |element| match element {
+                    Element::Node(node) => elements.push(format!("n{}", node.id())),
+                    Element::Way(way) => elements.push(format!("w{}", way.id())),
+                    Element::Relation(rel) => elements.push(format!("r{}", rel.id())),
+                    Element::DenseNode(_) => panic!("unexpected dense node"),
+                },
+            )
+            .unwrap();
+
+        assert_eq!(
+            elements,
+            vec!["r20", "r21", "w10", "w11", "n1", "n2", "n3", "n4"]
+        );
+
+        let ranges: Vec<_> = reader
+            .iter_blobs()
+            .map(|s| s.id_ranges().cloned())
+            .collect();
+        assert!(ranges[0].is_none());
+        assert_eq!(ranges[2].as_ref().unwrap().way_ids(), Some(10..=12));
+        assert_eq!(ranges[3].as_ref().unwrap().relation_ids(), Some(20..=22));
+        assert_eq!(ranges[3].as_ref().unwrap().node_ids(), None);
+    }
+
+    #[test]
+    fn test_read_relations_and_deps_with_bbox() {
+        use proto::osmformat::Relation_MemberType::{NODE, RELATION, WAY};
+
+        let pbf = encode_test_pbf(&[
+            block(
+                vec![
+                    node_at(1, 10, 20),
+                    node_at(2, 30, -40),
+                    node_at(3, -50, 60),
+                    node_at(4, 70, 0),
+                    node_at(5, 1000, 1000),
+                ],
+                vec![],
+            ),
+            block(vec![], vec![way(10, &[1, 2]), way(11, &[3, 99])]),
+            relation_block(vec![
+                relation(20, &[(WAY, 10), (NODE, 4), (RELATION, 21)]),
+                relation(21, &[(WAY, 11)]),
+                relation(22, &[(NODE, 98), (WAY, 97), (RELATION, 96)]),
+            ]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+
+        let mut elements = 0;
+        let mut bboxes = vec![];
+        reader
+            .read_relations_and_deps_with_bbox(
+                |relation| relation.id() == 20 || relation.id() == 22,
+                |_element| elements += 1,
+                |relation, bbox| bboxes.push((relation.id(), bbox)),
+            )
+            .unwrap();
+
+        // r20, r22, r21, w10, w11, n1, n2, n3, n4
+        assert_eq!(elements, 9);
+        assert_eq!(
+            bboxes,
+            vec![
+                (
+                    20,
+                    Some(BoundingBox::new(
+                        Coordinate::new(-5_000, -4_000),
+                        Coordinate::new(7_000, 6_000)
+                    ))
+                ),
+                (22, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_relations_and_deps_with_bbox_unresolved() {
+        use proto::osmformat::Relation_MemberType::{NODE, WAY};
+
+        let pbf = encode_test_pbf(&[
+            block(vec![node_at(1, 10, 20)], vec![]),
+            block(vec![], vec![way(10, &[2, 3])]),
+            relation_block(vec![relation(20, &[(WAY, 10), (NODE, 4)])]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+
+        let mut bboxes = vec![];
+        reader
+            .read_relations_and_deps_with_bbox(
+                |_relation| true,
+                |_element| {},
+                |relation, bbox| bboxes.push((relation.id(), bbox)),
+            )
+            .unwrap();
+
+        // The way exists, but none of its nodes
+        assert_eq!(bboxes, vec![(20, None)]);
+    }
+
+    fn read_bbox_ids<R: Read + Seek>(
+        reader: &mut IndexedReader<R>,
+        bbox: (f64, f64, f64, f64),
+    ) -> Vec<i64> {
+        let mut ids = vec![];
+        reader
+            .read_bbox(bbox, |element| match element {
+                Element::Node(node) => ids.push(node.id()),
+                _ => panic!("unexpected element"),
+            })
+            .unwrap();
+        ids
+    }
+
+    fn read_polygon_ids<R: Read + Seek>(
+        reader: &mut IndexedReader<R>,
+        ring: &[(f64, f64)],
+        completeness: WayCompleteness,
+    ) -> Vec<String> {
+        let mut ids = vec![];
+        reader
+            .read_polygon(ring, completeness, |element| match element {
+                Element::Node(node) => ids.push(format!("n{}", node.id())),
+                Element::Way(way) => ids.push(format!("w{}", way.id())),
+                _ => panic!("unexpected element"),
+            })
+            .unwrap();
+        ids
+    }
+
+    #[test]
+    fn test_read_polygon() {
+        // A triangle with the corners (0, 0), (0, 2) and (2, 0) in degrees
+        let pbf = encode_test_pbf(&[
+            block(
+                vec![
+                    node_at(1, 5_000_000, 5_000_000),
+                    node_at(2, 15_000_000, 15_000_000),
+                    node_at(3, 5_000_000, 25_000_000),
+                    node_at(4, -5_000_000, 5_000_000),
+                ],
+                vec![],
+            ),
+            block(
+                vec![],
+                vec![way(10, &[1, 3]), way(11, &[2, 4]), way(12, &[3, 4])],
+            ),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+        let ring = [(0.0, 0.0), (0.0, 2.0), (2.0, 0.0)];
+
+        assert_eq!(
+            read_polygon_ids(&mut reader, &ring, WayCompleteness::InsideNodes),
+            vec!["w10", "n1"]
+        );
+        assert_eq!(
+            read_polygon_ids(&mut reader, &ring, WayCompleteness::CompleteWays),
+            vec!["w10", "n1", "n3"]
+        );
+
+        // Same ring in the other direction and closed
+        let reversed = [(0.0, 0.0), (2.0, 0.0), (0.0, 2.0), (0.0, 0.0)];
+        assert_eq!(
+            read_polygon_ids(&mut reader, &reversed, WayCompleteness::InsideNodes),
+            vec!["w10", "n1"]
+        );
+
+        assert!(
+            read_polygon_ids(&mut reader, &ring[..2], WayCompleteness::CompleteWays).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_read_polygon_antimeridian() {
+        let pbf = encode_test_pbf(&[
+            block(
+                vec![
+                    node_at(1, 0, 1_795_000_000),
+                    node_at(2, 0, -1_795_000_000),
+                    node_at(3, 0, 1_780_000_000),
+                    node_at(4, 0, 0),
+                ],
+                vec![],
+            ),
+            block(vec![], vec![way(10, &[1, 2]), way(11, &[3, 4])]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+        let ring = [(-1.0, 179.0), (-1.0, -179.0), (1.0, -179.0), (1.0, 179.0)];
+
+        assert_eq!(
+            read_polygon_ids(&mut reader, &ring, WayCompleteness::InsideNodes),
+            vec!["w10", "n1", "n2"]
+        );
+    }
+
+    #[test]
+    fn test_read_bbox() {
+        // Nodes at (0.0011, -0.0008) and (0.0015, -0.0005) with a granularity of 1000 nanodegrees
+        // and offsets.
+        let mut shifted = block(vec![node_at(3, 100, 200), node_at(4, 500, 500)], vec![]);
+        shifted.set_granularity(1000);
+        shifted.set_lat_offset(1_000_000);
+        shifted.set_lon_offset(-1_000_000);
+
+        let pbf = encode_test_pbf(&[
+            block(
+                vec![
+                    node_at(1, 10_000_000, 10_000_000),
+                    node_at(2, 20_000_000, 20_000_000),
+                ],
+                vec![],
+            ),
+            shifted,
+            block(vec![], vec![way(10, &[1])]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+
+        assert_eq!(read_bbox_ids(&mut reader, (0.5, 0.5, 1.5, 1.5)), vec![1]);
+        assert_eq!(
+            read_bbox_ids(&mut reader, (0.001, -0.001, 0.0012, 0.0)),
+            vec![3]
+        );
+        assert_eq!(
+            read_bbox_ids(&mut reader, (0.0015, -0.0005, 1.0, 1.0)),
+            vec![1, 4]
+        );
+        assert!(read_bbox_ids(&mut reader, (-1.0, -1.0, 0.0, 0.0)).is_empty());
+
+        let bboxes: Vec<_> = reader.iter_blobs().map(|s| s.node_bbox()).collect();
+        assert_eq!(
+            bboxes,
+            vec![
+                None,
+                Some(BoundingBox::new(
+                    Coordinate::new(1_000_000_000, 1_000_000_000),
+                    Coordinate::new(2_000_000_000, 2_000_000_000)
+                )),
+                Some(BoundingBox::new(
+                    Coordinate::new(1_100_000, -800_000),
+                    Coordinate::new(1_500_000, -500_000)
+                )),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_references() {
+        use proto::osmformat::Relation_MemberType::{NODE, RELATION, WAY};
+
+        let pbf = encode_test_pbf(&[
+            block(vec![node(1), node(2)], vec![]),
+            block(vec![], vec![way(10, &[1, 2, 3]), way(12, &[3, 5, 1])]),
+            relation_block(vec![
+                relation(20, &[(NODE, 1), (NODE, 4), (WAY, 10), (WAY, 11)]),
+                relation(21, &[(RELATION, 20), (RELATION, 22)]),
+            ]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+        let report = reader.validate_references().unwrap();
+
+        assert!(!report.is_valid());
+        assert_eq!(
+            report,
+            ValidationReport {
+                missing_way_nodes: 2,
+                missing_way_node_samples: vec![3, 5],
+                missing_relation_members: 3,
+                missing_relation_member_samples: vec![
+                    (RelMemberType::Node, 4),
+                    (RelMemberType::Way, 11),
+                    (RelMemberType::Relation, 22),
+                ],
+            }
+        );
+
+        let pbf = encode_test_pbf(&[
+            block(vec![node(1), node(2)], vec![way(10, &[1, 2])]),
+            relation_block(vec![relation(20, &[(NODE, 1), (WAY, 10)])]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+        assert!(reader.validate_references().unwrap().is_valid());
+    }
+
+    #[test]
+    fn test_extract_bbox_to() {
+        let pbf = encode_test_pbf(&[
+            block(
+                vec![node_at(1, 5_000_000, 5_000_000), node_at(2, 0, 20_000_000)],
+                vec![],
+            ),
+            block(
+                vec![node_at(3, -20_000_000, 0), node_at(4, 30_000_000, 0)],
+                vec![],
+            ),
+            block(vec![], vec![way(10, &[1, 2, 5]), way(11, &[3, 4])]),
+            block(vec![node_at(6, 10_000_000, 10_000_000)], vec![]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+        let mut extract = vec![];
+        reader
+            .extract_bbox_to((0.0, 0.0, 1.0, 1.0), &mut extract)
+            .unwrap();
+
+        let mut blobs = BlobReader::new(&extract[..]);
+        let header = blobs.next().unwrap().unwrap().to_headerblock().unwrap();
+        assert_eq!(
+            header.bbox(),
+            Some(BoundingBox::new(
+                Coordinate::from_degrees(0.0, 0.0),
+                Coordinate::from_degrees(1.0, 1.0)
+            ))
+        );
+
+        let mut elements = vec![];
+        for blob in blobs {
+            let block = blob.unwrap().to_primitiveblock().unwrap();
+            block.for_each_element(|element| match element {
+                Element::DenseNode(node) => elements.push(format!("n{}", node.id)),
+                Element::Way(way) => {
+                    let refs = way.refs().collect::<Vec<_>>();
+                    elements.push(format!("w{}{:?}", way.id(), refs))
+                }
+                _ => panic!("unexpected element"),
+            });
+        }
+        assert_eq!(elements, vec!["n1", "n2", "n6", "w10[1, 2, 5]"]);
+    }
+
+    #[test]
+    fn test_compute_bounding_box() {
+        // Nodes at (0.0011, -0.0008) and (0.0015, -0.0005) with a granularity of 1000 nanodegrees
+        // and offsets.
+        let mut shifted = block(vec![node_at(3, 100, 200), node_at(4, 500, 500)], vec![]);
+        shifted.set_granularity(1000);
+        shifted.set_lat_offset(1_000_000);
+        shifted.set_lon_offset(-1_000_000);
+
+        let pbf = encode_test_pbf(&[
+            block(vec![node_at(1, 10_000_000, 20_000_000)], vec![]),
+            shifted,
+            block(vec![], vec![way(10, &[1])]),
+        ]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+        let expected = BoundingBox::new(
+            Coordinate::new(1_100_000, -800_000),
+            Coordinate::new(1_000_000_000, 2_000_000_000),
+        );
+        assert_eq!(reader.compute_bounding_box().unwrap(), Some(expected));
+        assert!(reader.iter_blobs().skip(1).all(|s| s.id_ranges().is_some()));
+
+        // The second call uses the stored bounding boxes of the blobs.
+        assert_eq!(reader.compute_bounding_box().unwrap(), Some(expected));
+
+        let pbf = encode_test_pbf(&[block(vec![], vec![way(10, &[1])])]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+        assert_eq!(reader.compute_bounding_box().unwrap(), None);
+        let mut reader = IndexedReader::new(Cursor::new(encode_test_pbf(&[]))).unwrap();
+        assert_eq!(reader.compute_bounding_box().unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_bbox_header() {
+        let mut bbox = osmformat::HeaderBBox::new();
+        bbox.set_left(0);
+        bbox.set_right(2_000_000_000);
+        bbox.set_bottom(0);
+        bbox.set_top(2_000_000_000);
+        let mut header = osmformat::HeaderBlock::new();
+        header.set_bbox(bbox);
+
+        let pbf = encode_test_pbf_with_header(
+            header,
+            &[block(vec![node_at(1, 10_000_000, 10_000_000)], vec![])],
+        );
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+
+        // Outside of the file bbox, no block is decoded
+        assert!(read_bbox_ids(&mut reader, (3.0, 3.0, 4.0, 4.0)).is_empty());
+        assert!(reader.iter_blobs().all(|s| s.id_ranges().is_none()));
+
+        assert_eq!(read_bbox_ids(&mut reader, (0.5, 0.5, 3.0, 3.0)), vec![1]);
+        assert!(reader.iter_blobs().nth(1).unwrap().id_ranges().is_some());
+    }
+
+    fn invalid_index_reason(err: ::error::Error) -> &'static str {
+        match *err.kind() {
+            ErrorKind::InvalidIndex { reason } => reason,
+            ref kind => panic!("unexpected error kind: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_save_and_load_index() {
+        let pbf = encode_test_pbf(&[
+            block(vec![node_at(1, 10, 20), node_at(2, 30, 40)], vec![]),
+            block(vec![], vec![way(10, &[1, 2])]),
+            relation_block(vec![relation(20, &[])]),
+        ]);
+
+        let mut reader = IndexedReader::new(Cursor::new(&pbf[..])).unwrap();
+        reader.read_ways_and_deps(|_| true, |_| {}).unwrap();
+        let mut saved = vec![];
+        reader.save_index(&mut saved).unwrap();
+
+        let mut loaded = IndexedReader::new(Cursor::new(&pbf[..])).unwrap();
+        loaded.load_index(&saved[..]).unwrap();
+        assert_eq!(
+            format!("{:?}", loaded.iter_blobs().collect::<Vec<_>>()),
+            format!("{:?}", reader.iter_blobs().collect::<Vec<_>>())
+        );
+        assert_eq!(
+            loaded.iter_blobs().nth(1).unwrap().node_bbox(),
+            Some(BoundingBox::new(
+                Coordinate::new(1000, 2000),
+                Coordinate::new(3000, 4000)
+            ))
+        );
+
+        // The loaded index is used without scanning the file again
+        let mut ways = vec![];
+        loaded
+            .read_ways_and_deps(
+                |_| true,
+                |element| {
+                    if let Element::Way(way) = element {
+                        ways.push(way.id());
+                    }
+                },
+            )
+            .unwrap();
+        assert_eq!(ways, vec![10]);
+    }
+
+    #[test]
+    fn test_load_invalid_index() {
+        let pbf = encode_test_pbf(&[block(vec![node(1)], vec![way(10, &[1])])]);
+        let mut reader = IndexedReader::new(Cursor::new(pbf.clone())).unwrap();
+        let mut saved = vec![];
+        reader.save_index(&mut saved).unwrap();
+
+        // Same length, different content
+        let mut changed = pbf.clone();
+        let last = changed.len() - 1;
+        changed[last] ^= 0xff;
+        let mut reader = IndexedReader::new(Cursor::new(changed)).unwrap();
+        let err = reader.load_index(&saved[..]).unwrap_err();
+        assert_eq!(invalid_index_reason(err), "file content differs");
+
+        // Different length
+        let mut longer = pbf.clone();
+        longer.extend_from_slice(&pbf[..4]);
+        let mut reader = IndexedReader::new(Cursor::new(longer)).unwrap();
+        let err = reader.load_index(&saved[..]).unwrap_err();
+        assert_eq!(invalid_index_reason(err), "file length differs");
+
+        let mut reader = IndexedReader::new(Cursor::new(pbf)).unwrap();
+        let err = reader.load_index(&b"not an index"[..]).unwrap_err();
+        assert_eq!(invalid_index_reason(err), "unknown format");
+
+        let err = reader.load_index(&saved[..saved.len() - 1]).unwrap_err();
+        assert_eq!(invalid_index_reason(err), "truncated data");
+        assert_eq!(reader.iter_blobs().len(), 0);
+
+        reader.load_index(&saved[..]).unwrap();
+        assert_eq!(reader.iter_blobs().len(), 2);
     }
 }
@@ -70,28 +70,62 @@ extern crate memmap;
 extern crate protobuf;
 extern crate rayon;
 
-#[cfg(feature = "system-libz")]
+#[cfg(any(feature = "system-libz", feature = "gzip"))]
 extern crate flate2;
 
 #[cfg(not(feature = "system-libz"))]
 extern crate inflate;
 
+#[cfg(feature = "libdeflate")]
+extern crate libdeflater;
+
+#[cfg(feature = "zstd")]
+extern crate zstd;
+
+#[cfg(feature = "lzma")]
+extern crate xz2;
+
+#[cfg(feature = "tokio")]
+extern crate futures_core;
+#[cfg(feature = "tokio")]
+extern crate tokio_runtime as tokio;
+
+#[cfg(feature = "tokio")]
+pub use async_blob::*;
 pub use blob::*;
 pub use block::*;
 pub use dense::*;
+pub use diff::*;
 pub use elements::*;
 pub use error::{BlobError, Error, ErrorKind, Result};
+pub use filter::*;
+pub use framing::*;
 pub use indexed::*;
+pub use locations::*;
 pub use mmap_blob::*;
+pub use multi::*;
+pub use owned::*;
 pub use reader::*;
+pub use stats::*;
+pub use writer::*;
 
+#[cfg(feature = "tokio")]
+pub mod async_blob;
 pub mod blob;
 pub mod block;
 pub mod dense;
+pub mod diff;
 pub mod elements;
 mod error;
+pub mod filter;
+pub mod framing;
 pub mod indexed;
+pub mod locations;
 pub mod mmap_blob;
+pub mod multi;
+pub mod owned;
 mod proto;
 pub mod reader;
+pub mod stats;
 mod util;
+pub mod writer;
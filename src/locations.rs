@@ -0,0 +1,164 @@
+//! Look up the coordinates of nodes by their id
+
+use elements::{Coordinate, Element};
+use error::Result;
+use reader::ElementReader;
+use std::collections::HashMap;
+use std::io::Read;
+
+/// The data structure that a `NodeLocationStore` uses to store coordinates.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LocationStorage {
+    /// A hash map from ids to coordinates. Lookups take constant time, but every entry has the
+    /// overhead of the hash table.
+    HashMap,
+    /// A vector of ids and coordinates that is sorted by id and searched with binary search.
+    /// Every entry takes 16 bytes (an `i64` id and two `i32` coordinates), which makes it the
+    /// better choice for large files.
+    SortedVec,
+}
+
+/// Builds a `NodeLocationStore`.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+/// let store = NodeLocationStoreBuilder::new()
+///     .storage(LocationStorage::HashMap)
+///     .build(reader)?;
+///
+/// if let Some(coord) = store.get(105) {
+///     println!("node 105 is at {}, {}", coord.lat(), coord.lon());
+/// }
+/// # assert_eq!(store.len(), 3);
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct NodeLocationStoreBuilder {
+    storage: LocationStorage,
+}
+
+impl NodeLocationStoreBuilder {
+    /// Creates a new builder that uses `LocationStorage::SortedVec`.
+    pub fn new() -> NodeLocationStoreBuilder {
+        NodeLocationStoreBuilder {
+            storage: LocationStorage::SortedVec,
+        }
+    }
+
+    /// Sets the data structure that stores the coordinates.
+    pub fn storage(mut self, storage: LocationStorage) -> NodeLocationStoreBuilder {
+        self.storage = storage;
+        self
+    }
+
+    /// Decodes all elements of the given reader in parallel and stores the coordinates of all
+    /// nodes and dense nodes. If a node id occurs more than once, one of its coordinates is
+    /// kept.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    pub fn build<R: Read>(&self, reader: ElementReader<R>) -> Result<NodeLocationStore> {
+        let mut locations = reader.par_for_each_init(
+            Vec::new,
+            |locations: &mut Vec<NodeLocation>, element| match element {
+                Element::Node(node) => locations.push(NodeLocation {
+                    id: node.id(),
+                    decimicro_lat: node.decimicro_lat(),
+                    decimicro_lon: node.decimicro_lon(),
+                }),
+                Element::DenseNode(node) => locations.push(NodeLocation {
+                    id: node.id,
+                    decimicro_lat: node.decimicro_lat(),
+                    decimicro_lon: node.decimicro_lon(),
+                }),
+                Element::Way(_) | Element::Relation(_) => {}
+            },
+            |mut a, mut b| {
+                a.append(&mut b);
+                a
+            },
+        )?;
+
+        let storage = match self.storage {
+            LocationStorage::HashMap => Storage::HashMap(
+                locations
+                    .into_iter()
+                    .map(|l| (l.id, (l.decimicro_lat, l.decimicro_lon)))
+                    .collect(),
+            ),
+            LocationStorage::SortedVec => {
+                locations.sort_unstable_by_key(|l| l.id);
+                locations.dedup_by_key(|l| l.id);
+                locations.shrink_to_fit();
+                Storage::SortedVec(locations)
+            }
+        };
+
+        Ok(NodeLocationStore { storage })
+    }
+}
+
+impl Default for NodeLocationStoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct NodeLocation {
+    id: i64,
+    decimicro_lat: i32,
+    decimicro_lon: i32,
+}
+
+#[derive(Clone, Debug)]
+enum Storage {
+    HashMap(HashMap<i64, (i32, i32)>),
+    SortedVec(Vec<NodeLocation>),
+}
+
+/// Maps node ids to their coordinates. This is useful to build the geometry of ways when
+/// reading the whole file anyway. Use a `NodeLocationStoreBuilder` to create it.
+///
+/// Coordinates are stored in decimicrodegrees (10⁻⁷, see `Coordinate::decimicro_lat`), the
+/// precision of the OpenStreetMap database, to save memory. Finer coordinates are truncated.
+#[derive(Clone, Debug)]
+pub struct NodeLocationStore {
+    storage: Storage,
+}
+
+impl NodeLocationStore {
+    /// Returns the coordinates of the node with the given id or `None` if it is unknown.
+    pub fn get(&self, id: i64) -> Option<Coordinate> {
+        let (decimicro_lat, decimicro_lon) = match self.storage {
+            Storage::HashMap(ref map) => *map.get(&id)?,
+            Storage::SortedVec(ref vec) => {
+                let index = vec.binary_search_by_key(&id, |l| l.id).ok()?;
+                (vec[index].decimicro_lat, vec[index].decimicro_lon)
+            }
+        };
+        Some(Coordinate::new(
+            i64::from(decimicro_lat) * 100,
+            i64::from(decimicro_lon) * 100,
+        ))
+    }
+
+    /// Returns the number of stored nodes.
+    pub fn len(&self) -> usize {
+        match self.storage {
+            Storage::HashMap(ref map) => map.len(),
+            Storage::SortedVec(ref vec) => vec.len(),
+        }
+    }
+
+    /// Returns true if no node is stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -1,14 +1,14 @@
 //! Iterate over blobs from a memory map
 
-extern crate byteorder;
 extern crate memmap;
 extern crate protobuf;
 
 use self::fileformat::BlobHeader;
-use blob::{decode_blob, BlobDecode, BlobType, ByteOffset};
+use blob::{
+    blob_data_size, decode_blob, decode_blob_header_size, BlobDecode, BlobType, ByteOffset,
+};
 use block::{HeaderBlock, PrimitiveBlock};
-use byteorder::ByteOrder;
-use error::{new_blob_error, new_protobuf_error, BlobError, Result};
+use error::{new_protobuf_error, new_unexpected_eof_error, Result};
 use proto::{fileformat, osmformat};
 use std::fs::File;
 use std::path::Path;
@@ -74,7 +74,7 @@ impl Mmap {
         MmapBlobReader::new(self)
     }
 
-    fn as_slice(&self) -> &[u8] {
+    pub(crate) fn as_slice(&self) -> &[u8] {
         &self.mmap
     }
 }
@@ -100,7 +100,7 @@ impl<'a> MmapBlob<'a> {
             }
             "OSMData" => {
                 let block: osmformat::PrimitiveBlock = decode_blob(&blob)?;
-                Ok(BlobDecode::OsmData(PrimitiveBlock::new(block)))
+                Ok(BlobDecode::OsmData(PrimitiveBlock::new(block)?))
             }
             x => Ok(BlobDecode::Unknown(x)),
         }
@@ -187,31 +187,27 @@ impl<'a> Iterator for MmapBlobReader<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         let slice = &self.mmap.as_slice()[self.offset..];
 
-        match slice.len() {
-            0 => return None,
-            1..=3 => {
-                self.last_blob_ok = false;
-                return Some(Err(new_blob_error(BlobError::InvalidHeaderSize)));
-            }
-            _ => {}
+        if slice.is_empty() {
+            return None;
         }
 
-        let header_size = byteorder::BigEndian::read_u32(slice) as usize;
+        // The offset where the content ends, for errors about truncated blobs.
+        let end_offset = Some(ByteOffset(self.mmap.as_slice().len() as u64));
 
-        if header_size as u64 >= ::blob::MAX_BLOB_HEADER_SIZE {
-            self.last_blob_ok = false;
-            return Some(Err(new_blob_error(BlobError::HeaderTooBig {
-                size: header_size as u64,
-            })));
-        }
+        let header_size = match decode_blob_header_size(
+            &slice[..slice.len().min(4)],
+            Some(ByteOffset(self.offset as u64)),
+        ) {
+            Ok(size) => size as usize,
+            Err(err) => {
+                self.last_blob_ok = false;
+                return Some(Err(err));
+            }
+        };
 
         if slice.len() < 4 + header_size {
             self.last_blob_ok = false;
-            let io_error = ::std::io::Error::new(
-                ::std::io::ErrorKind::UnexpectedEof,
-                "content too short for header",
-            );
-            return Some(Err(io_error.into()));
+            return Some(Err(new_unexpected_eof_error(end_offset)));
         }
 
         let header: BlobHeader = match parse_message_from_bytes(&slice[4..(4 + header_size)]) {
@@ -222,16 +218,18 @@ impl<'a> Iterator for MmapBlobReader<'a> {
             }
         };
 
-        let data_size = header.get_datasize() as usize;
+        let data_size = match blob_data_size(&header) {
+            Ok(size) => size as usize,
+            Err(err) => {
+                self.last_blob_ok = false;
+                return Some(Err(err));
+            }
+        };
         let chunk_size = 4 + header_size + data_size;
 
         if slice.len() < chunk_size {
             self.last_blob_ok = false;
-            let io_error = ::std::io::Error::new(
-                ::std::io::ErrorKind::UnexpectedEof,
-                "content too short for block data",
-            );
-            return Some(Err(io_error.into()));
+            return Some(Err(new_unexpected_eof_error(end_offset)));
         }
 
         let prev_offset = self.offset;
@@ -0,0 +1,201 @@
+//! Read several PBF files as one stream of elements
+
+use elements::Element;
+use error::Result;
+use reader::ElementReader;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// Number of ids that one chunk of an `IdBitmap` covers, as a power of two.
+const CHUNK_BITS: u32 = 16;
+
+/// A set of ids that stores one bit per id. The bits are allocated in chunks of `2^CHUNK_BITS`
+/// ids, so the memory usage follows the ranges of ids that are actually used: dense ranges like
+/// the nodes of a planet file take an eighth of a byte per id, while a few scattered ids only
+/// allocate a few chunks.
+#[derive(Clone, Debug, Default)]
+struct IdBitmap {
+    chunks: HashMap<i64, Vec<u64>>,
+}
+
+impl IdBitmap {
+    /// Inserts the given id and returns true if it was not contained before.
+    fn insert(&mut self, id: i64) -> bool {
+        // The arithmetic shift also maps negative ids to their own chunks.
+        let chunk = self
+            .chunks
+            .entry(id >> CHUNK_BITS)
+            .or_insert_with(|| vec![0; 1 << (CHUNK_BITS - 6)]);
+        let bit = (id & ((1 << CHUNK_BITS) - 1)) as usize;
+        let mask = 1 << (bit % 64);
+        let word = &mut chunk[bit / 64];
+        let new = *word & mask == 0;
+        *word |= mask;
+        new
+    }
+}
+
+/// The ids of the elements that have been seen, per element type.
+#[derive(Clone, Debug, Default)]
+struct SeenElements {
+    nodes: IdBitmap,
+    ways: IdBitmap,
+    relations: IdBitmap,
+}
+
+impl SeenElements {
+    /// Returns true if the given element has not been seen before.
+    fn insert(&mut self, element: &Element) -> bool {
+        match *element {
+            Element::Node(_) | Element::DenseNode(_) => self.nodes.insert(element.id()),
+            Element::Way(_) => self.ways.insert(element.id()),
+            Element::Relation(_) => self.relations.insert(element.id()),
+        }
+    }
+}
+
+/// A reader that reads the elements of several `ElementReader`s in sequence, as if they were one
+/// file. This is useful for regions that are split into several extracts.
+///
+/// Extracts usually overlap at their borders, so elements can occur in several files. With
+/// `deduplicate`, only the first occurrence of every element is passed on.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let paths = ["tests/test.osm.pbf", "tests/test_nozlib.osm.pbf"];
+/// let reader = MultiElementReader::from_paths(&paths)?.deduplicate(true);
+/// let mut ways = 0_u64;
+///
+/// reader.for_each(|element| {
+///     if let Element::Way(_) = element {
+///         ways += 1;
+///     }
+/// })?;
+///
+/// // Both files contain the same way
+/// assert_eq!(ways, 1);
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct MultiElementReader<R: Read> {
+    readers: Vec<ElementReader<R>>,
+    deduplicate: bool,
+}
+
+impl<R: Read> MultiElementReader<R> {
+    /// Creates a new `MultiElementReader` that reads the given readers in order.
+    pub fn new(readers: Vec<ElementReader<R>>) -> MultiElementReader<R> {
+        MultiElementReader {
+            readers,
+            deduplicate: false,
+        }
+    }
+
+    /// Sets whether elements with the same type and id as an element of a previous reader are
+    /// skipped. Defaults to false.
+    ///
+    /// The seen ids are kept in bitmaps that take one bit per id in the used ranges of ids, e.g.
+    /// about 1.5 GiB for the nodes of a planet file. Only enable it if the files overlap.
+    /// Elements are compared by type and id only, so if the files contain different versions
+    /// of an element, the one of the first file wins.
+    pub fn deduplicate(mut self, deduplicate: bool) -> MultiElementReader<R> {
+        self.deduplicate = deduplicate;
+        self
+    }
+
+    /// Decodes the readers sequentially and calls the given closure on each element.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure of any reader. The
+    /// following readers are not read then.
+    pub fn for_each<F>(self, mut f: F) -> Result<()>
+    where
+        F: for<'a> FnMut(Element<'a>),
+    {
+        self.for_each_labeled(|element, _| f(element))
+    }
+
+    /// Decodes the readers sequentially and calls the given closure on each element together
+    /// with the label of the reader that contains it (see `ElementReader::with_label`). Readers
+    /// that were opened with `from_paths` are labeled with their path.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure of any reader. The
+    /// following readers are not read then.
+    pub fn for_each_labeled<F>(self, mut f: F) -> Result<()>
+    where
+        F: for<'a> FnMut(Element<'a>, &str),
+    {
+        let mut seen = SeenElements::default();
+        for reader in self.readers {
+            if self.deduplicate {
+                reader.for_each_labeled(|element, label| {
+                    if seen.insert(&element) {
+                        f(element, label);
+                    }
+                })?;
+            } else {
+                reader.for_each_labeled(&mut f)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl MultiElementReader<BufReader<File>> {
+    /// Tries to open the files at the given paths and constructs a `MultiElementReader` that
+    /// reads them in the given order. Each reader is labeled with its path.
+    ///
+    /// # Errors
+    /// Returns the same errors that `std::fs::File::open` returns for the first file that cannot
+    /// be opened.
+    pub fn from_paths<I, P>(paths: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let readers = paths
+            .into_iter()
+            .map(|path| {
+                let label = path.as_ref().display().to_string();
+                ElementReader::from_path(path).map(|reader| reader.with_label(label))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(MultiElementReader::new(readers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn id_bitmap() {
+        let mut bitmap = IdBitmap::default();
+        for &id in &[
+            0,
+            1,
+            63,
+            64,
+            65_535,
+            65_536,
+            -1,
+            -65_536,
+            -65_537,
+            i64::max_value(),
+        ] {
+            assert!(bitmap.insert(id), "{}", id);
+            assert!(!bitmap.insert(id), "{}", id);
+        }
+        assert!(bitmap.insert(2));
+        assert_eq!(bitmap.chunks.len(), 5);
+    }
+}
@@ -0,0 +1,98 @@
+//! Owned copies of elements that do not borrow from a `PrimitiveBlock`
+
+use elements::{Coordinate, RelMemberType};
+
+/// An owned copy of an `Element` (see `Element::to_owned`). Dense nodes become
+/// `OwnedElement::Node`, as there is no difference between nodes and dense nodes once they are
+/// decoded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OwnedElement {
+    /// A node or dense node.
+    Node(OwnedNode),
+    /// A way.
+    Way(OwnedWay),
+    /// A relation.
+    Relation(OwnedRelation),
+}
+
+impl OwnedElement {
+    /// Returns the id of the element. Ids are only unique among elements of the same type.
+    pub fn id(&self) -> i64 {
+        match *self {
+            OwnedElement::Node(ref node) => node.id,
+            OwnedElement::Way(ref way) => way.id,
+            OwnedElement::Relation(ref relation) => relation.id,
+        }
+    }
+
+    /// Returns the tags of the element as key-value pairs, regardless of its type.
+    pub fn tags(&self) -> &[(String, String)] {
+        match *self {
+            OwnedElement::Node(ref node) => &node.tags,
+            OwnedElement::Way(ref way) => &way.tags,
+            OwnedElement::Relation(ref relation) => &relation.tags,
+        }
+    }
+}
+
+/// An owned copy of a `Node` or `DenseNode` (see `Node::to_owned`). All strings are resolved
+/// when it is created, so it can outlive its block and be sent to other threads.
+///
+/// Metadata (see `Info`) is not copied.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OwnedNode {
+    /// The id of the node.
+    pub id: i64,
+    /// The coordinates of the node.
+    pub coordinate: Coordinate,
+    /// The tags of the node as key-value pairs.
+    pub tags: Vec<(String, String)>,
+}
+
+/// An owned copy of a `Way` (see `Way::to_owned`). All strings are resolved and all references
+/// are decoded when it is created, so it can outlive its block and be sent to other threads.
+///
+/// Metadata (see `Info`) is not copied.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OwnedWay {
+    /// The id of the way.
+    pub id: i64,
+    /// The ids of the nodes of the way.
+    pub refs: Vec<i64>,
+    /// The tags of the way as key-value pairs.
+    pub tags: Vec<(String, String)>,
+}
+
+/// An owned copy of a `Relation` (see `Relation::to_owned`). All strings are resolved and all
+/// member ids are decoded when it is created, so it can outlive its block and be sent to other
+/// threads.
+///
+/// Metadata (see `Info`) is not copied.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OwnedRelation {
+    /// The id of the relation.
+    pub id: i64,
+    /// The members of the relation.
+    pub members: Vec<OwnedRelMember>,
+    /// The tags of the relation as key-value pairs.
+    pub tags: Vec<(String, String)>,
+}
+
+/// An owned copy of a `RelMember`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OwnedRelMember {
+    /// The element type of the referenced member.
+    pub member_type: RelMemberType,
+    /// The id of the referenced member.
+    pub member_id: i64,
+    /// The role of the member.
+    pub role: String,
+}
+
+/// Resolves the given tags to owned strings.
+pub(crate) fn owned_tags<'a, I>(tags: I) -> Vec<(String, String)>
+where
+    I: Iterator<Item = (&'a str, &'a str)>,
+{
+    tags.map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
@@ -34,6 +34,7 @@ pub struct Blob {
     zlib_data: ::protobuf::SingularField<::std::vec::Vec<u8>>,
     lzma_data: ::protobuf::SingularField<::std::vec::Vec<u8>>,
     OBSOLETE_bzip2_data: ::protobuf::SingularField<::std::vec::Vec<u8>>,
+    zstd_data: ::protobuf::SingularField<::std::vec::Vec<u8>>,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
@@ -212,6 +213,42 @@ impl Blob {
     pub fn take_OBSOLETE_bzip2_data(&mut self) -> ::std::vec::Vec<u8> {
         self.OBSOLETE_bzip2_data.take().unwrap_or_else(|| ::std::vec::Vec::new())
     }
+
+    // optional bytes zstd_data = 7;
+
+
+    pub fn get_zstd_data(&self) -> &[u8] {
+        match self.zstd_data.as_ref() {
+            Some(v) => &v,
+            None => &[],
+        }
+    }
+    pub fn clear_zstd_data(&mut self) {
+        self.zstd_data.clear();
+    }
+
+    pub fn has_zstd_data(&self) -> bool {
+        self.zstd_data.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_zstd_data(&mut self, v: ::std::vec::Vec<u8>) {
+        self.zstd_data = ::protobuf::SingularField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_zstd_data(&mut self) -> &mut ::std::vec::Vec<u8> {
+        if self.zstd_data.is_none() {
+            self.zstd_data.set_default();
+        }
+        self.zstd_data.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_zstd_data(&mut self) -> ::std::vec::Vec<u8> {
+        self.zstd_data.take().unwrap_or_else(|| ::std::vec::Vec::new())
+    }
 }
 
 impl ::protobuf::Message for Blob {
@@ -242,6 +279,9 @@ impl ::protobuf::Message for Blob {
                 5 => {
                     ::protobuf::rt::read_singular_bytes_into(wire_type, is, &mut self.OBSOLETE_bzip2_data)?;
                 },
+                7 => {
+                    ::protobuf::rt::read_singular_bytes_into(wire_type, is, &mut self.zstd_data)?;
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -269,6 +309,9 @@ impl ::protobuf::Message for Blob {
         if let Some(ref v) = self.OBSOLETE_bzip2_data.as_ref() {
             my_size += ::protobuf::rt::bytes_size(5, &v);
         }
+        if let Some(ref v) = self.zstd_data.as_ref() {
+            my_size += ::protobuf::rt::bytes_size(7, &v);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -290,6 +333,9 @@ impl ::protobuf::Message for Blob {
         if let Some(ref v) = self.OBSOLETE_bzip2_data.as_ref() {
             os.write_bytes(5, &v)?;
         }
+        if let Some(ref v) = self.zstd_data.as_ref() {
+            os.write_bytes(7, &v)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -342,6 +388,7 @@ impl ::protobuf::Clear for Blob {
         self.zlib_data.clear();
         self.lzma_data.clear();
         self.OBSOLETE_bzip2_data.clear();
+        self.zstd_data.clear();
         self.unknown_fields.clear();
     }
 }
@@ -1,17 +1,90 @@
 //! High level reader interface
 
-use blob::{BlobDecode, BlobReader};
-use elements::Element;
-use error::Result;
+use blob::{BlobDecode, BlobReader, BlobType, ByteOffset};
+use block::PrimitiveBlock;
+use elements::{Element, ElementTypeSet};
+use error::{new_error, ErrorKind, Result};
+use owned::OwnedElement;
 use rayon::prelude::*;
+use rayon::ThreadPool;
+use stats::{ElementCounts, NodeEncoding};
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::ops::ControlFlow;
 use std::path::Path;
+use std::sync::Arc;
+
+/// Options for the parallel methods of `ElementReader` like `par_map_reduce_with_config`.
+#[derive(Clone, Debug, Default)]
+pub struct ParallelConfig {
+    max_inflight_blobs: Option<usize>,
+    thread_pool: Option<Arc<ThreadPool>>,
+}
+
+impl ParallelConfig {
+    /// Creates a new config without limits. This is the behavior of `par_map_reduce`.
+    pub fn new() -> ParallelConfig {
+        ParallelConfig::default()
+    }
+
+    /// Limits the number of blobs that are held in memory at the same time. The reader reads up
+    /// to `max` blobs, decodes and processes them in parallel and only then reads the next
+    /// blobs. Smaller values reduce memory usage on large files, but leave worker threads idle
+    /// while the next blobs are read, so a multiple of the number of threads is a good choice.
+    /// A value of 0 is treated as 1.
+    pub fn max_inflight_blobs(mut self, max: usize) -> ParallelConfig {
+        self.max_inflight_blobs = Some(max.max(1));
+        self
+    }
+
+    /// Decodes and processes the blobs on the given rayon thread pool instead of the global
+    /// pool. This bounds the number of threads that are used for parsing and keeps them from
+    /// competing with other rayon workloads of the application.
+    ///
+    /// # Example
+    /// ```
+    /// extern crate osmpbf;
+    /// extern crate rayon;
+    ///
+    /// use osmpbf::*;
+    /// use std::sync::Arc;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    /// let config = ParallelConfig::new().thread_pool(Arc::new(pool));
+    ///
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// let elements = reader.par_map_reduce_with_config(config, |_| 1, || 0_u64, |a, b| a + b)?;
+    ///
+    /// # assert_eq!(elements, 5);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn thread_pool(mut self, pool: Arc<ThreadPool>) -> ParallelConfig {
+        self.thread_pool = Some(pool);
+        self
+    }
+
+    /// Runs `op` on the configured thread pool or on the current one.
+    fn install<OP, T>(&self, op: OP) -> T
+    where
+        OP: FnOnce() -> T + Send,
+        T: Send,
+    {
+        match self.thread_pool {
+            Some(ref pool) => pool.install(op),
+            None => op(),
+        }
+    }
+}
 
 /// A reader for PBF files that gives access to the stored elements: nodes, ways and relations.
 #[derive(Clone, Debug)]
 pub struct ElementReader<R: Read> {
     blob_iter: BlobReader<R>,
+    label: String,
 }
 
 impl<R: Read> ElementReader<R> {
@@ -34,9 +107,41 @@ impl<R: Read> ElementReader<R> {
     pub fn new(reader: R) -> ElementReader<R> {
         ElementReader {
             blob_iter: BlobReader::new(reader),
+            label: String::new(),
         }
     }
 
+    /// Attaches a label to this reader, e.g. the name of the source file or region, to tell
+    /// elements from different readers apart (see `for_each_labeled`). The label is purely user
+    /// metadata: it is neither read from nor stored in the PBF file. Readers have an empty label
+    /// by default.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?.with_label("test");
+    ///
+    /// assert_eq!(reader.label(), "test");
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn with_label<S: Into<String>>(mut self, label: S) -> ElementReader<R> {
+        self.label = label.into();
+        self
+    }
+
+    /// Returns the label of this reader (see `with_label`).
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub(crate) fn into_blob_reader(self) -> BlobReader<R> {
+        self.blob_iter
+    }
+
     /// Decodes the PBF structure sequentially and calls the given closure on each element.
     /// Consider using `par_map_reduce` instead if you need better performance.
     ///
@@ -84,6 +189,203 @@ impl<R: Read> ElementReader<R> {
         Ok(())
     }
 
+    /// Decodes the PBF structure sequentially and calls the given closure on each element together
+    /// with the label of this reader (see `with_label`). This is useful to keep track of the
+    /// source of elements when the same closure processes the elements of multiple files.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    /// use std::collections::HashMap;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut ways_per_region = HashMap::new();
+    /// let mut count_ways = |element: Element, label: &str| {
+    ///     if let Element::Way(_) = element {
+    ///         *ways_per_region.entry(label.to_string()).or_insert(0) += 1;
+    ///     }
+    /// };
+    ///
+    /// for (path, region) in &[("tests/test.osm.pbf", "a"), ("tests/test_nozlib.osm.pbf", "b")] {
+    ///     let reader = ElementReader::from_path(path)?.with_label(*region);
+    ///     reader.for_each_labeled(&mut count_ways)?;
+    /// }
+    ///
+    /// # assert_eq!(ways_per_region["a"], 1);
+    /// # assert_eq!(ways_per_region["b"], 1);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn for_each_labeled<F>(self, mut f: F) -> Result<()>
+    where
+        F: for<'a> FnMut(Element<'a>, &str),
+    {
+        let label = self.label;
+        for blob in self.blob_iter {
+            match blob?.decode() {
+                Ok(BlobDecode::OsmHeader(_)) | Ok(BlobDecode::Unknown(_)) => {}
+                Ok(BlobDecode::OsmData(block)) => {
+                    block.for_each_element(|element| f(element, &label));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the PBF structure sequentially and calls the given closure on each element together
+    /// with the byte offset of the blob that contains it. This allows building an external index
+    /// of elements and later reading their blobs again with `BlobReader::seek`.
+    ///
+    /// The offset is `None` if the underlying reader does not know its position (see
+    /// `Blob::offset`).
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    /// use std::collections::HashMap;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// let mut way_offsets = HashMap::new();
+    ///
+    /// reader.for_each_with_offset(|element, offset| {
+    ///     if let Element::Way(way) = element {
+    ///         way_offsets.insert(way.id(), offset);
+    ///     }
+    /// })?;
+    ///
+    /// # assert!(way_offsets[&107].is_some());
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn for_each_with_offset<F>(self, mut f: F) -> Result<()>
+    where
+        F: for<'a> FnMut(Element<'a>, Option<ByteOffset>),
+    {
+        for blob in self.blob_iter {
+            let blob = blob?;
+            let offset = blob.offset();
+            match blob.decode() {
+                Ok(BlobDecode::OsmHeader(_)) | Ok(BlobDecode::Unknown(_)) => {}
+                Ok(BlobDecode::OsmData(block)) => {
+                    block.for_each_element(|element| f(element, offset));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the PBF structure sequentially and calls the given closure on each element until
+    /// the closure returns `ControlFlow::Break`. In contrast to `for_each`, blobs are read lazily,
+    /// so the underlying reader stops after the blob that contains the element that caused the
+    /// break.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    /// use std::ops::ControlFlow;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// let mut first_way_id = None;
+    ///
+    /// // Stop at the first way.
+    /// reader.for_each_until(|element| {
+    ///     if let Element::Way(way) = element {
+    ///         first_way_id = Some(way.id());
+    ///         return ControlFlow::Break(());
+    ///     }
+    ///     ControlFlow::Continue(())
+    /// })?;
+    ///
+    /// println!("First way: {:?}", first_way_id);
+    ///
+    /// # assert_eq!(first_way_id, Some(107));
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn for_each_until<F>(self, mut f: F) -> Result<()>
+    where
+        F: for<'a> FnMut(Element<'a>) -> ControlFlow<()>,
+    {
+        for blob in self.blob_iter {
+            let blob = blob?;
+            match blob.decode() {
+                Ok(BlobDecode::OsmHeader(_)) | Ok(BlobDecode::Unknown(_)) => {}
+                Ok(BlobDecode::OsmData(block)) => {
+                    for element in block.elements() {
+                        if let ControlFlow::Break(()) = f(element) {
+                            return Ok(());
+                        }
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the PBF structure sequentially and calls the given closure on each element whose
+    /// type is contained in `types`. Groups of elements that are not selected are skipped, which
+    /// is faster than filtering the elements in the closure of `for_each`.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// let mut ways_and_relations = 0_u64;
+    ///
+    /// reader.for_each_types(ElementTypeSet::WAY | ElementTypeSet::RELATION, |_element| {
+    ///     ways_and_relations += 1;
+    /// })?;
+    ///
+    /// println!("Number of ways and relations: {}", ways_and_relations);
+    ///
+    /// # assert_eq!(ways_and_relations, 2);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn for_each_types<F>(self, types: ElementTypeSet, mut f: F) -> Result<()>
+    where
+        F: for<'a> FnMut(Element<'a>),
+    {
+        for blob in self.blob_iter {
+            let blob = blob?;
+            match blob.decode() {
+                Ok(BlobDecode::OsmHeader(_)) | Ok(BlobDecode::Unknown(_)) => {}
+                Ok(BlobDecode::OsmData(block)) => {
+                    block.for_each_element_of_types(types, &mut f);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
     /// Parallel map/reduce. Decodes the PBF structure in parallel, calls the closure `map_op` on
     /// each element and then reduces the number of results to one item with the closure
     /// `reduce_op`. Similarly to the `init` argument in the `fold` method on iterators, the
@@ -91,6 +393,9 @@ impl<R: Read> ElementReader<R> {
     /// necessary. The number of times that this identity value is inserted should not alter the
     /// result.
     ///
+    /// All blobs are read into memory before they are decoded. Use `par_map_reduce_with_config`
+    /// to limit the memory usage for large files.
+    ///
     /// # Errors
     /// Returns the first Error encountered while parsing the PBF structure.
     ///
@@ -124,32 +429,639 @@ impl<R: Read> ElementReader<R> {
         RD: Fn(T, T) -> T + Sync + Send,
         ID: Fn() -> T + Sync + Send,
         T: Send,
+    {
+        self.par_map_reduce_with_config(ParallelConfig::new(), map_op, identity, reduce_op)
+    }
+
+    /// Like `par_map_reduce`, but with options that control the parallel processing.
+    ///
+    /// `par_map_reduce` reads all blobs of the file into memory before decoding them, because
+    /// reading is usually much faster than decoding. With `ParallelConfig::max_inflight_blobs`
+    /// the file is instead processed in batches of blobs, so that memory usage does not grow
+    /// with the size of the file. The result is the same as long as `reduce_op` is associative.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// let config = ParallelConfig::new().max_inflight_blobs(64);
+    ///
+    /// // Count the ways
+    /// let ways = reader.par_map_reduce_with_config(
+    ///     config,
+    ///     |element| {
+    ///         match element {
+    ///             Element::Way(_) => 1,
+    ///             _ => 0,
+    ///         }
+    ///     },
+    ///     || 0_u64,
+    ///     |a, b| a + b,
+    /// )?;
+    ///
+    /// println!("Number of ways: {}", ways);
+    /// # assert_eq!(ways, 1);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn par_map_reduce_with_config<MP, RD, ID, T>(
+        self,
+        config: ParallelConfig,
+        map_op: MP,
+        identity: ID,
+        reduce_op: RD,
+    ) -> Result<T>
+    where
+        MP: for<'a> Fn(Element<'a>) -> T + Sync + Send,
+        RD: Fn(T, T) -> T + Sync + Send,
+        ID: Fn() -> T + Sync + Send,
+        T: Send,
+    {
+        let batch_size = config.max_inflight_blobs.unwrap_or(usize::MAX);
+        let mut blob_iter = self.blob_iter;
+        let mut result = identity();
+
+        loop {
+            let blobs = blob_iter
+                .by_ref()
+                .take(batch_size)
+                .collect::<Result<Vec<_>>>()?;
+            if blobs.is_empty() {
+                return Ok(result);
+            }
+
+            let batch_result = config.install(|| {
+                blobs
+                    .into_par_iter()
+                    .map(|blob| match blob.decode() {
+                        Ok(BlobDecode::OsmHeader(_)) | Ok(BlobDecode::Unknown(_)) => Ok(identity()),
+                        Ok(BlobDecode::OsmData(block)) => Ok(block
+                            .elements()
+                            .map(|e| map_op(e))
+                            .fold(identity(), |a, b| reduce_op(a, b))),
+                        Err(e) => Err(e),
+                    })
+                    .reduce(
+                        || Ok(identity()),
+                        |a, b| match (a, b) {
+                            (Ok(x), Ok(y)) => Ok(reduce_op(x, y)),
+                            (x, y) => x.and(y),
+                        },
+                    )
+            })?;
+            result = reduce_op(result, batch_result);
+        }
+    }
+
+    /// Parallel map that preserves the order of the file. Decodes the PBF structure in parallel,
+    /// calls the closure `map_op` on each element and returns the results in the same order as
+    /// `for_each` would visit the elements. This makes the output reproducible, in contrast to
+    /// the order in which `par_map_reduce` combines results.
+    ///
+    /// The results of all elements are kept in memory. In addition, all blobs are read into
+    /// memory before they are decoded. Use `par_map_ordered_with_config` to limit the number of
+    /// blobs that are held at the same time.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// // Ids of all ways in file order
+    /// let way_ids: Vec<i64> = reader
+    ///     .par_map_ordered(|element| match element {
+    ///         Element::Way(way) => Some(way.id()),
+    ///         _ => None,
+    ///     })?
+    ///     .into_iter()
+    ///     .flatten()
+    ///     .collect();
+    ///
+    /// # assert_eq!(way_ids, vec![107]);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn par_map_ordered<MP, T>(self, map_op: MP) -> Result<Vec<T>>
+    where
+        MP: for<'a> Fn(Element<'a>) -> T + Sync + Send,
+        T: Send,
+    {
+        self.par_map_ordered_with_config(ParallelConfig::new(), map_op)
+    }
+
+    /// Same as `par_map_ordered`, but with options that control the parallel processing (see
+    /// `ParallelConfig`).
+    ///
+    /// Blobs are read and decoded in batches of at most `max_inflight_blobs`. The results of a
+    /// batch are collected in blob order before the next batch is read, so blobs that finish
+    /// early wait for slower blobs of the same batch. Smaller batches bound the memory that is
+    /// used for buffering, but leave more threads idle at the end of each batch.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    pub fn par_map_ordered_with_config<MP, T>(
+        self,
+        config: ParallelConfig,
+        map_op: MP,
+    ) -> Result<Vec<T>>
+    where
+        MP: for<'a> Fn(Element<'a>) -> T + Sync + Send,
+        T: Send,
+    {
+        let batch_size = config.max_inflight_blobs.unwrap_or(usize::MAX);
+        let mut blob_iter = self.blob_iter;
+        let mut result = vec![];
+
+        loop {
+            let blobs = blob_iter
+                .by_ref()
+                .take(batch_size)
+                .collect::<Result<Vec<_>>>()?;
+            if blobs.is_empty() {
+                return Ok(result);
+            }
+
+            // Collecting an indexed parallel iterator keeps the order of the blobs.
+            let batch = config.install(|| {
+                blobs
+                    .into_par_iter()
+                    .map(|blob| match blob.decode() {
+                        Ok(BlobDecode::OsmHeader(_)) | Ok(BlobDecode::Unknown(_)) => Ok(vec![]),
+                        Ok(BlobDecode::OsmData(block)) => {
+                            let mut results = vec![];
+                            block.for_each_element(|e| results.push(map_op(e)));
+                            Ok(results)
+                        }
+                        Err(e) => Err(e),
+                    })
+                    .collect::<Result<Vec<Vec<T>>>>()
+            })?;
+            result.extend(batch.into_iter().flatten());
+        }
+    }
+
+    /// Parallel aggregation with thread-local accumulators. Decodes the PBF structure in parallel
+    /// and calls the closure `process` on each element together with an accumulator. Each worker
+    /// thread creates its own accumulators with the closure `init` and processes whole blocks into
+    /// them, so no locking is needed while processing elements. In the end, all accumulators are
+    /// combined into one with the closure `merge`.
+    ///
+    /// In contrast to `par_map_reduce`, the accumulator is modified in place and `merge` is only
+    /// called a few times per worker thread instead of once per element. This makes it a good fit
+    /// for accumulators that are expensive to combine, like maps or histograms, and avoids the
+    /// contention of sharing a single accumulator behind a `Mutex`. The number of accumulators
+    /// that `init` creates depends on how the work is split up and should not alter the result.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    /// use std::collections::HashMap;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// // Count the occurrences of each tag key
+    /// let key_counts = reader.par_for_each_init(
+    ///     HashMap::<String, u64>::new,
+    ///     |counts, element| {
+    ///         if let Element::Way(way) = element {
+    ///             for (key, _) in way.tags() {
+    ///                 *counts.entry(key.to_string()).or_insert(0) += 1;
+    ///             }
+    ///         }
+    ///     },
+    ///     |mut a, b| {
+    ///         for (key, count) in b {
+    ///             *a.entry(key).or_insert(0) += count;
+    ///         }
+    ///         a
+    ///     },
+    /// )?;
+    ///
+    /// println!("{:?}", key_counts);
+    /// # assert_eq!(key_counts["building"], 1);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn par_for_each_init<IN, PR, MG, A>(self, init: IN, process: PR, merge: MG) -> Result<A>
+    where
+        IN: Fn() -> A + Sync + Send,
+        PR: for<'a> Fn(&mut A, Element<'a>) + Sync + Send,
+        MG: Fn(A, A) -> A + Sync + Send,
+        A: Send,
     {
         let blobs = self.blob_iter.collect::<Result<Vec<_>>>()?;
 
         blobs
+            .into_par_iter()
+            .fold(
+                || Ok(init()),
+                |acc: Result<A>, blob| {
+                    let mut acc = acc?;
+                    match blob.decode()? {
+                        BlobDecode::OsmHeader(_) | BlobDecode::Unknown(_) => {}
+                        BlobDecode::OsmData(block) => {
+                            block.for_each_element(|element| process(&mut acc, element));
+                        }
+                    }
+                    Ok(acc)
+                },
+            )
+            .reduce(
+                || Ok(init()),
+                |a, b| match (a, b) {
+                    (Ok(x), Ok(y)) => Ok(merge(x, y)),
+                    (x, y) => x.and(y),
+                },
+            )
+    }
+
+    /// Returns the `k` most frequent values of the tag with the given `key` together with their
+    /// number of occurrences. The values are sorted by descending frequency. Tags of all element
+    /// types are counted and the file is decoded in parallel.
+    ///
+    /// The counts are exact. Memory usage is proportional to the number of distinct values of
+    /// the given key, so this should not be used with keys that have an unbounded number of
+    /// values like `name`. Values that are not valid UTF-8 are ignored.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for (value, count) in reader.top_tag_values("building", 10)? {
+    ///     println!("building={}: {}", value, count);
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn top_tag_values(self, key: &str, k: usize) -> Result<Vec<(String, u64)>> {
+        let blobs = self.blob_iter.collect::<Result<Vec<_>>>()?;
+
+        let counts = blobs
             .into_par_iter()
             .map(|blob| match blob.decode() {
-                Ok(BlobDecode::OsmHeader(_)) | Ok(BlobDecode::Unknown(_)) => Ok(identity()),
-                Ok(BlobDecode::OsmData(block)) => Ok(block
-                    .elements()
-                    .map(|e| map_op(e))
-                    .fold(identity(), |a, b| reduce_op(a, b))),
+                Ok(BlobDecode::OsmHeader(_)) | Ok(BlobDecode::Unknown(_)) => Ok(HashMap::new()),
+                Ok(BlobDecode::OsmData(block)) => Ok(count_tag_values(&block, key)),
                 Err(e) => Err(e),
             })
             .reduce(
-                || Ok(identity()),
+                || Ok(HashMap::new()),
                 |a, b| match (a, b) {
-                    (Ok(x), Ok(y)) => Ok(reduce_op(x, y)),
+                    (Ok(mut x), Ok(y)) => {
+                        for (value, count) in y {
+                            *x.entry(value).or_insert(0) += count;
+                        }
+                        Ok(x)
+                    }
                     (x, y) => x.and(y),
                 },
-            )
+            )?;
+
+        let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.truncate(k);
+        Ok(counts)
+    }
+
+    /// Returns the number of nodes (including dense nodes), ways and relations in the file.
+    ///
+    /// The PBF format does not define element counts in the `HeaderBlock` (the optional
+    /// `osmosis_replication_*` fields only describe the replication state), so the counts are
+    /// determined by decoding all blocks in parallel. Counting only looks at the lengths of the
+    /// element arrays and does not decode the elements themselves.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// let counts = reader.count_elements()?;
+    ///
+    /// println!("{} elements", counts.total());
+    /// # assert_eq!(counts.total(), 5);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn count_elements(self) -> Result<ElementCounts> {
+        let blobs = self.blob_iter.collect::<Result<Vec<_>>>()?;
+
+        let counts = blobs
+            .into_par_iter()
+            .map(|blob| match blob.decode() {
+                Ok(BlobDecode::OsmHeader(_)) | Ok(BlobDecode::Unknown(_)) => {
+                    Ok(ElementCounts::default())
+                }
+                Ok(BlobDecode::OsmData(block)) => Ok(ElementCounts::from_block(&block)),
+                Err(e) => Err(e),
+            })
+            .reduce(
+                || Ok(ElementCounts::default()),
+                |a, b| match (a, b) {
+                    (Ok(mut x), Ok(y)) => {
+                        x += y;
+                        Ok(x)
+                    }
+                    (x, y) => x.and(y),
+                },
+            )?;
+
+        Ok(counts)
+    }
+
+    /// Returns an iterator over the primitive blocks of the file in order, so elements can be read
+    /// with plain `for` loops. Header blobs and blobs of unknown types are skipped.
+    ///
+    /// The elements of a block borrow from it, so an iterator cannot return them directly. Iterate
+    /// over the elements of each block instead (see `PrimitiveBlock::elements`). This avoids
+    /// copying the elements, unlike iterating over the reader itself, which yields
+    /// `OwnedElement`s (see `ElementIter`). Use the closure based methods like `par_map_reduce`
+    /// for the parallel case.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// let mut ways = 0_u64;
+    ///
+    /// for block in reader.blocks() {
+    ///     for element in block?.elements() {
+    ///         if let Element::Way(_) = element {
+    ///             ways += 1;
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// println!("Number of ways: {}", ways);
+    /// # assert_eq!(ways, 1);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn blocks(self) -> PrimitiveBlockIter<R> {
+        PrimitiveBlockIter {
+            blob_iter: self.blob_iter,
+        }
+    }
+}
+
+impl<R: Read + Send> ElementReader<R> {
+    /// Returns a rayon `ParallelIterator` over owned copies of all elements, so the file can be
+    /// processed with the standard rayon combinators instead of the fixed shape of
+    /// `par_map_reduce`.
+    ///
+    /// Blobs are read sequentially and handed over to the thread pool as they are read, where
+    /// they are decoded and their elements copied to `OwnedElement`s. The elements of a block
+    /// are processed after the whole block was copied, so memory usage is bounded by the blocks
+    /// in flight and not by the size of the file. Copying every element is considerably slower
+    /// than `par_map_reduce`, which visits borrowed elements, so prefer it for hot paths.
+    ///
+    /// The order of the elements is not preserved. Errors are yielded as items and do not stop
+    /// the iteration, so `collect::<Result<Vec<_>>>()` or `try_for_each` are the easiest ways to
+    /// handle them.
+    ///
+    /// # Example
+    /// ```
+    /// extern crate osmpbf;
+    /// extern crate rayon;
+    ///
+    /// use osmpbf::*;
+    /// use rayon::prelude::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// let tagged: Vec<OwnedElement> = reader
+    ///     .par_iter()
+    ///     .filter(|element| match *element {
+    ///         Ok(ref element) => !element.tags().is_empty(),
+    ///         Err(_) => true,
+    ///     })
+    ///     .collect::<Result<_>>()?;
+    ///
+    /// # assert_eq!(tagged.len(), 2);
+    /// println!("{} elements with tags", tagged.len());
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn par_iter(self) -> impl ParallelIterator<Item = Result<OwnedElement>> {
+        self.blob_iter.par_bridge().flat_map(|blob| {
+            let elements: Vec<Result<OwnedElement>> = match blob.and_then(|blob| blob.decode()) {
+                Ok(BlobDecode::OsmHeader(_)) | Ok(BlobDecode::Unknown(_)) => vec![],
+                Ok(BlobDecode::OsmData(block)) => block.elements().map(|e| e.to_owned()).collect(),
+                Err(e) => vec![Err(e)],
+            };
+            elements.into_par_iter()
+        })
+    }
+}
+
+/// Counts the values of the tag with the given key in a block. Values are counted by their
+/// stringtable index and only resolved to strings once per block. A string may occur more than
+/// once in the stringtable, so all indices of the key are matched and the counts of values with
+/// the same string are added up.
+fn count_tag_values(block: &PrimitiveBlock, key: &str) -> HashMap<String, u64> {
+    let stringtable = block.raw_stringtable();
+    let key_indices: Vec<usize> = stringtable
+        .iter()
+        .enumerate()
+        .filter(|&(_, s)| s.as_slice() == key.as_bytes())
+        .map(|(i, _)| i)
+        .collect();
+    if key_indices.is_empty() {
+        return HashMap::new();
+    }
+
+    let mut index_counts: HashMap<usize, u64> = HashMap::new();
+    for element in block.elements() {
+        let mut count = |k: usize, v: usize| {
+            if key_indices.contains(&k) {
+                *index_counts.entry(v).or_insert(0) += 1;
+            }
+        };
+        match element {
+            Element::Node(node) => node
+                .raw_tags()
+                .for_each(|(k, v)| count(k as usize, v as usize)),
+            Element::DenseNode(node) => node
+                .raw_tags()
+                .for_each(|(k, v)| count(k as usize, v as usize)),
+            Element::Way(way) => way
+                .raw_tags()
+                .for_each(|(k, v)| count(k as usize, v as usize)),
+            Element::Relation(rel) => rel
+                .raw_tags()
+                .for_each(|(k, v)| count(k as usize, v as usize)),
+        }
+    }
+
+    let mut counts: HashMap<String, u64> = HashMap::new();
+    for (index, count) in index_counts {
+        let value = stringtable
+            .get(index)
+            .and_then(|s| std::str::from_utf8(s).ok());
+        if let Some(value) = value {
+            *counts.entry(value.to_string()).or_insert(0) += count;
+        }
+    }
+    counts
+}
+
+impl<R: Read + Seek> ElementReader<R> {
+    /// Creates a new `ElementReader` after checking that all required features of the file are
+    /// supported by this crate (see `SUPPORTED_FEATURES`). This prevents silently misreading
+    /// files that depend on features that are not implemented.
+    ///
+    /// The first blob is read to check the `HeaderBlock`. If the file does not start with a
+    /// header, the reader is moved back to the first blob and nothing is checked.
+    ///
+    /// # Errors
+    /// Returns `ErrorKind::UnsupportedFeature` if the file requires an unsupported feature and
+    /// the errors of reading and decoding the header.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let f = std::fs::File::open("tests/test.osm.pbf")?;
+    /// let reader = ElementReader::new_checked(std::io::BufReader::new(f))?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn new_checked(reader: R) -> Result<ElementReader<R>> {
+        let mut blob_iter = BlobReader::new_seekable(reader)?;
+        let start = blob_iter.next_offset();
+
+        if let Some(blob) = blob_iter.next() {
+            let blob = blob?;
+            if blob.get_type() == BlobType::OsmHeader {
+                if let Some(feature) = blob.to_headerblock()?.unsupported_feature() {
+                    return Err(new_error(ErrorKind::UnsupportedFeature {
+                        feature: feature.to_string(),
+                    }));
+                }
+            } else if let Some(start) = start {
+                blob_iter.seek(start)?;
+            }
+        }
+
+        Ok(ElementReader {
+            blob_iter,
+            label: String::new(),
+        })
+    }
+
+    /// Finds out whether the file stores nodes as plain `Node`s or as `DenseNode`s, e.g. to choose
+    /// a processing strategy up front. Only the first `PrimitiveBlock` that contains nodes is
+    /// decoded, so this is cheap but reports `NodeEncoding::Mixed` only if this block contains
+    /// both kinds of nodes. Afterwards, the reader is moved back to its previous position.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure. The position of the
+    /// reader is unspecified in this case.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// if reader.node_encoding()?.uses_dense_nodes() {
+    ///     println!("file contains dense nodes");
+    /// }
+    ///
+    /// # assert_eq!(reader.node_encoding()?, NodeEncoding::Dense);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn node_encoding(&mut self) -> Result<NodeEncoding> {
+        let start = self.blob_iter.seek_raw(SeekFrom::Current(0))?;
+        let mut encoding = NodeEncoding::NoNodes;
+
+        for blob in self.blob_iter.by_ref() {
+            let blob = blob?;
+            if blob.get_type() != BlobType::OsmData {
+                continue;
+            }
+            let block = blob.to_primitiveblock()?;
+            let plain = block.groups().any(|g| g.nodes().next().is_some());
+            let dense = block.groups().any(|g| g.dense_nodes_len() > 0);
+            encoding = NodeEncoding::from_flags(plain, dense);
+            if encoding != NodeEncoding::NoNodes {
+                break;
+            }
+        }
+
+        self.blob_iter.seek(ByteOffset(start))?;
+        Ok(encoding)
+    }
+}
+
+impl<'a> ElementReader<Cursor<&'a [u8]>> {
+    /// Constructs an `ElementReader` from a PBF file that is already stored in memory.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let data = std::fs::read("tests/test.osm.pbf")?;
+    /// let reader = ElementReader::from_bytes(&data);
+    ///
+    /// let mut elements = 0_u64;
+    /// reader.for_each(|_element| elements += 1)?;
+    /// # assert_eq!(elements, 5);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn from_bytes(bytes: &'a [u8]) -> Self {
+        ElementReader::new(Cursor::new(bytes))
     }
 }
 
 impl ElementReader<BufReader<File>> {
     /// Tries to open the file at the given path and constructs an `ElementReader` from this.
     ///
+    /// There is no `From<&Path>` implementation, because opening the file can fail and `From`
+    /// cannot report that.
+    ///
     /// # Errors
     /// Returns the same errors that `std::fs::File::open` returns.
     ///
@@ -166,6 +1078,203 @@ impl ElementReader<BufReader<File>> {
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
         Ok(ElementReader {
             blob_iter: BlobReader::from_path(path)?,
+            label: String::new(),
         })
     }
 }
+
+/// An iterator over the primitive blocks of an `ElementReader` that decodes one blob at a time,
+/// returned by `ElementReader::blocks`. Like `BlobReader`, it stops after the first read error. A
+/// block that cannot be decoded is returned as an error.
+#[derive(Debug)]
+pub struct PrimitiveBlockIter<R: Read> {
+    blob_iter: BlobReader<R>,
+}
+
+impl<R: Read> Iterator for PrimitiveBlockIter<R> {
+    type Item = Result<PrimitiveBlock>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let blob = match self.blob_iter.next()? {
+                Ok(blob) => blob,
+                Err(err) => return Some(Err(err)),
+            };
+            if blob.get_type() == BlobType::OsmData {
+                return Some(blob.to_primitiveblock());
+            }
+        }
+    }
+}
+
+impl<R: Read> IntoIterator for ElementReader<R> {
+    type Item = Result<OwnedElement>;
+    type IntoIter = ElementIter<R>;
+
+    /// Returns an iterator over owned copies of all elements in order (see `ElementIter`).
+    fn into_iter(self) -> ElementIter<R> {
+        ElementIter {
+            blocks: self.blocks(),
+            elements: Vec::new().into_iter(),
+        }
+    }
+}
+
+/// An iterator over owned copies of all elements of an `ElementReader`, returned by its
+/// `IntoIterator` implementation.
+///
+/// Blobs are decoded one at a time when the elements of the previous block are used up, and the
+/// elements of each block are copied to `OwnedElement`s. Use `ElementReader::blocks` to iterate
+/// over the borrowed elements of each block without copying them. Like `PrimitiveBlockIter`, it
+/// stops after the first read error.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+/// let mut ways = 0_u64;
+///
+/// for element in reader {
+///     if let OwnedElement::Way(_) = element? {
+///         ways += 1;
+///     }
+/// }
+///
+/// println!("Number of ways: {}", ways);
+/// # assert_eq!(ways, 1);
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ElementIter<R: Read> {
+    blocks: PrimitiveBlockIter<R>,
+    elements: std::vec::IntoIter<Result<OwnedElement>>,
+}
+
+impl<R: Read> Iterator for ElementIter<R> {
+    type Item = Result<OwnedElement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(element) = self.elements.next() {
+                return Some(element);
+            }
+            let block = match self.blocks.next()? {
+                Ok(block) => block,
+                Err(err) => return Some(Err(err)),
+            };
+            let elements: Vec<Result<OwnedElement>> =
+                block.elements().map(|e| e.to_owned()).collect();
+            self.elements = elements.into_iter();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filter::TagFilter;
+    use proto::osmformat;
+    use util::{block, encode_test_pbf_with_header, way};
+
+    #[test]
+    fn new_checked_unsupported_feature() {
+        let mut header = osmformat::HeaderBlock::new();
+        header
+            .mut_required_features()
+            .push("LocationsOnWays".to_string());
+        header
+            .mut_required_features()
+            .push("Sort.Type_then_ID".to_string());
+        let pbf = encode_test_pbf_with_header(header, &[]);
+
+        let err = ElementReader::new_checked(Cursor::new(&pbf[..])).unwrap_err();
+        match *err.kind() {
+            ErrorKind::UnsupportedFeature { ref feature } => {
+                assert_eq!(feature, "Sort.Type_then_ID")
+            }
+            ref kind => panic!("unexpected error kind: {:?}", kind),
+        }
+
+        let pbf = encode_test_pbf_with_header(osmformat::HeaderBlock::new(), &[]);
+        assert!(ElementReader::new_checked(Cursor::new(&pbf[..])).is_ok());
+
+        let mut header = osmformat::HeaderBlock::new();
+        header
+            .mut_required_features()
+            .push("LocationsOnWays".to_string());
+        let pbf = encode_test_pbf_with_header(header, &[]);
+        assert!(ElementReader::new_checked(Cursor::new(&pbf[..])).is_ok());
+    }
+
+    #[test]
+    fn new_checked_history_file() {
+        let mut header = osmformat::HeaderBlock::new();
+        header
+            .mut_required_features()
+            .push("HistoricalInformation".to_string());
+        let pbf = encode_test_pbf_with_header(header, &[]);
+        assert!(ElementReader::new_checked(Cursor::new(&pbf[..])).is_ok());
+
+        let blob = BlobReader::new(Cursor::new(&pbf[..])).next().unwrap();
+        let header = blob.unwrap().to_headerblock().unwrap();
+        assert!(header.has_historical_information());
+    }
+
+    #[test]
+    fn duplicate_stringtable_entries() {
+        // Some writers do not deduplicate the stringtable, so equal tags can use different indices.
+        let mut raw = block(
+            vec![],
+            vec![way(1, &[]), way(2, &[]), way(3, &[]), way(4, &[])],
+        );
+        for s in &["building", "yes", "building", "yes", "no"] {
+            raw.mut_stringtable().mut_s().push(s.as_bytes().to_vec());
+        }
+        let tags: [(u32, u32); 4] = [(1, 2), (3, 4), (1, 4), (3, 5)];
+        for (way, &(key, value)) in raw.mut_primitivegroup()[0].mut_ways().iter_mut().zip(&tags) {
+            way.mut_keys().push(key);
+            way.mut_vals().push(value);
+        }
+
+        let block = PrimitiveBlock::new(raw).unwrap();
+        let counts = count_tag_values(&block, "building");
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts["yes"], 3);
+        assert_eq!(counts["no"], 1);
+
+        let filter = TagFilter::new()
+            .key_value("building", "yes")
+            .compile(&block);
+        let ids: Vec<i64> = block
+            .groups()
+            .flat_map(|group| group.ways())
+            .filter(|way| filter.matches_raw_tags(way.raw_tags()))
+            .map(|way| way.id())
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn header_replication_fields() {
+        let mut header = osmformat::HeaderBlock::new();
+        header.set_osmosis_replication_timestamp(1_600_000_000);
+        header.set_osmosis_replication_sequence_number(4242);
+        header.set_osmosis_replication_base_url(
+            "https://planet.openstreetmap.org/replication/minute".to_string(),
+        );
+        let pbf = encode_test_pbf_with_header(header, &[]);
+
+        let blob = BlobReader::new(Cursor::new(&pbf[..])).next().unwrap();
+        let header = blob.unwrap().to_headerblock().unwrap();
+        assert_eq!(header.replication_timestamp(), Some(1_600_000_000));
+        assert_eq!(header.replication_sequence(), Some(4242));
+        assert_eq!(
+            header.replication_base_url(),
+            Some("https://planet.openstreetmap.org/replication/minute")
+        );
+    }
+}
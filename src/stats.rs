@@ -0,0 +1,237 @@
+//! Summary statistics of PBF files
+
+use blob::{Blob, BlobType, ByteOffset, Compression};
+use block::PrimitiveBlock;
+use indexed::{IdRanges, SimpleBlobType};
+use std::fmt;
+use std::ops::AddAssign;
+
+/// The number of elements of each type.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ElementCounts {
+    /// The number of nodes including dense nodes.
+    pub nodes: u64,
+    /// The number of ways.
+    pub ways: u64,
+    /// The number of relations.
+    pub relations: u64,
+}
+
+impl ElementCounts {
+    /// Counts the elements of a `PrimitiveBlock` without decoding them.
+    pub fn from_block(block: &PrimitiveBlock) -> ElementCounts {
+        let mut counts = ElementCounts::default();
+        for group in block.groups() {
+            counts.nodes += (group.nodes().len() + group.dense_nodes().len()) as u64;
+            counts.ways += group.ways().len() as u64;
+            counts.relations += group.relations().len() as u64;
+        }
+        counts
+    }
+
+    /// Returns the total number of elements.
+    pub fn total(&self) -> u64 {
+        self.nodes + self.ways + self.relations
+    }
+}
+
+impl AddAssign for ElementCounts {
+    fn add_assign(&mut self, other: ElementCounts) {
+        self.nodes += other.nodes;
+        self.ways += other.ways;
+        self.relations += other.relations;
+    }
+}
+
+/// How the nodes of a file are encoded (see `ElementReader::node_encoding`).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum NodeEncoding {
+    /// No nodes were found.
+    NoNodes,
+    /// All nodes are stored as plain `Node`s.
+    Plain,
+    /// All nodes are stored as `DenseNode`s.
+    Dense,
+    /// Nodes are stored both ways.
+    Mixed,
+}
+
+impl NodeEncoding {
+    /// Determines the encoding from whether plain and dense nodes occur.
+    pub(crate) fn from_flags(plain: bool, dense: bool) -> NodeEncoding {
+        match (plain, dense) {
+            (false, false) => NodeEncoding::NoNodes,
+            (true, false) => NodeEncoding::Plain,
+            (false, true) => NodeEncoding::Dense,
+            (true, true) => NodeEncoding::Mixed,
+        }
+    }
+
+    /// Returns true if dense nodes occur.
+    pub fn uses_dense_nodes(&self) -> bool {
+        match *self {
+            NodeEncoding::Dense | NodeEncoding::Mixed => true,
+            NodeEncoding::NoNodes | NodeEncoding::Plain => false,
+        }
+    }
+}
+
+/// Statistics about the blobs of a PBF file.
+///
+/// All fields except `element_counts` are computed from the blob headers and the blob messages
+/// without decompressing any content. `element_counts` requires decompressing and decoding every
+/// `PrimitiveBlock` and is only available if it was requested.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FileStats {
+    /// The number of `HeaderBlock` blobs.
+    pub header_blobs: u64,
+    /// The number of `PrimitiveBlock` blobs.
+    pub primitive_blobs: u64,
+    /// The number of blobs with an unknown type.
+    pub unknown_blobs: u64,
+    /// The total size of all blob messages in bytes as stored in the file.
+    pub compressed_size: u64,
+    /// The estimated total size of all blob contents in bytes after decompression. Only blobs
+    /// that are stored uncompressed or declare their `raw_size` are included.
+    pub uncompressed_size: u64,
+    /// The number of blobs for which the uncompressed size is unknown.
+    pub unknown_size_blobs: u64,
+    /// The number of blobs that are stored uncompressed.
+    pub uncompressed_blobs: u64,
+    /// The number of blobs that are compressed with zlib.
+    pub zlib_blobs: u64,
+    /// The number of blobs that are compressed with LZMA.
+    pub lzma_blobs: u64,
+    /// The number of blobs that are compressed with Zstandard.
+    pub zstd_blobs: u64,
+    /// The number of blobs with unknown or missing content.
+    pub unknown_compression_blobs: u64,
+    /// The number of elements of each type. This is `None` if element counting was not requested.
+    pub element_counts: Option<ElementCounts>,
+    /// The id ranges of all elements in the file. This is only filled by
+    /// `IndexedReader::collect_stats`.
+    pub id_ranges: Option<IdRanges>,
+    /// Statistics of each blob in file order. This is only filled by
+    /// `IndexedReader::collect_stats`.
+    pub blob_stats: Vec<BlobStats>,
+}
+
+impl FileStats {
+    /// Returns the total number of blobs.
+    pub fn blobs(&self) -> u64 {
+        self.header_blobs + self.primitive_blobs + self.unknown_blobs
+    }
+
+    /// Returns the ratio of `uncompressed_size` to `compressed_size` or `None` if the
+    /// uncompressed size of any blob is unknown.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        if self.unknown_size_blobs > 0 || self.compressed_size == 0 {
+            None
+        } else {
+            Some(self.uncompressed_size as f64 / self.compressed_size as f64)
+        }
+    }
+
+    pub(crate) fn add_blob(&mut self, blob: &Blob) {
+        match blob.get_type() {
+            BlobType::OsmHeader => self.header_blobs += 1,
+            BlobType::OsmData => self.primitive_blobs += 1,
+            BlobType::Unknown(_) => self.unknown_blobs += 1,
+        }
+
+        self.compressed_size += blob.data_size();
+        match blob.uncompressed_size_hint() {
+            Some(size) => self.uncompressed_size += size,
+            None => self.unknown_size_blobs += 1,
+        }
+
+        match blob.compression() {
+            Compression::None => self.uncompressed_blobs += 1,
+            Compression::Zlib => self.zlib_blobs += 1,
+            Compression::Lzma => self.lzma_blobs += 1,
+            Compression::Zstd => self.zstd_blobs += 1,
+            Compression::Unknown => self.unknown_compression_blobs += 1,
+        }
+    }
+}
+
+impl fmt::Display for FileStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "blobs: {} ({} header, {} primitive, {} unknown)",
+            self.blobs(),
+            self.header_blobs,
+            self.primitive_blobs,
+            self.unknown_blobs
+        )?;
+        writeln!(f, "compressed size: {} bytes", self.compressed_size)?;
+        write!(f, "uncompressed size: {} bytes", self.uncompressed_size)?;
+        match self.compression_ratio() {
+            Some(ratio) => writeln!(f, " (ratio {:.2})", ratio)?,
+            None => writeln!(f, " (unknown for {} blobs)", self.unknown_size_blobs)?,
+        }
+        write!(
+            f,
+            "compression: {} none, {} zlib, {} lzma, {} zstd, {} unknown",
+            self.uncompressed_blobs,
+            self.zlib_blobs,
+            self.lzma_blobs,
+            self.zstd_blobs,
+            self.unknown_compression_blobs
+        )?;
+        if let Some(counts) = self.element_counts {
+            write!(
+                f,
+                "\nelements: {} nodes, {} ways, {} relations",
+                counts.nodes, counts.ways, counts.relations
+            )?;
+        }
+        if let Some(ref ranges) = self.id_ranges {
+            let types = [
+                ("node", ranges.node_ids()),
+                ("way", ranges.way_ids()),
+                ("relation", ranges.relation_ids()),
+            ];
+            for &(name, ref range) in &types {
+                if let Some(ref range) = *range {
+                    write!(f, "\n{} ids: {} to {}", name, range.start(), range.end())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Statistics about a single blob (see `IndexedReader::collect_stats`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlobStats {
+    /// The offset of the blob in the file.
+    pub offset: ByteOffset,
+    /// The content type of the blob.
+    pub blob_type: SimpleBlobType,
+    /// The compression method of the blob content.
+    pub compression: Compression,
+    /// The size of the blob message in bytes as stored in the file.
+    pub compressed_size: u64,
+    /// The size of the blob content in bytes after decompression, if the blob declares it.
+    pub uncompressed_size: Option<u64>,
+    /// The number of elements of each type. This is `None` for blobs that are not
+    /// `PrimitiveBlock`s.
+    pub element_counts: Option<ElementCounts>,
+    /// The id ranges of the elements. This is `None` for blobs that are not `PrimitiveBlock`s.
+    pub id_ranges: Option<IdRanges>,
+}
+
+impl BlobStats {
+    /// Returns the ratio of `uncompressed_size` to `compressed_size` or `None` if the
+    /// uncompressed size is unknown.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        match self.uncompressed_size {
+            Some(size) if self.compressed_size > 0 => {
+                Some(size as f64 / self.compressed_size as f64)
+            }
+            _ => None,
+        }
+    }
+}
@@ -33,3 +33,130 @@ where
         Err(::protobuf::ProtobufError::message_not_initialized(""))
     }
 }
+
+/// Serializes the given blocks to an in-memory PBF file. A header blob is inserted at the start
+/// and all blobs are stored uncompressed.
+#[cfg(test)]
+pub(crate) fn encode_test_pbf(blocks: &[::proto::osmformat::PrimitiveBlock]) -> Vec<u8> {
+    encode_test_pbf_with_header(::proto::osmformat::HeaderBlock::new(), blocks)
+}
+
+/// Like `encode_test_pbf`, but starts with the given header. The required features are added.
+#[cfg(test)]
+pub(crate) fn encode_test_pbf_with_header(
+    mut header: ::proto::osmformat::HeaderBlock,
+    blocks: &[::proto::osmformat::PrimitiveBlock],
+) -> Vec<u8> {
+    use byteorder::{BigEndian, WriteBytesExt};
+    use proto::fileformat;
+    use protobuf::Message;
+
+    fn write_blob(buf: &mut Vec<u8>, blob_type: &str, content: Vec<u8>) {
+        let mut blob = fileformat::Blob::new();
+        blob.set_raw_size(content.len() as i32);
+        blob.set_raw(content);
+        let blob = blob.write_to_bytes().unwrap();
+
+        let mut header = fileformat::BlobHeader::new();
+        header.set_field_type(blob_type.to_string());
+        header.set_datasize(blob.len() as i32);
+        let header = header.write_to_bytes().unwrap();
+
+        buf.write_u32::<BigEndian>(header.len() as u32).unwrap();
+        buf.extend_from_slice(&header);
+        buf.extend_from_slice(&blob);
+    }
+
+    header.mut_required_features().push("OsmSchema-V0.6".to_string());
+    header.mut_required_features().push("DenseNodes".to_string());
+
+    let mut buf = vec![];
+    write_blob(&mut buf, "OSMHeader", header.write_to_bytes().unwrap());
+    for block in blocks {
+        write_blob(&mut buf, "OSMData", block.write_to_bytes().unwrap());
+    }
+    buf
+}
+
+/// Creates a node with the given id at `(0, 0)`.
+#[cfg(test)]
+pub(crate) fn node(id: i64) -> ::proto::osmformat::Node {
+    node_at(id, 0, 0)
+}
+
+/// Creates a node with the given id and raw coordinates.
+#[cfg(test)]
+pub(crate) fn node_at(id: i64, lat: i64, lon: i64) -> ::proto::osmformat::Node {
+    let mut node = ::proto::osmformat::Node::new();
+    node.set_id(id);
+    node.set_lat(lat);
+    node.set_lon(lon);
+    node
+}
+
+/// Creates a way with the given id and node ids. The ids are delta-encoded.
+#[cfg(test)]
+pub(crate) fn way(id: i64, refs: &[i64]) -> ::proto::osmformat::Way {
+    let mut way = ::proto::osmformat::Way::new();
+    way.set_id(id);
+    let mut prev = 0;
+    for &r in refs {
+        way.mut_refs().push(r - prev);
+        prev = r;
+    }
+    way
+}
+
+/// Creates a block with an empty stringtable and one group for the nodes and one for the ways.
+/// Groups without elements are left out.
+#[cfg(test)]
+pub(crate) fn block(
+    nodes: Vec<::proto::osmformat::Node>,
+    ways: Vec<::proto::osmformat::Way>,
+) -> ::proto::osmformat::PrimitiveBlock {
+    use proto::osmformat;
+
+    let mut block = osmformat::PrimitiveBlock::new();
+    block.mut_stringtable().mut_s().push(vec![]);
+    if !nodes.is_empty() {
+        let mut group = osmformat::PrimitiveGroup::new();
+        group.set_nodes(nodes.into());
+        block.mut_primitivegroup().push(group);
+    }
+    if !ways.is_empty() {
+        let mut group = osmformat::PrimitiveGroup::new();
+        group.set_ways(ways.into());
+        block.mut_primitivegroup().push(group);
+    }
+    block
+}
+
+/// Creates a relation with the given id and members. All members have an empty role.
+#[cfg(test)]
+pub(crate) fn relation(
+    id: i64,
+    members: &[(::proto::osmformat::Relation_MemberType, i64)],
+) -> ::proto::osmformat::Relation {
+    let mut relation = ::proto::osmformat::Relation::new();
+    relation.set_id(id);
+    let mut prev = 0;
+    for &(member_type, member_id) in members {
+        relation.mut_types().push(member_type);
+        relation.mut_memids().push(member_id - prev);
+        relation.mut_roles_sid().push(0);
+        prev = member_id;
+    }
+    relation
+}
+
+/// Creates a block with a single group of relations.
+#[cfg(test)]
+pub(crate) fn relation_block(
+    relations: Vec<::proto::osmformat::Relation>,
+) -> ::proto::osmformat::PrimitiveBlock {
+    let mut block = block(vec![], vec![]);
+    let mut group = ::proto::osmformat::PrimitiveGroup::new();
+    group.set_relations(relations.into());
+    block.mut_primitivegroup().push(group);
+    block
+}
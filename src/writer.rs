@@ -0,0 +1,823 @@
+//! Write blobs and build blocks to create PBF files
+
+use blob::MAX_BLOB_MESSAGE_SIZE;
+use byteorder::{BigEndian, ByteOrder};
+use dense::DenseNodeInfo;
+use elements::{BoundingBox, Element, Info, RelMemberType};
+use error::{new_blob_error, new_protobuf_error, BlobError, Result};
+use proto::{fileformat, osmformat};
+use protobuf::{Message, RepeatedField};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::mem;
+use std::path::Path;
+
+#[cfg(feature = "system-libz")]
+use flate2::write::ZlibEncoder;
+
+/// Writes a PBF file blob by blob.
+///
+/// The writer starts the file with an `OSMHeader` blob that declares the required features
+/// "OsmSchema-V0.6" and "DenseNodes". Each following `OSMData` blob contains one block that was
+/// assembled with a [`PrimitiveBlockBuilder`](struct.PrimitiveBlockBuilder.html).
+///
+/// Blob content is compressed with zlib if the `system-libz` feature is enabled (the default)
+/// and stored uncompressed otherwise.
+///
+/// Elements can also be added one by one with [`add_element`](#method.add_element). They are
+/// collected in a pending block, which is written automatically whenever it reaches
+/// [`max_elements_per_block`](struct.PrimitiveBlockBuilder.html#method.max_elements_per_block)
+/// elements.
+///
+/// Call [`into_inner`](#method.into_inner) when done, so that the pending block and the header are
+/// written even for files without any blocks and the underlying writer is flushed.
+///
+/// # Example
+/// ```
+/// use osmpbf::{BlobWriter, PrimitiveBlockBuilder};
+///
+/// # fn foo() -> osmpbf::Result<()> {
+/// let mut writer = BlobWriter::new(Vec::new());
+///
+/// let mut block = PrimitiveBlockBuilder::new();
+/// block.add_node(1, 52.5, 13.4, vec![("name", "Berlin")]);
+/// block.add_node(2, 48.1, 11.6, vec![("name", "München")]);
+/// block.add_way(3, vec![1, 2], vec![("highway", "motorway")]);
+/// writer.write_block(block)?;
+///
+/// let bytes = writer.into_inner()?;
+/// # assert!(!bytes.is_empty());
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct BlobWriter<W: Write> {
+    writer: W,
+    header_written: bool,
+    bbox: Option<BoundingBox>,
+    /// The block that `add_element` adds to.
+    block: PrimitiveBlockBuilder,
+}
+
+impl<W: Write> BlobWriter<W> {
+    /// Creates a new `BlobWriter`.
+    pub fn new(writer: W) -> BlobWriter<W> {
+        BlobWriter {
+            writer,
+            header_written: false,
+            bbox: None,
+            block: PrimitiveBlockBuilder::new(),
+        }
+    }
+
+    /// Sets the bounding box that the header declares (see `HeaderBlock::bbox`). This has no
+    /// effect if the header has already been written.
+    pub fn set_bbox(&mut self, bbox: BoundingBox) {
+        self.bbox = Some(bbox);
+    }
+
+    /// Sets the number of elements after which the pending block of `add_element` is written.
+    /// Defaults to `DEFAULT_MAX_ELEMENTS_PER_BLOCK` (8000), the block size that osmosis uses.
+    pub fn set_max_elements_per_block(&mut self, max: usize) {
+        self.block.set_max_elements_per_block(max);
+    }
+
+    /// Adds a copy of an element to the pending block and writes that block if it is full (see
+    /// `PrimitiveBlockBuilder::add_element`).
+    pub fn add_element(&mut self, element: &Element) -> Result<()> {
+        self.block.add_element(element)?;
+        if self.block.is_full() {
+            self.flush_block()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the pending block of `add_element` if it contains any elements.
+    pub fn flush_block(&mut self) -> Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let mut next = PrimitiveBlockBuilder::new();
+        next.set_max_elements_per_block(self.block.max_elements_per_block());
+        let block = mem::replace(&mut self.block, next);
+        self.write_block_content(block)
+    }
+
+    /// Writes the `OSMHeader` blob. This is done automatically before the first block, so it
+    /// only needs to be called explicitly to control when the header is written. Calling it more
+    /// than once has no effect.
+    pub fn write_header(&mut self) -> Result<()> {
+        if self.header_written {
+            return Ok(());
+        }
+
+        let mut header = osmformat::HeaderBlock::new();
+        header
+            .mut_required_features()
+            .push("OsmSchema-V0.6".to_string());
+        header
+            .mut_required_features()
+            .push("DenseNodes".to_string());
+        header.set_writingprogram(concat!("osmpbf ", env!("CARGO_PKG_VERSION")).to_string());
+        if let Some(bbox) = self.bbox {
+            let header_bbox = header.mut_bbox();
+            header_bbox.set_left(bbox.min().nano_lon());
+            header_bbox.set_right(bbox.max().nano_lon());
+            header_bbox.set_top(bbox.max().nano_lat());
+            header_bbox.set_bottom(bbox.min().nano_lat());
+        }
+        let content = header
+            .write_to_bytes()
+            .map_err(|e| new_protobuf_error(e, "header block"))?;
+
+        self.write_blob("OSMHeader", content)?;
+        self.header_written = true;
+        Ok(())
+    }
+
+    /// Encodes the given block and writes it as an `OSMData` blob.
+    ///
+    /// Returns an error of kind `BlobError::MessageTooBig` if the encoded block is bigger than
+    /// [`MAX_BLOB_MESSAGE_SIZE`](blob/MAX_BLOB_MESSAGE_SIZE.v.html), because readers would reject
+    /// it. Keeping blocks at about 8000 elements is a safe choice. The block is written as it is,
+    /// even if it has more than `max_elements_per_block` elements. The pending block of
+    /// `add_element` is written before it, so the order of the elements is kept.
+    pub fn write_block(&mut self, block: PrimitiveBlockBuilder) -> Result<()> {
+        self.flush_block()?;
+        self.write_block_content(block)
+    }
+
+    fn write_block_content(&mut self, block: PrimitiveBlockBuilder) -> Result<()> {
+        self.write_header()?;
+
+        let content = block
+            .into_proto()
+            .write_to_bytes()
+            .map_err(|e| new_protobuf_error(e, "primitive block"))?;
+        self.write_blob("OSMData", content)
+    }
+
+    /// Writes the pending block and the header if that has not happened yet, flushes and returns
+    /// the underlying writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.write_header()?;
+        self.flush_block()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_blob(&mut self, blob_type: &str, content: Vec<u8>) -> Result<()> {
+        let size = content.len() as u64;
+        if size > MAX_BLOB_MESSAGE_SIZE {
+            return Err(new_blob_error(BlobError::MessageTooBig { size }));
+        }
+
+        let blob = encode_blob(content)?
+            .write_to_bytes()
+            .map_err(|e| new_protobuf_error(e, "blob"))?;
+
+        let mut header = fileformat::BlobHeader::new();
+        header.set_field_type(blob_type.to_string());
+        header.set_datasize(blob.len() as i32);
+        let header = header
+            .write_to_bytes()
+            .map_err(|e| new_protobuf_error(e, "blob header"))?;
+
+        let mut header_size = [0_u8; 4];
+        BigEndian::write_u32(&mut header_size, header.len() as u32);
+        self.writer.write_all(&header_size)?;
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&blob)?;
+        Ok(())
+    }
+}
+
+impl BlobWriter<BufWriter<File>> {
+    /// Creates a new `BlobWriter` that writes to the file at the given path. An existing file is
+    /// truncated.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let f = File::create(path)?;
+        Ok(BlobWriter::new(BufWriter::new(f)))
+    }
+}
+
+#[cfg(feature = "system-libz")]
+fn encode_blob(content: Vec<u8>) -> Result<fileformat::Blob> {
+    let mut blob = fileformat::Blob::new();
+    blob.set_raw_size(content.len() as i32);
+    let mut encoder = ZlibEncoder::new(Vec::new(), ::flate2::Compression::default());
+    encoder.write_all(&content)?;
+    blob.set_zlib_data(encoder.finish()?);
+    Ok(blob)
+}
+
+#[cfg(not(feature = "system-libz"))]
+fn encode_blob(content: Vec<u8>) -> Result<fileformat::Blob> {
+    let mut blob = fileformat::Blob::new();
+    blob.set_raw(content);
+    Ok(blob)
+}
+
+/// The metadata of an element that is written by `PrimitiveBlockBuilder`. Values that are `None`
+/// are not written. Use `from_info` and `from_dense_info` to copy the metadata of an element that
+/// was read.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ElementInfo {
+    /// The version of the element.
+    pub version: Option<i32>,
+    /// The time stamp in milliseconds since the epoch. It is written with a granularity of one
+    /// second, so milliseconds are rounded down.
+    pub milli_timestamp: Option<i64>,
+    /// The changeset id.
+    pub changeset: Option<i64>,
+    /// The user id.
+    pub uid: Option<i32>,
+    /// The user name.
+    pub user: Option<String>,
+    /// The visibility status of the element. This is only relevant for files with historical
+    /// information. Defaults to true.
+    pub visible: bool,
+}
+
+impl ElementInfo {
+    /// Copies the metadata of a `Node`, `Way` or `Relation`. Fails if the user name is not valid
+    /// UTF-8.
+    pub fn from_info(info: &Info) -> Result<ElementInfo> {
+        Ok(ElementInfo {
+            version: info.version(),
+            milli_timestamp: info.milli_timestamp(),
+            changeset: info.changeset(),
+            uid: info.uid(),
+            user: match info.user() {
+                Some(user) => Some(user?.to_string()),
+                None => None,
+            },
+            visible: info.visible(),
+        })
+    }
+
+    /// Copies the metadata of a `DenseNode`. A version of -1 marks a node without metadata and is
+    /// copied as `None`. Fails if the user name is not valid UTF-8.
+    pub fn from_dense_info(info: &DenseNodeInfo) -> Result<ElementInfo> {
+        Ok(ElementInfo {
+            version: if info.version() == -1 {
+                None
+            } else {
+                Some(info.version())
+            },
+            milli_timestamp: Some(info.milli_timestamp()),
+            changeset: Some(info.changeset()),
+            uid: Some(info.uid()),
+            user: Some(info.user()?.to_string()),
+            visible: info.visible(),
+        })
+    }
+
+    /// Returns true if no values are set and the element is visible, so there is nothing to
+    /// write.
+    fn is_empty(&self) -> bool {
+        *self == ElementInfo::default()
+    }
+
+    /// Returns the time stamp in units of the default date granularity of 1000 milliseconds.
+    fn timestamp(&self) -> Option<i64> {
+        self.milli_timestamp.map(|t| t.div_euclid(1000))
+    }
+}
+
+impl Default for ElementInfo {
+    fn default() -> Self {
+        ElementInfo {
+            version: None,
+            milli_timestamp: None,
+            changeset: None,
+            uid: None,
+            user: None,
+            visible: true,
+        }
+    }
+}
+
+/// The default for `PrimitiveBlockBuilder::max_elements_per_block`, the block size that osmosis
+/// uses.
+pub const DEFAULT_MAX_ELEMENTS_PER_BLOCK: usize = 8000;
+
+/// Assembles a `PrimitiveBlock` from individual elements.
+///
+/// Nodes are stored as dense nodes, ways and relations in groups of their own. Strings are
+/// collected in a shared string table and ids, coordinates and member ids are delta-encoded.
+/// Coordinates are stored with the default granularity of 100 nanodegrees.
+///
+/// Element metadata (version, timestamp, user, ...) is written if it is given to one of the
+/// `add_*_with_info` methods (see `ElementInfo`), `add_element` copies it from the element. If
+/// only some of the nodes have metadata, the others are written with a version of -1 and empty
+/// values, because the metadata of dense nodes is stored for all nodes of a group or for none.
+#[derive(Clone, Debug)]
+pub struct PrimitiveBlockBuilder {
+    strings: Vec<Vec<u8>>,
+    string_indices: HashMap<String, u32>,
+    dense: osmformat::DenseNodes,
+    // Last (id, lat, lon) of the dense nodes, the base for the next deltas.
+    dense_last: (i64, i64, i64),
+    // Last (timestamp, changeset, uid, user_sid) of the dense metadata, the base for the next
+    // deltas.
+    dense_info_last: (i64, i64, i32, i32),
+    dense_has_info: bool,
+    ways: Vec<osmformat::Way>,
+    relations: Vec<osmformat::Relation>,
+    max_elements: usize,
+}
+
+impl PrimitiveBlockBuilder {
+    /// Creates an empty builder.
+    pub fn new() -> PrimitiveBlockBuilder {
+        // Index 0 of the string table is reserved as a delimiter in the `keys_vals` array of
+        // dense nodes, so it is not used for actual strings (not even for empty ones).
+        PrimitiveBlockBuilder {
+            strings: vec![vec![]],
+            string_indices: HashMap::new(),
+            dense: osmformat::DenseNodes::new(),
+            dense_last: (0, 0, 0),
+            dense_info_last: (0, 0, 0, 0),
+            dense_has_info: false,
+            ways: vec![],
+            relations: vec![],
+            max_elements: DEFAULT_MAX_ELEMENTS_PER_BLOCK,
+        }
+    }
+
+    /// Returns the number of elements that were added to the block.
+    pub fn len(&self) -> usize {
+        self.dense.get_id().len() + self.ways.len() + self.relations.len()
+    }
+
+    /// Returns true if no elements were added to the block.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of elements after which the block is considered full (see `is_full`).
+    /// Defaults to `DEFAULT_MAX_ELEMENTS_PER_BLOCK`.
+    pub fn max_elements_per_block(&self) -> usize {
+        self.max_elements
+    }
+
+    /// Sets the number of elements after which the block is considered full. Values below 1 are
+    /// raised to 1.
+    pub fn set_max_elements_per_block(&mut self, max: usize) {
+        self.max_elements = max.max(1);
+    }
+
+    /// Returns true if the block has at least `max_elements_per_block` elements and should be
+    /// written before more elements are added. Adding more elements is still possible.
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.max_elements
+    }
+
+    /// Adds a node with the given id, coordinate in degrees and tags.
+    pub fn add_node<I, K, V>(&mut self, id: i64, lat: f64, lon: f64, tags: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.add_node_with_info(id, lat, lon, tags, &ElementInfo::default());
+    }
+
+    /// Adds a node with the given id, coordinate in degrees, tags and metadata.
+    pub fn add_node_with_info<I, K, V>(
+        &mut self,
+        id: i64,
+        lat: f64,
+        lon: f64,
+        tags: I,
+        info: &ElementInfo,
+    ) where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let lat = (lat * 1.0e7).round() as i64;
+        let lon = (lon * 1.0e7).round() as i64;
+        let (last_id, last_lat, last_lon) = self.dense_last;
+
+        self.dense.mut_id().push(id - last_id);
+        self.dense.mut_lat().push(lat - last_lat);
+        self.dense.mut_lon().push(lon - last_lon);
+        self.dense_last = (id, lat, lon);
+
+        for (key, val) in tags {
+            let key = self.string_index(key.as_ref()) as i32;
+            let val = self.string_index(val.as_ref()) as i32;
+            self.dense.mut_keys_vals().push(key);
+            self.dense.mut_keys_vals().push(val);
+        }
+        self.dense.mut_keys_vals().push(0);
+
+        if !self.dense_has_info {
+            if info.is_empty() {
+                return;
+            }
+            // The nodes that were added before get empty metadata.
+            self.dense_has_info = true;
+            let previous_nodes = self.dense.get_id().len() - 1;
+            for _ in 0..previous_nodes {
+                self.add_dense_info(&ElementInfo::default());
+            }
+        }
+        self.add_dense_info(info);
+    }
+
+    /// Appends the metadata of the last node to the dense metadata.
+    fn add_dense_info(&mut self, info: &ElementInfo) {
+        let timestamp = info.timestamp().unwrap_or(0);
+        let changeset = info.changeset.unwrap_or(0);
+        let uid = info.uid.unwrap_or(0);
+        // Index 0 is the empty string.
+        let user_sid = match info.user {
+            Some(ref user) => self.string_index(user) as i32,
+            None => 0,
+        };
+        let (last_timestamp, last_changeset, last_uid, last_user_sid) = self.dense_info_last;
+
+        let dense_info = self.dense.mut_denseinfo();
+        dense_info.mut_version().push(info.version.unwrap_or(-1));
+        dense_info.mut_timestamp().push(timestamp - last_timestamp);
+        dense_info.mut_changeset().push(changeset - last_changeset);
+        dense_info.mut_uid().push(uid - last_uid);
+        dense_info.mut_user_sid().push(user_sid - last_user_sid);
+        dense_info.mut_visible().push(info.visible);
+        self.dense_info_last = (timestamp, changeset, uid, user_sid);
+    }
+
+    /// Encodes the metadata of a way or relation, or returns `None` if there is nothing to write.
+    fn encode_info(&mut self, info: &ElementInfo) -> Option<osmformat::Info> {
+        if info.is_empty() {
+            return None;
+        }
+
+        let mut encoded = osmformat::Info::new();
+        if let Some(version) = info.version {
+            encoded.set_version(version);
+        }
+        if let Some(timestamp) = info.timestamp() {
+            encoded.set_timestamp(timestamp);
+        }
+        if let Some(changeset) = info.changeset {
+            encoded.set_changeset(changeset);
+        }
+        if let Some(uid) = info.uid {
+            encoded.set_uid(uid);
+        }
+        if let Some(ref user) = info.user {
+            encoded.set_user_sid(self.string_index(user));
+        }
+        if !info.visible {
+            encoded.set_visible(false);
+        }
+        Some(encoded)
+    }
+
+    /// Adds a way with the given id, node references and tags.
+    pub fn add_way<R, I, K, V>(&mut self, id: i64, refs: R, tags: I)
+    where
+        R: IntoIterator<Item = i64>,
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.add_way_with_info(id, refs, tags, &ElementInfo::default());
+    }
+
+    /// Adds a way with the given id, node references, tags and metadata.
+    pub fn add_way_with_info<R, I, K, V>(&mut self, id: i64, refs: R, tags: I, info: &ElementInfo)
+    where
+        R: IntoIterator<Item = i64>,
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut way = osmformat::Way::new();
+        way.set_id(id);
+
+        let mut last = 0;
+        for node_id in refs {
+            way.mut_refs().push(node_id - last);
+            last = node_id;
+        }
+
+        for (key, val) in tags {
+            let key = self.string_index(key.as_ref());
+            let val = self.string_index(val.as_ref());
+            way.mut_keys().push(key);
+            way.mut_vals().push(val);
+        }
+
+        if let Some(info) = self.encode_info(info) {
+            way.set_info(info);
+        }
+        self.ways.push(way);
+    }
+
+    /// Adds a relation with the given id, members and tags. Each member is given as a tuple of
+    /// member type, member id and role.
+    pub fn add_relation<M, S, I, K, V>(&mut self, id: i64, members: M, tags: I)
+    where
+        M: IntoIterator<Item = (RelMemberType, i64, S)>,
+        S: AsRef<str>,
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.add_relation_with_info(id, members, tags, &ElementInfo::default());
+    }
+
+    /// Adds a relation with the given id, members, tags and metadata (see `add_relation`).
+    pub fn add_relation_with_info<M, S, I, K, V>(
+        &mut self,
+        id: i64,
+        members: M,
+        tags: I,
+        info: &ElementInfo,
+    ) where
+        M: IntoIterator<Item = (RelMemberType, i64, S)>,
+        S: AsRef<str>,
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut relation = osmformat::Relation::new();
+        relation.set_id(id);
+
+        let mut last = 0;
+        for (member_type, member_id, role) in members {
+            let role = self.string_index(role.as_ref()) as i32;
+            relation.mut_roles_sid().push(role);
+            relation.mut_memids().push(member_id - last);
+            relation.mut_types().push(match member_type {
+                RelMemberType::Node => osmformat::Relation_MemberType::NODE,
+                RelMemberType::Way => osmformat::Relation_MemberType::WAY,
+                RelMemberType::Relation => osmformat::Relation_MemberType::RELATION,
+            });
+            last = member_id;
+        }
+
+        for (key, val) in tags {
+            let key = self.string_index(key.as_ref());
+            let val = self.string_index(val.as_ref());
+            relation.mut_keys().push(key);
+            relation.mut_vals().push(val);
+        }
+
+        if let Some(info) = self.encode_info(info) {
+            relation.set_info(info);
+        }
+        self.relations.push(relation);
+    }
+
+    /// Adds a copy of an element that was read from another file, including its metadata. Fails
+    /// if the role of a relation member or the user name is not valid UTF-8.
+    pub fn add_element(&mut self, element: &Element) -> Result<()> {
+        match *element {
+            Element::Node(ref node) => {
+                let info = ElementInfo::from_info(&node.info())?;
+                self.add_node_with_info(node.id(), node.lat(), node.lon(), node.tags(), &info)
+            }
+            Element::DenseNode(ref node) => {
+                let info = match node.info() {
+                    Some(info) => ElementInfo::from_dense_info(&info)?,
+                    None => ElementInfo::default(),
+                };
+                self.add_node_with_info(node.id, node.lat(), node.lon(), node.tags(), &info)
+            }
+            Element::Way(ref way) => {
+                let info = ElementInfo::from_info(&way.info())?;
+                self.add_way_with_info(way.id(), way.refs(), way.tags(), &info)
+            }
+            Element::Relation(ref relation) => {
+                let members = relation
+                    .members()
+                    .map(|m| Ok((m.member_type, m.member_id, m.role()?)))
+                    .collect::<Result<Vec<_>>>()?;
+                let info = ElementInfo::from_info(&relation.info())?;
+                self.add_relation_with_info(relation.id(), members, relation.tags(), &info);
+            }
+        }
+        Ok(())
+    }
+
+    fn string_index(&mut self, s: &str) -> u32 {
+        if let Some(&index) = self.string_indices.get(s) {
+            return index;
+        }
+        let index = self.strings.len() as u32;
+        self.strings.push(s.as_bytes().to_vec());
+        self.string_indices.insert(s.to_string(), index);
+        index
+    }
+
+    fn into_proto(mut self) -> osmformat::PrimitiveBlock {
+        // The visibility flags are only needed if an element is not visible.
+        if self.dense.has_denseinfo() && self.dense.get_denseinfo().get_visible().iter().all(|&v| v)
+        {
+            self.dense.mut_denseinfo().clear_visible();
+        }
+
+        let mut block = osmformat::PrimitiveBlock::new();
+        block
+            .mut_stringtable()
+            .set_s(RepeatedField::from_vec(self.strings));
+
+        if !self.dense.get_id().is_empty() {
+            let mut group = osmformat::PrimitiveGroup::new();
+            group.set_dense(self.dense);
+            block.mut_primitivegroup().push(group);
+        }
+        if !self.ways.is_empty() {
+            let mut group = osmformat::PrimitiveGroup::new();
+            group.set_ways(RepeatedField::from_vec(self.ways));
+            block.mut_primitivegroup().push(group);
+        }
+        if !self.relations.is_empty() {
+            let mut group = osmformat::PrimitiveGroup::new();
+            group.set_relations(RepeatedField::from_vec(self.relations));
+            block.mut_primitivegroup().push(group);
+        }
+
+        block
+    }
+}
+
+impl Default for PrimitiveBlockBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::{GroupKind, PrimitiveBlock};
+
+    #[test]
+    fn build_block() {
+        let mut builder = PrimitiveBlockBuilder::new();
+        assert!(builder.is_empty());
+        builder.add_node(-5, -33.8688, 151.2093, vec![("name", "Sydney")]);
+        builder.add_node(-3, 0.0, 0.0, Vec::<(&str, &str)>::new());
+        builder.add_node(7, 64.1466, -21.9426, vec![("name", "Reykjavík"), ("a", "")]);
+        builder.add_way(10, vec![7, -5, -3], vec![("name", "Sydney")]);
+        builder.add_relation(
+            20,
+            vec![
+                (RelMemberType::Way, 10, "outer"),
+                (RelMemberType::Node, -5, ""),
+            ],
+            vec![("type", "multipolygon")],
+        );
+        assert_eq!(builder.len(), 5);
+
+        let block = PrimitiveBlock::new(builder.into_proto()).unwrap();
+        assert_eq!(
+            block.groups().map(|g| g.kind()).collect::<Vec<_>>(),
+            vec![GroupKind::DenseNodes, GroupKind::Ways, GroupKind::Relations]
+        );
+
+        let nodes: Vec<_> = block.groups().flat_map(|g| g.dense_nodes()).collect();
+        assert_eq!(
+            nodes.iter().map(|n| n.id).collect::<Vec<_>>(),
+            vec![-5, -3, 7]
+        );
+        assert_eq!(nodes[0].decimicro_lat(), -338688000);
+        assert_eq!(nodes[0].decimicro_lon(), 1512093000);
+        assert_eq!(nodes[2].decimicro_lon(), -219426000);
+        assert_eq!(
+            nodes[0].tags().collect::<Vec<_>>(),
+            vec![("name", "Sydney")]
+        );
+        assert_eq!(nodes[1].tags().count(), 0);
+        assert_eq!(
+            nodes[2].tags().collect::<Vec<_>>(),
+            vec![("name", "Reykjavík"), ("a", "")]
+        );
+
+        let ways: Vec<_> = block.groups().flat_map(|g| g.ways()).collect();
+        assert_eq!(ways[0].refs().collect::<Vec<_>>(), vec![7, -5, -3]);
+        assert_eq!(ways[0].tags().collect::<Vec<_>>(), vec![("name", "Sydney")]);
+
+        let relations: Vec<_> = block.groups().flat_map(|g| g.relations()).collect();
+        let members: Vec<_> = relations[0]
+            .members()
+            .map(|m| (m.member_type, m.member_id, m.role().unwrap()))
+            .collect();
+        assert_eq!(
+            members,
+            vec![
+                (RelMemberType::Way, 10, "outer"),
+                (RelMemberType::Node, -5, "")
+            ]
+        );
+
+        // "Sydney" is only stored once, the empty string gets an index other than 0
+        assert_eq!(block.raw_stringtable().len(), 9);
+    }
+
+    #[test]
+    fn build_block_with_info() {
+        let no_tags = Vec::<(&str, &str)>::new;
+        let info = ElementInfo {
+            version: Some(3),
+            milli_timestamp: Some(1_500_000_000_999),
+            changeset: Some(42),
+            uid: Some(7),
+            user: Some("mapper".to_string()),
+            visible: true,
+        };
+        let deleted = ElementInfo {
+            version: Some(4),
+            visible: false,
+            ..info.clone()
+        };
+
+        let mut builder = PrimitiveBlockBuilder::new();
+        builder.add_node(1, 0.0, 0.0, no_tags());
+        builder.add_node_with_info(2, 0.0, 0.0, no_tags(), &info);
+        builder.add_node_with_info(3, 0.0, 0.0, no_tags(), &deleted);
+        builder.add_way_with_info(10, vec![1, 2], no_tags(), &info);
+        builder.add_way(11, vec![2, 3], no_tags());
+        builder.add_relation_with_info(20, vec![(RelMemberType::Way, 10, "")], no_tags(), &deleted);
+
+        let block = PrimitiveBlock::new(builder.into_proto()).unwrap();
+        let infos: Vec<_> = block
+            .groups()
+            .flat_map(|g| g.dense_nodes())
+            .map(|n| n.info().unwrap())
+            .collect();
+        // The node without metadata was filled up with empty values.
+        assert_eq!(infos[0].version(), -1);
+        assert_eq!(infos[0].user().unwrap(), "");
+        assert_eq!(infos[1].version(), 3);
+        assert_eq!(infos[1].milli_timestamp(), 1_500_000_000_000);
+        assert_eq!(infos[1].changeset(), 42);
+        assert_eq!(infos[1].uid(), 7);
+        assert_eq!(infos[1].user().unwrap(), "mapper");
+        assert!(infos[1].visible());
+        assert_eq!(infos[2].version(), 4);
+        assert_eq!(infos[2].changeset(), 42);
+        assert_eq!(infos[2].user().unwrap(), "mapper");
+        assert!(!infos[2].visible());
+
+        let rounded = ElementInfo {
+            milli_timestamp: Some(1_500_000_000_000),
+            ..info.clone()
+        };
+        let ways: Vec<_> = block.groups().flat_map(|g| g.ways()).collect();
+        assert_eq!(ElementInfo::from_info(&ways[0].info()).unwrap(), rounded);
+        assert_eq!(
+            ElementInfo::from_info(&ways[1].info()).unwrap(),
+            ElementInfo::default()
+        );
+
+        let relations: Vec<_> = block.groups().flat_map(|g| g.relations()).collect();
+        let relation_info = ElementInfo::from_info(&relations[0].info()).unwrap();
+        assert_eq!(relation_info.version, Some(4));
+        assert!(!relation_info.visible);
+    }
+
+    #[test]
+    fn split_into_blocks() {
+        use blob::{BlobReader, BlobType};
+        use std::io::Cursor;
+
+        let mut source = PrimitiveBlockBuilder::new();
+        for id in 0..20_001 {
+            source.add_node(id, 0.0, 0.0, Vec::<(&str, &str)>::new());
+        }
+        let source = PrimitiveBlock::new(source.into_proto()).unwrap();
+
+        let mut writer = BlobWriter::new(vec![]);
+        for element in source.elements() {
+            writer.add_element(&element).unwrap();
+        }
+        let data = writer.into_inner().unwrap();
+
+        let mut reader = BlobReader::new_seekable(Cursor::new(&data[..])).unwrap();
+        let header = reader.next().unwrap().unwrap();
+        assert_eq!(header.get_type(), BlobType::OsmHeader);
+
+        let mut ids = vec![];
+        let mut sizes = vec![];
+        for blob in reader {
+            let blob = blob.unwrap();
+            assert_eq!(blob.get_type(), BlobType::OsmData);
+            let block = blob.to_primitiveblock().unwrap();
+            let len = ids.len();
+            ids.extend(block.elements().map(|e| e.id()));
+            sizes.push(ids.len() - len);
+        }
+        assert_eq!(sizes, vec![8000, 8000, 4001]);
+        assert_eq!(ids, (0..20_001).collect::<Vec<_>>());
+    }
+}
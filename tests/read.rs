@@ -1,4 +1,10 @@
 extern crate osmpbf;
+extern crate rayon;
+
+#[cfg(feature = "tokio")]
+extern crate futures_core;
+#[cfg(feature = "tokio")]
+extern crate tokio_runtime as tokio;
 
 use osmpbf::*;
 
@@ -20,10 +26,16 @@ fn check_header_block_content(block: &HeaderBlock) {
         }
     }
     assert_eq!(block.optional_features().len(), 0);
+    assert!(!block.has_historical_information());
 }
 
 // Compare the content of a PrimitiveBlock with known values from the test file.
 fn check_primitive_block_content(block: &PrimitiveBlock) {
+    assert_eq!(block.granularity(), 100);
+    assert_eq!(block.lat_offset(), 0);
+    assert_eq!(block.lon_offset(), 0);
+    assert_eq!(block.date_granularity(), 1000);
+
     let nodes: Vec<_> = block.groups().flat_map(|g| g.nodes()).collect();
     if !nodes.is_empty() {
         assert_eq!(nodes.len(), 3);
@@ -46,6 +58,14 @@ fn check_primitive_block_content(block: &PrimitiveBlock) {
         assert_eq!(nodes[2].nano_lon(), 11631019200);
         assert_eq!(nodes[2].decimicro_lon(), 116310192);
 
+        // The coordinate type applies granularity and offsets just like the accessors.
+        let coord = nodes[1].coordinate();
+        assert_eq!(coord, Coordinate::new(52119923500, 11625644600));
+        assert!(approx_eq(coord.lat(), 52.11992359584));
+        assert!(approx_eq(coord.lon(), 11.62564468943));
+        assert_eq!(coord.decimicro_lat(), nodes[1].decimicro_lat());
+        assert_eq!(coord.decimicro_lon(), nodes[1].decimicro_lon());
+
         assert_eq!(nodes[0].id(), 105);
         assert_eq!(nodes[1].id(), 106);
         assert_eq!(nodes[2].id(), 108);
@@ -53,6 +73,9 @@ fn check_primitive_block_content(block: &PrimitiveBlock) {
         assert_eq!(nodes[0].info().uid(), Some(17));
         assert_eq!(nodes[1].info().uid(), Some(17));
         assert_eq!(nodes[2].info().uid(), Some(17));
+
+        assert_eq!(nodes[0].info().milli_timestamp(), Some(1049522828000));
+        assert_eq!(nodes[0].info().timestamp_secs(), Some(1049522828));
     }
 
     let dense_nodes: Vec<_> = block.groups().flat_map(|g| g.dense_nodes()).collect();
@@ -77,6 +100,11 @@ fn check_primitive_block_content(block: &PrimitiveBlock) {
         assert_eq!(dense_nodes[2].nano_lon(), 11631019200);
         assert_eq!(dense_nodes[2].decimicro_lon(), 116310192);
 
+        let coord = dense_nodes[1].coordinate();
+        assert_eq!(coord, Coordinate::new(52119923500, 11625644600));
+        assert!(approx_eq(coord.lat(), 52.11992359584));
+        assert!(approx_eq(coord.lon(), 11.62564468943));
+
         assert_eq!(dense_nodes[0].id, 105);
         assert_eq!(dense_nodes[1].id, 106);
         assert_eq!(dense_nodes[2].id, 108);
@@ -84,6 +112,51 @@ fn check_primitive_block_content(block: &PrimitiveBlock) {
         assert_eq!(dense_nodes[0].uid, 17);
         assert_eq!(dense_nodes[1].uid, 17);
         assert_eq!(dense_nodes[2].uid, 17);
+
+        assert_eq!(dense_nodes[0].milli_timestamp(), 1049522828000);
+        assert_eq!(dense_nodes[0].timestamp_secs(), 1049522828);
+
+        let info = dense_nodes[0].info().unwrap();
+        assert_eq!(info.version(), 1);
+        assert_eq!(info.uid(), 17);
+        assert_eq!(info.user().unwrap(), "testuser");
+        assert_eq!(info.timestamp_secs(), 1049522828);
+        assert!(info.visible());
+    }
+
+    {
+        let dense_nodes_len: usize = block.groups().map(|g| g.dense_nodes_len()).sum();
+        assert_eq!(dense_nodes_len, dense_nodes.len());
+        for group in block.groups() {
+            assert_eq!(group.nodes_len(), group.nodes().count());
+            assert_eq!(group.ways_len(), group.ways().count());
+            assert_eq!(group.relations_len(), group.relations().count());
+        }
+        let elements_len: usize = block.groups().map(|g| g.elements_len()).sum();
+        assert_eq!(elements_len, block.elements().count());
+
+        let infos: Vec<_> = block.groups().flat_map(|g| g.dense_infos()).collect();
+        assert_eq!(infos.len(), dense_nodes.len());
+        for (node, &(id, ref info)) in dense_nodes.iter().zip(&infos) {
+            assert_eq!(node.id, id);
+            assert_eq!(node.milli_timestamp(), info.milli_timestamp());
+            assert_eq!(node.uid, info.uid());
+        }
+
+        let arrays = block.dense_arrays();
+        assert_eq!(arrays.len(), dense_nodes.len());
+        assert_eq!(
+            arrays.ids(),
+            &dense_nodes.iter().map(|n| n.id).collect::<Vec<_>>()[..]
+        );
+        assert_eq!(
+            arrays.nano_lats(),
+            &dense_nodes.iter().map(|n| n.nano_lat()).collect::<Vec<_>>()[..]
+        );
+        assert_eq!(
+            arrays.nano_lons(),
+            &dense_nodes.iter().map(|n| n.nano_lon()).collect::<Vec<_>>()[..]
+        );
     }
 
     {
@@ -95,6 +168,27 @@ fn check_primitive_block_content(block: &PrimitiveBlock) {
 
         assert!(way_tags.contains(&("building", "yes")));
         assert!(way_tags.contains(&("name", "triangle")));
+
+        assert_eq!(ways[0].info().timestamp_secs(), Some(1049522831));
+
+        let raw_refs = ways[0].raw_refs();
+        assert_eq!(raw_refs.len(), ways[0].refs().len());
+        assert_eq!(ways[0].node_count(), 4);
+        assert!(ways[0].is_closed());
+        let mut node_id = 0;
+        for (&delta, id) in raw_refs.iter().zip(ways[0].refs()) {
+            node_id += delta;
+            assert_eq!(node_id, id);
+        }
+
+        let strings = block.strings().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(strings.len(), block.stringtable_len());
+        let resolved = ways[0]
+            .raw_tags()
+            .map(|(k, v)| (strings[k as usize], block.string(v as usize).unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(resolved, way_tags);
+        assert!(block.string(block.stringtable_len()).is_err());
     }
 
     {
@@ -108,6 +202,8 @@ fn check_primitive_block_content(block: &PrimitiveBlock) {
         let members = relations[0].members().collect::<Vec<_>>();
         assert_eq!(members.len(), 1);
         assert_eq!(members[0].role().unwrap(), "test_role");
+        assert_eq!(members[0].member_type(), RelMemberType::Way);
+        assert_eq!(members[0].member_id(), 107);
     }
 }
 
@@ -123,6 +219,9 @@ fn read_blobs() {
 
         let header = blobs[0].to_headerblock().unwrap();
         check_header_block_content(&header);
+        // The test files were written by osmosis 0.43.1
+        assert_eq!(header.writing_program(), Some("0.43.1"));
+        assert_eq!(header.source(), None);
 
         let primitive_block = blobs[1].to_primitiveblock().unwrap();
         check_primitive_block_content(&primitive_block);
@@ -171,6 +270,11 @@ fn decode_blob() {
 
         assert!(blobs[0].to_headerblock().is_ok());
         assert!(blobs[1].to_primitiveblock().is_ok());
+
+        // The raw content is available for all blob types.
+        for blob in &blobs {
+            assert!(!blob.decompress().unwrap().is_empty());
+        }
     }
 }
 
@@ -186,6 +290,121 @@ fn read_elements() {
     }
 }
 
+#[test]
+fn read_elements_checked() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::new_checked(std::fs::File::open(path).unwrap()).unwrap();
+        let mut elements = 0_usize;
+
+        reader.for_each(|_element| elements += 1).unwrap();
+
+        assert_eq!(elements, 5);
+    }
+}
+
+#[test]
+fn read_elements_with_blocks() {
+    for path in &TEST_FILE_PATHS {
+        let mut ids = vec![];
+        for block in ElementReader::from_path(path).unwrap().blocks() {
+            for element in block.unwrap().elements() {
+                ids.push(element.id());
+            }
+        }
+
+        let mut expected = vec![];
+        let reader = ElementReader::from_path(path).unwrap();
+        reader
+            .for_each(|element| expected.push(element.id()))
+            .unwrap();
+        assert_eq!(ids, expected);
+        assert_eq!(ids.len(), 5);
+    }
+}
+
+#[test]
+fn read_elements_with_into_iter() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let elements = reader.into_iter().collect::<Result<Vec<_>>>().unwrap();
+        let ids: Vec<i64> = elements.iter().map(|element| element.id()).collect();
+        assert_eq!(ids, vec![105, 106, 108, 107, 120]);
+
+        let tagged = elements.iter().filter(|e| !e.tags().is_empty()).count();
+        assert_eq!(tagged, 2);
+    }
+}
+
+#[test]
+fn element_ids_and_tags() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let mut elements = vec![];
+        reader
+            .for_each(|element| {
+                let tags = element.tags();
+                assert_eq!(tags.len(), tags.clone().count());
+                let tags = tags.collect::<Vec<_>>();
+                let expected = match element {
+                    Element::Node(ref node) => node.tags().collect::<Vec<_>>(),
+                    Element::DenseNode(ref node) => node.tags().collect(),
+                    Element::Way(ref way) => way.tags().collect(),
+                    Element::Relation(ref rel) => rel.tags().collect(),
+                };
+                assert_eq!(tags, expected);
+                elements.push((element.id(), tags));
+            })
+            .unwrap();
+
+        elements.sort();
+        let ids: Vec<_> = elements.iter().map(|e| e.0).collect();
+        assert_eq!(ids, vec![105, 106, 107, 108, 120]);
+        assert!(elements[2].1.contains(&("building", "yes")));
+        assert!(elements[4].1.contains(&("rel_key", "rel_value")));
+    }
+}
+
+#[test]
+fn read_elements_with_offset() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let mut offsets = vec![];
+
+        reader
+            .for_each_with_offset(|element, offset| offsets.push((element.id(), offset.unwrap())))
+            .unwrap();
+        assert_eq!(offsets.len(), 5);
+
+        let mut blob_reader = BlobReader::seekable_from_path(path).unwrap();
+        for (id, offset) in offsets {
+            blob_reader.seek(offset).unwrap();
+            let blob = blob_reader.next().unwrap().unwrap();
+            assert_eq!(blob.offset(), Some(offset));
+
+            let block = blob.to_primitiveblock().unwrap();
+            assert!(block.elements().any(|e| e.id() == id));
+        }
+    }
+}
+
+#[test]
+fn node_encoding() {
+    let expected = [
+        NodeEncoding::Dense,
+        NodeEncoding::Dense,
+        NodeEncoding::Plain,
+    ];
+    for (path, &encoding) in TEST_FILE_PATHS.iter().zip(&expected) {
+        let mut reader = ElementReader::from_path(path).unwrap();
+        assert_eq!(reader.node_encoding().unwrap(), encoding);
+
+        // The reader is moved back to the start
+        let mut elements = 0_usize;
+        reader.for_each(|_element| elements += 1).unwrap();
+        assert_eq!(elements, 5);
+    }
+}
+
 #[test]
 fn par_read_elements() {
     for path in &TEST_FILE_PATHS {
@@ -199,6 +418,30 @@ fn par_read_elements() {
     }
 }
 
+#[test]
+fn par_map_ordered() {
+    for path in &TEST_FILE_PATHS {
+        let mut expected = vec![];
+        ElementReader::from_path(path)
+            .unwrap()
+            .for_each(|element| expected.push(describe_element(&element)))
+            .unwrap();
+
+        let actual = ElementReader::from_path(path)
+            .unwrap()
+            .par_map_ordered(|element| describe_element(&element))
+            .unwrap();
+        assert_eq!(actual, expected);
+
+        let config = ParallelConfig::new().max_inflight_blobs(1);
+        let actual = ElementReader::from_path(path)
+            .unwrap()
+            .par_map_ordered_with_config(config, |element| describe_element(&element))
+            .unwrap();
+        assert_eq!(actual, expected);
+    }
+}
+
 #[test]
 fn read_ways_and_deps() {
     for path in &TEST_FILE_PATHS {
@@ -227,3 +470,1384 @@ fn read_ways_and_deps() {
         assert_eq!(nodes, 3);
     }
 }
+
+#[test]
+fn read_ways_and_deps_mmap() {
+    for path in &TEST_FILE_PATHS {
+        let mmap = unsafe { Mmap::from_path(path).unwrap() };
+        let mut reader = IndexedReader::from_mmap(&mmap).unwrap();
+
+        let mut ids = vec![];
+        let missing = reader
+            .read_ways_and_deps(
+                |way| way.id() == 107,
+                |element| match element {
+                    Element::Way(way) => ids.push(way.id()),
+                    Element::Node(node) => ids.push(node.id()),
+                    Element::DenseNode(node) => ids.push(node.id),
+                    Element::Relation(_) => panic!(), // should not occur
+                },
+            )
+            .unwrap();
+
+        assert_eq!(ids, vec![107, 105, 106, 108]);
+        assert!(missing.is_empty());
+    }
+}
+
+#[test]
+fn iter_blob_summaries() {
+    fn assert_clone_send<T: Clone + Send>(_: &T) {}
+
+    for path in &TEST_FILE_PATHS {
+        let mut reader = IndexedReader::from_path(path).unwrap();
+        assert_eq!(reader.iter_blobs().len(), 0);
+
+        reader.read_ways_and_deps(|_| false, |_| {}).unwrap();
+
+        let summaries: Vec<_> = reader.iter_blobs().collect();
+        assert_eq!(summaries.len(), 2);
+        assert_clone_send(&summaries[0]);
+
+        assert_eq!(summaries[0].blob_type(), SimpleBlobType::Header);
+        assert_eq!(summaries[0].offset(), ByteOffset(0));
+        assert!(summaries[0].id_ranges().is_none());
+
+        assert_eq!(summaries[1].blob_type(), SimpleBlobType::Primitive);
+        let ranges = summaries[1].id_ranges().unwrap();
+        assert_eq!(ranges.node_ids(), Some(105..=108));
+        assert_eq!(ranges.way_ids(), Some(107..=107));
+        assert_eq!(ranges.relation_ids(), Some(120..=120));
+
+        let bbox = summaries[1].node_bbox().unwrap();
+        assert_eq!(bbox.min(), Coordinate::new(52119899100, 11625644600));
+        assert!(approx_eq(bbox.max().lat(), 52.12240315616));
+        assert_eq!(bbox.max().nano_lon(), 11631019200);
+    }
+}
+
+#[test]
+fn read_bbox() {
+    for path in &TEST_FILE_PATHS {
+        let mut reader = IndexedReader::from_path(path).unwrap();
+        let mut read = |bbox| {
+            let mut ids = vec![];
+            reader
+                .read_bbox(bbox, |element| match element {
+                    Element::Node(node) => ids.push(node.id()),
+                    Element::DenseNode(node) => ids.push(node.id),
+                    _ => panic!("unexpected element"),
+                })
+                .unwrap();
+            ids
+        };
+
+        assert_eq!(read((52.1198, 11.62, 52.1200, 11.64)), vec![106, 108]);
+        assert_eq!(read((52.0, 11.628, 53.0, 11.629)), vec![105]);
+        assert_eq!(read((52.0, 11.0, 53.0, 12.0)), vec![105, 106, 108]);
+        assert!(read((-1.0, -1.0, 1.0, 1.0)).is_empty());
+    }
+}
+
+#[test]
+fn read_elements_until() {
+    use std::ops::ControlFlow;
+
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let mut elements = 0_usize;
+
+        reader
+            .for_each_until(|element| {
+                elements += 1;
+                match element {
+                    Element::Way(_) => ControlFlow::Break(()),
+                    _ => ControlFlow::Continue(()),
+                }
+            })
+            .unwrap();
+
+        // Three nodes and the way
+        assert_eq!(elements, 4);
+    }
+}
+
+#[test]
+fn read_elements_of_types() {
+    for path in &TEST_FILE_PATHS {
+        let selections = [
+            (ElementTypeSet::NONE, 0),
+            (ElementTypeSet::NODES, 3),
+            (ElementTypeSet::WAY, 1),
+            (ElementTypeSet::RELATION, 1),
+            (ElementTypeSet::WAY | ElementTypeSet::RELATION, 2),
+            (ElementTypeSet::ALL, 5),
+        ];
+
+        for &(types, expected) in &selections {
+            let reader = ElementReader::from_path(path).unwrap();
+            let mut elements = 0_usize;
+
+            reader
+                .for_each_types(types, |element| {
+                    assert!(types.contains_element(&element));
+                    elements += 1;
+                })
+                .unwrap();
+
+            assert_eq!(elements, expected);
+        }
+    }
+}
+
+#[test]
+fn top_tag_values() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let values = reader.top_tag_values("building", 10).unwrap();
+        assert_eq!(values, vec![("yes".to_string(), 1)]);
+
+        let reader = ElementReader::from_path(path).unwrap();
+        let values = reader.top_tag_values("building", 0).unwrap();
+        assert!(values.is_empty());
+
+        let reader = ElementReader::from_path(path).unwrap();
+        let values = reader.top_tag_values("no_such_key", 10).unwrap();
+        assert!(values.is_empty());
+    }
+}
+
+#[test]
+fn iterate_without_decompression() {
+    use std::io::Cursor;
+
+    // Corrupt the compressed content of the last blob but keep its framing intact.
+    let mut data = std::fs::read("tests/test.osm.pbf").unwrap();
+    let len = data.len();
+    for byte in &mut data[len - 64..len - 4] {
+        *byte = 0xff;
+    }
+
+    let blobs = BlobReader::new(Cursor::new(&data))
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(blobs.len(), 2);
+    assert_eq!(blobs[1].get_type(), BlobType::OsmData);
+    assert!(blobs[1].to_primitiveblock().is_err());
+
+    let mut reader = IndexedReader::new(Cursor::new(&data)).unwrap();
+    reader.create_index().unwrap();
+    assert_eq!(reader.iter_blobs().len(), 2);
+}
+
+#[test]
+fn truncated_blob_header_size() {
+    use std::io::Cursor;
+
+    let data = std::fs::read("tests/test.osm.pbf").unwrap();
+
+    // A clean end of the stream after the last blob.
+    let blobs = BlobReader::new(Cursor::new(&data))
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(blobs.len(), 2);
+
+    // The stream ends in the middle of the size prefix of the first blob or of an appended blob.
+    for prefix_len in 1..4 {
+        let mut appended = data.clone();
+        appended.extend_from_slice(&data[..prefix_len]);
+
+        for &(truncated, valid_blobs) in &[(&data[..prefix_len], 0), (&appended[..], 2)] {
+            let mut reader = BlobReader::new_seekable(Cursor::new(truncated)).unwrap();
+            for _ in 0..valid_blobs {
+                assert!(reader.next().unwrap().is_ok());
+            }
+            match reader.next().unwrap().unwrap_err().into_kind() {
+                ErrorKind::UnexpectedEof { offset } => {
+                    assert_eq!(offset, Some(ByteOffset(truncated.len() as u64)))
+                }
+                kind => panic!("unexpected error kind: {:?}", kind),
+            }
+            assert!(reader.next().is_none());
+        }
+    }
+}
+
+#[test]
+fn read_blob_ranges() {
+    for path in &TEST_FILE_PATHS {
+        let len = std::fs::metadata(path).unwrap().len();
+        let mut reader = BlobReader::seekable_from_path(path).unwrap();
+        let mut offsets = vec![];
+        while let Some(result) = reader.next_header_skip_blob() {
+            offsets.push(result.unwrap().1.unwrap());
+        }
+        assert_eq!(offsets.len(), 2);
+
+        let mut range = |start: ByteOffset, end: ByteOffset| {
+            reader
+                .seek_range(start, end)
+                .unwrap()
+                .map(|blob| blob.unwrap().offset().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(range(offsets[0], ByteOffset(len)), offsets);
+        assert_eq!(range(offsets[0], offsets[1]), vec![offsets[0]]);
+        assert_eq!(range(offsets[0], ByteOffset(1)), vec![offsets[0]]);
+        assert_eq!(range(offsets[1], ByteOffset(len)), vec![offsets[1]]);
+        assert_eq!(range(offsets[1], offsets[1]), vec![]);
+        assert_eq!(range(offsets[1], ByteOffset(u64::MAX)), vec![offsets[1]]);
+    }
+}
+
+#[test]
+fn read_blobs_with_builder() {
+    for path in &TEST_FILE_PATHS {
+        let file = std::fs::File::open(path).unwrap();
+        let reader = BlobReaderBuilder::new(file)
+            .buffer_size(1 << 20)
+            .check_blob_types(true)
+            .build_seekable()
+            .unwrap();
+        let blobs = reader.collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(blobs.len(), 2);
+        assert_eq!(blobs[0].offset(), Some(ByteOffset(0)));
+        assert_eq!(blobs[0].get_type(), BlobType::OsmHeader);
+        assert_eq!(blobs[1].get_type(), BlobType::OsmData);
+    }
+}
+
+#[test]
+fn truncated_blob() {
+    use std::io::Cursor;
+
+    let data = std::fs::read("tests/test.osm.pbf").unwrap();
+    let blobs = BlobReader::seekable_from_path("tests/test.osm.pbf")
+        .unwrap()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    let last_offset = blobs[1].offset().unwrap().0 as usize;
+
+    // Cut off the file in the header and in the content of the last blob.
+    for &cut in &[last_offset + 6, data.len() - 10] {
+        let truncated = &data[..cut];
+        let mut reader = BlobReader::new_seekable(Cursor::new(truncated)).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        match reader.next().unwrap().unwrap_err().into_kind() {
+            ErrorKind::UnexpectedEof { offset } => {
+                assert_eq!(offset, Some(ByteOffset(cut as u64)))
+            }
+            kind => panic!("unexpected error kind: {:?}", kind),
+        }
+        assert!(reader.next().is_none());
+    }
+
+    // Corrupt protobuf data is reported as such.
+    let mut corrupt = data.clone();
+    for byte in &mut corrupt[last_offset + 4..last_offset + 12] {
+        *byte = 0xff;
+    }
+    let mut reader = BlobReader::new(Cursor::new(&corrupt));
+    assert!(reader.next().unwrap().is_ok());
+    match reader.next().unwrap().unwrap_err().into_kind() {
+        ErrorKind::Protobuf { .. } => {}
+        kind => panic!("unexpected error kind: {:?}", kind),
+    }
+}
+
+#[test]
+fn read_ways_matching() {
+    let filters = [
+        (TagFilter::new(), 0, 0),
+        (TagFilter::new().key("building"), 1, 3),
+        (TagFilter::new().key_value("building", "yes"), 1, 3),
+        (TagFilter::new().key_value("building", "no"), 0, 0),
+        (TagFilter::new().key_value("building", "no").key("name"), 1, 3),
+        (TagFilter::new().key("rel_key"), 0, 0),
+    ];
+
+    for path in &TEST_FILE_PATHS {
+        for (filter, expected_ways, expected_nodes) in &filters {
+            let mut reader = IndexedReader::from_path(path).unwrap();
+
+            let mut ways = 0;
+            let mut nodes = 0;
+
+            reader
+                .read_ways_matching(filter, |element| match element {
+                    Element::Way(_) => ways += 1,
+                    Element::Node(_) | Element::DenseNode(_) => nodes += 1,
+                    Element::Relation(_) => panic!(), // should not occur
+                })
+                .unwrap();
+
+            assert_eq!(ways, *expected_ways);
+            assert_eq!(nodes, *expected_nodes);
+        }
+    }
+}
+
+#[test]
+fn read_ways_with_predicate() {
+    let predicates = [
+        (any_of(vec![]), 0, 0),
+        (all_of(vec![]), 1, 3),
+        (has_key("building"), 1, 3),
+        (key_value("building", "yes"), 1, 3),
+        (key_value("building", "no"), 0, 0),
+        (
+            any_of(vec![key_value("building", "no"), has_key("name")]),
+            1,
+            3,
+        ),
+        (
+            all_of(vec![key_value("building", "no"), has_key("name")]),
+            0,
+            0,
+        ),
+        (all_of(vec![has_key("building"), has_key("name")]), 1, 3),
+        (has_key("rel_key"), 0, 0),
+    ];
+
+    for path in &TEST_FILE_PATHS {
+        for (predicate, expected_ways, expected_nodes) in &predicates {
+            let mut reader = IndexedReader::from_path(path).unwrap();
+
+            let mut ways = 0;
+            let mut nodes = 0;
+
+            reader
+                .read_ways_and_deps(predicate.clone().ways(), |element| match element {
+                    Element::Way(_) => ways += 1,
+                    Element::Node(_) | Element::DenseNode(_) => nodes += 1,
+                    Element::Relation(_) => panic!(), // should not occur
+                })
+                .unwrap();
+
+            assert_eq!(ways, *expected_ways);
+            assert_eq!(nodes, *expected_nodes);
+        }
+    }
+
+    let mut reader = IndexedReader::from_path(TEST_FILE_PATHS[0]).unwrap();
+    let mut relations = 0;
+    reader
+        .read_relations_and_deps(has_key("rel_key").relations(), |element| {
+            if let Element::Relation(_) = element {
+                relations += 1;
+            }
+        })
+        .unwrap();
+    assert_eq!(relations, 1);
+}
+
+#[test]
+fn read_ways_and_deps_with_progress() {
+    for path in &TEST_FILE_PATHS {
+        let blobs = BlobReader::from_path(path)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let data_offset = blobs[1].offset().unwrap();
+
+        let mut reader = IndexedReader::from_path(path).unwrap();
+        let mut elements = 0;
+        let mut progress = vec![];
+        reader
+            .read_ways_and_deps_with_progress(
+                |way| way.id() == 107,
+                |_element| elements += 1,
+                |p| progress.push(p),
+            )
+            .unwrap();
+
+        assert_eq!(elements, 4);
+        assert_eq!(
+            progress,
+            vec![
+                Progress {
+                    pass: 1,
+                    blobs_processed: 1,
+                    total_blobs: 1,
+                    offset: data_offset,
+                },
+                Progress {
+                    pass: 2,
+                    blobs_processed: 1,
+                    total_blobs: 1,
+                    offset: data_offset,
+                },
+            ]
+        );
+    }
+}
+
+#[test]
+fn read_ways_and_deps_buffered() {
+    for path in &TEST_FILE_PATHS {
+        let stream: Box<dyn std::io::Read> = Box::new(std::fs::File::open(path).unwrap());
+        let mut reader = IndexedReader::from_reader_buffered(stream).unwrap();
+
+        let mut ids = vec![];
+        reader
+            .read_ways_and_deps(
+                |way| way.id() == 107,
+                |element| match element {
+                    Element::Way(way) => ids.push(way.id()),
+                    Element::Node(node) => ids.push(node.id()),
+                    Element::DenseNode(node) => ids.push(node.id),
+                    Element::Relation(_) => panic!(), // should not occur
+                },
+            )
+            .unwrap();
+
+        assert_eq!(ids, vec![107, 105, 106, 108]);
+    }
+}
+
+#[test]
+fn file_stats() {
+    for path in &TEST_FILE_PATHS {
+        let mut reader = IndexedReader::from_path(path).unwrap();
+        let stats = reader.file_stats(false).unwrap();
+
+        assert_eq!(stats.blobs(), 2);
+        assert_eq!(stats.header_blobs, 1);
+        assert_eq!(stats.primitive_blobs, 1);
+        assert_eq!(stats.unknown_blobs, 0);
+        assert_eq!(stats.uncompressed_blobs + stats.zlib_blobs, 2);
+        assert!(stats.compressed_size > 0);
+        assert!(stats.element_counts.is_none());
+
+        // The index was created in the same pass and is reused
+        assert_eq!(reader.iter_blobs().len(), 2);
+        assert_eq!(reader.file_stats(false).unwrap(), stats);
+
+        let stats = reader.file_stats(true).unwrap();
+        let counts = stats.element_counts.unwrap();
+        assert_eq!(counts.nodes, 3);
+        assert_eq!(counts.ways, 1);
+        assert_eq!(counts.relations, 1);
+    }
+}
+
+#[test]
+fn collect_stats() {
+    for path in &TEST_FILE_PATHS {
+        let mut reader = IndexedReader::from_path(path).unwrap();
+        let stats = reader.collect_stats().unwrap();
+        assert_eq!(stats.blobs(), 2);
+        assert_eq!(stats.element_counts.unwrap().total(), 5);
+
+        let ranges = stats.id_ranges.clone().unwrap();
+        assert_eq!(ranges.node_ids(), Some(105..=108));
+        assert_eq!(ranges.way_ids(), Some(107..=107));
+        assert_eq!(ranges.relation_ids(), Some(120..=120));
+
+        assert_eq!(stats.blob_stats.len(), 2);
+        assert_eq!(stats.blob_stats[0].blob_type, SimpleBlobType::Header);
+        assert!(stats.blob_stats[0].element_counts.is_none());
+        let data = &stats.blob_stats[1];
+        assert_eq!(data.blob_type, SimpleBlobType::Primitive);
+        assert_eq!(data.element_counts.unwrap().total(), 5);
+        assert_eq!(data.id_ranges.as_ref(), Some(&ranges));
+        assert_eq!(
+            stats
+                .blob_stats
+                .iter()
+                .map(|b| b.compressed_size)
+                .sum::<u64>(),
+            stats.compressed_size
+        );
+
+        let summary = stats.to_string();
+        assert!(summary.contains("elements: 3 nodes, 1 ways, 1 relations"));
+        assert!(summary.contains("way ids: 107 to 107"));
+    }
+}
+
+#[test]
+fn count_elements() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        assert_eq!(
+            reader.count_elements().unwrap(),
+            ElementCounts {
+                nodes: 3,
+                ways: 1,
+                relations: 1,
+            }
+        );
+    }
+}
+
+#[test]
+fn diff_elements_of_equal_files() {
+    // All test files contain the same elements, only encoded differently.
+    for old_path in &TEST_FILE_PATHS {
+        for new_path in &TEST_FILE_PATHS {
+            let old = ElementReader::from_path(old_path).unwrap();
+            let new = ElementReader::from_path(new_path).unwrap();
+            let mut events = 0;
+            diff_elements(old, new, |_event| events += 1).unwrap();
+            assert_eq!(events, 0);
+        }
+    }
+}
+
+#[test]
+fn read_from_trait_objects() {
+    for path in &TEST_FILE_PATHS {
+        let source: Box<dyn std::io::Read> = Box::new(std::fs::File::open(path).unwrap());
+        let mut elements = 0;
+        ElementReader::new(source)
+            .for_each(|_element| elements += 1)
+            .unwrap();
+        assert_eq!(elements, 5);
+
+        let source: Box<dyn ReadSeek> = Box::new(std::fs::File::open(path).unwrap());
+        let mut reader = IndexedReader::new(source).unwrap();
+        let mut elements = 0;
+        reader
+            .read_ways_and_deps(|_way| true, |_element| elements += 1)
+            .unwrap();
+        assert_eq!(elements, 4);
+    }
+}
+
+#[test]
+fn resolve_nodes() {
+    for path in &TEST_FILE_PATHS {
+        let reader = BlobReader::from_path(path).unwrap();
+        for blob in reader {
+            if let BlobDecode::OsmData(block) = blob.unwrap().decode().unwrap() {
+                let resolved = block.resolve_nodes(&[104, 105, 106, 106, 107, 108]);
+                let ids: Vec<i64> = resolved.iter().map(|r| r.0).collect();
+                assert_eq!(ids, vec![104, 105, 106, 106, 107, 108]);
+
+                let coord = Coordinate::new(52119923500, 11625644600);
+                assert!(approx_eq(coord.lat(), 52.1199235));
+                assert_eq!(resolved[0].1, None);
+                assert!(resolved[1].1.is_some());
+                assert_eq!(resolved[2].1, Some(coord));
+                assert_eq!(resolved[3].1, Some(coord));
+                assert_eq!(resolved[4].1, None);
+                assert_eq!(
+                    resolved[5].1,
+                    Some(Coordinate::new(52119899100, 11631019200))
+                );
+
+                assert!(block.resolve_nodes(&[]).is_empty());
+            }
+        }
+    }
+}
+
+#[test]
+fn read_elements_labeled() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        assert_eq!(reader.label(), "");
+
+        let reader = reader.with_label(*path);
+        let mut elements = 0;
+        reader
+            .for_each_labeled(|_element, label| {
+                assert_eq!(label, *path);
+                elements += 1;
+            })
+            .unwrap();
+        assert_eq!(elements, 5);
+    }
+}
+
+#[test]
+fn par_for_each_init() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let (nodes, ways, relations) = reader
+            .par_for_each_init(
+                || (0, 0, 0),
+                |acc, element| match element {
+                    Element::Node(_) | Element::DenseNode(_) => acc.0 += 1,
+                    Element::Way(_) => acc.1 += 1,
+                    Element::Relation(_) => acc.2 += 1,
+                },
+                |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2),
+            )
+            .unwrap();
+        assert_eq!((nodes, ways, relations), (3, 1, 1));
+    }
+}
+
+#[test]
+fn read_relations_and_deps() {
+    for path in &TEST_FILE_PATHS {
+        let mut reader = IndexedReader::from_path(path).unwrap();
+        let mut ids = vec![];
+        reader
+            .read_relations_and_deps(
+                |relation| relation.id() == 120,
+                |element| match element {
+                    Element::Node(node) => ids.push(node.id()),
+                    Element::DenseNode(node) => ids.push(node.id),
+                    Element::Way(way) => ids.push(way.id()),
+                    Element::Relation(rel) => ids.push(rel.id()),
+                },
+            )
+            .unwrap();
+        assert_eq!(ids, vec![120, 107, 105, 106, 108]);
+    }
+}
+
+#[test]
+fn read_nodes_and_deps() {
+    for path in &TEST_FILE_PATHS {
+        for &include_ways in &[false, true] {
+            let mut reader = IndexedReader::from_path(path).unwrap();
+            let mut ids = vec![];
+            reader
+                .read_nodes_and_deps(
+                    |element| match element {
+                        Element::Node(node) => node.id() != 106,
+                        Element::DenseNode(node) => node.id != 106,
+                        _ => panic!("unexpected element"),
+                    },
+                    include_ways,
+                    |element| match element {
+                        Element::Node(node) => ids.push(node.id()),
+                        Element::DenseNode(node) => ids.push(node.id),
+                        Element::Way(way) => ids.push(way.id()),
+                        Element::Relation(_) => panic!("unexpected relation"),
+                    },
+                )
+                .unwrap();
+
+            if include_ways {
+                assert_eq!(ids, vec![105, 108, 107]);
+            } else {
+                assert_eq!(ids, vec![105, 108]);
+            }
+        }
+    }
+}
+
+// Describe an element by everything the writer preserves.
+fn describe_element(element: &Element) -> String {
+    let description = match element {
+        Element::Node(node) => format!(
+            "node {} {} {} {:?}",
+            node.id(),
+            node.nano_lat(),
+            node.nano_lon(),
+            node.tags().collect::<Vec<_>>()
+        ),
+        Element::DenseNode(node) => format!(
+            "node {} {} {} {:?}",
+            node.id,
+            node.nano_lat(),
+            node.nano_lon(),
+            node.tags().collect::<Vec<_>>()
+        ),
+        Element::Way(way) => format!(
+            "way {} {:?} {:?}",
+            way.id(),
+            way.refs().collect::<Vec<_>>(),
+            way.tags().collect::<Vec<_>>()
+        ),
+        Element::Relation(rel) => format!(
+            "relation {} {:?} {:?}",
+            rel.id(),
+            rel.members()
+                .map(|m| (m.role().unwrap(), m.member_type, m.member_id))
+                .collect::<Vec<_>>(),
+            rel.tags().collect::<Vec<_>>()
+        ),
+    };
+    format!("{} {:?}", description, element_info(element))
+}
+
+fn element_info(element: &Element) -> ElementInfo {
+    match element {
+        Element::Node(node) => ElementInfo::from_info(&node.info()).unwrap(),
+        Element::DenseNode(node) => match node.info() {
+            Some(info) => ElementInfo::from_dense_info(&info).unwrap(),
+            None => ElementInfo::default(),
+        },
+        Element::Way(way) => ElementInfo::from_info(&way.info()).unwrap(),
+        Element::Relation(rel) => ElementInfo::from_info(&rel.info()).unwrap(),
+    }
+}
+
+#[test]
+fn relation_member_roles() {
+    use std::io::Cursor;
+
+    let mut builder = PrimitiveBlockBuilder::new();
+    builder.add_relation(
+        1,
+        vec![
+            (RelMemberType::Way, 30, "outer"),
+            (RelMemberType::Way, 10, "inner"),
+            (RelMemberType::Way, 20, "inner"),
+            (RelMemberType::Node, -5, "label"),
+            (RelMemberType::Relation, 2, ""),
+        ],
+        vec![("type", "multipolygon")],
+    );
+    let mut writer = BlobWriter::new(vec![]);
+    writer.write_block(builder).unwrap();
+    let data = writer.into_inner().unwrap();
+
+    let mut members = vec![];
+    ElementReader::new(Cursor::new(&data))
+        .for_each(|element| {
+            if let Element::Relation(rel) = element {
+                for member in rel.members() {
+                    members.push((
+                        member.member_type(),
+                        member.member_id(),
+                        member.role().unwrap().to_string(),
+                    ));
+                }
+            }
+        })
+        .unwrap();
+
+    assert_eq!(
+        members,
+        vec![
+            (RelMemberType::Way, 30, "outer".to_string()),
+            (RelMemberType::Way, 10, "inner".to_string()),
+            (RelMemberType::Way, 20, "inner".to_string()),
+            (RelMemberType::Node, -5, "label".to_string()),
+            (RelMemberType::Relation, 2, "".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn node_location_store() {
+    for path in &TEST_FILE_PATHS {
+        for &storage in &[LocationStorage::HashMap, LocationStorage::SortedVec] {
+            let reader = ElementReader::from_path(path).unwrap();
+            let store = NodeLocationStoreBuilder::new()
+                .storage(storage)
+                .build(reader)
+                .unwrap();
+
+            assert_eq!(store.len(), 3);
+            assert_eq!(
+                store.get(106),
+                Some(Coordinate::new(52119923500, 11625644600))
+            );
+            assert!(store.get(105).is_some());
+            assert!(store.get(108).is_some());
+            assert_eq!(store.get(107), None);
+        }
+    }
+}
+
+#[test]
+fn write_and_read_back() {
+    use std::io::Cursor;
+
+    for path in &TEST_FILE_PATHS {
+        let mut expected = vec![];
+        let mut builder = PrimitiveBlockBuilder::new();
+        ElementReader::from_path(path)
+            .unwrap()
+            .for_each(|element| {
+                expected.push(describe_element(&element));
+                builder.add_element(&element).unwrap();
+            })
+            .unwrap();
+        assert_eq!(builder.len(), 5);
+
+        let mut writer = BlobWriter::new(vec![]);
+        writer.write_block(builder).unwrap();
+        let data = writer.into_inner().unwrap();
+
+        let mut blobs = BlobReader::new(Cursor::new(&data));
+        match blobs.next().unwrap().unwrap().decode().unwrap() {
+            BlobDecode::OsmHeader(header) => {
+                check_header_block_content(&header);
+                assert!(header.writing_program().unwrap().starts_with("osmpbf "));
+            }
+            _ => panic!("first blob is not a header"),
+        }
+
+        let mut actual = vec![];
+        ElementReader::new(Cursor::new(&data))
+            .for_each(|element| actual.push(describe_element(&element)))
+            .unwrap();
+        assert_eq!(actual, expected);
+    }
+}
+
+#[test]
+fn filter_and_write_with_info() {
+    use std::io::Cursor;
+
+    for path in &TEST_FILE_PATHS {
+        // Keep the tagged elements
+        let mut expected = vec![];
+        let mut writer = BlobWriter::new(vec![]);
+        ElementReader::from_path(path)
+            .unwrap()
+            .for_each(|element| {
+                if element.tags().next().is_some() {
+                    expected.push((element.id(), element_info(&element)));
+                    writer.add_element(&element).unwrap();
+                }
+            })
+            .unwrap();
+        let data = writer.into_inner().unwrap();
+
+        let mut actual = vec![];
+        ElementReader::new(Cursor::new(&data))
+            .for_each(|element| actual.push((element.id(), element_info(&element))))
+            .unwrap();
+
+        assert_eq!(actual.len(), 2);
+        assert_eq!(actual, expected);
+        for (_, info) in actual {
+            assert_eq!(info.version, Some(1));
+            assert_eq!(info.uid, Some(17));
+            assert_eq!(info.changeset, Some(0));
+            assert_eq!(info.user.as_ref().map(|u| &u[..]), Some("testuser"));
+            assert!(info.milli_timestamp.is_some());
+            assert!(info.visible);
+        }
+    }
+}
+
+#[test]
+fn par_map_reduce_with_bounded_inflight_blobs() {
+    use std::io::Cursor;
+
+    // A file with many small blocks
+    let mut writer = BlobWriter::new(vec![]);
+    for b in 0..20 {
+        let mut builder = PrimitiveBlockBuilder::new();
+        for i in 0..10 {
+            let id = b * 10 + i;
+            builder.add_node(id, 0.0, 0.0, vec![("key", "value")]);
+        }
+        builder.add_way(
+            1000 + b,
+            vec![b * 10, b * 10 + 1],
+            Vec::<(&str, &str)>::new(),
+        );
+        writer.write_block(builder).unwrap();
+    }
+    let data = writer.into_inner().unwrap();
+
+    let sum_ids = |config: ParallelConfig| {
+        ElementReader::new(Cursor::new(&data))
+            .par_map_reduce_with_config(
+                config,
+                |element| match element {
+                    Element::DenseNode(node) => (node.id, 0),
+                    Element::Way(way) => (0, way.id()),
+                    _ => panic!("unexpected element"),
+                },
+                || (0_i64, 0_i64),
+                |a, b| (a.0 + b.0, a.1 + b.1),
+            )
+            .unwrap()
+    };
+
+    let unbounded = sum_ids(ParallelConfig::new());
+    assert_eq!(unbounded, ((0..200).sum(), (1000..1020).sum()));
+    for &max in &[0, 1, 3, 21, 100] {
+        assert_eq!(
+            sum_ids(ParallelConfig::new().max_inflight_blobs(max)),
+            unbounded
+        );
+    }
+}
+
+#[test]
+fn par_map_with_thread_pool() {
+    use std::sync::Arc;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(2)
+        .thread_name(|i| format!("pbf-{}", i))
+        .build()
+        .unwrap();
+    let config = ParallelConfig::new().thread_pool(Arc::new(pool));
+    let on_pool = || {
+        let thread = std::thread::current();
+        match thread.name() {
+            Some(name) => name.starts_with("pbf-"),
+            None => false,
+        }
+    };
+
+    let reader = ElementReader::from_path("tests/test.osm.pbf").unwrap();
+    let results = reader
+        .par_map_ordered_with_config(config.clone(), |element| (element.id(), on_pool()))
+        .unwrap();
+    assert_eq!(results.len(), 5);
+    assert!(results.iter().all(|r| r.1));
+
+    let reader = ElementReader::from_path("tests/test.osm.pbf").unwrap();
+    let count = reader
+        .par_map_reduce_with_config(config, |_| on_pool() as u64, || 0, |a, b| a + b)
+        .unwrap();
+    assert_eq!(count, 5);
+}
+
+#[test]
+fn resume_from_checkpoint() {
+    use std::io::Cursor;
+
+    let mut writer = BlobWriter::new(vec![]);
+    for b in 0..10 {
+        let mut builder = PrimitiveBlockBuilder::new();
+        for i in 0..(b + 1) {
+            builder.add_node(b * 100 + i, 0.0, 0.0, Vec::<(&str, &str)>::new());
+        }
+        writer.write_block(builder).unwrap();
+    }
+    let data = writer.into_inner().unwrap();
+
+    fn count_nodes<R: std::io::Read>(reader: &mut BlobReader<R>) -> usize {
+        let mut nodes = 0;
+        for blob in reader {
+            if let BlobDecode::OsmData(block) = blob.unwrap().decode().unwrap() {
+                nodes += block.elements().count();
+            }
+        }
+        nodes
+    }
+
+    // Read the header and the first four blocks, then store a checkpoint.
+    let mut reader = BlobReader::new_seekable(Cursor::new(&data)).unwrap();
+    for _ in 0..5 {
+        reader.next().unwrap().unwrap();
+    }
+    let checkpoint = reader.next_offset().unwrap().to_string();
+    let remaining = count_nodes(&mut reader);
+    assert_eq!(remaining, 5 + 6 + 7 + 8 + 9 + 10);
+    assert_eq!(reader.next_offset(), None);
+
+    // Resume with a fresh reader
+    let mut reader = BlobReader::new_seekable(Cursor::new(&data)).unwrap();
+    reader.seek(checkpoint.parse().unwrap()).unwrap();
+    assert_eq!(count_nodes(&mut reader), remaining);
+
+    // The position of a plain stream is unknown
+    let reader = BlobReader::new(Cursor::new(&data));
+    assert_eq!(reader.next_offset(), None);
+}
+
+/// A future that collects the blobs of a stream.
+#[cfg(feature = "tokio")]
+struct CollectBlobs<S>(S, Vec<Blob>);
+
+#[cfg(feature = "tokio")]
+impl<S> std::future::Future for CollectBlobs<S>
+where
+    S: futures_core::Stream<Item = Result<Blob>> + Unpin,
+{
+    type Output = Result<Vec<Blob>>;
+
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context,
+    ) -> std::task::Poll<Self::Output> {
+        use std::pin::Pin;
+        use std::task::Poll;
+
+        loop {
+            match Pin::new(&mut self.0).poll_next(cx) {
+                Poll::Ready(Some(Ok(blob))) => self.1.push(blob),
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(err)),
+                Poll::Ready(None) => {
+                    return Poll::Ready(Ok(std::mem::replace(&mut self.1, vec![])))
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn read_blobs_async() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    for path in &TEST_FILE_PATHS {
+        let file = rt.block_on(tokio::fs::File::open(path)).unwrap();
+        let reader = AsyncBlobReader::new(file);
+        let mut blobs = rt.block_on(CollectBlobs(reader, vec![])).unwrap();
+
+        let sync_blobs = BlobReader::seekable_from_path(path)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(blobs.len(), sync_blobs.len());
+        for (blob, sync_blob) in blobs.iter().zip(&sync_blobs) {
+            assert_eq!(blob.offset(), sync_blob.offset());
+            assert_eq!(blob.get_type(), sync_blob.get_type());
+        }
+
+        let last = blobs.pop().unwrap();
+        let block = rt.block_on(spawn_to_primitiveblock(last)).unwrap().unwrap();
+        check_primitive_block_content(&block);
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn read_blobs_async_truncated() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    let data = std::fs::read("tests/test.osm.pbf").unwrap();
+    let len = data.len() as u64;
+    let reader = AsyncBlobReader::new(std::io::Cursor::new(data[..len as usize - 1].to_vec()));
+    let err = rt.block_on(CollectBlobs(reader, vec![])).unwrap_err();
+    match *err.kind() {
+        ErrorKind::UnexpectedEof { offset } => assert_eq!(offset, Some(ByteOffset(len - 1))),
+        ref kind => panic!("unexpected error kind: {:?}", kind),
+    }
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn read_gzip_autodetect() {
+    use std::fs::File;
+
+    fn read_elements<R: std::io::Read>(reader: ElementReader<R>) -> Vec<(i64, Vec<String>)> {
+        let mut elements = vec![];
+        reader
+            .for_each(|element| {
+                let tags = element.tags().map(|(k, v)| format!("{}={}", k, v));
+                elements.push((element.id(), tags.collect()));
+            })
+            .unwrap();
+        elements
+    }
+
+    let expected = read_elements(ElementReader::from_path("tests/test.osm.pbf").unwrap());
+
+    let gzip = AutodetectReader::new(File::open("tests/test.osm.pbf.gz").unwrap()).unwrap();
+    assert!(gzip.is_gzip());
+    assert_eq!(read_elements(ElementReader::new(gzip)), expected);
+
+    // Plain files pass through unchanged
+    let plain = AutodetectReader::new(File::open("tests/test.osm.pbf").unwrap()).unwrap();
+    assert!(!plain.is_gzip());
+    assert_eq!(read_elements(ElementReader::new(plain)), expected);
+
+    // The magic bytes are detected even if they arrive in separate reads
+    struct ByteByByte<R>(R);
+
+    impl<R: std::io::Read> std::io::Read for ByteByByte<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    for path in &["tests/test.osm.pbf.gz", "tests/test.osm.pbf"] {
+        let slow = AutodetectReader::new(ByteByByte(File::open(path).unwrap())).unwrap();
+        assert_eq!(slow.is_gzip(), path.ends_with(".gz"));
+        assert_eq!(read_elements(ElementReader::new(slow)), expected);
+    }
+
+    let blobs = BlobReader::new_autodetect(File::open("tests/test.osm.pbf.gz").unwrap()).unwrap();
+    let blobs = blobs.collect::<Result<Vec<_>>>().unwrap();
+    assert_eq!(blobs.len(), 2);
+    assert!(blobs.iter().all(|blob| blob.offset().is_none()));
+}
+
+#[test]
+fn collect_tags_into_buffer() {
+    for path in &TEST_FILE_PATHS {
+        let reader = BlobReader::from_path(path).unwrap();
+        for blob in reader {
+            if let BlobDecode::OsmData(block) = blob.unwrap().decode().unwrap() {
+                // One buffer is reused for all elements of the block
+                let mut buf = vec![("stale", "tag")];
+                for group in block.groups() {
+                    for node in group.nodes() {
+                        node.collect_tags_into(&mut buf);
+                        assert_eq!(buf, node.tags().collect::<Vec<_>>());
+                    }
+                    for node in group.dense_nodes() {
+                        node.collect_tags_into(&mut buf);
+                        assert_eq!(buf, node.tags().collect::<Vec<_>>());
+                    }
+                    for way in group.ways() {
+                        way.collect_tags_into(&mut buf);
+                        assert!(buf.contains(&("building", "yes")));
+                        assert_eq!(buf, way.tags().collect::<Vec<_>>());
+                    }
+                    for relation in group.relations() {
+                        Element::Relation(relation.clone()).collect_tags_into(&mut buf);
+                        assert_eq!(buf, relation.tags().collect::<Vec<_>>());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn read_header_block_only() {
+    for path in &TEST_FILE_PATHS {
+        let header = HeaderBlock::read_from_path(path).unwrap();
+        let expected = BlobReader::from_path(path)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .to_headerblock()
+            .unwrap();
+        assert_eq!(header.required_features(), expected.required_features());
+        assert_eq!(header.optional_features(), expected.optional_features());
+    }
+
+    let empty: &[u8] = &[];
+    match HeaderBlock::read_from(empty).unwrap_err().kind() {
+        ErrorKind::MissingHeaderBlock => {}
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn owned_elements() {
+    let mut reader = IndexedReader::from_path("tests/test.osm.pbf").unwrap();
+    let mut ways = vec![];
+    let mut nodes = vec![];
+    reader
+        .read_ways_and_deps(
+            |_| true,
+            |element| match *element {
+                Element::Way(ref way) => ways.push(way.to_owned()),
+                Element::Node(ref node) => nodes.push(node.to_owned()),
+                Element::DenseNode(ref node) => nodes.push(node.to_owned()),
+                Element::Relation(_) => panic!("unexpected relation"),
+            },
+        )
+        .unwrap();
+    drop(reader);
+
+    // Owned elements can be sent to other threads
+    let (ways, nodes) = std::thread::spawn(move || (ways, nodes)).join().unwrap();
+    assert_eq!(ways.len(), 1);
+    assert_eq!(ways[0].id, 107);
+    assert_eq!(ways[0].refs.len(), 4);
+    assert!(ways[0]
+        .tags
+        .contains(&("building".to_string(), "yes".to_string())));
+
+    let mut node_ids: Vec<_> = nodes.iter().map(|node| node.id).collect();
+    node_ids.sort();
+    assert_eq!(node_ids, vec![105, 106, 108]);
+
+    let reader = ElementReader::from_path("tests/test.osm.pbf").unwrap();
+    let mut relations = vec![];
+    reader
+        .for_each(|element| {
+            if let Element::Relation(relation) = element {
+                relations.push(relation.to_owned().unwrap());
+            }
+        })
+        .unwrap();
+    assert_eq!(relations.len(), 1);
+    assert_eq!(relations[0].id, 120);
+    assert!(relations[0]
+        .tags
+        .contains(&("rel_key".to_string(), "rel_value".to_string())));
+}
+
+#[test]
+fn get_elements_by_id() {
+    for path in &TEST_FILE_PATHS {
+        let mut reader = IndexedReader::from_path(path).unwrap();
+        let way = reader.get_way(107).unwrap().unwrap();
+        assert_eq!(way.id, 107);
+        assert_eq!(way.refs.len(), 4);
+        assert!(way
+            .tags
+            .contains(&("name".to_string(), "triangle".to_string())));
+        assert!(reader.get_way(105).unwrap().is_none());
+
+        assert_eq!(reader.get_node(106).unwrap().unwrap().id, 106);
+        assert_eq!(reader.get_relation(120).unwrap().unwrap().id, 120);
+    }
+}
+
+#[test]
+fn tags_filtered_by_allowlist() {
+    let allowlist = KeyAllowlist::new(&["building", "rel_key"]).key("not_in_file");
+    let allowed = |key: &str| key == "building" || key == "rel_key";
+
+    for path in &TEST_FILE_PATHS {
+        let reader = BlobReader::from_path(path).unwrap();
+        let mut kept = 0;
+        for blob in reader {
+            if let BlobDecode::OsmData(block) = blob.unwrap().decode().unwrap() {
+                let keys = allowlist.compile(&block);
+                assert!(!keys.is_empty());
+                for element in block.elements() {
+                    let filtered: Vec<_> = element.tags_filtered(&keys).collect();
+                    let expected: Vec<_> = element.tags().filter(|tag| allowed(tag.0)).collect();
+                    assert_eq!(filtered, expected);
+                    kept += filtered.len();
+                }
+
+                let nothing = KeyAllowlist::new(&["not_in_file"]).compile(&block);
+                assert!(nothing.is_empty());
+                assert_eq!(
+                    block
+                        .elements()
+                        .map(|e| e.tags_filtered(&nothing).count())
+                        .sum::<usize>(),
+                    0
+                );
+            }
+        }
+        assert_eq!(kept, 2);
+    }
+}
+
+#[test]
+fn into_inner_reuses_reader() {
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    for path in &TEST_FILE_PATHS {
+        let data = std::fs::read(path).unwrap();
+        let mut reader = BlobReader::new_seekable(Cursor::new(data.clone())).unwrap();
+        assert_eq!(reader.get_ref().get_ref().len(), data.len());
+        reader.next().unwrap().unwrap();
+        let next = reader.next_offset().unwrap();
+        let mut cursor = reader.into_inner();
+        assert_eq!(cursor.position(), next.0);
+
+        // The returned reader can be used to start over.
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(BlobReader::new(cursor).count(), 2);
+
+        let mut indexed = IndexedReader::new(Cursor::new(data.clone())).unwrap();
+        assert!(indexed.get_way(107).unwrap().is_some());
+        assert_eq!(indexed.into_inner().into_inner(), data);
+    }
+}
+
+#[test]
+fn par_iter_owned_elements() {
+    use rayon::prelude::*;
+
+    for path in &TEST_FILE_PATHS {
+        let mut elements = ElementReader::from_path(path)
+            .unwrap()
+            .par_iter()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        elements.sort_by_key(|e| e.id());
+        let ids: Vec<_> = elements.iter().map(|e| e.id()).collect();
+        assert_eq!(ids, vec![105, 106, 107, 108, 120]);
+
+        match elements[2] {
+            OwnedElement::Way(ref way) => assert_eq!(way.refs.len(), 4),
+            ref other => panic!("unexpected element: {:?}", other),
+        }
+        let node_ids: Vec<_> = elements
+            .iter()
+            .filter_map(|e| match *e {
+                OwnedElement::Node(ref node) => Some(node.id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(node_ids, vec![105, 106, 108]);
+        assert_eq!(
+            elements[4].tags(),
+            &[("rel_key".to_string(), "rel_value".to_string())][..]
+        );
+
+        let ways = ElementReader::from_path(path)
+            .unwrap()
+            .par_iter()
+            .filter_map(|e| match e {
+                Ok(OwnedElement::Way(way)) => Some(way.id),
+                _ => None,
+            })
+            .count();
+        assert_eq!(ways, 1);
+    }
+}
+
+#[test]
+fn skip_to_data() {
+    for path in &TEST_FILE_PATHS {
+        let data_offset = BlobReader::from_path(path)
+            .unwrap()
+            .nth(1)
+            .unwrap()
+            .unwrap()
+            .offset();
+
+        let mut reader = BlobReader::from_path(path).unwrap();
+        assert!(reader.skip_to_data().unwrap());
+        assert_eq!(reader.next_offset(), data_offset);
+        // A second call stays at the same blob.
+        assert!(reader.skip_to_data().unwrap());
+        assert_eq!(reader.next_offset(), data_offset);
+        let blob = reader.next().unwrap().unwrap();
+        assert_eq!(blob.get_type(), BlobType::OsmData);
+        assert_eq!(blob.offset(), data_offset);
+        assert!(reader.next().is_none());
+        assert!(!reader.skip_to_data().unwrap());
+
+        // Without offsets, e.g. for a stream that cannot seek
+        let data = std::fs::read(path).unwrap();
+        let mut reader = BlobReader::new(std::io::Cursor::new(data));
+        assert!(reader.skip_to_data().unwrap());
+        assert_eq!(reader.next_offset(), None);
+        let blob = reader.next().unwrap().unwrap();
+        assert_eq!(blob.get_type(), BlobType::OsmData);
+        assert!(blob.to_primitiveblock().is_ok());
+
+        // The header that was read ahead is also used by `next_header_skip_blob`.
+        let mut reader = BlobReader::seekable_from_path(path).unwrap();
+        assert!(reader.skip_to_data().unwrap());
+        let (header, offset) = reader.next_header_skip_blob().unwrap().unwrap();
+        assert_eq!(header.blob_type(), BlobType::OsmData);
+        assert_eq!(offset, data_offset);
+    }
+}
+
+#[test]
+fn multi_element_reader() {
+    let count = |deduplicate| {
+        let mut labels = vec![];
+        MultiElementReader::from_paths(&TEST_FILE_PATHS)
+            .unwrap()
+            .deduplicate(deduplicate)
+            .for_each_labeled(|element, label| labels.push((element.id(), label.to_string())))
+            .unwrap();
+        labels
+    };
+
+    let all = count(false);
+    assert_eq!(all.len(), 5 * TEST_FILE_PATHS.len());
+    assert_eq!(all.last().unwrap().1, TEST_FILE_PATHS[2]);
+
+    let unique = count(true);
+    assert_eq!(unique.len(), 5);
+    assert!(unique
+        .iter()
+        .all(|&(_, ref label)| label == TEST_FILE_PATHS[0]));
+    assert_eq!(&unique[..], &all[..5]);
+}